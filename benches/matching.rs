@@ -0,0 +1,73 @@
+// Matching core at 100k resting orders
+//   cargo bench --bench matching
+// Plain timing loop (no bench harness), numbers are per operation.
+
+use distli_mesh_bc::blockchain::matching::{BookOrder, MatchingBook};
+use distli_mesh_bc::blockchain::OrderSide;
+use std::time::Instant;
+
+const RESTING: u64 = 100_000;
+
+#[derive(Clone)]
+struct BenchOrder {
+    id: String,
+    side: OrderSide,
+    price: u64,
+    quantity: u64,
+}
+
+impl BookOrder for BenchOrder {
+    fn order_id(&self) -> &str { &self.id }
+    fn asset(&self) -> &str { "BTC" }
+    fn side(&self) -> OrderSide { self.side }
+    fn price(&self) -> u64 { self.price }
+    fn quantity(&self) -> u64 { self.quantity }
+    fn set_quantity(&mut self, quantity: u64) { self.quantity = quantity; }
+}
+
+fn sell(i: u64) -> BenchOrder {
+    // 1000 price levels, 100 orders deep
+    BenchOrder { id: format!("s{}", i), side: OrderSide::Sell, price: 10_000 + i % 1_000, quantity: 10 }
+}
+
+fn report(name: &str, ops: u64, start: Instant) {
+    let elapsed = start.elapsed();
+    println!("{:<32} {:>8} ops  {:>10.0} ns/op", name, ops, elapsed.as_nanos() as f64 / ops as f64);
+}
+
+fn main() {
+    let mut book = MatchingBook::new();
+    let start = Instant::now();
+    for i in 0..RESTING {
+        book.insert(sell(i));
+    }
+    report("insert (to 100k resting)", RESTING, start);
+
+    let start = Instant::now();
+    for i in (0..RESTING).step_by(10) {
+        book.cancel(&format!("s{}", i));
+    }
+    report("cancel by id", RESTING / 10, start);
+    for i in (0..RESTING).step_by(10) {
+        book.insert(sell(i));
+    }
+
+    // Marketable buys that each take one full resting order
+    let start = Instant::now();
+    let takers = 10_000;
+    for i in 0..takers {
+        let mut taker = BenchOrder { id: format!("b{}", i), side: OrderSide::Buy, price: 20_000, quantity: 10 };
+        book.match_order(&mut taker);
+    }
+    report("match (1 fill each)", takers, start);
+
+    // Non-marketable buys that rest below the book
+    let start = Instant::now();
+    for i in 0..takers {
+        let mut order = BenchOrder { id: format!("r{}", i), side: OrderSide::Buy, price: 5_000 + i % 100, quantity: 10 };
+        book.match_order(&mut order);
+        book.insert(order);
+    }
+    report("place non-crossing", takers, start);
+    println!("{} orders resting", book.len());
+}
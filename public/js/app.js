@@ -1,17 +1,23 @@
 // app.js - Updated with trade execution handling
-import init, { Blockchain, OrderBook } from '../pkg/distli_mesh_bc.js';
+import init, { Blockchain, BlockSync, OrderBook, TenantKey } from '../pkg/distli_mesh_bc.js';
 
 class DistliApp {
     constructor() {
         this.blockchain = null;
         this.orderBook = null;
+        this.orderTxIds = {}; // local order id -> id of the Trading transaction the enterprise BC knows it by
         this.ws = null;
         this.connected = false;
         this.currentNetwork = null;
         this.dataChannels = new Map();
         this.peers = new Map();
         this.availablePeers = [];
+        this.blockSync = null; // catching up with a peer, see startBlockSync
+        this.syncCandidates = [];
+        this.syncTimer = null;
         this.userId = 'user_' + Math.random().toString(36).substr(2, 9);
+        this.tenantKey = null; // for networks in the tenant registry, see answerChallenge
+        this.apiKey = new URLSearchParams(window.location.search).get('api_key'); // read-only key for a tracker with API_KEYS set
         this.recentBlocks = [];
 		this.remoteOrders = { bids: [], asks: [] };
         this.processedTrades = new Set(); // Track processed trade executions to prevent duplicates
@@ -29,7 +35,9 @@ class DistliApp {
             await init();
             this.blockchain = new Blockchain();
             this.orderBook = new OrderBook();
-            this.blockchain.add_validator(this.userId, 1000);
+            // Our own key, so the chain accepts what we issue
+            this.blockchain.add_validator_with_key(this.userId, 1000, this.blockchain.get_public_key());
+            this.issueOpeningBalances();
             
             this.setupEventListeners();
             this.setupDefaultServer();
@@ -161,7 +169,7 @@ class DistliApp {
         this.recentBlocks.forEach(block => {
             if (block.height > 0) {
                 console.log('Sending offline block #' + block.height + ' to enterprise BC');
-                this.send({ type: 'block', block: block });
+                this.sendBlock(block);
                 setTimeout(() => {}, 100);
             }
         });
@@ -183,7 +191,27 @@ class DistliApp {
                 this.availablePeers = message.peers;
                 this.connectAllPeers();
                 this.updateUI();
-                setTimeout(() => this.requestSync(), 2000);
+                this.syncCandidates = [...message.peers];
+                this.startBlockSync();
+                break;
+            case 'challenge':
+                this.answerChallenge(message.network_id, message.nonce);
+                break;
+            case 'join_refused':
+                localStorage.removeItem(`tenantKey:${message.network_id}`);
+                alert(`Could not join network ${message.network_id}: ${message.reason}`);
+                break;
+            case 'get_headers':
+            case 'get_blocks': {
+                const answer = this.blockchain.answer_sync_json(JSON.stringify(message));
+                if (answer) {
+                    this.send(JSON.parse(answer));
+                }
+                break;
+            }
+            case 'headers':
+            case 'blocks':
+                this.handleSyncMessage(message);
                 break;
             case 'offer':
                 this.handleOffer(message.target, message.offer);
@@ -204,7 +232,12 @@ class DistliApp {
 				if (message.sync_data) {
 					if (message.sync_data.type === 'order_book_update') {
 						console.log('Processing order book update');
+					   	this.handleCancelledOrders(message.sync_data.cancelled);
 					   	this.handleRemoteOrderBook(message.sync_data.orders);
+					} else if (message.sync_data.type === 'settlement') {
+						this.handleSettlement(message.sync_data);
+					} else if (message.sync_data.type === 'order_ack') {
+						this.handleOrderAck(message.sync_data.ack);
 					} else if ( message.sync_data.type === 'trade_execution') {
 						console.log('Processing trade execution from enterprise BC');
 						this.handleTradeExecution(message.sync_data.trade);
@@ -214,6 +247,30 @@ class DistliApp {
         }
     }
 
+    // The network is in the tenant registry: prove we hold its key. The
+    // secret is asked for once and kept for the next visit.
+    answerChallenge(networkId, nonce) {
+        const stored = `tenantKey:${networkId}`;
+        const secret = localStorage.getItem(stored) || prompt(`Tenant key for network ${networkId}`);
+        const key = secret ? TenantKey.from_secret(secret) : undefined;
+        if (!key) {
+            alert(`Network ${networkId} needs its tenant key to join`);
+            return;
+        }
+        localStorage.setItem(stored, secret);
+        this.tenantKey = key;
+        this.send({ type: 'challenge_response', network_id: networkId, signature: key.sign_join(networkId, nonce) });
+    }
+
+    // Blocks of a registered network carry the tenant's signature for the enterprise BC
+    sendBlock(block) {
+        const message = { type: 'block', block };
+        if (this.tenantKey && this.currentNetwork) {
+            message.tenant_signature = this.tenantKey.sign_block_json(this.currentNetwork, JSON.stringify(block));
+        }
+        this.send(message);
+    }
+
     send(message) {
         if (this.ws && this.ws.readyState === WebSocket.OPEN) {
             this.ws.send(JSON.stringify(message));
@@ -223,6 +280,7 @@ class DistliApp {
     joinNetwork() {
         const networkId = this.getSelectedNetwork();
         if (networkId && this.connected) {
+            this.tenantKey = null;
             this.send({ type: 'join_network', network_id: networkId });
             setTimeout(() => this.discoverPeers(), 1000);
         }
@@ -234,6 +292,21 @@ class DistliApp {
         return selectValue || inputValue;
     }
 	
+	// Orders the enterprise BC dropped without a trade (expired GTT/GTD,
+	// or their network went silent)
+	handleCancelledOrders(cancelled) {
+			if (!cancelled || cancelled.length === 0) return;
+			cancelled.forEach(order => {
+				console.log(`Order ${order.order_id} from network ${order.network_id} cancelled (${order.reason}), ${order.remaining} ${order.asset} unfilled`);
+			});
+			if (this.orderBook) {
+				const expired = this.orderBook.expire_orders();
+				if (expired > 0) {
+					console.log(`Expired ${expired} local orders`);
+				}
+			}
+	}
+
 	handleRemoteOrderBook(orders) {
 			console.log('Received remote order book update:', orders);
 			console.log('Current network:', this.currentNetwork);
@@ -268,7 +341,7 @@ class DistliApp {
         if (!this.connected) return;
         try {
             const server = document.getElementById('server-input')?.value?.trim();
-            const response = await fetch(`http://${server}/api/network-list`);
+            const response = await fetch(`http://${server}/api/network-list`, this.apiKey ? { headers: { 'X-Api-Key': this.apiKey } } : {});
             const networks = await response.json();
             this.updateNetworkDropdown(networks);
         } catch (error) {
@@ -399,12 +472,59 @@ class DistliApp {
         }
     }
 
-    requestSync() {
-        this.broadcastToP2P({
-            type: 'sync_request',
-            current_height: this.blockchain.get_chain_length() - 1,
-            sender: this.userId
-        });
+    // Catch up with the network through the tracker, headers first (see
+    // src/blockchain/sync.rs). A peer that fails or stops answering is given
+    // up for the next one.
+    startBlockSync() {
+        clearTimeout(this.syncTimer);
+        const peer = this.syncCandidates.shift();
+        if (!peer) {
+            this.blockSync = null;
+            return;
+        }
+        console.log('Syncing chain from peer', peer);
+        this.blockSync = new BlockSync();
+        this.send(JSON.parse(this.blockSync.start_json(this.blockchain, peer)));
+        this.syncTimer = setTimeout(() => this.startBlockSync(), 10000);
+    }
+
+    handleSyncMessage(message) {
+        if (!this.blockSync) return;
+        const fromHeight = this.blockchain.get_chain_length() - 1;
+        const next = this.blockSync.handle_json(this.blockchain, JSON.stringify(message));
+
+        // Blocks that extended our chain, or a branch we switched to
+        const applied = JSON.parse(this.blockSync.take_applied_json());
+        const reorgs = JSON.parse(this.blockchain.take_reorg_events_json());
+        reorgs.forEach(reorg => this.handleChainReorg(reorg));
+        const viaReorg = new Set(reorgs.flatMap(reorg => reorg.added.map(b => b.hash)));
+        const onChain = new Set(JSON.parse(this.blockchain.get_blocks_since_json(fromHeight)).map(b => b.hash));
+        for (const block of applied) {
+            if (onChain.has(block.hash) && !viaReorg.has(block.hash)) {
+                this.storeBlock(block);
+                this.placeBlockOrders(block, new Set());
+            }
+        }
+        if (applied.length > 0) {
+            this.updateOrderBook();
+            this.updateUI();
+        }
+
+        if (next) {
+            clearTimeout(this.syncTimer);
+            this.send(JSON.parse(next));
+            this.syncTimer = setTimeout(() => this.startBlockSync(), 10000);
+        } else if (this.blockSync.is_finished()) {
+            const error = this.blockSync.get_error();
+            if (error) {
+                console.warn('Sync failed:', error);
+                this.startBlockSync();
+            } else {
+                console.log('Caught up at block #' + (this.blockchain.get_chain_length() - 1));
+                clearTimeout(this.syncTimer);
+                this.blockSync = null;
+            }
+        }
     }
 
     // UPDATED: Handle P2P messages including trade executions
@@ -491,9 +611,12 @@ class DistliApp {
 		    timestamp: trade.timestamp
 		};
 		
-		// Add to blockchain
-		this.blockchain.add_p2p_transaction(JSON.stringify(executionTx));
-		const success = this.blockchain.mine_block();
+		// Signed with our node key; refused if already recorded or unaffordable
+		const recorded = this.blockchain.record_trade_execution_json(JSON.stringify(executionTx));
+		if (!recorded) {
+		    console.warn('Trade execution not recorded:', executionTx.id);
+		}
+		const success = recorded && this.blockchain.mine_block();
 		
 		if (success) {
 		    const execBlock = JSON.parse(this.blockchain.get_latest_block_json());
@@ -510,7 +633,7 @@ class DistliApp {
 		    
 		    // Send to enterprise BC if connected
 		    if (this.connected && execBlock.height > 0) {
-			this.send({ type: 'block', block: execBlock });
+			this.sendBlock(execBlock);
 		    }
 		    
 		    console.log('Trade execution processed and broadcast');
@@ -531,15 +654,85 @@ class DistliApp {
 		    console.log('Order cancelled:', orderId);
 		    this.updateOrderBook();
 
-		    // Notify enterprise BC about cancellation
-		    if (this.connected) {
-			// could send a cancellation message to enterprise
-			// this.send({ type: 'cancel_order', order_id: orderId });
+		    // Withdraw it from cross-network matching too
+		    const txId = this.orderTxIds[orderId];
+		    if (txId) {
+			delete this.orderTxIds[orderId];
+			this.blockchain.call_contract_cancel(txId, this.userId);
+			this.publishOrderChange();
 		    }
 		}
 	    }
 	}
 
+	// Amend price and/or quantity (0 keeps the current value) across networks
+	amendOrder(orderId, price, quantity) {
+	    const txId = this.orderTxIds[orderId];
+	    if (!txId) return;
+	    const result = JSON.parse(this.blockchain.call_contract_amend(txId, price, quantity, this.userId));
+	    if (result.status === 'success') {
+		this.publishOrderChange();
+	    } else {
+		console.log('Amend rejected:', result.message);
+	    }
+	}
+
+	// Mine the queued cancel/amend and send it on like any order block
+	publishOrderChange() {
+	    if (!this.blockchain.mine_block()) return;
+	    const block = JSON.parse(this.blockchain.get_latest_block_json());
+	    this.storeBlock(block);
+	    this.broadcastToP2P({ type: 'blockchain_block', block: block, sender: this.userId });
+	    if (this.connected && block.height > 0) {
+		this.sendBlock(block);
+	    }
+	}
+
+	// Demo funds: coin for buying and some of every listed asset for selling
+	issueOpeningBalances() {
+	    this.blockchain.issue(this.userId, '', 100000000);
+	    ['BTC', 'ETH', 'ADA', 'DOT', 'SOL'].forEach(asset => this.blockchain.issue(this.userId, asset, 10000));
+	    this.blockchain.mine_block();
+	}
+
+	// Why our trader cannot cover their side of the trade, or null
+	missingFunds(trade) {
+	    const cash = Math.floor(trade.quantity * trade.price / 100);
+	    if (trade.buyer_network === this.currentNetwork && Number(this.blockchain.get_balance(trade.buyer, '')) < cash) {
+		return `${trade.buyer} cannot pay ${cash}`;
+	    }
+	    if (trade.seller_network === this.currentNetwork && Number(this.blockchain.get_balance(trade.seller, trade.asset)) < trade.quantity) {
+		return `${trade.seller} does not hold ${trade.quantity} ${trade.asset}`;
+	    }
+	    return null;
+	}
+
+	// Two-phase settlement: vote on prepares for this network's side of a
+	// trade; the trade itself arrives as trade_execution once committed
+	handleSettlement(settlement) {
+	    const trade = settlement.trade;
+	    if (!trade) return;
+	    const ourSide = trade.buyer_network === this.currentNetwork || trade.seller_network === this.currentNetwork;
+	    if (settlement.phase === 'prepare' && ourSide) {
+		const missing = this.missingFunds(trade);
+		console.log(`Preparing settlement of trade ${trade.trade_id}: ${missing || 'funds available'}`);
+		this.send({ type: 'settlement_vote', trade_id: trade.trade_id, prepared: !missing, reason: missing || undefined });
+	    } else if (settlement.phase === 'abort') {
+		console.log(`Settlement of trade ${trade.trade_id} aborted: ${settlement.status && settlement.status.reason}`);
+	    } else if (settlement.phase === 'commit') {
+		console.log(`Settlement of trade ${trade.trade_id} committed`);
+	    }
+	}
+
+	handleOrderAck(ack) {
+	    if (!ack) return;
+	    if (ack.accepted) {
+		console.log(`Enterprise BC accepted ${ack.action} of order ${ack.order_id}`);
+	    } else {
+		console.log(`Enterprise BC rejected ${ack.action} of order ${ack.order_id}: ${ack.reason}`);
+	    }
+	}
+
 	// Add this new method after handleTradeExecution
 	updateLocalOrderBookAfterTrade(trade) {
 		// Get current order book
@@ -608,44 +801,82 @@ class DistliApp {
         if (!block || !block.height) return;
         
         try {
-            const currentHeight = this.blockchain.get_chain_length() - 1;
-            
-            if (block.height > currentHeight) {
-                if (block.validator && block.stake_weight) {
-                    try {
+            // Blocks at or below our height may be a competing branch, which
+            // the chain keeps and switches to if it wins the fork choice
+            if (block.validator && block.stake_weight) {
+                try {
+                    if (block.validator_key) {
+                        // Pins the validator to the key that signed its first block
+                        this.blockchain.add_validator_with_key(block.validator, block.stake_weight, block.validator_key);
+                    } else {
                         this.blockchain.add_validator(block.validator, block.stake_weight);
-                    } catch (e) {
-                        // Validator already exists
                     }
+                } catch (e) {
+                    // Validator already exists
                 }
-                
-                const success = this.blockchain.add_p2p_block(JSON.stringify(block));
-                if (success) {
-                    this.storeBlock(block);
-                    
-                    // Update OrderBook if trading transaction
-                    if (block.transactions) {
-                        for (const tx of block.transactions) {
-                            if (tx.tx_type && tx.tx_type.Trading) {
-                                const trading = tx.tx_type.Trading;
-                                if (tx.id.includes('buy_')) {
-                                    this.orderBook.place_buy_order(tx.from, trading.asset, trading.quantity, trading.price);
-                                } else if (tx.id.includes('sell_')) {
-                                    this.orderBook.place_sell_order(tx.from, trading.asset, trading.quantity, trading.price);
-                                }
-                            }
-                        }
-                        this.updateOrderBook();
-                    }
-                    
-                    this.updateUI();
-                }
+            }
+            
+            const success = this.blockchain.add_p2p_block(JSON.stringify(block));
+            const reorgs = JSON.parse(this.blockchain.take_reorg_events_json());
+            if (reorgs.length > 0) {
+                reorgs.forEach(reorg => this.handleChainReorg(reorg));
+            } else if (success) {
+                this.storeBlock(block);
+                this.placeBlockOrders(block, new Set());
+                this.updateOrderBook();
+            }
+            if (success) {
+                this.updateUI();
             }
         } catch (error) {
             console.error('Error processing P2P block:', error);
         }
     }
 
+    // Update OrderBook with the trading transactions of a block, except those in `skip`
+    placeBlockOrders(block, skip) {
+        for (const tx of block.transactions || []) {
+            if (tx.tx_type && tx.tx_type.Trading && !skip.has(tx.id)) {
+                const trading = tx.tx_type.Trading;
+                const side = this.tradingSide(tx);
+                if (side) {
+                    this.orderBook.place_order(tx.from, trading.asset, side, trading.quantity, trading.price, trading.client_order_id || '');
+                }
+            }
+        }
+    }
+
+    // Our chain switched to another branch (see src/blockchain/forks.rs).
+    // Our own orders from abandoned blocks that did not make it back come off
+    // the book, orders new to this branch go on it, and the tracker passes
+    // the reorg on to the enterprise BC.
+    handleChainReorg(reorg) {
+        console.log(`Chain reorg at block #${reorg.fork_height}: ${reorg.removed.length} blocks replaced by ${reorg.added.length}`);
+        const removedHashes = new Set(reorg.removed.map(b => b.hash));
+        this.recentBlocks = this.recentBlocks.filter(b => !removedHashes.has(b.hash));
+        reorg.added.forEach(b => this.storeBlock(b));
+
+        const txIds = blocks => new Set(blocks.flatMap(b => (b.transactions || []).map(tx => tx.id)));
+        const removedTxs = txIds(reorg.removed);
+        const kept = new Set([...txIds(reorg.added), ...reorg.returned_to_pending]);
+        for (const [orderId, txId] of Object.entries(this.orderTxIds)) {
+            if (removedTxs.has(txId) && !kept.has(txId)) {
+                this.orderBook.cancel_order(orderId);
+                delete this.orderTxIds[orderId];
+            }
+        }
+        reorg.added.forEach(b => this.placeBlockOrders(b, removedTxs));
+        this.updateOrderBook();
+
+        if (this.connected) {
+            const message = { type: 'chain_reorg', reorg };
+            if (this.tenantKey && this.currentNetwork) {
+                message.tenant_signature = this.tenantKey.sign_reorg_json(this.currentNetwork, JSON.stringify(reorg));
+            }
+            this.send(message);
+        }
+    }
+
     sendToP2PPeer(peerId, message) {
         const channel = this.dataChannels.get(peerId);
         if (channel && channel.readyState === 'open') {
@@ -680,7 +911,11 @@ class DistliApp {
         if (!messageText) return;
 
         try {
-            this.blockchain.add_message(messageText, this.userId);
+            // Empty id: the chain refused it, e.g. we cannot pay the fee
+            if (!this.blockchain.add_message(messageText, this.userId)) {
+                console.log('Message rejected');
+                return;
+            }
             const success = this.blockchain.mine_block();
             
             if (success) {
@@ -697,7 +932,7 @@ class DistliApp {
                     
                     // Only send non-genesis blocks to enterprise
                     if (this.connected && minedBlock.height > 0) {
-                        this.send({ type: 'block', block: minedBlock });
+                        this.sendBlock(minedBlock);
                     }
                 }
             }
@@ -722,7 +957,11 @@ class DistliApp {
         const priceInt = Math.floor(price * 100);
 
         // Step 1: Create BUY order block
-        this.blockchain.call_contract_buy(asset, quantityInt, priceInt, this.userId);
+        const buyResult = JSON.parse(this.blockchain.call_contract_buy(asset, quantityInt, priceInt, this.userId));
+        if (buyResult.status !== 'success') {
+            console.log('Order rejected:', buyResult.message);
+            return;
+        }
         const orderSuccess = this.blockchain.mine_block();
 
         if (orderSuccess) {
@@ -730,12 +969,13 @@ class DistliApp {
             this.storeBlock(orderBlock);
             this.broadcastToP2P({ type: 'blockchain_block', block: orderBlock, sender: this.userId });
             if (this.connected && orderBlock.height > 0) {
-                this.send({ type: 'block', block: orderBlock });
+                this.sendBlock(orderBlock);
             }
         }
         
         // Step 2: Place order ONCE in local order book
         const orderId = this.orderBook.place_buy_order(this.userId, asset, quantityInt, priceInt);
+        this.orderTxIds[orderId] = buyResult.orderId;
         console.log('Placed buy order locally with ID:', orderId);
         
         // Rest of the execution logic remains the same...
@@ -766,15 +1006,19 @@ class DistliApp {
                 timestamp: currentTime
             };
             
-            this.blockchain.add_p2p_transaction(JSON.stringify(executionTx));
-            const execSuccess = this.blockchain.mine_block();
+            // Signed with our node key; refused if already recorded or unaffordable
+            const recorded = this.blockchain.record_trade_execution_json(JSON.stringify(executionTx));
+            if (!recorded) {
+                console.warn('Trade execution not recorded:', executionTx.id);
+            }
+            const execSuccess = recorded && this.blockchain.mine_block();
             
             if (execSuccess) {
                 const execBlock = JSON.parse(this.blockchain.get_latest_block_json());
                 this.storeBlock(execBlock);
                 this.broadcastToP2P({ type: 'blockchain_block', block: execBlock, sender: this.userId });
                 if (this.connected && execBlock.height > 0) {
-                    this.send({ type: 'block', block: execBlock });
+                    this.sendBlock(execBlock);
                 }
             }
         }
@@ -803,7 +1047,11 @@ placeSellOrder() {
         const priceInt = Math.floor(price * 100);
 
         // Step 1: Create SELL order block
-        this.blockchain.call_contract_sell(asset, quantityInt, priceInt, this.userId);
+        const sellResult = JSON.parse(this.blockchain.call_contract_sell(asset, quantityInt, priceInt, this.userId));
+        if (sellResult.status !== 'success') {
+            console.log('Order rejected:', sellResult.message);
+            return;
+        }
         const orderSuccess = this.blockchain.mine_block();
 
         if (orderSuccess) {
@@ -811,12 +1059,13 @@ placeSellOrder() {
             this.storeBlock(orderBlock);
             this.broadcastToP2P({ type: 'blockchain_block', block: orderBlock, sender: this.userId });
             if (this.connected && orderBlock.height > 0) {
-                this.send({ type: 'block', block: orderBlock });
+                this.sendBlock(orderBlock);
             }
         }
         
         // Step 2: Place order ONCE in local order book
         const orderId = this.orderBook.place_sell_order(this.userId, asset, quantityInt, priceInt);
+        this.orderTxIds[orderId] = sellResult.orderId;
         console.log('Placed sell order locally with ID:', orderId);
         
         // Rest of the execution logic remains the same...
@@ -847,15 +1096,19 @@ placeSellOrder() {
                 timestamp: currentTime
             };
             
-            this.blockchain.add_p2p_transaction(JSON.stringify(executionTx));
-            const execSuccess = this.blockchain.mine_block();
+            // Signed with our node key; refused if already recorded or unaffordable
+            const recorded = this.blockchain.record_trade_execution_json(JSON.stringify(executionTx));
+            if (!recorded) {
+                console.warn('Trade execution not recorded:', executionTx.id);
+            }
+            const execSuccess = recorded && this.blockchain.mine_block();
             
             if (execSuccess) {
                 const execBlock = JSON.parse(this.blockchain.get_latest_block_json());
                 this.storeBlock(execBlock);
                 this.broadcastToP2P({ type: 'blockchain_block', block: execBlock, sender: this.userId });
                 if (this.connected && execBlock.height > 0) {
-                    this.send({ type: 'block', block: execBlock });
+                    this.sendBlock(execBlock);
                 }
             }
         }
@@ -1052,6 +1305,16 @@ updateTable(tableId, localOrders, type) {
     }
 
     // UPDATED: Generate block display with trade execution support
+    // Explicit side, or the buy_/sell_ id prefix used by blocks written before it existed
+    tradingSide(tx) {
+        const trading = tx.tx_type && tx.tx_type.Trading;
+        if (!trading) return null;
+        if (trading.side) return trading.side.toLowerCase();
+        if (tx.id.includes('buy_')) return 'buy';
+        if (tx.id.includes('sell_')) return 'sell';
+        return null;
+    }
+
     generateBlockDisplay(block) {
         if (!block || !block.transactions || block.transactions.length === 0) return '';
 
@@ -1067,7 +1330,7 @@ updateTable(tableId, localOrders, type) {
             const quantity = (trading.quantity / 100).toFixed(2);
             const price = (trading.price / 100).toFixed(2);
             
-            const orderType = tx.id.includes('buy_') ? "BUY" : "SELL";
+            const orderType = (this.tradingSide(tx) || 'unknown').toUpperCase();
             content = `${orderType} ORDER: ${quantity} ${trading.asset} @ $${price}`;
             typeLabel = `${orderType} Order`;
         } else if (tx.tx_type?.TradeExecution) {
@@ -0,0 +1,263 @@
+// Full-chain integrity audit
+// Re-checks a loaded chain from genesis (or from the snapshot or pruning point
+// it starts at): height and hash linkage, Merkle roots, header hashes,
+// membership of the validator set as it stood at each height, and signatures.
+// Stops at the first broken block and reports why, so operators can tell
+// tampering from a truncated or legacy file.
+
+use super::Blockchain;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChainFault {
+    MissingGenesis,
+    BadGenesis,
+    HeightGap { expected: u32, found: u32 },
+    BrokenLink { expected_previous: String, found_previous: String },
+    MerkleRootMismatch { stored: String, computed: String },
+    HashMismatch { stored: String, computed: String },
+    UnknownValidator { validator: String },
+    BadSignature { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrokenBlock {
+    pub height: u32,
+    pub hash: String,
+    pub fault: ChainFault,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainReport {
+    pub valid: bool,
+    pub blocks_checked: usize,
+    pub chain_length: usize,
+    // Height of the first block; above zero when the chain starts at a snapshot
+    #[serde(default)]
+    pub base_height: u32,
+    // First block that failed; everything before it checked out
+    pub first_broken: Option<BrokenBlock>,
+}
+
+impl ChainReport {
+    pub fn summary(&self) -> String {
+        match &self.first_broken {
+            None if self.base_height > 0 => format!(
+                "chain OK: {} blocks verified from height {}",
+                self.blocks_checked, self.base_height
+            ),
+            None => format!("chain OK: {} blocks verified", self.blocks_checked),
+            Some(broken) => format!(
+                "chain BROKEN at height {} (block {}): {:?}; {} of {} blocks verified before it",
+                broken.height,
+                &broken.hash[..16.min(broken.hash.len())],
+                broken.fault,
+                self.blocks_checked,
+                self.chain_length
+            ),
+        }
+    }
+}
+
+impl Blockchain {
+    pub fn verify_chain(&self) -> ChainReport {
+        let chain_length = self.chain.len();
+        let base_height = self.chain.first().map_or(0, |b| b.height);
+        let report = |blocks_checked: usize, first_broken: Option<BrokenBlock>| ChainReport {
+            valid: first_broken.is_none(),
+            blocks_checked,
+            chain_length,
+            base_height,
+            first_broken,
+        };
+
+        let genesis = match self.chain.first() {
+            Some(genesis) => genesis,
+            None => return report(0, Some(BrokenBlock {
+                height: 0,
+                hash: String::new(),
+                fault: ChainFault::MissingGenesis,
+            })),
+        };
+        if genesis.height == 0 && !genesis.transactions.is_empty() {
+            return report(0, Some(BrokenBlock {
+                height: genesis.height,
+                hash: genesis.hash.clone(),
+                fault: ChainFault::BadGenesis,
+            }));
+        }
+        // A snapshot anchor is trusted as a starting point, but must still be
+        // the block it claims to be
+        if genesis.height > 0 {
+            let fault = if genesis.calculate_merkle_root() != genesis.merkle_root {
+                Some(ChainFault::MerkleRootMismatch {
+                    stored: genesis.merkle_root.clone(),
+                    computed: genesis.calculate_merkle_root(),
+                })
+            } else if genesis.calculate_hash() != genesis.hash {
+                Some(ChainFault::HashMismatch { stored: genesis.hash.clone(), computed: genesis.calculate_hash() })
+            } else {
+                None
+            };
+            if let Some(fault) = fault {
+                return report(0, Some(BrokenBlock { height: genesis.height, hash: genesis.hash.clone(), fault }));
+            }
+        }
+
+        // Replay account key bindings from scratch, and the validator set as
+        // it stood at each height; validator keys pinned so far (discovery or
+        // first block) stay authoritative. Without genesis the bindings made
+        // before the first block can't be replayed.
+        let mut replay = Blockchain::new();
+        replay.chain = vec![genesis.clone()];
+        if genesis.height > 0 {
+            replay.account_keys = self.account_keys.clone();
+        }
+
+        for (index, block) in self.chain.iter().enumerate().skip(1) {
+            let previous = &self.chain[index - 1];
+            replay.validators = self.validators_at(block.height);
+            let fault = if block.height != previous.height + 1 {
+                Some(ChainFault::HeightGap { expected: previous.height + 1, found: block.height })
+            } else if block.previous_hash != previous.hash {
+                Some(ChainFault::BrokenLink {
+                    expected_previous: previous.hash.clone(),
+                    found_previous: block.previous_hash.clone(),
+                })
+            } else if block.calculate_merkle_root() != block.merkle_root {
+                Some(ChainFault::MerkleRootMismatch {
+                    stored: block.merkle_root.clone(),
+                    computed: block.calculate_merkle_root(),
+                })
+            } else if block.calculate_hash() != block.hash {
+                Some(ChainFault::HashMismatch { stored: block.hash.clone(), computed: block.calculate_hash() })
+            } else if !replay.validators.contains_key(&block.validator) {
+                Some(ChainFault::UnknownValidator { validator: block.validator.clone() })
+            } else {
+                replay.verify_block_signatures(block).err().map(|reason| ChainFault::BadSignature { reason })
+            };
+
+            if let Some(fault) = fault {
+                return report(index, Some(BrokenBlock { height: block.height, hash: block.hash.clone(), fault }));
+            }
+            replay.bind_keys(block);
+            replay.chain.push(block.clone());
+        }
+
+        report(chain_length, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn three_block_chain() -> Blockchain {
+        let mut bc = Blockchain::new();
+        bc.add_validator("alice".to_string(), 100);
+        for i in 0..3 {
+            bc.add_message(format!("message {}", i), "alice".to_string());
+            let block = bc.create_block_proposal("alice", 0).unwrap();
+            assert!(bc.add_block(block));
+        }
+        bc
+    }
+
+    #[test]
+    fn test_untouched_chain_verifies() {
+        let report = three_block_chain().verify_chain();
+        assert!(report.valid, "{}", report.summary());
+        assert_eq!(report.blocks_checked, 4);
+    }
+
+    #[test]
+    fn test_report_points_at_first_tampered_block() {
+        let mut bc = three_block_chain();
+        bc.chain[2].transactions[0].amount = 500;
+        let report = bc.verify_chain();
+        let broken = report.first_broken.unwrap();
+        assert_eq!(broken.height, 2);
+        assert!(matches!(broken.fault, ChainFault::MerkleRootMismatch { .. }));
+        assert_eq!(report.blocks_checked, 2);
+
+        // Fixing up the root still leaves the header hash (and signature) wrong
+        let mut bc = three_block_chain();
+        bc.chain[3].transactions[0].amount = 500;
+        bc.chain[3].merkle_root = bc.chain[3].calculate_merkle_root();
+        assert!(matches!(bc.verify_chain().first_broken.unwrap().fault, ChainFault::HashMismatch { .. }));
+
+        let mut bc = three_block_chain();
+        bc.chain[1].previous_hash = "1".repeat(64);
+        assert!(matches!(bc.verify_chain().first_broken.unwrap().fault, ChainFault::BrokenLink { .. }));
+    }
+
+    #[test]
+    fn test_resigned_block_from_other_key_is_reported() {
+        let mut bc = three_block_chain();
+        let mallory = crate::blockchain::signing::KeyPair::from_seed(b"mallory");
+        bc.chain[3].sign(&mallory);
+        match bc.verify_chain().first_broken.unwrap().fault {
+            ChainFault::BadSignature { .. } => {}
+            other => panic!("unexpected fault {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_blocks_are_checked_against_the_validators_of_their_height() {
+        use crate::blockchain::staking::{StakingConfig, STAKING_ACCOUNT};
+        use crate::blockchain::{ledger, Transaction, TransactionType};
+        use crate::blockchain::signing::KeyPair;
+
+        let mut bc = Blockchain::new();
+        bc.enable_staking(StakingConfig { epoch_length: 2, unbonding_epochs: 1, ..Default::default() });
+        bc.add_validator("v1".to_string(), 100);
+        let mint = Transaction {
+            id: "fund_alice".to_string(),
+            from: "genesis".to_string(),
+            to: "alice".to_string(),
+            amount: 1_000,
+            tx_type: TransactionType::Mint { asset: ledger::NATIVE_COIN.to_string() },
+            timestamp: 0,
+            public_key: None,
+            signature: None,
+        };
+        bc.ledger.apply(&mint).unwrap();
+        let alice = KeyPair::from_seed(b"alice");
+        bc.account_keys.insert("alice".to_string(), alice.public_key_hex());
+        let mut stake = Transaction {
+            id: "s1".to_string(),
+            from: "alice".to_string(),
+            to: STAKING_ACCOUNT.to_string(),
+            amount: 300,
+            tx_type: TransactionType::Stake,
+            timestamp: 1,
+            public_key: None,
+            signature: None,
+        };
+        stake.sign(&alice);
+        assert!(bc.add_p2p_transaction(serde_json::to_string(&stake).unwrap()));
+
+        // alice joins the set at the end of block 2
+        for i in 0..4 {
+            let proposer = bc.get_next_proposer();
+            bc.set_node_key(KeyPair::from_seed(proposer.as_bytes()));
+            bc.add_message(format!("message {}", i), proposer.clone());
+            let block = bc.create_block_proposal(&proposer, 0).unwrap();
+            assert!(bc.add_block(block));
+        }
+        assert!(bc.validators.contains_key("alice"));
+        let report = bc.verify_chain();
+        assert!(report.valid, "{}", report.summary());
+
+        // A block of hers from before she joined is not one she could propose
+        bc.chain.truncate(2);
+        bc.chain[1].validator = "alice".to_string();
+        bc.chain[1].hash = bc.chain[1].calculate_hash();
+        bc.chain[1].sign(&alice);
+        match bc.verify_chain().first_broken.unwrap().fault {
+            ChainFault::UnknownValidator { validator } => assert_eq!(validator, "alice"),
+            other => panic!("unexpected fault {:?}", other),
+        }
+    }
+}
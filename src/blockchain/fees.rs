@@ -0,0 +1,221 @@
+// Fee schedule: what transactions and trades cost, and who is paid
+//
+// Fees are in the native coin and come out of account balances when the
+// block commits:
+//   any transaction    flat + per_byte * its JSON size, paid by `from`
+//   ContractDeploy /   plus gas * gas_price, the gas being what the contract
+//   ContractCall       executor meters for a deploy or a call with one write
+//   TradeExecution     no transaction fee; buyer and seller pay the maker or
+//                      taker rate, in basis points of quantity * price / 100
+//   Mint, Evidence     free
+// A block's fees go to the active validators in proportion to stake; what
+// does not divide evenly goes to the block's validator. The block records
+// what it charged and paid (Block::fees), so replaying a chain gives the
+// same balances whatever schedule is configured later. Validators check
+// that a proposed block charges what their own schedule says, so every
+// validator of a network needs the same schedule.
+
+use super::tenants::TenantRegistry;
+use super::{OrderSide, Transaction, TransactionType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Gas per operation, as metered by SmartContractExecutor
+pub const GAS_DEPLOY: u64 = 100;
+pub const GAS_READ: u64 = 5;
+pub const GAS_WRITE: u64 = 20;
+pub const GAS_COMPUTE: u64 = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeFees {
+    pub maker_bps: u64,
+    pub taker_bps: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    #[serde(default)]
+    pub flat: u64, // per transaction
+    #[serde(default)]
+    pub per_byte: u64,
+    #[serde(default)]
+    pub gas_price: u64, // coin per unit of gas
+    #[serde(default)]
+    pub trade: TradeFees, // unless the asset has its own
+    #[serde(default)]
+    pub assets: BTreeMap<String, TradeFees>,
+}
+
+// Fees of one block, all in the native coin
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockFees {
+    pub total: u64,
+    pub charged: BTreeMap<String, u64>, // by payer
+    pub paid: BTreeMap<String, u64>,    // by validator
+}
+
+impl FeeSchedule {
+    // FEE_FLAT, FEE_PER_BYTE, FEE_GAS_PRICE, FEE_MAKER_BPS, FEE_TAKER_BPS and
+    // FEE_ASSETS ("BTC:5:10,ETH:2:4" for asset:maker:taker); all default to 0
+    #[cfg(feature = "native")]
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok()).unwrap_or(0);
+        let assets = std::env::var("FEE_ASSETS").unwrap_or_default();
+        Self {
+            flat: var("FEE_FLAT"),
+            per_byte: var("FEE_PER_BYTE"),
+            gas_price: var("FEE_GAS_PRICE"),
+            trade: TradeFees { maker_bps: var("FEE_MAKER_BPS"), taker_bps: var("FEE_TAKER_BPS") },
+            assets: Self::parse_assets(&assets),
+        }
+    }
+
+    // "asset:maker_bps:taker_bps,..."; malformed entries are skipped
+    pub fn parse_assets(spec: &str) -> BTreeMap<String, TradeFees> {
+        spec.split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.trim().split(':');
+                let asset = parts.next()?.trim();
+                let maker_bps = parts.next()?.trim().parse().ok()?;
+                let taker_bps = parts.next()?.trim().parse().ok()?;
+                (!asset.is_empty() && parts.next().is_none())
+                    .then(|| (asset.to_string(), TradeFees { maker_bps, taker_bps }))
+            })
+            .collect()
+    }
+
+    pub fn is_free(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn trade_fees(&self, asset: &str) -> TradeFees {
+        self.assets.get(asset).copied().unwrap_or(self.trade)
+    }
+
+    pub fn gas_fee(&self, gas: u64) -> u64 {
+        gas.saturating_mul(self.gas_price)
+    }
+
+    // Who pays what for `tx`, payers with nothing to pay left out
+    pub fn charges(&self, tx: &Transaction) -> Vec<(String, u64)> {
+        let charges = match &tx.tx_type {
+            TransactionType::Mint { .. } | TransactionType::Evidence { .. } => Vec::new(),
+            _ if TenantRegistry::is_registry(tx) => Vec::new(),
+            TransactionType::TradeExecution { asset, quantity, price, buyer, seller, taker, .. } => {
+                let rates = self.trade_fees(asset);
+                let value = quantity.saturating_mul(*price) / 100;
+                // Without a recorded taker both sides pay the taker rate
+                let (buyer_bps, seller_bps) = match taker {
+                    Some(OrderSide::Buy) => (rates.taker_bps, rates.maker_bps),
+                    Some(OrderSide::Sell) => (rates.maker_bps, rates.taker_bps),
+                    None => (rates.taker_bps, rates.taker_bps),
+                };
+                vec![(buyer.clone(), bps_of(value, buyer_bps)), (seller.clone(), bps_of(value, seller_bps))]
+            }
+            _ => {
+                let size = serde_json::to_vec(tx).map(|json| json.len() as u64).unwrap_or(0);
+                let fee = self.flat
+                    .saturating_add(self.per_byte.saturating_mul(size))
+                    .saturating_add(self.gas_fee(gas(tx)));
+                vec![(tx.from.clone(), fee)]
+            }
+        };
+        charges.into_iter().filter(|(_, fee)| *fee > 0).collect()
+    }
+
+    // What a block of `transactions` by `proposer` charges and pays; None
+    // when it is free. `validators` is the active set with stakes.
+    pub fn assess(&self, transactions: &[Transaction], validators: &[(String, u64)], proposer: &str) -> Option<BlockFees> {
+        let mut fees = BlockFees::default();
+        for (payer, fee) in transactions.iter().flat_map(|tx| self.charges(tx)) {
+            let charged = fees.charged.entry(payer).or_insert(0);
+            *charged = charged.saturating_add(fee);
+            fees.total = fees.total.saturating_add(fee);
+        }
+        if fees.total == 0 {
+            return None;
+        }
+
+        let total_stake: u128 = validators.iter().map(|(_, stake)| *stake as u128).sum();
+        let mut left = fees.total;
+        for (validator, stake) in validators {
+            let share = (fees.total as u128 * *stake as u128).checked_div(total_stake).unwrap_or(0) as u64;
+            if share > 0 {
+                fees.paid.insert(validator.clone(), share);
+                left -= share;
+            }
+        }
+        if left > 0 {
+            *fees.paid.entry(proposer.to_string()).or_insert(0) += left;
+        }
+        Some(fees)
+    }
+}
+
+// Gas a transaction is charged for
+pub fn gas(tx: &Transaction) -> u64 {
+    match tx.tx_type {
+        TransactionType::ContractDeploy { .. } => GAS_DEPLOY,
+        TransactionType::ContractCall { .. } => GAS_COMPUTE + GAS_WRITE,
+        _ => 0,
+    }
+}
+
+fn bps_of(value: u64, bps: u64) -> u64 {
+    (value as u128 * bps as u128 / 10_000) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(id: &str, from: &str, tx_type: TransactionType) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            from: from.to_string(),
+            to: "somebody".to_string(),
+            amount: 0,
+            tx_type,
+            timestamp: 1,
+            public_key: None,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_charges_and_stake_weighted_payout() {
+        let schedule = FeeSchedule {
+            flat: 2,
+            gas_price: 1,
+            trade: TradeFees { maker_bps: 10, taker_bps: 20 },
+            assets: FeeSchedule::parse_assets("ETH:0:5, bad, :1:2"),
+            ..Default::default()
+        };
+        assert_eq!(schedule.assets.len(), 1);
+
+        let trade = |asset: &str, taker| tx("t", "node", TransactionType::TradeExecution {
+            asset: asset.to_string(),
+            quantity: 100,
+            price: 10_000, // 10_000 coin
+            buyer: "bob".to_string(),
+            seller: "alice".to_string(),
+            trade_id: None,
+            taker,
+        });
+        assert_eq!(schedule.charges(&trade("BTC", Some(OrderSide::Buy))), vec![("bob".to_string(), 20), ("alice".to_string(), 10)]);
+        assert_eq!(schedule.charges(&trade("ETH", Some(OrderSide::Buy))), vec![("bob".to_string(), 5)]);
+        assert_eq!(schedule.charges(&trade("BTC", None)), vec![("bob".to_string(), 20), ("alice".to_string(), 20)]);
+        let call = tx("c", "carol", TransactionType::ContractCall { function: "inc".to_string(), params: String::new() });
+        assert_eq!(schedule.charges(&call), vec![("carol".to_string(), 2 + GAS_COMPUTE + GAS_WRITE)]);
+        assert!(schedule.charges(&tx("m", "v1", TransactionType::Mint { asset: String::new() })).is_empty());
+
+        // 62 coin over stakes 1:2, the odd coin to the proposer
+        let validators = vec![("v1".to_string(), 100), ("v2".to_string(), 200)];
+        let fees = schedule.assess(&[trade("BTC", Some(OrderSide::Sell)), call], &validators, "v1").unwrap();
+        assert_eq!(fees.total, 62);
+        assert_eq!(fees.charged.get("alice"), Some(&20));
+        assert_eq!(fees.paid.values().sum::<u64>(), 62);
+        assert_eq!((fees.paid["v1"], fees.paid["v2"]), (21, 41));
+        assert!(FeeSchedule::default().assess(&[trade("BTC", None)], &validators, "v1").is_none());
+    }
+}
@@ -0,0 +1,186 @@
+// Forks of a tenant chain, and which branch to follow
+//
+// Tenant peers mine without a BFT round, so two of them can build on the same
+// parent at once and the network splits. A block that does not extend our
+// tip is kept in the ForkTree if its parent is known, on our chain or in the
+// tree. When its branch beats ours we reorganize: state rolls back to the
+// fork point and the branch is applied block by block. Fork choice, the same
+// on every peer:
+//   1. more stake behind it, the sum of stake_weight of the blocks past the
+//      fork point; validate_block holds each to its validator's stake
+//   2. more blocks
+//   3. the lower tip hash
+// State to roll back to is checkpointed before every block for the last
+// reorg_depth blocks, in memory only, so a fork deeper than that (or older
+// than the last restart) cannot win. A depth of 0 turns forks off, as for
+// the enterprise chain whose BFT commits are final.
+
+use super::{ledger::Ledger, staking::Staking, tenants::TenantRegistry, Block, Validator};
+#[cfg(feature = "native")]
+use super::SmartContract;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const DEFAULT_REORG_DEPTH: u32 = 32;
+const MAX_SIDE_BLOCKS: usize = 256;
+pub const MAX_REORG_EVENTS: usize = 64; // kept until taken
+
+#[derive(Debug, Clone, Default)]
+pub struct ForkTree {
+    blocks: HashMap<String, Block>, // by hash
+}
+
+impl ForkTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    // When full the lowest blocks make room, they are the least likely to win
+    pub fn insert(&mut self, block: Block) {
+        while self.blocks.len() >= MAX_SIDE_BLOCKS {
+            let lowest = self.blocks.values().min_by_key(|b| (b.height, b.hash.clone())).map(|b| b.hash.clone());
+            match lowest {
+                Some(hash) => self.blocks.remove(&hash),
+                None => break,
+            };
+        }
+        self.blocks.insert(block.hash.clone(), block);
+    }
+
+    pub fn remove(&mut self, hash: &str) -> Option<Block> {
+        self.blocks.remove(hash)
+    }
+
+    // `hash` and every block built on it
+    pub fn remove_with_descendants(&mut self, hash: &str) {
+        let mut doomed = vec![hash.to_string()];
+        while let Some(hash) = doomed.pop() {
+            self.blocks.remove(&hash);
+            doomed.extend(self.blocks.values().filter(|b| b.previous_hash == hash).map(|b| b.hash.clone()));
+        }
+    }
+
+    // Blocks below `height` can no longer win
+    pub fn prune_below(&mut self, height: u32) {
+        self.blocks.retain(|_, block| block.height >= height);
+    }
+
+    // The blocks from the fork point up to `tip`, oldest first; None unless
+    // the branch leads back to a block for which `on_chain` holds
+    pub fn branch(&self, tip: &str, on_chain: impl Fn(u32, &str) -> bool) -> Option<Vec<Block>> {
+        let mut branch = Vec::new();
+        let mut block = self.blocks.get(tip)?;
+        loop {
+            branch.push(block.clone());
+            let parent_height = block.height.checked_sub(1)?;
+            if on_chain(parent_height, &block.previous_hash) {
+                break;
+            }
+            match self.blocks.get(&block.previous_hash) {
+                Some(parent) if parent.height == parent_height => block = parent,
+                _ => return None,
+            }
+        }
+        branch.reverse();
+        Some(branch)
+    }
+}
+
+// Whether `candidate` wins over `current`, both starting after the same fork point
+pub fn prefer(candidate: &[Block], current: &[Block]) -> bool {
+    let rank = |branch: &[Block]| {
+        let weight = branch.iter().fold(0u64, |sum, b| sum.saturating_add(b.stake_weight));
+        let tip = branch.last().map(|b| b.hash.clone()).unwrap_or_default();
+        (weight, branch.len(), std::cmp::Reverse(tip))
+    };
+    rank(candidate) > rank(current)
+}
+
+// State before the block at `height` was applied
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub height: u32,
+    pub ledger: Ledger,
+    pub staking: Staking,
+    pub tenants: TenantRegistry,
+    pub account_keys: HashMap<String, String>,
+    pub validators: HashMap<String, Validator>,
+    pub total_stake: u64,
+    #[cfg(feature = "native")]
+    pub contracts: HashMap<String, SmartContract>,
+}
+
+// A switch to another branch. Transactions of removed blocks that the new
+// branch does not include go back to pending if they still apply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainReorg {
+    pub fork_height: u32,
+    pub fork_hash: String,
+    pub old_tip: String,
+    pub new_tip: String,
+    pub removed: Vec<Block>, // oldest first
+    pub added: Vec<Block>,
+    pub returned_to_pending: Vec<String>, // transaction ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(height: u32, hash: &str, previous_hash: &str, stake_weight: u64) -> Block {
+        Block {
+            height,
+            hash: hash.to_string(),
+            previous_hash: previous_hash.to_string(),
+            timestamp: 0,
+            validator: "v".to_string(),
+            transactions: Vec::new(),
+            merkle_root: String::new(),
+            stake_weight,
+            round: 0,
+            nonce: None,
+            data: None,
+            validator_key: None,
+            signature: None,
+            fees: None,
+        }
+    }
+
+    #[test]
+    fn test_branches_lead_back_to_the_chain_and_heaviest_wins() {
+        let mut tree = ForkTree::new();
+        tree.insert(block(3, "b3", "a2", 100));
+        tree.insert(block(4, "b4", "b3", 100));
+        tree.insert(block(5, "x5", "unknown", 100));
+        let on_chain = |height: u32, hash: &str| height == 2 && hash == "a2";
+
+        let branch = tree.branch("b4", on_chain).unwrap();
+        assert_eq!(branch.iter().map(|b| b.hash.as_str()).collect::<Vec<_>>(), vec!["b3", "b4"]);
+        assert!(tree.branch("x5", on_chain).is_none());
+
+        // Stake first, then length, then the lower tip hash
+        let current = vec![block(3, "a3", "a2", 150)];
+        assert!(prefer(&branch, &current));
+        assert!(!prefer(&branch, &[block(3, "a3", "a2", 300)]));
+        assert!(prefer(&[block(3, "a3", "a2", 100), block(4, "a4", "a3", 0)], &[block(3, "c3", "a2", 100)]));
+        assert!(prefer(&[block(3, "a3", "a2", 100)], &[block(3, "c3", "a2", 100)]));
+        assert!(!prefer(&[block(3, "c3", "a2", 100)], &[block(3, "a3", "a2", 100)]));
+
+        tree.remove_with_descendants("b3");
+        assert_eq!(tree.len(), 1);
+        tree.prune_below(6);
+        assert!(tree.is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+// Account balances of the native coin and named assets
+//
+// Only committed blocks change balances. What a transaction moves:
+//   Transfer        `amount` coin from `from` to `to`
+//   TradeExecution  quantity * price / 100 coin from buyer to seller, and
+//                   `quantity` of the asset from seller to buyer; the chain
+//                   only accepts it signed by a validator's key
+//   Mint            `amount` of the asset (or coin) to `to`, out of nothing;
+//                   likewise only from a validator's key
+//   Stake/Delegate  `amount` coin from `from` to the staking account, which
+//                   pays out rewards and unbonded coin, see staking.rs
+// Anything else moves nothing. A transaction that would take an account
+// below zero, fees included (see fees.rs), is rejected as a whole.
+
+use super::fees::{BlockFees, FeeSchedule};
+use super::staking::STAKING_ACCOUNT;
+use super::{Transaction, TransactionType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const NATIVE_COIN: &str = "coin";
+
+// Balances changed by staged transactions, by (account, asset)
+type Overlay = BTreeMap<(String, String), u64>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movement {
+    pub from: Option<String>, // None when issued
+    pub to: String,
+    pub asset: String,
+    pub amount: u64,
+}
+
+// Balances by account, then by asset
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Ledger {
+    balances: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.balances.is_empty()
+    }
+
+    pub fn balance(&self, account: &str, asset: &str) -> u64 {
+        self.balances.get(account).and_then(|assets| assets.get(asset)).copied().unwrap_or(0)
+    }
+
+    // Every non-zero balance of one account
+    pub fn balances(&self, account: &str) -> BTreeMap<String, u64> {
+        self.balances.get(account).cloned().unwrap_or_default()
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &str> {
+        self.balances.keys().map(|account| account.as_str())
+    }
+
+    // What `tx` moves, in the order it is applied
+    pub fn movements(tx: &Transaction) -> Vec<Movement> {
+        let moved = |from: &str, to: &str, asset: &str, amount: u64| Movement {
+            from: Some(from.to_string()),
+            to: to.to_string(),
+            asset: asset.to_string(),
+            amount,
+        };
+        match &tx.tx_type {
+            TransactionType::Transfer => vec![moved(&tx.from, &tx.to, NATIVE_COIN, tx.amount)],
+            TransactionType::TradeExecution { asset, quantity, price, buyer, seller, .. } => vec![
+                moved(buyer, seller, NATIVE_COIN, quantity.saturating_mul(*price) / 100),
+                moved(seller, buyer, asset, *quantity),
+            ],
+            TransactionType::Stake | TransactionType::Delegate { .. } => {
+                vec![moved(&tx.from, STAKING_ACCOUNT, NATIVE_COIN, tx.amount)]
+            }
+            TransactionType::Mint { asset } => vec![Movement {
+                from: None,
+                to: tx.to.clone(),
+                asset: if asset.is_empty() { NATIVE_COIN.to_string() } else { asset.clone() },
+                amount: tx.amount,
+            }],
+            _ => Vec::new(),
+        }
+        .into_iter()
+        .filter(|movement| movement.amount > 0)
+        .collect()
+    }
+
+    // All of the transaction's movements or none of them; fees are applied
+    // per block, see apply_fees
+    pub fn apply(&mut self, tx: &Transaction) -> Result<(), String> {
+        let mut overlay = Overlay::new();
+        self.stage(&mut overlay, tx, &FeeSchedule::default())?;
+        self.commit(overlay);
+        Ok(())
+    }
+
+    // Take a committed block's fees from the payers and credit the validators
+    pub fn apply_fees(&mut self, fees: &BlockFees) -> Result<(), String> {
+        let mut overlay = Overlay::new();
+        for (payer, fee) in &fees.charged {
+            self.debit(&mut overlay, payer, NATIVE_COIN, *fee)
+                .map_err(|(balance, _)| format!("{} has {} {}, owes {} in fees", payer, balance, NATIVE_COIN, fee))?;
+        }
+        for (validator, fee) in &fees.paid {
+            self.credit(&mut overlay, validator, NATIVE_COIN, *fee)
+                .map_err(|_| format!("{} balance of {} overflows", NATIVE_COIN, validator))?;
+        }
+        self.commit(overlay);
+        Ok(())
+    }
+
+    // Move `amount` between accounts outside of any transaction, e.g. coin
+    // the staking account releases when a block commits
+    pub fn transfer(&mut self, from: &str, to: &str, asset: &str, amount: u64) -> Result<(), String> {
+        let mut overlay = Overlay::new();
+        self.debit(&mut overlay, from, asset, amount)
+            .map_err(|(balance, _)| format!("{} has {} {}, cannot pay out {}", from, balance, asset, amount))?;
+        self.credit(&mut overlay, to, asset, amount)
+            .map_err(|_| format!("{} balance of {} overflows", asset, to))?;
+        self.commit(overlay);
+        Ok(())
+    }
+
+    // Credit `amount` out of nothing, e.g. genesis stake
+    pub fn issue(&mut self, to: &str, asset: &str, amount: u64) -> Result<(), String> {
+        let mut overlay = Overlay::new();
+        self.credit(&mut overlay, to, asset, amount)
+            .map_err(|_| format!("{} balance of {} overflows", asset, to))?;
+        self.commit(overlay);
+        Ok(())
+    }
+
+    // Take `amount` out of circulation, e.g. slashed stake
+    pub fn burn(&mut self, from: &str, asset: &str, amount: u64) -> Result<(), String> {
+        let mut overlay = Overlay::new();
+        self.debit(&mut overlay, from, asset, amount)
+            .map_err(|(balance, _)| format!("{} has {} {}, cannot burn {}", from, balance, asset, amount))?;
+        self.commit(overlay);
+        Ok(())
+    }
+
+    // Whether the transactions and their fees apply one after the other,
+    // without changing anything
+    pub fn check<'a>(&self, txs: impl IntoIterator<Item = &'a Transaction>, fees: &FeeSchedule) -> Result<(), String> {
+        let mut overlay = Overlay::new();
+        for tx in txs {
+            self.stage(&mut overlay, tx, fees)?;
+        }
+        Ok(())
+    }
+
+    // Keep the transactions that still apply in order, e.g. pending ones
+    // after a block spent the same funds; returns how many were dropped
+    pub fn retain_applicable(&self, txs: &mut Vec<Transaction>, fees: &FeeSchedule) -> usize {
+        let before = txs.len();
+        let mut overlay = Overlay::new();
+        txs.retain(|tx| {
+            let mut attempt = overlay.clone();
+            let fits = self.stage(&mut attempt, tx, fees).is_ok();
+            if fits {
+                overlay = attempt;
+            }
+            fits
+        });
+        before - txs.len()
+    }
+
+    // Balances after `tx` and its fees on top of `overlay`; `overlay` is
+    // untouched on error. Fees leave the payer here and reach the
+    // validators with the block.
+    fn stage(&self, overlay: &mut Overlay, tx: &Transaction, fees: &FeeSchedule) -> Result<(), String> {
+        let mut next = overlay.clone();
+        for movement in Self::movements(tx) {
+            if let Some(from) = &movement.from {
+                self.debit(&mut next, from, &movement.asset, movement.amount).map_err(|(balance, needed)| {
+                    format!("transaction {}: {} has {} {}, needs {}", tx.id, from, balance, movement.asset, needed)
+                })?;
+            }
+            self.credit(&mut next, &movement.to, &movement.asset, movement.amount)
+                .map_err(|_| format!("transaction {}: {} balance of {} overflows", tx.id, movement.asset, movement.to))?;
+        }
+        for (payer, fee) in fees.charges(tx) {
+            self.debit(&mut next, &payer, NATIVE_COIN, fee).map_err(|(balance, needed)| {
+                format!("transaction {}: {} has {} {} left, needs {} for fees", tx.id, payer, balance, NATIVE_COIN, needed)
+            })?;
+        }
+        *overlay = next;
+        Ok(())
+    }
+
+    // Err((balance, amount)) when the account has too little
+    fn debit(&self, overlay: &mut Overlay, account: &str, asset: &str, amount: u64) -> Result<(), (u64, u64)> {
+        let key = (account.to_string(), asset.to_string());
+        let balance = *overlay.entry(key.clone()).or_insert_with(|| self.balance(account, asset));
+        let left = balance.checked_sub(amount).ok_or((balance, amount))?;
+        overlay.insert(key, left);
+        Ok(())
+    }
+
+    fn credit(&self, overlay: &mut Overlay, account: &str, asset: &str, amount: u64) -> Result<(), ()> {
+        let key = (account.to_string(), asset.to_string());
+        let balance = *overlay.entry(key.clone()).or_insert_with(|| self.balance(account, asset));
+        overlay.insert(key, balance.checked_add(amount).ok_or(())?);
+        Ok(())
+    }
+
+    fn commit(&mut self, overlay: Overlay) {
+        for ((account, asset), balance) in overlay {
+            self.set(&account, &asset, balance);
+        }
+    }
+
+    fn set(&mut self, account: &str, asset: &str, balance: u64) {
+        if balance == 0 {
+            if let Some(assets) = self.balances.get_mut(account) {
+                assets.remove(asset);
+                if assets.is_empty() {
+                    self.balances.remove(account);
+                }
+            }
+        } else {
+            self.balances.entry(account.to_string()).or_default().insert(asset.to_string(), balance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(id: &str, from: &str, to: &str, amount: u64, tx_type: TransactionType) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+            tx_type,
+            timestamp: 1,
+            public_key: None,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_transfers_and_trades_move_balances_or_nothing() {
+        let mut ledger = Ledger::new();
+        let mint = |id, to, asset: &str, amount| tx(id, "validator", to, amount, TransactionType::Mint { asset: asset.to_string() });
+        ledger.apply(&mint("m1", "alice", "", 500)).unwrap();
+        ledger.apply(&mint("m2", "bob", "BTC", 3)).unwrap();
+
+        assert!(ledger.apply(&tx("t1", "alice", "carol", 501, TransactionType::Transfer)).is_err());
+        ledger.apply(&tx("t2", "alice", "carol", 100, TransactionType::Transfer)).unwrap();
+        assert_eq!((ledger.balance("alice", NATIVE_COIN), ledger.balance("carol", NATIVE_COIN)), (400, 100));
+
+        let trade = |id, quantity| tx(id, "node", "settlement", 0, TransactionType::TradeExecution {
+            asset: "BTC".to_string(),
+            quantity,
+            price: 10_000,
+            buyer: "alice".to_string(),
+            seller: "bob".to_string(),
+            trade_id: None,
+            taker: None,
+        });
+        // alice can pay for 4 but bob only has 3: nothing moves
+        assert!(ledger.apply(&trade("x1", 4)).is_err());
+        assert_eq!(ledger.balance("alice", NATIVE_COIN), 400);
+        ledger.apply(&trade("x2", 3)).unwrap();
+        assert_eq!(ledger.balances("alice"), BTreeMap::from([("BTC".to_string(), 3), (NATIVE_COIN.to_string(), 100)]));
+        assert_eq!(ledger.balances("bob"), BTreeMap::from([(NATIVE_COIN.to_string(), 300)]));
+    }
+}
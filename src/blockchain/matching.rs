@@ -0,0 +1,932 @@
+// Price-time priority matching core
+// Shared by the browser OrderBook and the enterprise order engine, so a local
+// match and a cross-network match follow the same rules:
+//   - one book per asset, each side a BTreeMap of price levels
+//   - best price first; within a level, first come first served
+//   - a fill executes at the resting (maker) order's price
+// Every resting order gets an arrival sequence number. A level is a BTreeMap
+// keyed by that number, so FIFO order and removal by id are both O(log n);
+// the id index finds an order's level without scanning.
+//
+// `submit` applies the order type and time in force on top of that:
+//   Limit/GTC  fills what crosses, rests the remainder
+//   IOC        fills what crosses, cancels the remainder
+//   FOK        fills in full or is rejected without touching the book
+//   Market     fills at any price, cancels whatever finds no liquidity
+//   PostOnly   rests, or is rejected if it would cross
+//   Stop(Limit) parks until the asset's last trade price reaches the
+//              trigger, then runs as a market (limit) order
+//   GTT/GTD    rests like GTC until its expiry time, then is dropped
+// Every submitted or triggered order comes back as an Execution with its
+// fills and final status.
+//
+// Self-trade prevention: orders with the same non-empty owner never trade
+// with each other. When an order reaches one of its owner's resting orders:
+//   CancelNewest   the incoming order stops there, its rest is cancelled
+//   CancelOldest   the resting order is cancelled and matching goes on
+//   DecrementBoth  both shrink by the smaller quantity, nothing trades
+// Each prevented match is listed in the Execution's `prevented`.
+
+use super::{OrderSide, OrderType, TimeInForce};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+pub trait BookOrder: Clone {
+    fn order_id(&self) -> &str;
+    fn asset(&self) -> &str;
+    fn side(&self) -> OrderSide;
+    fn price(&self) -> u64;
+    fn quantity(&self) -> u64;
+    fn set_quantity(&mut self, quantity: u64);
+
+    fn order_type(&self) -> OrderType {
+        OrderType::Limit
+    }
+
+    fn time_in_force(&self) -> TimeInForce {
+        TimeInForce::Gtc
+    }
+
+    // Who the order trades for; empty opts out of self-trade prevention
+    fn owner(&self) -> &str {
+        ""
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTradePrevention {
+    #[default]
+    None,
+    CancelNewest,
+    CancelOldest,
+    DecrementBoth,
+}
+
+impl SelfTradePrevention {
+    // "none", "cancel_newest", "cancel_oldest" or "decrement_both"
+    pub fn parse(mode: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(mode.trim().to_ascii_lowercase())).ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum OrderStatus {
+    Filled,
+    Resting { remaining: u64 },
+    Cancelled { remaining: u64, reason: String }, // unfilled rest of an IOC or market order, or self-trade prevented
+    Rejected { reason: String },                  // nothing executed, book untouched
+    StopPending,                                  // parked until its trigger price trades
+    Expired { remaining: u64 },                   // GTT/GTD order reached its expiry
+}
+
+// Outcome of one submitted, triggered or expired order; `order` is as
+// submitted, or as it was resting when it expired
+#[derive(Debug, Clone)]
+pub struct Execution<O> {
+    pub order: O,
+    pub fills: Vec<Fill<O>>,
+    pub status: OrderStatus,
+    pub prevented: Vec<SelfTrade<O>>,
+}
+
+impl<O> Execution<O> {
+    fn new(order: O, fills: Vec<Fill<O>>, status: OrderStatus) -> Self {
+        Self { order, fills, status, prevented: Vec::new() }
+    }
+
+    fn rejected(order: O, reason: &str) -> Self {
+        Self::new(order, Vec::new(), OrderStatus::Rejected { reason: reason.to_string() })
+    }
+}
+
+fn is_self_trade<O: BookOrder>(mode: SelfTradePrevention, taker: &O, maker: &O) -> bool {
+    mode != SelfTradePrevention::None && !taker.owner().is_empty() && taker.owner() == maker.owner()
+}
+
+// A match self-trade prevention stopped; `maker` is the resting order as it
+// was before, `cancelled` the quantity it lost (all of it under CancelOldest)
+#[derive(Debug, Clone)]
+pub struct SelfTrade<O> {
+    pub maker: O,
+    pub mode: SelfTradePrevention,
+    pub cancelled: u64,
+}
+
+// One execution against a resting order; `maker` is the resting order as it
+// was before this fill
+#[derive(Debug, Clone)]
+pub struct Fill<O> {
+    pub maker: O,
+    pub quantity: u64,
+    pub price: u64,
+}
+
+// One aggregated price level of a side (L2 depth)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthLevel {
+    pub price: u64,
+    pub quantity: u64,
+    pub orders: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Location {
+    asset: String,
+    side: OrderSide,
+    price: u64,
+    seq: u64,
+}
+
+type Level<O> = BTreeMap<u64, O>;
+
+#[derive(Debug, Clone)]
+struct AssetBook<O> {
+    bids: BTreeMap<u64, Level<O>>,
+    asks: BTreeMap<u64, Level<O>>,
+}
+
+impl<O> AssetBook<O> {
+    fn side_mut(&mut self, side: OrderSide) -> &mut BTreeMap<u64, Level<O>> {
+        match side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    // Levels in priority order: highest bid / lowest ask first
+    fn levels(&self, side: OrderSide) -> Box<dyn Iterator<Item = (&u64, &Level<O>)> + '_> {
+        match side {
+            OrderSide::Buy => Box::new(self.bids.iter().rev()),
+            OrderSide::Sell => Box::new(self.asks.iter()),
+        }
+    }
+}
+
+// Parked stop orders of one asset by (trigger price, arrival)
+#[derive(Debug, Clone, Default)]
+struct StopTriggers {
+    buy: BTreeMap<(u64, u64), String>,
+    sell: BTreeMap<(u64, u64), String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchingBook<O> {
+    books: BTreeMap<String, AssetBook<O>>,
+    index: HashMap<String, Location>,
+    stops: HashMap<String, (u64, O)>,
+    triggers: BTreeMap<String, StopTriggers>,
+    last_prices: BTreeMap<String, u64>,
+    expiries: BTreeMap<(u64, u64), String>, // (expires_at, seq) -> id, resting and parked
+    next_seq: u64,
+    self_trade_prevention: SelfTradePrevention,
+}
+
+impl<O: BookOrder> Default for MatchingBook<O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<O: BookOrder> MatchingBook<O> {
+    pub fn new() -> Self {
+        Self {
+            books: BTreeMap::new(),
+            index: HashMap::new(),
+            stops: HashMap::new(),
+            triggers: BTreeMap::new(),
+            last_prices: BTreeMap::new(),
+            expiries: BTreeMap::new(),
+            next_seq: 0,
+            self_trade_prevention: SelfTradePrevention::None,
+        }
+    }
+
+    pub fn self_trade_prevention(&self) -> SelfTradePrevention {
+        self.self_trade_prevention
+    }
+
+    pub fn set_self_trade_prevention(&mut self, mode: SelfTradePrevention) {
+        self.self_trade_prevention = mode;
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn side_len(&self, side: OrderSide) -> usize {
+        self.index.values().filter(|loc| loc.side == side).count()
+    }
+
+    // Resting or parked as a stop
+    pub fn contains(&self, order_id: &str) -> bool {
+        self.index.contains_key(order_id) || self.stops.contains_key(order_id)
+    }
+
+    pub fn get(&self, order_id: &str) -> Option<&O> {
+        let loc = self.index.get(order_id)?;
+        let book = self.books.get(&loc.asset)?;
+        let levels = match loc.side {
+            OrderSide::Buy => &book.bids,
+            OrderSide::Sell => &book.asks,
+        };
+        levels.get(&loc.price)?.get(&loc.seq)
+    }
+
+    pub fn get_stop(&self, order_id: &str) -> Option<&O> {
+        self.stops.get(order_id).map(|(_, order)| order)
+    }
+
+    // Parked stop orders, oldest first
+    pub fn stop_orders(&self) -> Vec<&O> {
+        let mut stops: Vec<&(u64, O)> = self.stops.values().collect();
+        stops.sort_by_key(|(seq, _)| *seq);
+        stops.into_iter().map(|(_, order)| order).collect()
+    }
+
+    pub fn last_price(&self, asset: &str) -> Option<u64> {
+        self.last_prices.get(asset).copied()
+    }
+
+    pub fn last_prices(&self) -> &BTreeMap<String, u64> {
+        &self.last_prices
+    }
+
+    // Restore the last trade price, e.g. when reloading a saved book
+    pub fn set_last_price(&mut self, asset: &str, price: u64) {
+        self.last_prices.insert(asset.to_string(), price);
+    }
+
+    // Best bid (Buy) or best ask (Sell) for an asset
+    pub fn best_price(&self, asset: &str, side: OrderSide) -> Option<u64> {
+        self.books.get(asset)?.levels(side).next().map(|(price, _)| *price)
+    }
+
+    // Up to `levels` price levels of one side, best first
+    pub fn depth(&self, asset: &str, side: OrderSide, levels: usize) -> Vec<DepthLevel> {
+        self.books.get(asset)
+            .map(|book| book.levels(side).take(levels).map(|(price, level)| DepthLevel {
+                price: *price,
+                quantity: level.values().map(|order| order.quantity()).sum(),
+                orders: level.len(),
+            }).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn assets(&self) -> impl Iterator<Item = &str> {
+        self.books.keys().map(|asset| asset.as_str())
+    }
+
+    // Resting orders on one side of one asset, in priority order
+    pub fn orders(&self, asset: &str, side: OrderSide) -> Vec<&O> {
+        self.books.get(asset)
+            .map(|book| book.levels(side).flat_map(|(_, level)| level.values()).collect())
+            .unwrap_or_default()
+    }
+
+    // One side across all assets: assets by name, then priority order
+    pub fn side_orders(&self, side: OrderSide) -> Vec<&O> {
+        self.books.values()
+            .flat_map(|book| book.levels(side).flat_map(|(_, level)| level.values()))
+            .collect()
+    }
+
+    // Rest an order at the back of its price level. False (and nothing
+    // changes) for a duplicate id or an empty order.
+    pub fn insert(&mut self, order: O) -> bool {
+        if order.quantity() == 0 || self.contains(order.order_id()) {
+            return false;
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let loc = Location {
+            asset: order.asset().to_string(),
+            side: order.side(),
+            price: order.price(),
+            seq,
+        };
+        self.index.insert(order.order_id().to_string(), loc.clone());
+        if let Some(expires_at) = order.time_in_force().expires_at() {
+            self.expiries.insert((expires_at, seq), order.order_id().to_string());
+        }
+        self.books.entry(loc.asset)
+            .or_insert_with(|| AssetBook { bids: BTreeMap::new(), asks: BTreeMap::new() })
+            .side_mut(loc.side)
+            .entry(loc.price)
+            .or_default()
+            .insert(seq, order);
+        true
+    }
+
+    pub fn cancel(&mut self, order_id: &str) -> Option<O> {
+        if let Some(stop) = self.remove_stop(order_id) {
+            return Some(stop);
+        }
+        let loc = self.index.remove(order_id)?;
+        let book = self.books.get_mut(&loc.asset)?;
+        let levels = book.side_mut(loc.side);
+        let level = levels.get_mut(&loc.price)?;
+        let order = level.remove(&loc.seq);
+        if level.is_empty() {
+            levels.remove(&loc.price);
+        }
+        if book.is_empty() {
+            self.books.remove(&loc.asset);
+        }
+        if let Some(expires_at) = order.as_ref().and_then(|o| o.time_in_force().expires_at()) {
+            self.expiries.remove(&(expires_at, loc.seq));
+        }
+        order
+    }
+
+    // Remove every resting and parked order the predicate selects, e.g. all
+    // orders of one network. A full scan; not for the matching path.
+    pub fn cancel_where(&mut self, mut select: impl FnMut(&O) -> bool) -> Vec<O> {
+        let mut ids: Vec<(u64, String)> = self.stops.values()
+            .filter(|(_, order)| select(order))
+            .map(|(seq, order)| (*seq, order.order_id().to_string()))
+            .collect();
+        ids.extend(self.books.values()
+            .flat_map(|book| book.bids.values().chain(book.asks.values()))
+            .flat_map(|level| level.iter())
+            .filter(|(_, order)| select(order))
+            .map(|(seq, order)| (*seq, order.order_id().to_string())));
+        ids.sort();
+        ids.iter().filter_map(|(_, id)| self.cancel(id)).collect()
+    }
+
+    // Drop every order whose GTT/GTD expiry is at or before `now`, oldest expiry first
+    pub fn expire(&mut self, now: u64) -> Vec<O> {
+        let due: Vec<String> = self.expiries.range(..=(now, u64::MAX)).map(|(_, id)| id.clone()).collect();
+        due.iter().filter_map(|id| self.cancel(id)).collect()
+    }
+
+    // Reducing keeps the order's place in the queue; increasing sends it to
+    // the back of its level, as a new order would be. Zero cancels.
+    pub fn set_quantity(&mut self, order_id: &str, quantity: u64) -> bool {
+        let current = match self.get(order_id) {
+            Some(order) => order.quantity(),
+            None => return false,
+        };
+        if quantity == 0 {
+            self.cancel(order_id);
+        } else if quantity > current {
+            let mut order = self.cancel(order_id).expect("indexed order");
+            order.set_quantity(quantity);
+            self.insert(order);
+        } else if let Some(order) = self.get_mut(order_id) {
+            order.set_quantity(quantity);
+        }
+        true
+    }
+
+    fn get_mut(&mut self, order_id: &str) -> Option<&mut O> {
+        let loc = self.index.get(order_id)?;
+        self.books.get_mut(&loc.asset)?
+            .side_mut(loc.side)
+            .get_mut(&loc.price)?
+            .get_mut(&loc.seq)
+    }
+
+    // Park a stop order without checking its trigger, e.g. when reloading a
+    // saved book. False for a duplicate id or an order that is not a stop.
+    pub fn park_stop(&mut self, order: O) -> bool {
+        let trigger = match order.order_type().trigger_price() {
+            Some(trigger) => trigger,
+            None => return false,
+        };
+        if self.contains(order.order_id()) {
+            return false;
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let triggers = self.triggers.entry(order.asset().to_string()).or_default();
+        let side = match order.side() {
+            OrderSide::Buy => &mut triggers.buy,
+            OrderSide::Sell => &mut triggers.sell,
+        };
+        side.insert((trigger, seq), order.order_id().to_string());
+        if let Some(expires_at) = order.time_in_force().expires_at() {
+            self.expiries.insert((expires_at, seq), order.order_id().to_string());
+        }
+        self.stops.insert(order.order_id().to_string(), (seq, order));
+        true
+    }
+
+    fn remove_stop(&mut self, order_id: &str) -> Option<O> {
+        let (seq, order) = self.stops.remove(order_id)?;
+        let trigger = order.order_type().trigger_price().unwrap_or_default();
+        if let Some(triggers) = self.triggers.get_mut(order.asset()) {
+            match order.side() {
+                OrderSide::Buy => triggers.buy.remove(&(trigger, seq)),
+                OrderSide::Sell => triggers.sell.remove(&(trigger, seq)),
+            };
+            if triggers.buy.is_empty() && triggers.sell.is_empty() {
+                self.triggers.remove(order.asset());
+            }
+        }
+        if let Some(expires_at) = order.time_in_force().expires_at() {
+            self.expiries.remove(&(expires_at, seq));
+        }
+        Some(order)
+    }
+
+    fn stop_reached(side: OrderSide, trigger: u64, last: u64) -> bool {
+        match side {
+            OrderSide::Buy => last >= trigger,
+            OrderSide::Sell => last <= trigger,
+        }
+    }
+
+    // Submit an order with its type and time in force at time `now`. The
+    // first execution is the order itself; any stops its trades triggered
+    // follow, oldest first, then orders that had expired by `now` (they are
+    // dropped before matching, so nothing trades against them).
+    pub fn submit(&mut self, order: O, now: u64) -> Vec<Execution<O>> {
+        let expired = self.expire(now);
+        let asset = order.asset().to_string();
+        let mut executions = vec![self.accept(order, now)];
+        self.run_triggered_stops(&asset, &mut executions);
+        executions.extend(expired.into_iter().map(|order| {
+            let status = OrderStatus::Expired { remaining: order.quantity() };
+            Execution::new(order, Vec::new(), status)
+        }));
+        executions
+    }
+
+    fn accept(&mut self, order: O, now: u64) -> Execution<O> {
+        if order.quantity() == 0 {
+            return Execution::rejected(order, "quantity must be positive");
+        }
+        if order.time_in_force().expires_at().is_some_and(|expires_at| expires_at <= now) {
+            return Execution::rejected(order, "order expired before it arrived");
+        }
+        if self.contains(order.order_id()) {
+            return Execution::rejected(order, "duplicate order id");
+        }
+        if let Some(trigger) = order.order_type().trigger_price() {
+            let reached = self.last_price(order.asset())
+                .is_some_and(|last| Self::stop_reached(order.side(), trigger, last));
+            if !reached {
+                self.park_stop(order.clone());
+                return Execution::new(order, Vec::new(), OrderStatus::StopPending);
+            }
+        }
+        self.execute(order)
+    }
+
+    fn execute(&mut self, order: O) -> Execution<O> {
+        let side = order.side();
+        let time_in_force = order.time_in_force();
+        let (limit, may_rest) = match order.order_type() {
+            OrderType::Market | OrderType::Stop { .. } => match side {
+                OrderSide::Buy => (u64::MAX, false),
+                OrderSide::Sell => (0, false),
+            },
+            OrderType::Limit | OrderType::PostOnly | OrderType::StopLimit { .. } => {
+                (order.price(), time_in_force.rests())
+            }
+        };
+
+        // Post-only counts the owner's own orders too, it must never rest crossed
+        if order.order_type() == OrderType::PostOnly && self.fillable(&order, limit, 1, false) > 0 {
+            return Execution::rejected(order, "post-only order would take liquidity");
+        }
+        if time_in_force == TimeInForce::Fok && self.fillable(&order, limit, order.quantity(), true) < order.quantity() {
+            return Execution::rejected(order, "fill-or-kill order cannot be filled in full");
+        }
+
+        let mut taker = order.clone();
+        let (fills, prevented) = self.match_up_to(&mut taker, limit);
+        if let Some(last) = fills.last() {
+            self.last_prices.insert(order.asset().to_string(), last.price);
+        }
+
+        let remaining = taker.quantity();
+        // An order shrunk by DecrementBoth was not filled in full, even if nothing is left
+        let stopped = prevented.iter().any(|p| p.mode == SelfTradePrevention::CancelNewest);
+        let decremented = prevented.iter().any(|p| p.mode == SelfTradePrevention::DecrementBoth);
+        let status = if stopped || (remaining == 0 && decremented) {
+            OrderStatus::Cancelled { remaining, reason: "self-trade prevention".to_string() }
+        } else if remaining == 0 {
+            OrderStatus::Filled
+        } else if may_rest {
+            self.insert(taker);
+            OrderStatus::Resting { remaining }
+        } else {
+            let reason = match order.order_type() {
+                OrderType::Market | OrderType::Stop { .. } => "no more liquidity",
+                _ => "not immediately fillable",
+            };
+            OrderStatus::Cancelled { remaining, reason: reason.to_string() }
+        };
+        Execution { order, fills, status, prevented }
+    }
+
+    // Quantity on the opposite side at prices `limit` accepts, counted up to
+    // `wanted`. With `prevent_self_trades`, the owner's own orders are
+    // skipped (CancelOldest) or end the count (they would stop or shrink
+    // the order).
+    fn fillable(&self, taker: &O, limit: u64, wanted: u64, prevent_self_trades: bool) -> u64 {
+        let (asset, side) = (taker.asset(), taker.side());
+        let book = match self.books.get(asset) {
+            Some(book) => book,
+            None => return 0,
+        };
+        let maker_side = match side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        };
+        let mut total = 0u64;
+        for (price, level) in book.levels(maker_side) {
+            let crosses = match side {
+                OrderSide::Buy => limit >= *price,
+                OrderSide::Sell => limit <= *price,
+            };
+            if !crosses || total >= wanted {
+                break;
+            }
+            for maker in level.values() {
+                if prevent_self_trades && is_self_trade(self.self_trade_prevention, taker, maker) {
+                    if self.self_trade_prevention == SelfTradePrevention::CancelOldest {
+                        continue;
+                    }
+                    return total;
+                }
+                total = total.saturating_add(maker.quantity());
+            }
+        }
+        total
+    }
+
+    // Stops whose trigger the last price has reached, run one at a time as
+    // each run can move the price again
+    fn run_triggered_stops(&mut self, asset: &str, executions: &mut Vec<Execution<O>>) {
+        while let Some(last) = self.last_price(asset) {
+            let next = self.triggers.get(asset).and_then(|triggers| {
+                let buys = triggers.buy.range(..=(last, u64::MAX));
+                let sells = triggers.sell.range((last, 0)..);
+                buys.chain(sells).min_by_key(|((_, seq), _)| *seq).map(|(_, id)| id.clone())
+            });
+            let order = match next.and_then(|id| self.remove_stop(&id)) {
+                Some(order) => order,
+                None => break,
+            };
+            executions.push(self.execute(order));
+        }
+    }
+
+    // Match an incoming order against the opposite side until it is filled or
+    // the prices no longer cross. The taker's quantity is reduced in place;
+    // resting whatever is left is up to the caller.
+    pub fn match_order(&mut self, taker: &mut O) -> Vec<Fill<O>> {
+        let limit = taker.price();
+        self.match_up_to(taker, limit).0
+    }
+
+    fn match_up_to(&mut self, taker: &mut O, limit: u64) -> (Vec<Fill<O>>, Vec<SelfTrade<O>>) {
+        let mut fills = Vec::new();
+        let mut prevented = Vec::new();
+        let asset = taker.asset().to_string();
+        let taker_side = taker.side();
+        let maker_side = match taker_side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        };
+
+        while taker.quantity() > 0 {
+            let book = match self.books.get_mut(&asset) {
+                Some(book) => book,
+                None => break,
+            };
+            let levels = book.side_mut(maker_side);
+            let best = match maker_side {
+                OrderSide::Sell => levels.keys().next().copied(),
+                OrderSide::Buy => levels.keys().next_back().copied(),
+            };
+            let price = match best {
+                Some(price) => price,
+                None => break,
+            };
+            let crosses = match taker_side {
+                OrderSide::Buy => limit >= price,
+                OrderSide::Sell => limit <= price,
+            };
+            if !crosses {
+                break;
+            }
+
+            let mode = self.self_trade_prevention;
+            let level = levels.get_mut(&price).expect("best level");
+            let mut entry = level.first_entry().expect("levels are never empty");
+            let maker = entry.get_mut();
+            let quantity = taker.quantity().min(maker.quantity());
+            let left = if !is_self_trade(mode, taker, maker) {
+                fills.push(Fill { maker: maker.clone(), quantity, price });
+                taker.set_quantity(taker.quantity() - quantity);
+                maker.quantity() - quantity
+            } else {
+                match mode {
+                    SelfTradePrevention::CancelNewest => {
+                        prevented.push(SelfTrade { maker: maker.clone(), mode, cancelled: 0 });
+                        break;
+                    }
+                    SelfTradePrevention::CancelOldest => {
+                        prevented.push(SelfTrade { maker: maker.clone(), mode, cancelled: maker.quantity() });
+                        0
+                    }
+                    _ => {
+                        prevented.push(SelfTrade { maker: maker.clone(), mode, cancelled: quantity });
+                        taker.set_quantity(taker.quantity() - quantity);
+                        maker.quantity() - quantity
+                    }
+                }
+            };
+            maker.set_quantity(left);
+
+            if left == 0 {
+                let (seq, maker) = entry.remove_entry();
+                self.index.remove(maker.order_id());
+                if let Some(expires_at) = maker.time_in_force().expires_at() {
+                    self.expiries.remove(&(expires_at, seq));
+                }
+                if level.is_empty() {
+                    levels.remove(&price);
+                }
+                if book.is_empty() {
+                    self.books.remove(&asset);
+                }
+            }
+        }
+        (fills, prevented)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct TestOrder {
+        id: String,
+        asset: String,
+        side: OrderSide,
+        price: u64,
+        quantity: u64,
+        order_type: OrderType,
+        time_in_force: TimeInForce,
+        owner: String,
+    }
+
+    impl BookOrder for TestOrder {
+        fn order_id(&self) -> &str { &self.id }
+        fn asset(&self) -> &str { &self.asset }
+        fn side(&self) -> OrderSide { self.side }
+        fn price(&self) -> u64 { self.price }
+        fn quantity(&self) -> u64 { self.quantity }
+        fn set_quantity(&mut self, quantity: u64) { self.quantity = quantity; }
+        fn order_type(&self) -> OrderType { self.order_type }
+        fn time_in_force(&self) -> TimeInForce { self.time_in_force }
+        fn owner(&self) -> &str { &self.owner }
+    }
+
+    fn order(id: &str, asset: &str, side: OrderSide, price: u64, quantity: u64) -> TestOrder {
+        TestOrder {
+            id: id.to_string(),
+            asset: asset.to_string(),
+            side,
+            price,
+            quantity,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            owner: String::new(),
+        }
+    }
+
+    fn typed(id: &str, side: OrderSide, price: u64, quantity: u64, order_type: OrderType, time_in_force: TimeInForce) -> TestOrder {
+        TestOrder { order_type, time_in_force, ..order(id, "BTC", side, price, quantity) }
+    }
+
+    fn asks(levels: &[(u64, u64)]) -> MatchingBook<TestOrder> {
+        let mut book = MatchingBook::new();
+        for (i, (price, quantity)) in levels.iter().enumerate() {
+            book.insert(order(&format!("s{}", i), "BTC", OrderSide::Sell, *price, *quantity));
+        }
+        book
+    }
+
+    #[test]
+    fn test_price_then_time_priority_per_asset() {
+        let mut book = MatchingBook::new();
+        book.insert(order("s1", "BTC", OrderSide::Sell, 101, 5));
+        book.insert(order("s2", "BTC", OrderSide::Sell, 100, 5));
+        book.insert(order("s3", "BTC", OrderSide::Sell, 100, 5));
+        book.insert(order("e1", "ETH", OrderSide::Sell, 1, 50));
+        let depth: Vec<(u64, u64, usize)> = book.depth("BTC", OrderSide::Sell, 5).iter()
+            .map(|level| (level.price, level.quantity, level.orders))
+            .collect();
+        assert_eq!(depth, vec![(100, 10, 2), (101, 5, 1)]);
+
+        let mut taker = order("b1", "BTC", OrderSide::Buy, 101, 12);
+        let fills = book.match_order(&mut taker);
+        let makers: Vec<(&str, u64, u64)> = fills.iter()
+            .map(|f| (f.maker.id.as_str(), f.quantity, f.price))
+            .collect();
+        assert_eq!(makers, vec![("s2", 5, 100), ("s3", 5, 100), ("s1", 2, 101)]);
+        assert_eq!(taker.quantity, 0);
+        assert_eq!(book.get("s1").unwrap().quantity, 3);
+        assert_eq!(book.best_price("BTC", OrderSide::Sell), Some(101));
+        // Other assets never match, however cheap
+        assert_eq!(book.get("e1").unwrap().quantity, 50);
+        assert_eq!(book.len(), 2);
+    }
+
+    #[test]
+    fn test_cancel_and_requantity_keep_index_consistent() {
+        let mut book = MatchingBook::new();
+        book.insert(order("b1", "BTC", OrderSide::Buy, 100, 5));
+        book.insert(order("b2", "BTC", OrderSide::Buy, 100, 5));
+        assert!(!book.insert(order("b1", "BTC", OrderSide::Buy, 90, 1)));
+
+        // Growing an order loses its place in the queue
+        assert!(book.set_quantity("b1", 6));
+        let ids: Vec<&str> = book.orders("BTC", OrderSide::Buy).iter().map(|o| o.id.as_str()).collect();
+        assert_eq!(ids, vec!["b2", "b1"]);
+
+        assert_eq!(book.cancel("b2").unwrap().quantity, 5);
+        assert!(book.cancel("b2").is_none());
+        assert!(book.set_quantity("b1", 0));
+        assert!(book.is_empty());
+        assert_eq!(book.best_price("BTC", OrderSide::Buy), None);
+    }
+
+    #[test]
+    fn test_order_types_fill_or_reject() {
+        use OrderSide::Buy;
+        let gtc = TimeInForce::Gtc;
+
+        // Market: sweeps any price, never rests
+        let mut book = asks(&[(100, 5), (150, 5)]);
+        let done = book.submit(typed("m", Buy, 0, 12, OrderType::Market, gtc), 0);
+        assert_eq!(done[0].fills.len(), 2);
+        assert_eq!(done[0].status, OrderStatus::Cancelled { remaining: 2, reason: "no more liquidity".to_string() });
+        assert!(book.is_empty());
+
+        // IOC: only what crosses now
+        let mut book = asks(&[(100, 5), (150, 5)]);
+        let done = book.submit(typed("i", Buy, 120, 8, OrderType::Limit, TimeInForce::Ioc), 0);
+        assert!(matches!(done[0].status, OrderStatus::Cancelled { remaining: 3, .. }));
+        assert!(!book.contains("i"));
+
+        // FOK: all or nothing, book untouched on reject
+        let done = book.submit(typed("f1", Buy, 150, 6, OrderType::Limit, TimeInForce::Fok), 0);
+        assert!(matches!(done[0].status, OrderStatus::Rejected { .. }));
+        assert_eq!(book.get("s1").unwrap().quantity, 5);
+        let done = book.submit(typed("f2", Buy, 150, 5, OrderType::Limit, TimeInForce::Fok), 0);
+        assert_eq!(done[0].status, OrderStatus::Filled);
+
+        // Post-only: maker or nothing
+        let mut book = asks(&[(100, 5)]);
+        let done = book.submit(typed("p1", Buy, 100, 1, OrderType::PostOnly, gtc), 0);
+        assert!(matches!(done[0].status, OrderStatus::Rejected { .. }));
+        let done = book.submit(typed("p2", Buy, 99, 1, OrderType::PostOnly, gtc), 0);
+        assert_eq!(done[0].status, OrderStatus::Resting { remaining: 1 });
+        assert_eq!(book.best_price("BTC", Buy), Some(99));
+    }
+
+    #[test]
+    fn test_stop_orders_trigger_on_last_trade() {
+        use OrderSide::{Buy, Sell};
+        let gtc = TimeInForce::Gtc;
+        let mut book = asks(&[(100, 1), (105, 1), (110, 5)]);
+        book.insert(order("b0", "BTC", Buy, 90, 5));
+
+        // No trade yet, so both park
+        let stop = book.submit(typed("stop", Buy, 0, 1, OrderType::Stop { trigger_price: 105 }, gtc), 0);
+        assert_eq!(stop[0].status, OrderStatus::StopPending);
+        book.submit(typed("sl", Sell, 85, 3, OrderType::StopLimit { trigger_price: 95 }, gtc), 0);
+        assert_eq!(book.stop_orders().len(), 2);
+
+        // 100 trades: below the buy stop's trigger
+        assert!(book.submit(order("t1", "BTC", Buy, 100, 1), 0)[1..].is_empty());
+        // 105 trades: the stop fires as a market buy and takes 110
+        let done = book.submit(order("t2", "BTC", Buy, 105, 1), 0);
+        assert_eq!(done.len(), 2);
+        assert_eq!(done[1].order.id, "stop");
+        assert_eq!(done[1].fills[0].price, 110);
+        assert_eq!(book.last_price("BTC"), Some(110));
+
+        // A sell into the 90 bid triggers the stop-limit, which sells down to 85
+        let done = book.submit(order("t3", "BTC", Sell, 90, 1), 0);
+        assert_eq!(done[1].order.id, "sl");
+        assert_eq!((done[1].fills[0].quantity, done[1].fills[0].price), (3, 90));
+        assert_eq!(done[1].status, OrderStatus::Filled);
+        assert!(book.stop_orders().is_empty());
+        assert!(book.cancel("stop").is_none());
+    }
+
+    #[test]
+    fn test_expired_orders_never_trade() {
+        use OrderSide::{Buy, Sell};
+        let gtt = |expires_at| TimeInForce::Gtt { expires_at };
+        let mut book = MatchingBook::new();
+        book.submit(typed("s1", Sell, 100, 5, OrderType::Limit, gtt(50)), 10);
+        book.submit(typed("s2", Sell, 101, 5, OrderType::Limit, gtt(80)), 10);
+        book.submit(typed("stop", Buy, 0, 1, OrderType::Stop { trigger_price: 200 }, gtt(50)), 10);
+
+        // At 60 the cheaper ask is gone before the buy arrives
+        let done = book.submit(order("b1", "BTC", Buy, 101, 2), 60);
+        assert_eq!(done[0].fills[0].maker.id, "s2");
+        let expired: Vec<&str> = done[1..].iter()
+            .filter(|e| matches!(e.status, OrderStatus::Expired { .. }))
+            .map(|e| e.order.id.as_str())
+            .collect();
+        assert_eq!(expired, vec!["s1", "stop"]);
+        assert!(matches!(book.submit(typed("late", Sell, 90, 1, OrderType::Limit, gtt(60)), 60)[0].status,
+                         OrderStatus::Rejected { .. }));
+
+        // A partly filled order keeps its expiry
+        assert_eq!(book.expire(79).len(), 0);
+        assert_eq!(book.expire(80)[0].quantity, 3);
+        assert!(book.is_empty() && book.expiries.is_empty());
+        assert_eq!(TimeInForce::parse("gtd", 86_400 * 3 + 5), Ok(TimeInForce::Gtd { expires_at: 86_400 * 4 - 1 }));
+        assert!(TimeInForce::parse("gtd", u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_hundred_thousand_resting_orders() {
+        let mut book = MatchingBook::new();
+        for i in 0..100_000u64 {
+            book.insert(order(&format!("s{}", i), "BTC", OrderSide::Sell, 1_000 + i % 500, 10));
+        }
+        for i in (0..100_000u64).step_by(7) {
+            assert!(book.cancel(&format!("s{}", i)).is_some());
+        }
+        let mut taker = order("sweep", "BTC", OrderSide::Buy, 1_000, u64::MAX);
+        let fills = book.match_order(&mut taker);
+        // Everything left at 1000, oldest first
+        assert_eq!(fills.len(), 200 - 200 / 7 - 1);
+        assert!(fills.windows(2).all(|w| {
+            w[0].maker.id[1..].parse::<u64>().unwrap() < w[1].maker.id[1..].parse::<u64>().unwrap()
+        }));
+        assert_eq!(book.best_price("BTC", OrderSide::Sell), Some(1_001));
+    }
+
+    #[test]
+    fn test_self_trade_prevention_modes() {
+        use OrderSide::*;
+        let owned = |id: &str, side, price, quantity, owner: &str| TestOrder { owner: owner.to_string(), ..order(id, "BTC", side, price, quantity) };
+        let book_with = |mode| {
+            let mut book = MatchingBook::new();
+            book.set_self_trade_prevention(mode);
+            book.insert(owned("own", Sell, 100, 4, "alice"));
+            book.insert(owned("other", Sell, 100, 5, "bob"));
+            book
+        };
+
+        // Without prevention alice trades with herself
+        let mut book = book_with(SelfTradePrevention::None);
+        let done = book.submit(owned("b", Buy, 100, 6, "alice"), 0);
+        assert_eq!(done[0].fills[0].maker.id, "own");
+        assert!(done[0].prevented.is_empty());
+
+        // CancelNewest: the buy stops at her own ask, nothing rests
+        let mut book = book_with(SelfTradePrevention::CancelNewest);
+        let done = book.submit(owned("b", Buy, 100, 6, "alice"), 0);
+        assert!(done[0].fills.is_empty());
+        assert_eq!(done[0].status, OrderStatus::Cancelled { remaining: 6, reason: "self-trade prevention".to_string() });
+        assert_eq!(book.get("own").unwrap().quantity, 4);
+        assert!(book.get("b").is_none());
+        // Fill-or-kill does not count what it could never reach
+        assert!(matches!(book.submit(TestOrder { time_in_force: TimeInForce::Fok, ..owned("f", Buy, 100, 5, "alice") }, 0)[0].status,
+                         OrderStatus::Rejected { .. }));
+
+        // CancelOldest: her ask goes, the buy trades with bob and rests the rest
+        let mut book = book_with(SelfTradePrevention::CancelOldest);
+        let done = book.submit(owned("b", Buy, 100, 6, "alice"), 0);
+        assert_eq!((done[0].prevented[0].maker.id.as_str(), done[0].prevented[0].cancelled), ("own", 4));
+        assert_eq!(done[0].fills.iter().map(|f| (f.maker.id.as_str(), f.quantity)).collect::<Vec<_>>(), vec![("other", 5)]);
+        assert_eq!(done[0].status, OrderStatus::Resting { remaining: 1 });
+        assert!(book.get("own").is_none());
+
+        // DecrementBoth: both lose 4 without a trade, then 2 fill against bob
+        let mut book = book_with(SelfTradePrevention::DecrementBoth);
+        let done = book.submit(owned("b", Buy, 100, 6, "alice"), 0);
+        assert_eq!(done[0].prevented[0].cancelled, 4);
+        assert_eq!(done[0].fills[0].quantity, 2);
+        assert_eq!(done[0].status, OrderStatus::Cancelled { remaining: 0, reason: "self-trade prevention".to_string() });
+        assert!(book.get("own").is_none());
+        assert_eq!(book.get("other").unwrap().quantity, 3);
+        assert_eq!(SelfTradePrevention::parse("Decrement_Both"), Some(SelfTradePrevention::DecrementBoth));
+        assert_eq!(SelfTradePrevention::parse("sometimes"), None);
+    }
+}
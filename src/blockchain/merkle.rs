@@ -0,0 +1,156 @@
+// Binary Merkle tree over transaction hashes
+// Leaves and inner nodes use distinct prefixes so a leaf can never be passed
+// off as an inner node. An odd node at the end of a level is promoted as is
+// rather than duplicated, so two different transaction lists can't share a root.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+pub fn empty_root() -> String {
+    "0".repeat(64)
+}
+
+pub fn leaf_hash(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level.chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+pub fn merkle_root(leaves: &[[u8; 32]]) -> String {
+    if leaves.is_empty() {
+        return empty_root();
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    hex::encode(level[0])
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofStep {
+    pub hash: String,
+    pub is_left: bool, // sibling sits on the left of the running hash
+}
+
+// Sibling hashes from the leaf up to the root
+pub fn build_proof(leaves: &[[u8; 32]], index: usize) -> Option<Vec<ProofStep>> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut position = index;
+    while level.len() > 1 {
+        let sibling = position ^ 1;
+        if sibling < level.len() {
+            path.push(ProofStep {
+                hash: hex::encode(level[sibling]),
+                is_left: sibling < position,
+            });
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+    Some(path)
+}
+
+pub fn verify_proof(leaf: &[u8; 32], path: &[ProofStep], root: &str) -> bool {
+    let mut running = *leaf;
+    for step in path {
+        let sibling: [u8; 32] = match hex::decode(&step.hash).ok().and_then(|b| b.try_into().ok()) {
+            Some(hash) => hash,
+            None => return false,
+        };
+        running = if step.is_left {
+            node_hash(&sibling, &running)
+        } else {
+            node_hash(&running, &sibling)
+        };
+    }
+    hex::encode(running) == root
+}
+
+// Inclusion proof for one transaction, self-contained so light clients can
+// check it against a block header they trust
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxProof {
+    pub tx_id: String,
+    pub tx_hash: String,
+    pub block_height: u32,
+    pub block_hash: String,
+    pub merkle_root: String,
+    pub index: usize,
+    pub path: Vec<ProofStep>,
+}
+
+impl TxProof {
+    pub fn verify(&self) -> bool {
+        match hex::decode(&self.tx_hash).ok().and_then(|b| b.try_into().ok()) {
+            Some(leaf) => verify_proof(&leaf, &self.path, &self.merkle_root),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<[u8; 32]> {
+        (0..n).map(|i| leaf_hash(format!("tx{}", i).as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_every_leaf_has_a_valid_proof() {
+        for n in 1..=9 {
+            let leaves = leaves(n);
+            let root = merkle_root(&leaves);
+            for (i, leaf) in leaves.iter().enumerate() {
+                let path = build_proof(&leaves, i).unwrap();
+                assert!(verify_proof(leaf, &path, &root), "leaf {} of {}", i, n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_root_commits_to_order_and_content() {
+        let mut leaves = leaves(4);
+        let root = merkle_root(&leaves);
+        leaves.swap(0, 1);
+        assert_ne!(merkle_root(&leaves), root);
+
+        // Duplicating the odd leaf must not reproduce the same root
+        let three = self::leaves(3);
+        let mut padded = three.clone();
+        padded.push(three[2]);
+        assert_ne!(merkle_root(&three), merkle_root(&padded));
+
+        let path = build_proof(&three, 1).unwrap();
+        assert!(!verify_proof(&leaf_hash(b"forged"), &path, &merkle_root(&three)));
+    }
+}
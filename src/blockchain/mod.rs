@@ -4,6 +4,43 @@ use std::collections::HashMap;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+pub mod audit;
+pub mod fees;
+pub mod forks;
+pub mod ledger;
+pub mod matching;
+pub mod merkle;
+pub mod signing;
+pub mod slashing;
+#[cfg(feature = "native")]
+pub mod snapshot;
+pub mod staking;
+#[cfg(feature = "native")]
+pub mod store;
+pub mod sync;
+pub mod tenants;
+
+use fees::{BlockFees, FeeSchedule};
+use forks::{ChainReorg, Checkpoint, ForkTree};
+use ledger::Ledger;
+use matching::{BookOrder, Execution, MatchingBook};
+use merkle::TxProof;
+use signing::KeyPair;
+use slashing::Evidence;
+use staking::{Staking, StakingConfig, STAKING_ACCOUNT, TOMBSTONED};
+use tenants::TenantRegistry;
+#[cfg(feature = "native")]
+use snapshot::{PruningPolicy, TenantHead};
+#[cfg(feature = "native")]
+use store::ChainStore;
+
+// Without BFT consensus a block's round is the time since its parent in
+// steps of ROUND_SECONDS, so the next validator in the schedule only gets to
+// propose once the one before it had its turn
+const ROUND_SECONDS: u64 = 5;
+// How far ahead of our clock a block's timestamp may be
+const MAX_CLOCK_DRIFT: u64 = 30;
+
 // Shared blockchain types - work for both native and WASM
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
@@ -13,12 +50,26 @@ pub struct Block {
     pub timestamp: u64,
     pub validator: String,
     pub transactions: Vec<Transaction>,
+    // Root of the Merkle tree over transaction hashes; part of the block hash
+    #[serde(default)]
+    pub merkle_root: String,
     pub stake_weight: u64,
+    // Consensus round the block was proposed in; part of the proposer schedule
+    #[serde(default)]
+    pub round: u32,
     // Optional fields for WASM/Native compatibility
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub nonce: Option<u32>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub data: Option<String>,
+    // Validator public key (hex) and its signature over the block hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    // What the block charged in fees and paid to validators; None if nothing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fees: Option<BlockFees>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,13 +80,48 @@ pub struct Transaction {
     pub amount: u64,
     pub tx_type: TransactionType,
     pub timestamp: u64,
+    // Sender public key (hex) and its signature over the transaction contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TransactionType {
     Transfer,
+    // Staking, see staking.rs; the coin amount is the transaction's `amount`
     Stake,
-    Trading { asset: String, quantity: u64, price: u64 },
+    Delegate { validator: String },
+    Unbond { validator: String },
+    ClaimRewards { validator: String },
+    // Misbehaviour of a validator, see slashing.rs
+    Evidence { evidence: Evidence },
+    Trading {
+        asset: String,
+        quantity: u64,
+        price: u64,
+        // Optional fields are left out of the JSON when unset so transactions
+        // signed before they existed still hash and verify the same
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        side: Option<OrderSide>, // None only in legacy blocks, see Transaction::order_side
+        #[serde(default, skip_serializing_if = "is_default")]
+        order_type: OrderType,
+        #[serde(default, skip_serializing_if = "is_default")]
+        time_in_force: TimeInForce,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_order_id: Option<String>,
+    },
+    // Withdraw or change a resting order; `order_id` is the id of the Trading
+    // transaction that placed it, and only its trader and network may do so
+    OrderCancel { order_id: String },
+    OrderAmend {
+        order_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        price: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        quantity: Option<u64>,
+    },
     Message { content: String },
     ContractDeploy { 
         contract_name: String, 
@@ -52,7 +138,18 @@ pub enum TransactionType {
         buyer: String,
         seller: String,
         trade_id: Option<String>,
+        // Side of the incoming order, for maker/taker fees
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        taker: Option<OrderSide>,
     },
+    // `amount` of `asset` (empty for the native coin) credited to `to`;
+    // only accepted from a validator
+    Mint { asset: String },
+    // Changes to the tenant registry, see tenants.rs; only accepted from a validator
+    TenantRegister { network_id: String, public_key: String },
+    TenantRotateKey { network_id: String, public_key: String },
+    TenantSuspend { network_id: String },
+    TenantReinstate { network_id: String },
     RoamingConnect {
         imsi: String,
         home_network: String,
@@ -74,11 +171,234 @@ pub enum TransactionType {
     },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OrderType {
+    #[default]
+    Limit,
+    Market,   // takes whatever liquidity there is, never rests
+    PostOnly, // rejected if it would take liquidity
+    // Parked until the last trade price in the asset reaches trigger_price
+    // (at or above for a buy, at or below for a sell), then a market order
+    Stop { trigger_price: u64 },
+    // Same trigger, then a limit order at the order's price
+    StopLimit { trigger_price: u64 },
+}
+
+impl OrderType {
+    // "limit", "market", "post_only", "stop", "stop_limit"; the trigger
+    // price only applies to the stop types
+    pub fn parse(kind: &str, trigger_price: u64) -> Option<Self> {
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "" | "limit" => Some(OrderType::Limit),
+            "market" => Some(OrderType::Market),
+            "post_only" | "postonly" => Some(OrderType::PostOnly),
+            "stop" => Some(OrderType::Stop { trigger_price }),
+            "stop_limit" | "stoplimit" => Some(OrderType::StopLimit { trigger_price }),
+            _ => None,
+        }
+    }
+
+    pub fn trigger_price(&self) -> Option<u64> {
+        match self {
+            OrderType::Stop { trigger_price } | OrderType::StopLimit { trigger_price } => Some(*trigger_price),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TimeInForce {
+    #[default]
+    Gtc, // good till cancelled
+    Ioc, // immediate or cancel: fill what crosses now, cancel the rest
+    Fok, // fill or kill: fill in full right away or not at all
+    Gtt { expires_at: u64 }, // good till time (unix seconds)
+    Gtd { expires_at: u64 }, // good till date: the last second of that UTC day
+}
+
+impl TimeInForce {
+    // "gtc", "ioc", "fok", "gtt", "gtd"; expires_at (unix seconds) only
+    // applies to the last two, and "gtd" rounds it to the end of its day
+    pub fn parse(tif: &str, expires_at: u64) -> Result<Self, String> {
+        match tif.trim().to_ascii_lowercase().as_str() {
+            "" | "gtc" => Ok(TimeInForce::Gtc),
+            "ioc" => Ok(TimeInForce::Ioc),
+            "fok" => Ok(TimeInForce::Fok),
+            "gtt" => Ok(TimeInForce::Gtt { expires_at }),
+            "gtd" => (expires_at - expires_at % 86_400).checked_add(86_399)
+                .map(|end_of_day| TimeInForce::Gtd { expires_at: end_of_day })
+                .ok_or_else(|| format!("GTD expiry {} is past the last day we can represent", expires_at)),
+            _ => Err(format!("Unknown time in force: {}", tif)),
+        }
+    }
+
+    pub fn expires_at(&self) -> Option<u64> {
+        match self {
+            TimeInForce::Gtt { expires_at } | TimeInForce::Gtd { expires_at } => Some(*expires_at),
+            _ => None,
+        }
+    }
+
+    // Whether an unfilled remainder stays on the book
+    pub fn rests(&self) -> bool {
+        !matches!(self, TimeInForce::Ioc | TimeInForce::Fok)
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Validator {
     pub address: String,
     pub stake: u64,
     pub active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+impl Transaction {
+    // Everything except the signature itself
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&(
+            &self.id,
+            &self.from,
+            &self.to,
+            self.amount,
+            &self.tx_type,
+            self.timestamp,
+            &self.public_key,
+        )).unwrap_or_default()
+    }
+
+    pub fn sign(&mut self, key: &KeyPair) {
+        self.public_key = Some(key.public_key_hex());
+        self.signature = Some(key.sign(&self.signing_bytes()));
+    }
+
+    // Checks the signature against the embedded key only; whether that key
+    // belongs to `from` is up to the chain's key registry
+    pub fn verify_signature(&self) -> bool {
+        match (&self.public_key, &self.signature) {
+            (Some(key), Some(signature)) => signing::verify(key, &self.signing_bytes(), signature),
+            _ => false,
+        }
+    }
+
+    // Merkle leaf; the JSON form is what tenants forward to the enterprise
+    // chain, so both sides hash the same bytes
+    pub fn leaf_hash(&self) -> [u8; 32] {
+        merkle::leaf_hash(serde_json::to_string(self).unwrap_or_default().as_bytes())
+    }
+
+    // Explicit side, or for blocks written before it existed the old "buy_" /
+    // "sell_" id convention. Anything else has no side and is not an order.
+    pub fn order_side(&self) -> Option<OrderSide> {
+        match &self.tx_type {
+            TransactionType::Trading { side: Some(side), .. } => Some(*side),
+            TransactionType::Trading { side: None, .. } if self.id.contains("buy_") => Some(OrderSide::Buy),
+            TransactionType::Trading { side: None, .. } if self.id.contains("sell_") => Some(OrderSide::Sell),
+            _ => None,
+        }
+    }
+}
+
+impl Block {
+    pub fn calculate_merkle_root(&self) -> String {
+        let leaves: Vec<[u8; 32]> = self.transactions.iter().map(|tx| tx.leaf_hash()).collect();
+        merkle::merkle_root(&leaves)
+    }
+
+    // Header hash: commits to the transactions through the Merkle root
+    pub fn calculate_hash(&self) -> String {
+        use sha2::{Sha256, Digest};
+        let input = format!("{}:{}:{}:{}:{}:{}:{}:{}:{}",
+            self.height,
+            self.previous_hash,
+            self.merkle_root,
+            self.validator,
+            self.stake_weight,
+            self.timestamp,
+            self.round,
+            self.nonce.unwrap_or(0),
+            self.data.as_deref().unwrap_or("")
+        );
+        let mut hasher = Sha256::new();
+        hasher.update(input.as_bytes());
+        // Only blocks that charged fees commit to them, so the rest keep their hashes
+        if let Some(fees) = &self.fees {
+            hasher.update(format!(":{}", serde_json::to_string(fees).unwrap_or_default()).as_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    // Transactions match the Merkle root and the header matches the hash
+    pub fn verify_contents(&self) -> Result<(), String> {
+        if self.calculate_merkle_root() != self.merkle_root {
+            return Err(format!("block {} transactions do not match its merkle root", self.height));
+        }
+        if self.calculate_hash() != self.hash {
+            return Err(format!("block {} hash does not match its header", self.height));
+        }
+        Ok(())
+    }
+
+    // All a header without its transactions can show: the hash and the
+    // validator's signature
+    pub fn verify_header(&self) -> Result<(), String> {
+        if self.calculate_hash() != self.hash {
+            return Err(format!("block {} hash does not match its header", self.height));
+        }
+        if !self.is_signed() {
+            return Err(format!("block {} is not signed by its validator", self.height));
+        }
+        Ok(())
+    }
+
+    fn is_signed(&self) -> bool {
+        match (&self.validator_key, &self.signature) {
+            (Some(key), Some(signature)) => signing::verify(key, self.hash.as_bytes(), signature),
+            _ => false,
+        }
+    }
+
+    pub fn tx_proof(&self, tx_id: &str) -> Option<TxProof> {
+        let leaves: Vec<[u8; 32]> = self.transactions.iter().map(|tx| tx.leaf_hash()).collect();
+        let index = self.transactions.iter().position(|tx| tx.id == tx_id)?;
+        Some(TxProof {
+            tx_id: tx_id.to_string(),
+            tx_hash: hex::encode(leaves[index]),
+            block_height: self.height,
+            block_hash: self.hash.clone(),
+            merkle_root: self.merkle_root.clone(),
+            index,
+            path: merkle::build_proof(&leaves, index)?,
+        })
+    }
+
+    pub fn sign(&mut self, key: &KeyPair) {
+        self.validator_key = Some(key.public_key_hex());
+        self.signature = Some(key.sign(self.hash.as_bytes()));
+    }
+
+    // Header integrity, the block signature and every transaction signature,
+    // against embedded keys. Used where no key registry is available, e.g. the tracker relay.
+    pub fn verify_signatures(&self) -> Result<(), String> {
+        self.verify_contents()?;
+        if !self.is_signed() {
+            return Err(format!("block {} is not signed by its validator", self.height));
+        }
+        for tx in &self.transactions {
+            if !tx.verify_signature() {
+                return Err(format!("transaction {} has an invalid signature", tx.id));
+            }
+            if signing::is_key_address(&tx.from) && tx.public_key.as_deref() != Some(tx.from.as_str()) {
+                return Err(format!("transaction {} is not signed by {}", tx.id, tx.from));
+            }
+        }
+        Ok(())
+    }
 }
 
 // Enterprise types (native only)
@@ -89,6 +409,21 @@ pub struct TenantBlockchainUpdate {
     pub peer_id: String,
     pub new_blocks: Vec<TenantBlockData>,
     pub timestamp: u64,
+    // Set when the tenant switched branches; new_blocks is then its new branch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reorg: Option<TenantReorg>,
+}
+
+// Blocks a tenant abandoned in a reorg, see forks.rs
+#[cfg(feature = "native")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantReorg {
+    pub fork_height: u32,
+    pub fork_hash: String,
+    pub removed: Vec<String>, // block hashes
+    // By the tenant's key, see tenants::reorg_message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 #[cfg(feature = "native")]
@@ -100,6 +435,55 @@ pub struct TenantBlockData {
     pub timestamp: u64,
     pub previous_hash: String,
     pub network_id: String,  // Add this field
+    // Merkle root of the tenant block; lets us check the forwarded transactions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle_root: Option<String>,
+    // By the tenant's key, see tenants::block_message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_signature: Option<String>,
+}
+
+#[cfg(feature = "native")]
+impl TenantBlockData {
+    // Transactions are the tenant's JSON encoding, the same bytes its Merkle leaves hash
+    pub fn verify_merkle_root(&self) -> bool {
+        match &self.merkle_root {
+            Some(root) => {
+                let leaves: Vec<[u8; 32]> = self.transactions.iter()
+                    .map(|tx| merkle::leaf_hash(tx.as_bytes()))
+                    .collect();
+                &merkle::merkle_root(&leaves) == root
+            }
+            None => true, // tenants that predate Merkle roots
+        }
+    }
+
+    // What the block's transactions moved on the tenant's chain; the tenant
+    // already rejected the ones that do not apply
+    pub fn apply_to(&self, ledger: &mut Ledger) {
+        for tx in self.transactions.iter().filter_map(|tx| serde_json::from_str::<Transaction>(tx).ok()) {
+            let _ = ledger.apply(&tx);
+        }
+    }
+
+    pub fn tx_proof(&self, tx_id: &str) -> Option<TxProof> {
+        let root = self.merkle_root.clone()?;
+        let index = self.transactions.iter().position(|tx| {
+            serde_json::from_str::<serde_json::Value>(tx).is_ok_and(|v| v["id"] == tx_id)
+        })?;
+        let leaves: Vec<[u8; 32]> = self.transactions.iter()
+            .map(|tx| merkle::leaf_hash(tx.as_bytes()))
+            .collect();
+        Some(TxProof {
+            tx_id: tx_id.to_string(),
+            tx_hash: hex::encode(leaves[index]),
+            block_height: self.block_id,
+            block_hash: self.block_hash.clone(),
+            merkle_root: root,
+            index,
+            path: merkle::build_proof(&leaves, index)?,
+        })
+    }
 }
 
 // Smart contract support (native only)
@@ -146,14 +530,59 @@ pub struct Blockchain {
     pending_transactions: Vec<Transaction>,
     validators: HashMap<String, Validator>,
     total_stake: u64,
+    // Account address -> public key, bound when something is issued to the
+    // account or else the first time it signs
+    account_keys: HashMap<String, String>,
+    // Ids of the committed transactions, including those of pruned blocks
+    tx_ids: std::collections::HashSet<String>,
+    // The part of tx_ids from below our first block, for a chain started
+    // from a snapshot; the rest is read back from the blocks
+    #[cfg(feature = "native")]
+    base_tx_ids: std::collections::BTreeSet<String>,
+    // Signs our own transactions and blocks
+    node_key: KeyPair,
+    // Balances after the last block, see ledger.rs
+    ledger: Ledger,
+    // Charged on every block from now on, see fees.rs
+    fees: FeeSchedule,
+    // Bonds behind the validators, see staking.rs; None leaves the validator
+    // set to add_validator
+    staking: Staking,
+    staking_config: Option<StakingConfig>,
+    // Rounds follow the clock (see ROUND_SECONDS) unless BFT consensus
+    // agrees on them instead
+    timed_rounds: bool,
+    // Validator sets staking changed to, by the height of the block that
+    // changed them, so older blocks can be checked against their own set
+    validator_sets: std::collections::BTreeMap<u32, HashMap<String, Validator>>,
+    // Tenant networks and their keys, see tenants.rs
+    tenants: TenantRegistry,
+    // Blocks off our tip and the state to roll back to, see forks.rs
+    forks: ForkTree,
+    checkpoints: std::collections::VecDeque<Checkpoint>,
+    reorg_depth: u32,
+    reorg_events: Vec<ChainReorg>, // until taken
+    last_sync_block: u32,
     
     // Native-only features
     #[cfg(feature = "native")]
-    storage_path: Option<String>,
+    store: Option<Box<dyn ChainStore<Block>>>,
+    #[cfg(feature = "native")]
+    storage_error: Option<String>, // last failed write, cleared by the next good one
+    #[cfg(feature = "native")]
+    load_error: Option<String>, // the stored chain is unreadable and must not be saved over
+    #[cfg(feature = "native")]
+    base_tx_ids_stored: bool,
     #[cfg(feature = "native")]
     contracts: HashMap<String, SmartContract>,
     #[cfg(feature = "native")]
-    tenant_blocks: Vec<TenantBlockData>,
+    tenant_blocks: Vec<TenantBlockData>, // most recent only, older ones are archived
+    #[cfg(feature = "native")]
+    tenant_heads: HashMap<String, TenantHead>,
+    #[cfg(feature = "native")]
+    tenant_ledgers: HashMap<String, Ledger>, // balances through each tenant's archived blocks
+    #[cfg(feature = "native")]
+    pruning: PruningPolicy,
 }
 
 // WASM-compatible methods only
@@ -166,12 +595,41 @@ impl Blockchain {
             pending_transactions: Vec::new(),
             validators: HashMap::new(),
             total_stake: 0,
+            account_keys: HashMap::new(),
+            tx_ids: std::collections::HashSet::new(),
+            #[cfg(feature = "native")]
+            base_tx_ids: std::collections::BTreeSet::new(),
+            node_key: KeyPair::generate(),
+            ledger: Ledger::new(),
+            fees: FeeSchedule::default(),
+            staking: Staking::new(),
+            staking_config: None,
+            timed_rounds: true,
+            validator_sets: std::collections::BTreeMap::new(),
+            tenants: TenantRegistry::new(),
+            forks: ForkTree::new(),
+            checkpoints: std::collections::VecDeque::new(),
+            reorg_depth: forks::DEFAULT_REORG_DEPTH,
+            reorg_events: Vec::new(),
+            last_sync_block: 0,
             #[cfg(feature = "native")]
-            storage_path: None,
+            store: None,
+            #[cfg(feature = "native")]
+            storage_error: None,
+            #[cfg(feature = "native")]
+            load_error: None,
+            #[cfg(feature = "native")]
+            base_tx_ids_stored: false,
             #[cfg(feature = "native")]
             contracts: HashMap::new(),
             #[cfg(feature = "native")]
             tenant_blocks: Vec::new(),
+            #[cfg(feature = "native")]
+            tenant_heads: HashMap::new(),
+            #[cfg(feature = "native")]
+            tenant_ledgers: HashMap::new(),
+            #[cfg(feature = "native")]
+            pruning: PruningPolicy::default(),
         };
         blockchain.create_genesis_block();
         blockchain
@@ -182,7 +640,7 @@ impl Blockchain {
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn call_smart_contract(&mut self, contract_id: String, function: String, params: String, caller: String) -> String {
-        let tx_id = format!("call_{}_{}", contract_id, Self::current_timestamp());
+        let tx_id = Self::unique_id(&format!("call_{}", contract_id));
 
         let tx = Transaction {
             id: tx_id.clone(),
@@ -194,12 +652,16 @@ impl Blockchain {
                 params: params.clone(),
             },
             timestamp: Self::current_timestamp(),
+            public_key: None,
+            signature: None,
         };
 
-        self.pending_transactions.push(tx);
+        if let Err(e) = self.queue_signed(tx) {
+            return serde_json::json!({ "status": "error", "message": e }).to_string();
+        }
 
         #[cfg(feature = "native")]
-        self.save_to_disk();
+        self.autosave();
 
         serde_json::json!({
             "transaction_id": tx_id,
@@ -212,7 +674,7 @@ impl Blockchain {
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn deploy_smart_contract(&mut self, contract_type: String, init_data: String, owner: String) -> String {
-            let tx_id = format!("deploy_{}_{}", contract_type, Self::current_timestamp());
+            let tx_id = Self::unique_id(&format!("deploy_{}", contract_type));
             
             let tx = Transaction {
                 id: tx_id.clone(),
@@ -224,12 +686,16 @@ impl Blockchain {
                     init_params: init_data.clone(),
                 },
                 timestamp: Self::current_timestamp(),
+                public_key: None,
+                signature: None,
             };
             
-            self.pending_transactions.push(tx);
+            if let Err(e) = self.queue_signed(tx) {
+                return serde_json::json!({ "status": "error", "message": e }).to_string();
+            }
             
             #[cfg(feature = "native")]
-            self.save_to_disk();
+            self.autosave();
             
             serde_json::json!({
                 "transaction_id": tx_id,
@@ -307,7 +773,7 @@ impl Blockchain {
             }
         }
 
-        let tx_id = format!("msg_{}", Self::current_timestamp());
+        let tx_id = Self::unique_id("msg");
         let tx = Transaction {
             id: tx_id.clone(),
             from: sender.clone(),
@@ -315,10 +781,14 @@ impl Blockchain {
             amount: 0,
             tx_type: TransactionType::Message { content: message },
             timestamp: Self::current_timestamp(),
+            public_key: None,
+            signature: None,
         };
 
-        // Add transaction and immediately mine block
-        self.pending_transactions.push(tx);
+        // Add transaction and immediately mine block; empty id if refused
+        if self.queue_signed(tx).is_err() {
+            return String::new();
+        }
 
         // Only mine if we have validators and this sender can mine
         if !self.validators.is_empty() {
@@ -326,86 +796,125 @@ impl Blockchain {
         }
 
         #[cfg(feature = "native")]
-        self.save_to_disk();
+        self.autosave();
 
         tx_id
     }
         // Modified mine_block to return the created block for broadcasting
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn mine_block_and_get(&mut self) -> String {
-        if self.pending_transactions.is_empty() {
-            return "{}".to_string();
-        }
-
-        let validator = self.select_validator();
-        if validator.is_none() {
-            return "{}".to_string();
+        match self.mine_own_block() {
+            // Return the block as JSON for broadcasting
+            Some(block) => serde_json::to_string(&block).unwrap_or_default(),
+            None => "{}".to_string(),
         }
-
-        let last_block = self.chain.last().unwrap();
-        let validator_addr = validator.unwrap();
-        let stake_weight = self.validators.get(&validator_addr).unwrap().stake;
-
-        let mut block = Block {
-            height: last_block.height + 1,
-            hash: String::new(),
-            previous_hash: last_block.hash.clone(),
-            timestamp: Self::current_timestamp(),
-            validator: validator_addr,
-            transactions: self.pending_transactions.clone(),
-            stake_weight,
-            nonce: None,
-            data: None,
-        };
-
-        // Calculate hash
-        block.hash = self.calculate_hash(&block);
-
-        // For native builds, do proof-of-work mining
-        #[cfg(feature = "native")]
-        self.mine_block_native(&mut block);
-
-        self.chain.push(block.clone());
-        self.pending_transactions.clear();
-
-        #[cfg(feature = "native")]
-        self.save_to_disk();
-
-        // Return the block as JSON for broadcasting
-        serde_json::to_string(&block).unwrap_or_default()
     }
 
 
+    // With staking enabled only validators the chain starts with can be
+    // added this way, and their stake is bonded at genesis; later ones bond
+    // on chain
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn add_validator(&mut self, address: String, stake: u32) {
         let stake_u64 = stake as u64;  // Convert u32 to u64
+        if self.staking_config.is_some() && !self.validators.contains_key(&address) {
+            if self.height() > 0 {
+                println!("{} is not bonded on chain; validators join by staking", address);
+                return;
+            }
+            self.bond_genesis(&address, None, stake_u64);
+        }
         let validator = Validator {
             address: address.clone(),
             stake: stake_u64,
             active: true,
+            public_key: None,
         };
         self.total_stake += stake_u64;
         self.validators.insert(address, validator);
     }
 
+    // Validator whose blocks must be signed by the given key
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn add_validator_with_key(&mut self, address: String, stake: u32, public_key: String) -> bool {
+        if !self.validators.contains_key(&address) {
+            self.add_validator(address.clone(), stake);
+            if !self.validators.contains_key(&address) {
+                return true; // not a validator, so no key to disagree with
+            }
+        }
+        self.register_validator_key(&address, &public_key)
+    }
+
+    // Public key (hex) this node signs its transactions and blocks with
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_public_key(&self) -> String {
+        self.node_key.public_key_hex()
+    }
+
+    // Secret key (hex) so the page can keep its identity across reloads
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn export_signing_key(&self) -> String {
+        self.node_key.secret_hex()
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn import_signing_key(&mut self, secret_hex: String) -> bool {
+        match KeyPair::from_secret_hex(&secret_hex) {
+            Ok(key) => {
+                self.node_key = key;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn add_transaction(&mut self, from: String, to: String, amount: u32) -> String {
         let amount_u64 = amount as u64;  // Convert u32 to u64
         let tx = Transaction {
-            id: format!("tx_{}", Self::current_timestamp()),
+            id: Self::unique_id("tx"),
             from,
             to,
             amount: amount_u64,
             tx_type: TransactionType::Transfer,
             timestamp: Self::current_timestamp(),
+            public_key: None,
+            signature: None,
         };
+        // Empty id: the sender cannot cover the amount
         let tx_id = tx.id.clone();
-        self.pending_transactions.push(tx);
+        if self.queue_signed(tx).is_err() {
+            return String::new();
+        }
         #[cfg(feature = "native")]
-        self.save_to_disk();
+        self.autosave();
         tx_id
     }
 
+    // Credit `amount` of `asset` (empty for the native coin) to `to`; only
+    // works when this node's key belongs to a validator. Empty id if refused,
+    // also for an amount that is not a whole number of units.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn issue(&mut self, to: String, asset: String, amount: f64) -> String {
+        match Self::whole_units(amount).and_then(|amount| self.mint(&to, &asset, amount)) {
+            Ok(tx_id) => tx_id,
+            Err(e) => {
+                println!("Issue refused: {}", e);
+                String::new()
+            }
+        }
+    }
+
+    // JS numbers are f64; ledger amounts are whole units
+    fn whole_units(amount: f64) -> Result<u64, String> {
+        // u64::MAX as f64 rounds up to 2^64, which is already out of range
+        if !amount.is_finite() || amount < 0.0 || amount.fract() != 0.0 || amount >= u64::MAX as f64 {
+            return Err(format!("{} is not a whole amount", amount));
+        }
+        Ok(amount as u64)
+    }
+
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn add_message(&mut self, message: String, sender: String) -> String {
         // Check for duplicate content from same sender
@@ -417,7 +926,7 @@ impl Blockchain {
             }
         }
         
-        let tx_id = format!("msg_{}", Self::current_timestamp());
+        let tx_id = Self::unique_id("msg");
         let tx = Transaction {
             id: tx_id.clone(),
             from: sender.clone(),
@@ -425,16 +934,21 @@ impl Blockchain {
             amount: 0,
             tx_type: TransactionType::Message { content: message },
             timestamp: Self::current_timestamp(),
+            public_key: None,
+            signature: None,
         };
-        self.pending_transactions.push(tx);
+        // Empty id: the sender cannot pay the fee
+        if self.queue_signed(tx).is_err() {
+            return String::new();
+        }
         #[cfg(feature = "native")]
-        self.save_to_disk();
+        self.autosave();
         tx_id
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn deploy_trading_contract(&mut self, owner: String) -> bool {
-        let tx_id = format!("contract_{}", Self::current_timestamp());
+        let tx_id = Self::unique_id("contract");
         let tx = Transaction {
             id: tx_id,
             from: owner,
@@ -445,69 +959,149 @@ impl Blockchain {
                 init_params: "{}".to_string()
             },
             timestamp: Self::current_timestamp(),
+            public_key: None,
+            signature: None,
         };
-        self.pending_transactions.push(tx);
+        if self.queue_signed(tx).is_err() {
+            return false;
+        }
         #[cfg(feature = "native")]
-        self.save_to_disk();
+        self.autosave();
         true
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 pub fn call_contract_buy(&mut self, asset: String, quantity: f64, price: f64, sender: String) -> String {
-    let tx_id = format!("buy_{}", Self::current_timestamp());
-    let quantity_u64 = quantity as u64;  // Convert f64 to u64
-    let price_u64 = price as u64;        // Convert f64 to u64
+    self.call_contract_order(asset, "buy".to_string(), quantity, price, sender, String::new())
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn call_contract_sell(&mut self, asset: String, quantity: f64, price: f64, sender: String) -> String {
+    self.call_contract_order(asset, "sell".to_string(), quantity, price, sender, String::new())
+}
+
+// side is "buy" or "sell"; client_order_id may be empty
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn call_contract_order(&mut self, asset: String, side: String, quantity: f64, price: f64, sender: String, client_order_id: String) -> String {
+    self.queue_order(OrderRequest {
+        trader: sender,
+        asset,
+        side,
+        quantity: quantity as u64,  // Convert f64 to u64
+        price: price as u64,        // Convert f64 to u64
+        order_type: String::new(),
+        time_in_force: String::new(),
+        trigger_price: 0,
+        expires_at: 0,
+        client_order_id,
+    })
+}
+
+// Any order type as an OrderRequest JSON; `trader` is the sender
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn call_contract_order_json(&mut self, request_json: String) -> String {
+    match serde_json::from_str::<OrderRequest>(&request_json) {
+        Ok(request) => self.queue_order(request),
+        Err(e) => serde_json::json!({ "status": "error", "message": format!("Invalid order request: {}", e) }).to_string(),
+    }
+}
+
+fn queue_order(&mut self, request: OrderRequest) -> String {
+    let (side, order_type, time_in_force) = match request.parse_terms() {
+        Ok(terms) => terms,
+        Err(message) => return serde_json::json!({ "status": "error", "message": message }).to_string(),
+    };
+    let label = match side {
+        OrderSide::Buy => "buy",
+        OrderSide::Sell => "sell",
+    };
+    let tx_id = Self::unique_id(label);
+    let quantity_u64 = request.quantity;
+    let price_u64 = request.price;
     
     // Calculate amount correctly - price and quantity are already in cents
-    let amount = (quantity_u64 * price_u64) / 100; // Divide by 100 to get actual dollar amount
+    let amount = match quantity_u64.checked_mul(price_u64) {
+        Some(notional) => notional / 100, // Divide by 100 to get actual dollar amount
+        None => return serde_json::json!({ "status": "error", "message": "Order quantity times price overflows" }).to_string(),
+    };
     
     let tx = Transaction {
         id: tx_id.clone(),
-        from: sender,
+        from: request.trader.clone(),
         to: "trading_contract".to_string(),
         amount, // Use corrected amount
         tx_type: TransactionType::Trading { 
-            asset: asset.clone(), 
+            asset: request.asset.clone(), 
             quantity: quantity_u64, 
-            price: price_u64 
+            price: price_u64,
+            side: Some(side),
+            order_type,
+            time_in_force,
+            client_order_id: request.client_order_id(),
         },
         timestamp: Self::current_timestamp(),
+        public_key: None,
+        signature: None,
     };
-    self.pending_transactions.push(tx);
+    if let Err(e) = self.queue_signed(tx) {
+        return serde_json::json!({ "status": "error", "message": e }).to_string();
+    }
     #[cfg(feature = "native")]
-    self.save_to_disk();
+    self.autosave();
     
-    format!("{{\"status\": \"success\", \"message\": \"Buy order placed: {} {} @ {}\", \"orderId\": \"{}\"}}", 
-            quantity, asset, price, tx_id)
+    let capitalized = match side {
+        OrderSide::Buy => "Buy",
+        OrderSide::Sell => "Sell",
+    };
+    format!("{{\"status\": \"success\", \"message\": \"{} order placed: {} {} @ {}\", \"orderId\": \"{}\"}}", 
+            capitalized, quantity_u64, request.asset, price_u64, tx_id)
+}
+// Ask the enterprise order engine to cancel an order placed with one of
+// the call_contract_* functions
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn call_contract_cancel(&mut self, order_id: String, sender: String) -> String {
+    self.queue_order_change(sender, TransactionType::OrderCancel { order_id })
 }
 
+// New price and/or quantity for an order; 0 leaves that field unchanged
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-pub fn call_contract_sell(&mut self, asset: String, quantity: f64, price: f64, sender: String) -> String {
-    let tx_id = format!("sell_{}", Self::current_timestamp());
-    let quantity_u64 = quantity as u64;  // Convert f64 to u64
-    let price_u64 = price as u64;        // Convert f64 to u64
-    
-    // Calculate amount correctly - price and quantity are already in cents
-    let amount = (quantity_u64 * price_u64) / 100; // Divide by 100 to get actual dollar amount
-    
+pub fn call_contract_amend(&mut self, order_id: String, price: f64, quantity: f64, sender: String) -> String {
+    let price = Some(price as u64).filter(|p| *p > 0);
+    let quantity = Some(quantity as u64).filter(|q| *q > 0);
+    if price.is_none() && quantity.is_none() {
+        return serde_json::json!({ "status": "error", "message": "amend needs a new price or quantity" }).to_string();
+    }
+    self.queue_order_change(sender, TransactionType::OrderAmend { order_id, price, quantity })
+}
+
+fn queue_order_change(&mut self, sender: String, tx_type: TransactionType) -> String {
+    let (label, order_id) = match &tx_type {
+        TransactionType::OrderCancel { order_id } => ("cancel", order_id.clone()),
+        TransactionType::OrderAmend { order_id, .. } => ("amend", order_id.clone()),
+        _ => return serde_json::json!({ "status": "error", "message": "not an order change" }).to_string(),
+    };
+    let tx_id = Self::unique_id(label);
     let tx = Transaction {
         id: tx_id.clone(),
         from: sender,
         to: "trading_contract".to_string(),
-        amount, // Use corrected amount
-        tx_type: TransactionType::Trading { 
-            asset: asset.clone(), 
-            quantity: quantity_u64, 
-            price: price_u64 
-        },
+        amount: 0,
+        tx_type,
         timestamp: Self::current_timestamp(),
+        public_key: None,
+        signature: None,
     };
-    self.pending_transactions.push(tx);
+    if let Err(e) = self.queue_signed(tx) {
+        return serde_json::json!({ "status": "error", "message": e }).to_string();
+    }
     #[cfg(feature = "native")]
-    self.save_to_disk();
-    
-    format!("{{\"status\": \"success\", \"message\": \"Sell order placed: {} {} @ {}\", \"orderId\": \"{}\"}}", 
-            quantity, asset, price, tx_id)
+    self.autosave();
+
+    serde_json::json!({
+        "status": "success",
+        "message": format!("{} requested for order {}", label, order_id),
+        "requestId": tx_id
+    }).to_string()
 }
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_contract_order_book(&self) -> String {
@@ -515,32 +1109,32 @@ pub fn call_contract_sell(&mut self, asset: String, quantity: f64, price: f64, s
         let mut sell_orders = Vec::new();
         
         for tx in &self.pending_transactions {
-            if let TransactionType::Trading { asset, quantity, price } = &tx.tx_type {
+            if let TransactionType::Trading { asset, quantity, price, .. } = &tx.tx_type {
                 let order = format!(
                     "{{\"asset\": \"{}\", \"quantity\": {}, \"price\": {}, \"trader\": \"{}\", \"timestamp\": {}}}",
                     asset, quantity, price, &tx.from[..8.min(tx.from.len())], tx.timestamp
                 );
                 
-                if tx.to == "trading_contract" {
-                    buy_orders.push(order);
-                } else {
-                    sell_orders.push(order);
+                match tx.order_side() {
+                    Some(OrderSide::Buy) => buy_orders.push(order),
+                    Some(OrderSide::Sell) => sell_orders.push(order),
+                    None => {}
                 }
             }
         }
         
         for block in &self.chain {
             for tx in &block.transactions {
-                if let TransactionType::Trading { asset, quantity, price } = &tx.tx_type {
+                if let TransactionType::Trading { asset, quantity, price, .. } = &tx.tx_type {
                     let order = format!(
                         "{{\"asset\": \"{}\", \"quantity\": {}, \"price\": {}, \"trader\": \"{}\", \"timestamp\": {}}}",
                         asset, quantity, price, &tx.from[..8.min(tx.from.len())], tx.timestamp
                     );
                     
-                    if tx.to == "trading_contract" {
-                        buy_orders.push(order);
-                    } else {
-                        sell_orders.push(order);
+                    match tx.order_side() {
+                        Some(OrderSide::Buy) => buy_orders.push(order),
+                        Some(OrderSide::Sell) => sell_orders.push(order),
+                        None => {}
                     }
                 }
             }
@@ -556,18 +1150,22 @@ pub fn call_contract_sell(&mut self, asset: String, quantity: f64, price: f64, s
         
         for block in &self.chain {
             for tx in &block.transactions {
-                if let TransactionType::Trading { asset, quantity, price } = &tx.tx_type {
+                if let TransactionType::Trading { asset, quantity, price, .. } = &tx.tx_type {
                     trades.push(format!(
                         "{{\"asset\": \"{}\", \"quantity\": {}, \"price\": {}, \"trader\": \"{}\", \"timestamp\": {}, \"type\": \"{}\"}}",
                         asset, quantity, price, &tx.from[..8.min(tx.from.len())], tx.timestamp,
-                        if tx.to == "trading_contract" { "buy" } else { "sell" }
+                        match tx.order_side() {
+                            Some(OrderSide::Buy) => "buy",
+                            Some(OrderSide::Sell) => "sell",
+                            None => "unknown",
+                        }
                     ));
                 }
             }
         }
         
         for tx in &self.pending_transactions {
-            if let TransactionType::Trading { asset, quantity, price } = &tx.tx_type {
+            if let TransactionType::Trading { asset, quantity, price, .. } = &tx.tx_type {
                 trades.push(format!(
                     "{{\"asset\": \"{}\", \"quantity\": {}, \"price\": {}, \"trader\": \"{}\", \"timestamp\": {}, \"type\": \"pending\"}}",
                     asset, quantity, price, &tx.from[..8.min(tx.from.len())], tx.timestamp
@@ -581,51 +1179,13 @@ pub fn call_contract_sell(&mut self, asset: String, quantity: f64, price: f64, s
     // Proof of Stake mining
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn mine_block(&mut self) -> bool {
-        if self.pending_transactions.is_empty() {
-            return false;
-        }
-
-        let validator = self.select_validator();
-        if validator.is_none() {
-            return false;
-        }
-
-        let last_block = self.chain.last().unwrap();
-        let validator_addr = validator.unwrap();
-        let stake_weight = self.validators.get(&validator_addr).unwrap().stake;
-
-        let mut block = Block {
-            height: last_block.height + 1,
-            hash: String::new(),
-            previous_hash: last_block.hash.clone(),
-            timestamp: Self::current_timestamp(),
-            validator: validator_addr,
-            transactions: self.pending_transactions.clone(),
-            stake_weight,
-            nonce: None,  // WASM-compatible: always None
-            data: None,   // WASM-compatible: always None
-        };
-
-        // Calculate hash
-        block.hash = self.calculate_hash(&block);
-        
-        // For native builds, do proof-of-work mining
-        #[cfg(feature = "native")]
-        self.mine_block_native(&mut block);
-
-        self.chain.push(block);
-        self.pending_transactions.clear();
-        
-        #[cfg(feature = "native")]
-        self.save_to_disk();
-        
-        true
+        self.mine_own_block().is_some()
     }
 
     // Public getters - work for both WASM and native
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_chain_length(&self) -> u32 {
-        self.chain.len() as u32
+        self.height() + 1
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -638,6 +1198,47 @@ pub fn call_contract_sell(&mut self, asset: String, quantity: f64, price: f64, s
         self.validators.len() as u32
     }
 
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_total_stake(&self) -> u64 {
+        self.total_stake
+    }
+
+    // Committed balance; an empty asset means the native coin
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_balance(&self, account: String, asset: String) -> u64 {
+        let asset = if asset.is_empty() { ledger::NATIVE_COIN } else { asset.as_str() };
+        self.ledger.balance(&account, asset)
+    }
+
+    // {"asset": balance, ...} for one account
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_balances_json(&self, account: String) -> String {
+        serde_json::to_string(&self.ledger.balances(&account)).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    // Fee schedule as JSON, see fees.rs; every peer needs the same one
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_fee_schedule_json(&mut self, schedule: String) -> bool {
+        match serde_json::from_str(&schedule) {
+            Ok(fees) => {
+                self.fees = fees;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_fee_schedule_json(&self) -> String {
+        serde_json::to_string(&self.fees).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    // Who is entitled to propose the next block (round 0); empty if no validators
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_next_proposer(&self) -> String {
+        self.select_validator().unwrap_or_default()
+    }
+
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_latest_block_json(&self) -> String {
         match self.chain.last() {
@@ -646,6 +1247,33 @@ pub fn call_contract_sell(&mut self, asset: String, quantity: f64, price: f64, s
         }
     }
 
+    // Full-chain audit as JSON (see audit::ChainReport)
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn verify_chain_json(&self) -> String {
+        serde_json::to_string(&self.verify_chain()).unwrap_or_default()
+    }
+
+    // Merkle inclusion proof for a committed transaction, "{}" if not found
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_tx_proof(&self, tx_id: String) -> String {
+        match self.find_tx_proof(&tx_id) {
+            Some(proof) => serde_json::to_string(&proof).unwrap_or_default(),
+            None => "{}".to_string(),
+        }
+    }
+
+    // Valid proof for a block that is on our chain
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn verify_tx_proof(&self, proof_json: String) -> bool {
+        let proof = match serde_json::from_str::<TxProof>(&proof_json) {
+            Ok(proof) => proof,
+            Err(_) => return false,
+        };
+        let on_chain = self.block_at(proof.block_height)
+            .is_some_and(|b| b.hash == proof.block_hash && b.merkle_root == proof.merkle_root);
+        on_chain && proof.verify()
+    }
+
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_transactions_json(&self) -> String {
         serde_json::to_string(&self.pending_transactions).unwrap_or_default()
@@ -688,39 +1316,62 @@ pub fn call_contract_sell(&mut self, asset: String, quantity: f64, price: f64, s
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn add_p2p_transaction(&mut self, tx_json: String) -> bool {
         if let Ok(tx) = serde_json::from_str::<Transaction>(&tx_json) {
+            let known = self.tx_ids.contains(&tx.id)
+                || self.pending_transactions.iter().any(|pending| pending.id == tx.id);
+            if known || self.verify_transaction(&tx).is_err() || self.check_balances(&tx).is_err() {
+                return false;
+            }
             self.pending_transactions.push(tx);
             #[cfg(feature = "native")]
-            self.save_to_disk();
+            self.autosave();
             true
         } else {
             false
         }
     }
 
+    // A matched trade as a TradeExecution (JSON), recorded from and signed
+    // with this node's key; false if already recorded or it does not apply
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn record_trade_execution_json(&mut self, tx_json: String) -> bool {
+        match serde_json::from_str::<Transaction>(&tx_json) {
+            Ok(tx) => self.record_trade_execution(tx),
+            Err(_) => false,
+        }
+    }
+
+    // True if the block is now on our chain, extending it or through a
+    // reorg; see take_reorg_events_json for what a reorg changed
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn add_p2p_block(&mut self, block_json: String) -> bool {
-        if let Ok(block) = serde_json::from_str::<Block>(&block_json) {
-            if self.validate_block(&block) {
-                self.chain.push(block);
-                #[cfg(feature = "native")]
-                self.save_to_disk();
-                true
-            } else {
-                false
-            }
-        } else {
-            false
+        match serde_json::from_str::<Block>(&block_json) {
+            Ok(block) => self.add_peer_block(block),
+            Err(_) => false,
         }
     }
 
+    // Reorgs since the last call, as a JSON array of forks::ChainReorg
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn take_reorg_events_json(&mut self) -> String {
+        serde_json::to_string(&self.take_reorg_events()).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    // Blocks above `height`, as a JSON array
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_blocks_since_json(&self, height: u32) -> String {
+        let blocks: Vec<&Block> = self.chain.iter().filter(|b| b.height > height).collect();
+        serde_json::to_string(&blocks).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    // new_blocks: blocks since the last sync, see set_last_sync_block
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_sync_summary(&self) -> String {
-        let current_height = self.chain.len();
+        let current_height = self.height();
         let pending_transactions = self.pending_transactions.len();
         let validators = self.validators.len();
         
-        format!("{{\"current_height\": {}, \"last_sync_height\": {}, \"new_blocks\": 0, \"pending_transactions\": {}, \"validators\": {}}}", 
-                current_height, current_height, pending_transactions, validators)
+        format!("{{\"current_height\": {}, \"last_sync_height\": {}, \"new_blocks\": {}, \"pending_transactions\": {}, \"validators\": {}}}", 
+                current_height, self.last_sync_block, current_height.saturating_sub(self.last_sync_block), pending_transactions, validators)
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -736,24 +1387,61 @@ pub fn call_contract_sell(&mut self, asset: String, quantity: f64, price: f64, s
         sync_data.to_string()
     }
 
+    // Height we last caught up to or reported; BlockSync sets it when done
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-    pub fn set_last_sync_block(&mut self, _height: u32) {
-        // For WASM, this is a no-op since we don't persist state
-        // Native implementation would save this to disk
+    pub fn set_last_sync_block(&mut self, height: u32) {
+        self.last_sync_block = height;
+        #[cfg(feature = "native")]
+        self.autosave();
     }
 }
 
 // Native-only methods (not exposed to WASM)
 impl Blockchain {
-    // Native-only constructor with storage
+    // Native-only constructor with storage; "*.json" paths keep the legacy
+    // single-file format, anything else is an append-only block log directory
     #[cfg(feature = "native")]
     pub fn new_with_storage(storage_path: String) -> Self {
+        Self::new_with_store(store::open_store(&storage_path))
+    }
+
+    #[cfg(feature = "native")]
+    pub fn new_with_store(store: Box<dyn ChainStore<Block>>) -> Self {
         let mut blockchain = Self::new();
-        blockchain.storage_path = Some(storage_path);
-        blockchain.load_from_disk();
+        blockchain.store = Some(store);
+        if let Err(e) = blockchain.load_from_disk() {
+            println!("Failed to load stored chain: {}", e);
+            blockchain.load_error = Some(e.clone());
+            blockchain.storage_error = Some(e);
+        }
         blockchain
     }
 
+    // Read-only load for auditing; unlike new_with_storage a missing or
+    // unreadable chain is an error instead of a fresh chain
+    #[cfg(feature = "native")]
+    pub fn load_from_file(path: &str) -> Result<Self, String> {
+        let mut store = store::open_store::<Block>(path);
+        let mut blockchain = Self::new();
+        if blockchain.restore_from(store.as_mut())? {
+            Ok(blockchain)
+        } else {
+            Err(format!("no chain stored at {}", path))
+        }
+    }
+
+    // Switch backends, e.g. to migrate a legacy JSON file into a block log
+    #[cfg(feature = "native")]
+    pub fn set_store(&mut self, store: Box<dyn ChainStore<Block>>) {
+        self.store = Some(store);
+        self.base_tx_ids_stored = false;
+    }
+
+    #[cfg(feature = "native")]
+    pub fn storage_error(&self) -> Option<&str> {
+        self.storage_error.as_deref()
+    }
+
     fn create_genesis_block(&mut self) {
         let genesis = Block {
             height: 0,
@@ -762,9 +1450,14 @@ impl Blockchain {
             timestamp: Self::current_timestamp(),
             validator: "genesis".to_string(),
             transactions: Vec::new(),
+            merkle_root: merkle::empty_root(),
             stake_weight: 0,
+            round: 0,
             nonce: None,  // WASM-compatible: always None
             data: None,   // WASM-compatible: always None
+            validator_key: None,
+            signature: None,
+            fees: None,
         };
         self.chain.push(genesis);
     }
@@ -780,43 +1473,117 @@ impl Blockchain {
             if let Some(ref mut nonce) = block.nonce {
                 *nonce += 1;
             }
-            block.hash = self.calculate_hash(block);
+            block.hash = block.calculate_hash();
         }
     }
 
-    fn select_validator(&self) -> Option<String> {
-        if self.validators.is_empty() {
+    // The validator this node's key is pinned to, if any
+    fn own_validator(&self) -> Option<String> {
+        let key = self.node_key.public_key_hex();
+        self.validators.values()
+            .find(|v| v.public_key.as_deref() == Some(key.as_str()))
+            .map(|v| v.address.clone())
+    }
+
+    // A block of the pending transactions, proposed by this node if the
+    // current round is its turn, checked like anyone else's and committed.
+    // Nothing is mined if this node is not a validator.
+    fn mine_own_block(&mut self) -> Option<Block> {
+        self.drop_overdrawn_pending();
+        if self.pending_transactions.is_empty() {
             return None;
         }
+        let validator_addr = self.own_validator()?;
+        let last_block = self.chain.last()?;
+        let timestamp = Self::current_timestamp().max(last_block.timestamp);
+        let round = Self::round_at(last_block, timestamp);
+        if self.select_proposer(&last_block.hash, last_block.height + 1, round).as_deref() != Some(validator_addr.as_str()) {
+            return None;
+        }
+        let stake_weight = self.validators.get(&validator_addr)?.stake;
+        let fees = self.fees.assess(&self.pending_transactions, &self.validator_set(), &validator_addr);
 
-        let seed = Self::current_timestamp() % self.total_stake.max(1);
-        let mut cumulative = 0;
+        let mut block = Block {
+            height: last_block.height + 1,
+            hash: String::new(),
+            previous_hash: last_block.hash.clone(),
+            timestamp,
+            validator: validator_addr,
+            transactions: self.pending_transactions.clone(),
+            merkle_root: String::new(),
+            stake_weight,
+            round,
+            nonce: None,  // WASM-compatible: always None
+            data: None,   // WASM-compatible: always None
+            validator_key: None,
+            signature: None,
+            fees,
+        };
 
-        for (address, validator) in &self.validators {
-            if validator.active {
-                cumulative += validator.stake;
-                if seed < cumulative {
-                    return Some(address.clone());
-                }
-            }
+        // Calculate hash
+        block.merkle_root = block.calculate_merkle_root();
+        block.hash = block.calculate_hash();
+
+        // For native builds, do proof-of-work mining
+        #[cfg(feature = "native")]
+        self.mine_block_native(&mut block);
+
+        block.sign(&self.node_key);
+        if !self.validate_block(&block) {
+            println!("Block {} by {} does not validate, not mined", block.height, block.validator);
+            return None;
         }
+        self.commit_block(block.clone());
+        self.pending_transactions.clear();
 
-        self.validators.iter()
-            .find(|(_, v)| v.active)
-            .map(|(addr, _)| addr.clone())
+        #[cfg(feature = "native")]
+        self.autosave();
+
+        Some(block)
+    }
+
+    // Proposer for the next block in round 0
+    fn select_validator(&self) -> Option<String> {
+        let last_block = self.chain.last()?;
+        self.select_proposer(&last_block.hash, last_block.height + 1, 0)
     }
 
-    fn calculate_hash(&self, block: &Block) -> String {
+    // Deterministic stake-weighted lottery. The ticket is derived from the
+    // parent hash, height and round, and validators are walked in address
+    // order, so every node computes the same proposer and can check that a
+    // received block came from the rightful one.
+    pub fn select_proposer(&self, previous_hash: &str, height: u32, round: u32) -> Option<String> {
         use sha2::{Sha256, Digest};
-        let input = format!("{}{}{}{}", 
-            block.height, 
-            block.previous_hash, 
-            block.validator, 
-            block.timestamp
-        );
+
+        let set = self.validator_set();
+        let total_stake: u64 = set.iter().map(|(_, stake)| stake).sum();
+        if total_stake == 0 {
+            return None;
+        }
+
         let mut hasher = Sha256::new();
-        hasher.update(input.as_bytes());
-        hex::encode(hasher.finalize())
+        hasher.update(previous_hash.as_bytes());
+        hasher.update(height.to_be_bytes());
+        hasher.update(round.to_be_bytes());
+        let digest = hasher.finalize();
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&digest[..8]);
+        let ticket = u64::from_be_bytes(seed) % total_stake;
+
+        let mut cumulative = 0;
+        for (address, stake) in set {
+            cumulative += stake;
+            if ticket < cumulative {
+                return Some(address);
+            }
+        }
+        None
+    }
+
+    // Committed ids cannot be used again, so ids made here carry a random
+    // part besides the time
+    fn unique_id(label: &str) -> String {
+        format!("{}_{}_{}", label, Self::current_timestamp(), hex::encode(&signing::random_seed()[..8]))
     }
 
     fn current_timestamp() -> u64 {
@@ -836,20 +1603,62 @@ impl Blockchain {
     // Enterprise methods (native only)
     #[cfg(feature = "native")]
     pub fn add_tenant_blocks(&mut self, update: &TenantBlockchainUpdate) {
+        if let Some(reorg) = &update.reorg {
+            self.rewind_tenant_blocks(&update.network_id, reorg);
+        }
         for block in &update.new_blocks {
+            self.tenant_heads.entry(block.network_id.clone())
+                .or_insert_with(|| TenantHead::new(&block.network_id))
+                .record(block);
             self.tenant_blocks.push(block.clone());
         }
         
-        if self.tenant_blocks.len() > 100 {
-            let start_idx = self.tenant_blocks.len() - 100;
-            self.tenant_blocks = self.tenant_blocks[start_idx..].to_vec();
-        }
+        // Older blocks go to the archive, see PruningPolicy
+        self.archive_tenant_blocks();
         
         // Save tenant-specific blockchain to its own file
         self.save_tenant_blockchain(&update.network_id);
         
         // Also save the main enterprise blockchain
-        self.save_to_disk();
+        self.autosave();
+    }
+
+    // Forget the blocks a tenant abandoned. Archived blocks are older than
+    // any fork it can still switch to, so only blocks in memory go.
+    #[cfg(feature = "native")]
+    fn rewind_tenant_blocks(&mut self, network_id: &str, reorg: &TenantReorg) {
+        let (dropped, kept): (Vec<TenantBlockData>, Vec<TenantBlockData>) = std::mem::take(&mut self.tenant_blocks)
+            .into_iter()
+            .partition(|b| b.network_id == network_id && reorg.removed.contains(&b.block_hash));
+        self.tenant_blocks = kept;
+        let head = self.tenant_heads.entry(network_id.to_string())
+            .or_insert_with(|| TenantHead::new(network_id));
+        head.rewind(&dropped, reorg.fork_height, &reorg.fork_hash);
+        // The new branch may have arrived before the reorg did
+        let tip = self.tenant_blocks.iter()
+            .filter(|b| b.network_id == network_id && b.block_id > head.block_id)
+            .max_by_key(|b| b.block_id);
+        if let Some(tip) = tip {
+            head.block_id = tip.block_id;
+            head.block_hash = tip.block_hash.clone();
+        }
+        println!("Network {} reorganized at block {}: dropped {} of its blocks", network_id, reorg.fork_height, dropped.len());
+    }
+
+    // Balances on a tenant's own chain through block `through`, as its blocks
+    // report them. Blocks still in memory are replayed over the archived
+    // balances, so blocks a reorg dropped no longer count.
+    #[cfg(feature = "native")]
+    pub fn tenant_ledger(&self, network_id: &str, through: u32) -> Ledger {
+        let mut ledger = self.tenant_ledgers.get(network_id).cloned().unwrap_or_default();
+        let mut blocks: Vec<&TenantBlockData> = self.tenant_blocks.iter()
+            .filter(|b| b.network_id == network_id && b.block_id <= through)
+            .collect();
+        blocks.sort_by_key(|b| b.block_id);
+        for block in blocks {
+            block.apply_to(&mut ledger);
+        }
+        ledger
     }
 
     #[cfg(feature = "native")]
@@ -872,19 +1681,12 @@ impl Blockchain {
         }).collect()
     }
 
-    #[cfg(feature = "native")]
-    #[cfg(feature = "native")]
     #[cfg(feature = "native")]
     pub fn get_tenant_summaries(&self) -> Vec<serde_json::Value> {
-        let mut network_stats: std::collections::HashMap<String, (usize, usize, u64)> = std::collections::HashMap::new();
-
-        for block in &self.tenant_blocks {
-            let network_id = block.network_id.clone();
-            let entry = network_stats.entry(network_id).or_insert((0, 0, 0));
-            entry.0 += 1; // block count
-            entry.1 += block.transactions.len(); // transaction count
-            entry.2 = entry.2.max(block.timestamp); // latest activity
-        }
+        // Heads count archived blocks too
+        let network_stats: Vec<(String, (usize, usize, u64))> = self.tenant_heads.values()
+            .map(|head| (head.network_id.clone(), (head.block_count, head.transaction_count, head.timestamp)))
+            .collect();
 
         if network_stats.is_empty() {
             // Fallback for empty state
@@ -909,25 +1711,938 @@ impl Blockchain {
     // Native-only methods that can't be exposed to WASM
     pub fn add_block(&mut self, block: Block) -> bool {
         if self.validate_block(&block) {
-            self.chain.push(block);
+            // Drop pending transactions that the committed block already includes
+            let included: std::collections::HashSet<&str> = block.transactions.iter()
+                .map(|tx| tx.id.as_str())
+                .collect();
+            self.pending_transactions.retain(|tx| !included.contains(tx.id.as_str()));
+            self.commit_block(block);
+            self.drop_overdrawn_pending();
             #[cfg(feature = "native")]
-            self.save_to_disk();
+            self.autosave();
             true
         } else {
             false
         }
     }
 
+    // A block from a peer: extends our chain, or is kept as a fork and
+    // possibly switched to (see forks.rs)
+    pub fn add_peer_block(&mut self, block: Block) -> bool {
+        if self.validate_block(&block) {
+            // Transactions we had from the peer before its block arrived
+            let included: std::collections::HashSet<&str> = block.transactions.iter()
+                .map(|tx| tx.id.as_str())
+                .collect();
+            self.pending_transactions.retain(|tx| !included.contains(tx.id.as_str()));
+            self.commit_block(block);
+            self.drop_overdrawn_pending();
+            #[cfg(feature = "native")]
+            self.autosave();
+            true
+        } else {
+            self.add_side_block(block)
+        }
+    }
+
+    // Every block joins the chain through here, after the state it applies
+    // to is checkpointed for a reorg
+    fn commit_block(&mut self, block: Block) {
+        if self.reorg_depth > 0 {
+            self.checkpoints.push_back(self.checkpoint(block.height));
+            while self.checkpoints.len() > self.reorg_depth as usize {
+                self.checkpoints.pop_front();
+            }
+            if let Some(oldest) = self.checkpoints.front() {
+                self.forks.prune_below(oldest.height);
+            }
+        }
+        self.bind_keys(&block);
+        self.apply_balances(&block);
+        self.apply_registry(&block);
+        self.tx_ids.extend(block.transactions.iter().map(|tx| tx.id.clone()));
+        self.chain.push(block);
+    }
+
+    #[cfg(feature = "native")]
+    fn index_transactions(&mut self) {
+        self.tx_ids = self.chain.iter()
+            .flat_map(|block| block.transactions.iter().map(|tx| tx.id.clone()))
+            .chain(self.base_tx_ids.iter().cloned())
+            .collect();
+    }
+
+    fn unindex_transactions(&mut self, blocks: &[Block]) {
+        for tx in blocks.iter().flat_map(|block| block.transactions.iter()) {
+            self.tx_ids.remove(&tx.id);
+        }
+    }
+
+    fn checkpoint(&self, height: u32) -> Checkpoint {
+        Checkpoint {
+            height,
+            ledger: self.ledger.clone(),
+            staking: self.staking.clone(),
+            tenants: self.tenants.clone(),
+            account_keys: self.account_keys.clone(),
+            validators: self.validators.clone(),
+            total_stake: self.total_stake,
+            #[cfg(feature = "native")]
+            contracts: self.contracts.clone(),
+        }
+    }
+
+    // Without staking the validator set comes from add_validator rather than
+    // the chain, so it stays as it is
+    fn restore_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.ledger = checkpoint.ledger;
+        self.staking = checkpoint.staking;
+        self.tenants = checkpoint.tenants;
+        self.account_keys = checkpoint.account_keys;
+        if self.staking_config.is_some() {
+            self.validators = checkpoint.validators;
+            self.total_stake = checkpoint.total_stake;
+            self.validator_sets.split_off(&checkpoint.height);
+        }
+        #[cfg(feature = "native")]
+        {
+            self.contracts = checkpoint.contracts;
+        }
+    }
+
+    // A block that does not extend our tip: kept if its parent is known, and
+    // switched to if its branch wins the fork choice
+    fn add_side_block(&mut self, block: Block) -> bool {
+        let oldest = self.checkpoints.front().map_or(self.height() + 1, |c| c.height);
+        let known = self.forks.contains(&block.hash) || self.block_at(block.height).is_some_and(|b| b.hash == block.hash);
+        // A block on our tip that failed validate_block is simply invalid
+        if self.reorg_depth == 0 || known || block.height < oldest || block.previous_hash == self.get_latest().hash
+            || block.verify_contents().is_err() || block.verify_signatures().is_err() {
+            return false;
+        }
+
+        let tip = block.hash.clone();
+        self.forks.insert(block);
+        let branch = self.forks.branch(&tip, |height, hash| self.block_at(height).is_some_and(|b| b.hash == hash));
+        match branch {
+            Some(branch) => self.reorg_to(branch),
+            None => {
+                self.forks.remove(&tip);
+                false
+            }
+        }
+    }
+
+    // Roll back to the fork point and apply `branch` if it beats the blocks
+    // we have past it. If one of its blocks turns out invalid we stay where
+    // we were and forget that block and what was built on it.
+    fn reorg_to(&mut self, branch: Vec<Block>) -> bool {
+        let fork_height = branch[0].height - 1;
+        let fork_hash = branch[0].previous_hash.clone();
+        let new_tip = branch.last().map(|b| b.hash.clone()).unwrap_or_default();
+        let base = self.chain.first().map_or(0, |b| b.height);
+        let keep = (fork_height + 1).saturating_sub(base) as usize;
+        if keep >= self.chain.len() || !forks::prefer(&branch, &self.chain[keep..]) {
+            return false;
+        }
+        let checkpoint = match self.checkpoints.iter().position(|c| c.height == fork_height + 1) {
+            Some(index) => index,
+            None => {
+                println!("Branch to {} forks at block {}, further back than we can roll back", new_tip, fork_height);
+                return false;
+            }
+        };
+
+        let saved = self.checkpoint(self.height() + 1);
+        let saved_checkpoints = self.checkpoints.clone();
+        let removed = self.chain.split_off(keep);
+        self.unindex_transactions(&removed);
+        let restore = self.checkpoints[checkpoint].clone();
+        self.restore_checkpoint(restore);
+        self.checkpoints.truncate(checkpoint);
+        for block in &branch {
+            if !self.validate_block(block) {
+                println!("Block {} on the branch to {} is invalid, staying on our chain", block.hash, new_tip);
+                self.restore_checkpoint(saved);
+                let applied = self.chain.split_off(keep);
+                self.unindex_transactions(&applied);
+                self.tx_ids.extend(removed.iter().flat_map(|b| b.transactions.iter()).map(|tx| tx.id.clone()));
+                self.chain.extend(removed);
+                self.checkpoints = saved_checkpoints;
+                self.forks.remove_with_descendants(&block.hash);
+                return false;
+            }
+            self.commit_block(block.clone());
+        }
+
+        // Transactions of the abandoned blocks go back to pending, ahead of
+        // what was already there, unless the new branch has them
+        let added: std::collections::HashSet<&str> = branch.iter()
+            .flat_map(|b| b.transactions.iter())
+            .map(|tx| tx.id.as_str())
+            .collect();
+        let returned: Vec<Transaction> = removed.iter()
+            .flat_map(|b| b.transactions.iter())
+            .filter(|tx| !added.contains(tx.id.as_str()) && self.verify_transaction(tx).is_ok())
+            .cloned()
+            .collect();
+        let returned_ids: std::collections::HashSet<String> = returned.iter().map(|tx| tx.id.clone()).collect();
+        let mut pending = std::mem::take(&mut self.pending_transactions);
+        pending.retain(|tx| !added.contains(tx.id.as_str()) && !returned_ids.contains(&tx.id));
+        self.pending_transactions = returned;
+        self.pending_transactions.append(&mut pending);
+        self.drop_overdrawn_pending();
+        let returned_to_pending = self.pending_transactions.iter()
+            .filter(|tx| returned_ids.contains(&tx.id))
+            .map(|tx| tx.id.clone())
+            .collect();
+
+        for block in &branch {
+            self.forks.remove(&block.hash);
+        }
+        for block in &removed {
+            self.forks.insert(block.clone());
+        }
+        println!("Reorg at block {}: {} blocks replaced by {}, new tip {}", fork_height, removed.len(), branch.len(), new_tip);
+        let reorg = ChainReorg {
+            fork_height,
+            fork_hash,
+            old_tip: removed.last().map(|b| b.hash.clone()).unwrap_or_default(),
+            new_tip,
+            removed,
+            added: branch,
+            returned_to_pending,
+        };
+        self.reorg_events.push(reorg);
+        if self.reorg_events.len() > forks::MAX_REORG_EVENTS {
+            self.reorg_events.remove(0);
+        }
+        #[cfg(feature = "native")]
+        self.autosave();
+        true
+    }
+
+    pub fn take_reorg_events(&mut self) -> Vec<ChainReorg> {
+        std::mem::take(&mut self.reorg_events)
+    }
+
+    pub fn reorg_depth(&self) -> u32 {
+        self.reorg_depth
+    }
+
+    // How many blocks back a reorg can go; 0 for chains whose blocks are final
+    pub fn set_reorg_depth(&mut self, depth: u32) {
+        self.reorg_depth = depth;
+        while self.checkpoints.len() > depth as usize {
+            self.checkpoints.pop_front();
+        }
+        if depth == 0 {
+            self.forks = ForkTree::new();
+        }
+    }
+
     pub fn validate_block(&self, block: &Block) -> bool {
         if let Some(last_block) = self.chain.last() {
             block.height == last_block.height + 1 && 
             block.previous_hash == last_block.hash &&
-            self.validators.contains_key(&block.validator)
+            // Fork choice weighs branches by it, so it must be the stake the
+            // validator has, not what the proposer claims
+            self.validators.get(&block.validator).is_some_and(|v| v.stake == block.stake_weight) &&
+            self.round_is_due(last_block, block) &&
+            self.select_proposer(&last_block.hash, block.height, block.round).as_deref() == Some(block.validator.as_str()) &&
+            self.check_new_transactions(block).is_ok() &&
+            self.verify_block_signatures(block).is_ok() &&
+            self.check_block_balances(block).is_ok()
         } else {
             false
         }
     }
 
+    // The round a block made at `timestamp` on top of `parent` is in. Every
+    // node has its own genesis time, so the block after it is in round 0.
+    fn round_at(parent: &Block, timestamp: u64) -> u32 {
+        if parent.height == 0 {
+            return 0;
+        }
+        u32::try_from(timestamp.saturating_sub(parent.timestamp) / ROUND_SECONDS).unwrap_or(u32::MAX)
+    }
+
+    // The proposer does not get to pick its round: it is the time since the
+    // parent, and that time may not lie in our future
+    fn round_is_due(&self, parent: &Block, block: &Block) -> bool {
+        !self.timed_rounds || (
+            (parent.height == 0 || block.timestamp >= parent.timestamp) &&
+            block.timestamp <= Self::current_timestamp().saturating_add(MAX_CLOCK_DRIFT) &&
+            block.round == Self::round_at(parent, block.timestamp)
+        )
+    }
+
+    // BFT consensus agrees on rounds itself, see enterprise_bc/consensus.rs
+    pub fn set_timed_rounds(&mut self, timed: bool) {
+        self.timed_rounds = timed;
+    }
+
+    // A signed transaction goes on the chain once: not again in a later
+    // block, nor twice in the same one
+    fn check_new_transactions(&self, block: &Block) -> Result<(), String> {
+        let mut ids = std::collections::HashSet::new();
+        for tx in &block.transactions {
+            if self.tx_ids.contains(&tx.id) {
+                return Err(format!("transaction {} is already on the chain", tx.id));
+            }
+            if !ids.insert(tx.id.as_str()) {
+                return Err(format!("transaction {} appears twice in block {}", tx.id, block.height));
+            }
+        }
+        Ok(())
+    }
+
+    // Block signed by its validator's key and every transaction signed by its sender
+    pub fn verify_block_signatures(&self, block: &Block) -> Result<(), String> {
+        block.verify_signatures()?;
+
+        let key = block.validator_key.as_deref().unwrap_or_default();
+        let registered = self.validators.get(&block.validator).and_then(|v| v.public_key.as_deref());
+        let owns_key = match registered {
+            Some(registered) => registered == key,
+            None => !signing::is_key_address(&block.validator) || block.validator == key,
+        };
+        if !owns_key {
+            return Err(format!("block {} is signed with a key that does not belong to {}", block.height, block.validator));
+        }
+
+        // An unbound account may only bind one key per block, whether by
+        // signing or by being issued to
+        let mut first_use: HashMap<&str, &str> = HashMap::new();
+        for tx in &block.transactions {
+            self.check_account_key(tx)?;
+            let key = tx.public_key.as_deref().unwrap_or_default();
+            for account in std::iter::once(tx.from.as_str()).chain(Self::issued_to(tx)) {
+                if first_use.insert(account, key).is_some_and(|previous| previous != key) {
+                    return Err(format!("transactions for {} are signed with different keys", account));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn verify_transaction(&self, tx: &Transaction) -> Result<(), String> {
+        if !tx.verify_signature() {
+            return Err(format!("transaction {} has a missing or invalid signature", tx.id));
+        }
+        self.check_account_key(tx)
+    }
+
+    // Addresses that are themselves a public key can only ever be signed by
+    // that key. Named accounts are bound to a key when coin or assets are
+    // issued to them (see bind_keys), validators to their pinned key, and
+    // an account nobody has funded yet to the first key it signs with.
+    fn check_account_key(&self, tx: &Transaction) -> Result<(), String> {
+        let key = tx.public_key.as_deref().unwrap_or_default();
+        let bound = self.account_keys.get(&tx.from).map(String::as_str)
+            .or_else(|| self.validators.get(&tx.from).and_then(|v| v.public_key.as_deref()));
+        let owns_key = match bound {
+            Some(bound) => bound == key,
+            None if signing::is_key_address(&tx.from) => tx.from == key,
+            None => !self.ledger.balances(&tx.from).values().any(|&amount| amount > 0),
+        };
+        if owns_key {
+            Ok(())
+        } else {
+            Err(format!("transaction {} is not signed by {}", tx.id, tx.from))
+        }
+    }
+
+    // Named accounts that coin or assets are issued to, by Mint or as a
+    // party to a trade; they belong to whoever the issuer issued for
+    fn issued_to(tx: &Transaction) -> Vec<&str> {
+        let accounts = match &tx.tx_type {
+            TransactionType::Mint { .. } => vec![tx.to.as_str()],
+            TransactionType::TradeExecution { buyer, seller, .. } => vec![buyer.as_str(), seller.as_str()],
+            _ => Vec::new(),
+        };
+        accounts.into_iter().filter(|account| !signing::is_key_address(account)).collect()
+    }
+
+    fn bind_keys(&mut self, block: &Block) {
+        for tx in &block.transactions {
+            if let Some(key) = &tx.public_key {
+                self.account_keys.entry(tx.from.clone()).or_insert_with(|| key.clone());
+                for account in Self::issued_to(tx) {
+                    if !self.validators.contains_key(account) {
+                        self.account_keys.entry(account.to_string()).or_insert_with(|| key.clone());
+                    }
+                }
+            }
+        }
+        if let (Some(validator), Some(key)) = (self.validators.get_mut(&block.validator), &block.validator_key) {
+            if validator.public_key.is_none() {
+                validator.public_key = Some(key.clone());
+            }
+        }
+    }
+
+    // Blocks are checked before they are committed, so everything applies;
+    // a failure here means balances and chain disagree
+    fn apply_balances(&mut self, block: &Block) {
+        for tx in &block.transactions {
+            if let Err(e) = self.ledger.apply(tx) {
+                println!("Ledger out of step at block {}: {}", block.height, e);
+            }
+        }
+        if let Some(fees) = &block.fees {
+            // Fees of validators with a pool go to the staking account
+            let (paid, pooled) = self.staking.distribute(&fees.paid);
+            let mut fees = BlockFees { paid, ..fees.clone() };
+            if pooled > 0 {
+                *fees.paid.entry(STAKING_ACCOUNT.to_string()).or_insert(0) += pooled;
+            }
+            if let Err(e) = self.ledger.apply_fees(&fees) {
+                println!("Ledger out of step at block {}: {}", block.height, e);
+            }
+        }
+        self.apply_staking(block);
+    }
+
+    fn apply_registry(&mut self, block: &Block) {
+        for tx in block.transactions.iter().filter(|tx| TenantRegistry::is_registry(tx)) {
+            if let Err(e) = self.tenants.apply(tx, block.height) {
+                println!("Tenant registry out of step at block {}: {}", block.height, e);
+            }
+        }
+    }
+
+    // Staking transactions, unbonded coin that is due and, at the end of an
+    // epoch, the validator set for the next one
+    fn apply_staking(&mut self, block: &Block) {
+        let config = match self.staking_config {
+            Some(config) => config,
+            None => return,
+        };
+        // The set the first staked block was proposed under
+        if self.validator_sets.is_empty() {
+            self.validator_sets.insert(block.height.saturating_sub(1), self.validators.clone());
+        }
+        let mut payouts = Vec::new();
+        for tx in block.transactions.iter().filter(|tx| Staking::is_staking(tx)) {
+            match self.staking.apply(tx, block.height, &config) {
+                Ok(payout) => payouts.extend(payout),
+                Err(e) => println!("Staking out of step at block {}: {}", block.height, e),
+            }
+        }
+        for tx in &block.transactions {
+            if let TransactionType::Evidence { evidence } = &tx.tx_type {
+                self.punish(evidence, block.height, &config);
+            }
+        }
+        payouts.extend(self.staking.release(block.height).into_iter().map(|unbonded| (unbonded.delegator, unbonded.amount)));
+        for (to, amount) in payouts {
+            if let Err(e) = self.ledger.transfer(STAKING_ACCOUNT, &to, ledger::NATIVE_COIN, amount) {
+                println!("Ledger out of step at block {}: {}", block.height, e);
+            }
+        }
+        if config.is_epoch_boundary(block.height) {
+            self.start_epoch(block.height);
+        }
+    }
+
+    // Slash and jail the validator the evidence is against. The last active
+    // validator is slashed but stays, as the chain would stall without it.
+    fn punish(&mut self, evidence: &Evidence, height: u32, config: &StakingConfig) {
+        let validator = evidence.validator().to_string();
+        let (bps, jailed_until) = match evidence {
+            Evidence::DoubleSign { .. } => (config.slashing.double_sign_bps, TOMBSTONED),
+            Evidence::Downtime { .. } => (config.slashing.downtime_bps, config.jailed_until(height)),
+        };
+        let slashed = self.staking.slash(&validator, bps, height, jailed_until);
+        if let Err(e) = self.ledger.burn(STAKING_ACCOUNT, ledger::NATIVE_COIN, slashed) {
+            println!("Ledger out of step at block {}: {}", height, e);
+        }
+
+        let others_active = self.validators.values().any(|v| v.active && v.address != validator);
+        if let Some(jailed) = self.validators.get_mut(&validator) {
+            jailed.stake = self.staking.pool(&validator).map_or(0, |pool| pool.bonded);
+            jailed.active = !others_active;
+        }
+        self.total_stake = self.validators.values().filter(|v| v.active).map(|v| v.stake).sum();
+        self.staking.set_changed(height);
+        self.validator_sets.insert(height, self.validators.clone());
+        println!("Slashed {} coin from {} at block {} and jailed it {}", slashed, validator, height,
+                 if jailed_until == TOMBSTONED { "for good".to_string() } else { format!("until block {}", jailed_until) });
+    }
+
+    // Validators with their own stake bonded, at their pool's stake. A
+    // chain without any keeps the validators it has rather than stall.
+    fn start_epoch(&mut self, height: u32) {
+        let mut validators = HashMap::new();
+        for mut validator in self.staking.validators(height) {
+            if validator.public_key.is_none() {
+                validator.public_key = self.validators.get(&validator.address).and_then(|v| v.public_key.clone());
+            }
+            validators.insert(validator.address.clone(), validator);
+        }
+        if validators.is_empty() {
+            println!("Epoch at block {}: nobody has stake bonded, keeping the validator set", height);
+            return;
+        }
+        self.total_stake = validators.values().map(|v| v.stake).sum();
+        self.validators = validators;
+        self.staking.set_changed(height);
+        self.validator_sets.insert(height, self.validators.clone());
+        println!("Epoch at block {}: {} validators, total stake {}", height, self.validators.len(), self.total_stake);
+    }
+
+    // Validators the block at `height` was checked against: the set staking
+    // had changed to by then, with keys pinned since, or the current set for
+    // chains without staking (or saved before the sets were kept)
+    fn validators_at(&self, height: u32) -> HashMap<String, Validator> {
+        let set = self.validator_sets.range(..height).next_back()
+            .or_else(|| self.validator_sets.first_key_value())
+            .map(|(_, set)| set);
+        let mut validators = match set {
+            Some(set) => set.clone(),
+            None => return self.validators.clone(),
+        };
+        for validator in validators.values_mut() {
+            if validator.public_key.is_none() {
+                validator.public_key = self.validators.get(&validator.address).and_then(|v| v.public_key.clone());
+            }
+        }
+        validators
+    }
+
+    // Stake of a validator the chain starts with, issued to the staking account
+    fn bond_genesis(&mut self, address: &str, public_key: Option<String>, stake: u64) {
+        match self.ledger.issue(STAKING_ACCOUNT, ledger::NATIVE_COIN, stake) {
+            Ok(()) => self.staking.bond_genesis(address, public_key, stake),
+            Err(e) => println!("Cannot bond {} for {}: {}", stake, address, e),
+        }
+    }
+
+    // Take the validator set from the bonds from now on, changing it at the
+    // end of every epoch. Every validator of a network needs the same config.
+    // If nothing is bonded yet, e.g. on a chain that ran before staking, the
+    // validators it has are bonded at their stake.
+    pub fn enable_staking(&mut self, config: StakingConfig) {
+        if self.staking.is_empty() {
+            let mut validators: Vec<Validator> = self.validators.values().cloned().collect();
+            validators.sort_by(|a, b| a.address.cmp(&b.address));
+            for validator in validators {
+                self.bond_genesis(&validator.address, validator.public_key, validator.stake);
+            }
+        }
+        self.staking_config = Some(config);
+    }
+
+    // Evidence must hold against our chain and the keys we know, and accuse
+    // each validator once; `height` is that of the block it would go in
+    pub fn check_evidence_txs<'a>(&self, txs: impl IntoIterator<Item = &'a Transaction>, height: u32) -> Result<(), String> {
+        let mut accused = std::collections::HashSet::new();
+        for tx in txs {
+            if let TransactionType::Evidence { evidence } = &tx.tx_type {
+                self.check_evidence(evidence, height).map_err(|e| format!("transaction {}: {}", tx.id, e))?;
+                if !accused.insert(evidence.validator()) {
+                    return Err(format!("transaction {}: {} is already accused", tx.id, evidence.validator()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_evidence(&self, evidence: &Evidence, height: u32) -> Result<(), String> {
+        let config = self.staking_config.ok_or("staking is not enabled on this chain")?;
+        let validator = evidence.validator();
+        let pool = self.staking.pool(validator);
+        if pool.is_some_and(|pool| pool.jailed_until == TOMBSTONED) {
+            return Err(format!("{} is already jailed for good", validator));
+        }
+        match evidence {
+            Evidence::DoubleSign { first, second } => {
+                // Older offences may have been unbonded out of reach
+                if config.release_height(first.height) < height {
+                    return Err(format!("double signing at block {} is too old to punish", first.height));
+                }
+                let key = self.validators.get(validator).and_then(|v| v.public_key.as_deref())
+                    .or_else(|| pool.and_then(|pool| pool.public_key.as_deref()))
+                    .ok_or_else(|| format!("no key is known for {}", validator))?;
+                Evidence::check_double_sign(first, second, key)
+            }
+            Evidence::Downtime { validator, missed } => {
+                if !self.validator_set().iter().any(|(address, _)| address == validator) {
+                    return Err(format!("{} is not an active validator", validator));
+                }
+                if missed.len() < config.slashing.downtime_blocks as usize {
+                    return Err(format!("{} missed blocks are not downtime, {} are", missed.len(), config.slashing.downtime_blocks));
+                }
+                let since = self.staking.set_since().max(pool.map_or(0, |pool| pool.slashed_at));
+                let (first, last) = (missed[0], missed[missed.len() - 1]);
+                if first <= since || !missed.windows(2).all(|pair| pair[0] < pair[1]) {
+                    return Err(format!("missed blocks must be ascending and after block {}", since));
+                }
+                for height in first..=last {
+                    let block = self.block_at(height).ok_or_else(|| format!("block {} is not on our chain", height))?;
+                    if block.validator == *validator {
+                        return Err(format!("{} proposed block {}", validator, height));
+                    }
+                    let missed_here = missed.binary_search(&height).is_ok();
+                    if missed_here && (block.round == 0
+                        || self.select_proposer(&block.previous_hash, height, 0).as_deref() != Some(validator.as_str())) {
+                        return Err(format!("{} was not due to propose block {}", validator, height));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Downtime evidence against active validators that missed enough of their
+    // round 0 proposals since they last proposed a block
+    pub fn find_downtime(&self) -> Vec<Evidence> {
+        let config = match self.staking_config {
+            Some(config) => config,
+            None => return Vec::new(),
+        };
+        let mut found = Vec::new();
+        for (validator, _) in self.validator_set() {
+            let since = self.staking.set_since().max(self.staking.pool(&validator).map_or(0, |pool| pool.slashed_at));
+            let mut missed = Vec::new();
+            for height in since + 1..=self.height() {
+                match self.block_at(height) {
+                    Some(block) if block.validator == validator => missed.clear(),
+                    Some(block) if block.round > 0
+                        && self.select_proposer(&block.previous_hash, height, 0).as_deref() == Some(validator.as_str()) => {
+                        missed.push(height);
+                    }
+                    Some(_) => {}
+                    None => missed.clear(),
+                }
+            }
+            if missed.len() >= config.slashing.downtime_blocks as usize {
+                found.push(Evidence::Downtime { validator, missed });
+            }
+        }
+        found
+    }
+
+    // Queue evidence for the next block, sent from this node's key address
+    pub fn submit_evidence(&mut self, evidence: Evidence) -> Result<String, String> {
+        let mut tx = Transaction {
+            id: Self::unique_id("evidence"),
+            from: self.node_key.public_key_hex(),
+            to: evidence.validator().to_string(),
+            amount: 0,
+            tx_type: TransactionType::Evidence { evidence },
+            timestamp: Self::current_timestamp(),
+            public_key: None,
+            signature: None,
+        };
+        tx.sign(&self.node_key);
+        self.check_balances(&tx)?;
+        let tx_id = tx.id.clone();
+        self.pending_transactions.push(tx);
+        #[cfg(feature = "native")]
+        self.autosave();
+        Ok(tx_id)
+    }
+
+    pub fn staking(&self) -> &Staking {
+        &self.staking
+    }
+
+    pub fn staking_config(&self) -> Option<&StakingConfig> {
+        self.staking_config.as_ref()
+    }
+
+    // Queue a Stake, Delegate, Unbond or ClaimRewards from `from`, signed by
+    // this node's key
+    pub fn queue_staking(&mut self, from: &str, tx_type: TransactionType, amount: u64) -> Result<String, String> {
+        let mut tx = Transaction {
+            id: Self::unique_id("staking"),
+            from: from.to_string(),
+            to: STAKING_ACCOUNT.to_string(),
+            amount,
+            tx_type,
+            timestamp: Self::current_timestamp(),
+            public_key: None,
+            signature: None,
+        };
+        if !Staking::is_staking(&tx) {
+            return Err(format!("transaction {} is not a staking transaction", tx.id));
+        }
+        tx.sign(&self.node_key);
+        self.verify_transaction(&tx)?;
+        self.check_balances(&tx)?;
+        let tx_id = tx.id.clone();
+        self.pending_transactions.push(tx);
+        #[cfg(feature = "native")]
+        self.autosave();
+        Ok(tx_id)
+    }
+
+    pub fn tenants(&self) -> &TenantRegistry {
+        &self.tenants
+    }
+
+    // Queue a TenantRegister, TenantRotateKey, TenantSuspend or
+    // TenantReinstate signed by this node's key
+    pub fn queue_registry(&mut self, tx_type: TransactionType) -> Result<String, String> {
+        let network_id = match &tx_type {
+            TransactionType::TenantRegister { network_id, .. }
+            | TransactionType::TenantRotateKey { network_id, .. }
+            | TransactionType::TenantSuspend { network_id }
+            | TransactionType::TenantReinstate { network_id } => network_id.clone(),
+            _ => return Err("not a tenant registry transaction".to_string()),
+        };
+        let mut tx = Transaction {
+            id: Self::unique_id("tenant"),
+            from: self.node_key.public_key_hex(),
+            to: network_id,
+            amount: 0,
+            tx_type,
+            timestamp: Self::current_timestamp(),
+            public_key: None,
+            signature: None,
+        };
+        tx.sign(&self.node_key);
+        self.check_balances(&tx)?;
+        let tx_id = tx.id.clone();
+        self.pending_transactions.push(tx);
+        #[cfg(feature = "native")]
+        self.autosave();
+        Ok(tx_id)
+    }
+
+    // Pending transactions that are already on the chain or that the new
+    // balances no longer cover; a block repeating a committed id is invalid
+    fn drop_overdrawn_pending(&mut self) {
+        let height = self.height() + 1;
+        let mut pending = std::mem::take(&mut self.pending_transactions);
+        let before = pending.len();
+        let mut accused = std::collections::HashSet::new();
+        pending.retain(|tx| !self.tx_ids.contains(&tx.id));
+        pending.retain(|tx| match &tx.tx_type {
+            TransactionType::Evidence { evidence } => {
+                self.check_evidence(evidence, height).is_ok() && accused.insert(evidence.validator().to_string())
+            }
+            _ => true,
+        });
+        let dropped = before - pending.len()
+            + self.ledger.retain_applicable(&mut pending, &self.fees)
+            + self.staking.retain_applicable(&mut pending, height, self.staking_config.as_ref())
+            + self.tenants.retain_applicable(&mut pending, height);
+        self.pending_transactions = pending;
+        if dropped > 0 {
+            println!("Dropped {} pending transactions the ledger no longer covers", dropped);
+        }
+    }
+
+    // Issuing, recording matched trades (they move both sides' funds) and
+    // registry changes take a validator's pinned key; its address alone is
+    // no proof, anyone can send from an address that is not a key
+    fn check_issuer(&self, tx: &Transaction) -> Result<(), String> {
+        let action = match tx.tx_type {
+            TransactionType::Mint { .. } => "issue",
+            TransactionType::TradeExecution { .. } => "record trades",
+            _ if TenantRegistry::is_registry(tx) => "change the tenant registry",
+            _ => return Ok(()),
+        };
+        let is_validator = tx.public_key.as_deref()
+            .is_some_and(|key| self.validators.values().any(|v| v.public_key.as_deref() == Some(key)));
+        if is_validator {
+            Ok(())
+        } else {
+            Err(format!("transaction {}: {} is not a validator and cannot {}", tx.id, tx.from, action))
+        }
+    }
+
+    // Whether `tx` can be queued behind the pending transactions without
+    // taking any account below zero
+    pub fn check_balances(&self, tx: &Transaction) -> Result<(), String> {
+        self.check_issuer(tx)?;
+        self.staking.check(self.pending_transactions.iter().chain(std::iter::once(tx)), self.height() + 1, self.staking_config.as_ref())?;
+        self.check_evidence_txs(self.pending_transactions.iter().chain(std::iter::once(tx)), self.height() + 1)?;
+        self.tenants.check(self.pending_transactions.iter().chain(std::iter::once(tx)), self.height() + 1)?;
+        self.ledger.check(self.pending_transactions.iter().chain(std::iter::once(tx)), &self.fees)
+    }
+
+    // Also checks that the block charges and pays what our fee schedule says
+    pub fn check_block_balances(&self, block: &Block) -> Result<(), String> {
+        for tx in &block.transactions {
+            self.check_issuer(tx)?;
+        }
+        if self.fees.assess(&block.transactions, &self.validator_set(), &block.validator) != block.fees {
+            return Err(format!("block {} fees do not match the fee schedule", block.height));
+        }
+        self.staking.check(&block.transactions, block.height, self.staking_config.as_ref())?;
+        self.check_evidence_txs(&block.transactions, block.height)?;
+        self.tenants.check(&block.transactions, block.height)?;
+        self.ledger.check(&block.transactions, &self.fees)
+    }
+
+    pub fn set_fee_schedule(&mut self, fees: FeeSchedule) {
+        self.fees = fees;
+    }
+
+    pub fn fee_schedule(&self) -> &FeeSchedule {
+        &self.fees
+    }
+
+    pub fn balance(&self, account: &str, asset: &str) -> u64 {
+        self.ledger.balance(account, asset)
+    }
+
+    // Queue a Mint signed by this node's key
+    pub fn mint(&mut self, to: &str, asset: &str, amount: u64) -> Result<String, String> {
+        let mut tx = Transaction {
+            id: Self::unique_id("mint"),
+            from: self.node_key.public_key_hex(),
+            to: to.to_string(),
+            amount,
+            tx_type: TransactionType::Mint { asset: asset.to_string() },
+            timestamp: Self::current_timestamp(),
+            public_key: None,
+            signature: None,
+        };
+        tx.sign(&self.node_key);
+        self.check_balances(&tx)?;
+        let tx_id = tx.id.clone();
+        self.pending_transactions.push(tx);
+        #[cfg(feature = "native")]
+        self.autosave();
+        Ok(tx_id)
+    }
+
+    pub fn balances(&self, account: &str) -> std::collections::BTreeMap<String, u64> {
+        self.ledger.balances(account)
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    // Sign and queue a transaction created on this node, unless a block
+    // holding it would not validate, e.g. the sender cannot pay its fee
+    fn queue_signed(&mut self, mut tx: Transaction) -> Result<(), String> {
+        tx.sign(&self.node_key);
+        self.verify_transaction(&tx)?;
+        self.check_balances(&tx)?;
+        self.pending_transactions.push(tx);
+        Ok(())
+    }
+
+    // Queue a TradeExecution for the next block, sent from this node's key
+    // address. Every validator matches the same trade under the same id, so
+    // one already pending or on chain is not queued again.
+    pub fn record_trade_execution(&mut self, mut tx: Transaction) -> bool {
+        if !matches!(tx.tx_type, TransactionType::TradeExecution { .. }) {
+            return false;
+        }
+        let known = self.tx_ids.contains(&tx.id)
+            || self.pending_transactions.iter().any(|pending| pending.id == tx.id);
+        if known {
+            return false;
+        }
+        tx.from = self.node_key.public_key_hex();
+        tx.sign(&self.node_key);
+        if let Err(e) = self.check_balances(&tx) {
+            println!("Trade {} not recorded: {}", tx.id, e);
+            return false;
+        }
+        self.pending_transactions.push(tx);
+        #[cfg(feature = "native")]
+        self.autosave();
+        true
+    }
+
+    // Binds the key on first registration; afterwards only the same key is accepted
+    pub fn register_validator_key(&mut self, address: &str, public_key: &str) -> bool {
+        match self.validators.get_mut(address) {
+            Some(validator) => match &validator.public_key {
+                Some(existing) => existing == public_key,
+                None => {
+                    validator.public_key = Some(public_key.to_string());
+                    true
+                }
+            },
+            None => false,
+        }
+    }
+
+    pub fn set_node_key(&mut self, key: KeyPair) {
+        self.node_key = key;
+    }
+
+    pub fn sign_with_node_key(&self, message: &[u8]) -> String {
+        self.node_key.sign(message)
+    }
+
+    // Only validators with a registered key can produce a verifiable signature
+    pub fn verify_validator_signature(&self, address: &str, message: &[u8], signature: &str) -> bool {
+        self.validators.get(address)
+            .and_then(|v| v.public_key.as_deref())
+            .is_some_and(|key| signing::verify(key, message, signature))
+    }
+
+    // Build the next block from pending transactions without committing it.
+    // Used by BFT consensus: the block only lands on the chain once a quorum
+    // of validators precommits it via add_block.
+    pub fn create_block_proposal(&mut self, proposer: &str, round: u32) -> Option<Block> {
+        self.drop_overdrawn_pending();
+        if self.pending_transactions.is_empty() {
+            return None;
+        }
+
+        let last_block = self.chain.last()?;
+        let stake_weight = self.validators.get(proposer)?.stake;
+        let fees = self.fees.assess(&self.pending_transactions, &self.validator_set(), proposer);
+
+        let mut block = Block {
+            height: last_block.height + 1,
+            hash: String::new(),
+            previous_hash: last_block.hash.clone(),
+            timestamp: Self::current_timestamp(),
+            validator: proposer.to_string(),
+            transactions: self.pending_transactions.clone(),
+            merkle_root: String::new(),
+            stake_weight,
+            round,
+            nonce: None,
+            data: None,
+            validator_key: None,
+            signature: None,
+            fees,
+        };
+        block.merkle_root = block.calculate_merkle_root();
+        block.hash = block.calculate_hash();
+        block.sign(&self.node_key);
+        Some(block)
+    }
+
+    // Active validators sorted by address so every node iterates them identically
+    pub fn validator_set(&self) -> Vec<(String, u64)> {
+        let mut set: Vec<(String, u64)> = self.validators.values()
+            .filter(|v| v.active)
+            .map(|v| (v.address.clone(), v.stake))
+            .collect();
+        set.sort_by(|a, b| a.0.cmp(&b.0));
+        set
+    }
+
+    pub fn has_validator(&self, address: &str) -> bool {
+        self.validators.contains_key(address)
+    }
+
+    pub fn find_tx_proof(&self, tx_id: &str) -> Option<TxProof> {
+        self.chain.iter().rev().find_map(|block| block.tx_proof(tx_id))
+    }
+
+    // Proof from a forwarded tenant block, together with the tenant network id
+    #[cfg(feature = "native")]
+    pub fn find_tenant_tx_proof(&self, tx_id: &str) -> Option<(&str, TxProof)> {
+        self.tenant_blocks.iter().rev()
+            .find_map(|block| block.tx_proof(tx_id).map(|proof| (block.network_id.as_str(), proof)))
+    }
+
     pub fn get_blocks(&self) -> &Vec<Block> {
         &self.chain
     }
@@ -936,27 +2651,61 @@ impl Blockchain {
         self.chain.last().unwrap()
     }
 
-    pub fn height(&self) -> u32 {
-        (self.chain.len() - 1) as u32
+    // The chain may start at a snapshot or pruning point rather than genesis
+    pub fn height(&self) -> u32 {
+        self.get_latest().height
+    }
+
+    pub fn block_at(&self, height: u32) -> Option<&Block> {
+        let base = self.chain.first()?.height;
+        self.chain.get(height.checked_sub(base)? as usize)
+    }
+
+    // Storage methods (native only)
+    #[cfg(feature = "native")]
+    pub fn save_to_disk(&mut self) -> Result<(), String> {
+        // Saving the fresh chain we fell back to would cut the stored one to genesis
+        if let Some(e) = &self.load_error {
+            return Err(format!("not saving over a stored chain that failed to load: {}", e));
+        }
+        let state = serde_json::json!({
+            "height": self.height(),
+            "pending": self.pending_transactions,
+            "validators": self.validators,
+            "account_keys": self.account_keys,
+            "balances": self.ledger,
+            "staking": self.staking,
+            "staking_config": self.staking_config,
+            "validator_sets": self.validator_sets,
+            "tenants": self.tenants,
+            "last_sync_block": self.last_sync_block,
+            "tenant_blocks": self.tenant_blocks,
+            "tenant_heads": self.tenant_heads,
+            "tenant_ledgers": self.tenant_ledgers,
+            "contracts": self.contracts
+        });
+
+        let store = match self.store.as_mut() {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        if !self.base_tx_ids_stored && !self.base_tx_ids.is_empty() {
+            store.persist_base_tx_ids(&self.base_tx_ids)
+                .map_err(|e| format!("failed to save to {}: {}", store.describe(), e))?;
+        }
+        self.base_tx_ids_stored = true;
+        store.persist(&self.chain, &state)
+            .map_err(|e| format!("failed to save to {}: {}", store.describe(), e))
     }
 
-    // Storage methods (native only)
+    // Save after a state change; failures are logged and kept for the status API
     #[cfg(feature = "native")]
-    pub fn save_to_disk(&self) {
-        if let Some(path) = &self.storage_path {
-            let data = serde_json::json!({
-                "chain": self.chain,
-                "pending": self.pending_transactions,
-                "validators": self.validators,
-                "tenant_blocks": self.tenant_blocks,
-                "contracts": self.contracts
-            });
-
-            if let Ok(json) = serde_json::to_string_pretty(&data) {
-                if let Some(parent) = Path::new(path).parent() {
-                    let _ = fs::create_dir_all(parent);
-                }
-                let _ = fs::write(path, json);
+    fn autosave(&mut self) {
+        match self.save_to_disk() {
+            Ok(()) => self.storage_error = None,
+            Err(e) => {
+                println!("STORAGE ERROR: {}", e);
+                self.storage_error = Some(e);
             }
         }
     }
@@ -977,10 +2726,13 @@ impl Blockchain {
             .map(|block| block.transactions.len())
             .sum();
         
+        // Totals include archived blocks; "blocks" only the ones still in memory
+        let head = self.tenant_heads.get(network_id);
         let tenant_data = serde_json::json!({
             "network_id": network_id,
-            "total_blocks": tenant_blocks.len(),
-            "total_transactions": total_transactions,
+            "total_blocks": head.map_or(tenant_blocks.len(), |h| h.block_count),
+            "total_transactions": head.map_or(total_transactions, |h| h.transaction_count),
+            "archived_through": head.and_then(|h| h.archived_through),
             "blocks": tenant_blocks,
             "last_updated": std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
@@ -991,49 +2743,215 @@ impl Blockchain {
         let file_path = format!("{}/tenant_{}.json", data_dir, network_id);
         
         if let Ok(json) = serde_json::to_string_pretty(&tenant_data) {
-            let _ = fs::write(&file_path, json);
-            println!("Saved tenant blockchain: {} ({} blocks, {} transactions)", 
-                    file_path, tenant_blocks.len(), total_transactions);
+            match store::write_atomic(Path::new(&file_path), json.as_bytes()) {
+                Ok(()) => println!("Saved tenant blockchain: {} ({} blocks, {} transactions)", 
+                                   file_path, tenant_blocks.len(), total_transactions),
+                Err(e) => println!("STORAGE ERROR: failed to save {}: {}", file_path, e),
+            }
         }
     }
 
     #[cfg(feature = "native")]
-    pub fn load_from_disk(&mut self) {
-        if let Some(path) = &self.storage_path {
-            if Path::new(path).exists() {
-                if let Ok(content) = fs::read_to_string(path) {
-                    if let Ok(data) = serde_json::from_str::<serde_json::Value>(&content) {
-                        if let Ok(chain) = serde_json::from_value(data["chain"].clone()) {
-                            self.chain = chain;
-                        }
-                        if let Ok(pending) = serde_json::from_value(data["pending"].clone()) {
-                            self.pending_transactions = pending;
-                        }
-                        if let Ok(validators) = serde_json::from_value(data["validators"].clone()) {
-                            self.validators = validators;
-                        }
-                        if let Ok(tenant_blocks) = serde_json::from_value(data["tenant_blocks"].clone()) {
-                            self.tenant_blocks = tenant_blocks;
-                        }
-                        if let Ok(contracts) = serde_json::from_value(data["contracts"].clone()) {
-                            self.contracts = contracts;
-                        }
+    pub fn load_from_disk(&mut self) -> Result<(), String> {
+        let mut store = match self.store.take() {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        let loaded = self.restore_from(store.as_mut());
+        self.store = Some(store);
+        loaded.map(|_| ())
+    }
+
+    // Chain and state as stored; false if nothing is stored yet
+    #[cfg(feature = "native")]
+    fn restore_from(&mut self, store: &mut dyn ChainStore<Block>) -> Result<bool, String> {
+        let name = store.describe();
+        let failed = |e: std::io::Error| format!("failed to load {}: {}", name, e);
+        let (chain, state) = match store.load().map_err(failed)? {
+            Some(loaded) => loaded,
+            None => return Ok(false),
+        };
+        self.base_tx_ids = store.load_base_tx_ids().map_err(failed)?;
+        self.base_tx_ids_stored = true;
+        if !chain.is_empty() {
+            self.chain = chain;
+        }
+        // Blocks are stored before the state, so a crash in between leaves
+        // blocks the state does not include yet; they are applied again
+        let unapplied = self.split_unapplied(&state).map_err(|e| format!("failed to load {}: {}", name, e))?;
+        self.apply_stored_state(&state);
+        if !unapplied.is_empty() {
+            println!("Applying {} stored blocks saved after the state", unapplied.len());
+        }
+        for block in unapplied {
+            self.commit_block(block);
+        }
+        Ok(true)
+    }
+
+    // The blocks above the height the state was saved at
+    #[cfg(feature = "native")]
+    fn split_unapplied(&mut self, state: &serde_json::Value) -> Result<Vec<Block>, String> {
+        // Saved before the height was recorded
+        let height = match state["height"].as_u64() {
+            Some(height) => height,
+            None => return Ok(Vec::new()),
+        };
+        let base = self.chain[0].height as u64;
+        if height < base || height > self.height() as u64 {
+            return Err(format!("state was saved at height {} but the stored blocks run from {} to {}",
+                               height, base, self.height()));
+        }
+        Ok(self.chain.split_off((height - base + 1) as usize))
+    }
+
+    #[cfg(feature = "native")]
+    fn apply_stored_state(&mut self, data: &serde_json::Value) {
+        // Saved with the state before the ids were read back from the blocks:
+        // keep those the blocks do not have, and store them apart from now on
+        if let Ok(tx_ids) = serde_json::from_value::<Vec<String>>(data["tx_ids"].clone()) {
+            let in_blocks: std::collections::HashSet<&str> = self.chain.iter()
+                .flat_map(|block| block.transactions.iter().map(|tx| tx.id.as_str()))
+                .collect();
+            let legacy: Vec<String> = tx_ids.into_iter().filter(|id| !in_blocks.contains(id.as_str())).collect();
+            if !legacy.is_empty() {
+                self.base_tx_ids.extend(legacy);
+                self.base_tx_ids_stored = false;
+            }
+        }
+        self.index_transactions();
+        if let Ok(pending) = serde_json::from_value(data["pending"].clone()) {
+            self.pending_transactions = pending;
+        }
+        if let Ok(validators) = serde_json::from_value::<HashMap<String, Validator>>(data["validators"].clone()) {
+            self.total_stake = validators.values().filter(|v| v.active).map(|v| v.stake).sum();
+            self.validators = validators;
+        }
+        if let Ok(account_keys) = serde_json::from_value(data["account_keys"].clone()) {
+            self.account_keys = account_keys;
+        }
+        match serde_json::from_value(data["balances"].clone()) {
+            Ok(balances) => self.ledger = balances,
+            // Saved before balances were kept: replay the blocks we have,
+            // skipping what older chains let through without funds
+            Err(_) => {
+                self.ledger = Ledger::new();
+                for block in &self.chain {
+                    for tx in &block.transactions {
+                        let _ = self.ledger.apply(tx);
+                    }
+                    if let Some(fees) = &block.fees {
+                        let _ = self.ledger.apply_fees(fees);
                     }
                 }
             }
         }
+        if let Ok(staking) = serde_json::from_value(data["staking"].clone()) {
+            self.staking = staking;
+        }
+        if let Ok(Some(config)) = serde_json::from_value(data["staking_config"].clone()) {
+            self.staking_config = Some(config);
+        }
+        if let Ok(validator_sets) = serde_json::from_value(data["validator_sets"].clone()) {
+            self.validator_sets = validator_sets;
+        }
+        if let Ok(tenants) = serde_json::from_value(data["tenants"].clone()) {
+            self.tenants = tenants;
+        }
+        if let Some(height) = data["last_sync_block"].as_u64() {
+            self.last_sync_block = height as u32;
+        }
+        if let Ok(tenant_blocks) = serde_json::from_value::<Vec<TenantBlockData>>(data["tenant_blocks"].clone()) {
+            self.tenant_blocks = tenant_blocks;
+        }
+        match serde_json::from_value(data["tenant_heads"].clone()) {
+            Ok(tenant_heads) => self.tenant_heads = tenant_heads,
+            // Saved before heads were tracked: rebuild from the blocks we have
+            Err(_) => {
+                self.tenant_heads.clear();
+                for block in &self.tenant_blocks {
+                    self.tenant_heads.entry(block.network_id.clone())
+                        .or_insert_with(|| TenantHead::new(&block.network_id))
+                        .record(block);
+                }
+            }
+        }
+        if let Ok(tenant_ledgers) = serde_json::from_value(data["tenant_ledgers"].clone()) {
+            self.tenant_ledgers = tenant_ledgers;
+        }
+        if let Ok(contracts) = serde_json::from_value(data["contracts"].clone()) {
+            self.contracts = contracts;
+        }
     }
 }
 
 // Trading OrderBook - shared between WASM and native
 //
 //
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OrderSide {
     Buy,
     Sell,
 }
 
+impl OrderSide {
+    // "buy" / "sell", any case
+    pub fn parse(side: &str) -> Option<Self> {
+        match side.trim().to_ascii_lowercase().as_str() {
+            "buy" => Some(OrderSide::Buy),
+            "sell" => Some(OrderSide::Sell),
+            _ => None,
+        }
+    }
+}
+
+// An order as the page submits it, e.g.
+//   {"trader": "alice", "asset": "BTC", "side": "buy", "quantity": 10,
+//    "price": 5000, "order_type": "stop_limit", "trigger_price": 4900,
+//    "time_in_force": "gtc", "client_order_id": "c-1"}
+// order_type, time_in_force, trigger_price, expires_at and client_order_id
+// are optional; expires_at (unix seconds) goes with "gtt" and "gtd".
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrderRequest {
+    pub trader: String,
+    pub asset: String,
+    pub side: String,
+    pub quantity: u64,
+    #[serde(default)]
+    pub price: u64, // ignored for market and stop orders
+    #[serde(default)]
+    pub order_type: String,
+    #[serde(default)]
+    pub time_in_force: String,
+    #[serde(default)]
+    pub trigger_price: u64,
+    #[serde(default)]
+    pub expires_at: u64,
+    #[serde(default)]
+    pub client_order_id: String,
+}
+
+impl OrderRequest {
+    pub fn parse_terms(&self) -> Result<(OrderSide, OrderType, TimeInForce), String> {
+        let side = OrderSide::parse(&self.side)
+            .ok_or_else(|| format!("Unknown order side: {}", self.side))?;
+        let order_type = OrderType::parse(&self.order_type, self.trigger_price)
+            .ok_or_else(|| format!("Unknown order type: {}", self.order_type))?;
+        let time_in_force = TimeInForce::parse(&self.time_in_force, self.expires_at)?;
+        if order_type.trigger_price() == Some(0) {
+            return Err("Stop orders need a trigger_price".to_string());
+        }
+        if self.expires_at == 0 && time_in_force.expires_at().is_some() {
+            return Err("GTT and GTD orders need an expires_at".to_string());
+        }
+        Ok((side, order_type, time_in_force))
+    }
+
+    fn client_order_id(&self) -> Option<String> {
+        Some(self.client_order_id.clone()).filter(|id| !id.is_empty())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trade {
     pub id: String,
@@ -1053,14 +2971,53 @@ pub struct Order {
     pub quantity: u64,
     pub price: u64,
     pub side: OrderSide,
+    #[serde(default)]
+    pub order_type: OrderType,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
     pub timestamp: u64,
 }
 
 
+impl BookOrder for Order {
+    fn order_id(&self) -> &str {
+        &self.id
+    }
+
+    fn asset(&self) -> &str {
+        &self.asset
+    }
+
+    fn side(&self) -> OrderSide {
+        self.side
+    }
+
+    fn price(&self) -> u64 {
+        self.price
+    }
+
+    fn quantity(&self) -> u64 {
+        self.quantity
+    }
+
+    fn set_quantity(&mut self, quantity: u64) {
+        self.quantity = quantity;
+    }
+
+    fn order_type(&self) -> OrderType {
+        self.order_type
+    }
+
+    fn time_in_force(&self) -> TimeInForce {
+        self.time_in_force
+    }
+}
+
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 pub struct OrderBook {
-    bids: Vec<Order>,
-    asks: Vec<Order>,
+    book: MatchingBook<Order>,
     trades: Vec<Trade>,
     next_order_id: u64,
 }
@@ -1071,8 +3028,7 @@ impl OrderBook {
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
     pub fn new() -> Self {
         Self {
-            bids: Vec::new(),
-            asks: Vec::new(),
+            book: MatchingBook::new(),
             trades: Vec::new(),
             next_order_id: 1,
         }
@@ -1080,88 +3036,92 @@ impl OrderBook {
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn place_buy_order(&mut self, trader: String, asset: String, quantity: u32, price: u32) -> String {
-        let order = Order {
-            id: format!("buy_{}_{}", self.next_order_id, self.current_timestamp()),
+        self.place_order(trader, asset, "buy".to_string(), quantity, price, String::new())
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn place_sell_order(&mut self, trader: String, asset: String, quantity: u32, price: u32) -> String {
+        self.place_order(trader, asset, "sell".to_string(), quantity, price, String::new())
+    }
+
+    // side is "buy" or "sell" (e.g. the `side` of a Trading transaction);
+    // returns the order id, or an empty string for an unknown side
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn place_order(&mut self, trader: String, asset: String, side: String, quantity: u32, price: u32, client_order_id: String) -> String {
+        let request = OrderRequest {
             trader,
             asset,
+            side,
             quantity: quantity as u64,
             price: price as u64,
-            side: OrderSide::Buy,
-            timestamp: self.current_timestamp(),
+            order_type: String::new(),
+            time_in_force: String::new(),
+            trigger_price: 0,
+            expires_at: 0,
+            client_order_id,
         };
-        self.next_order_id += 1;
-        let order_id = order.id.clone();
-        self.match_order(order);
-        order_id
+        match self.place(request) {
+            Ok((order_id, _)) => order_id,
+            Err(_) => String::new(),
+        }
     }
 
+    // Any order type (see OrderRequest). Returns the status of the order and
+    // of any stop orders it triggered.
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-    pub fn place_sell_order(&mut self, trader: String, asset: String, quantity: u32, price: u32) -> String {
+    pub fn submit_order(&mut self, request_json: String) -> String {
+        let result = serde_json::from_str::<OrderRequest>(&request_json)
+            .map_err(|e| format!("Invalid order request: {}", e))
+            .and_then(|request| self.place(request));
+        match result {
+            Ok((order_id, executions)) => {
+                let executions: Vec<serde_json::Value> = executions.iter().map(|execution| {
+                    serde_json::json!({
+                        "order_id": execution.order.id,
+                        "filled": execution.fills.iter().map(|fill| fill.quantity).sum::<u64>(),
+                        "result": execution.status,
+                    })
+                }).collect();
+                serde_json::json!({ "status": "success", "order_id": order_id, "executions": executions }).to_string()
+            }
+            Err(message) => serde_json::json!({ "status": "error", "message": message }).to_string(),
+        }
+    }
+
+    fn place(&mut self, request: OrderRequest) -> Result<(String, Vec<Execution<Order>>), String> {
+        let (side, order_type, time_in_force) = request.parse_terms()?;
+        let prefix = match side {
+            OrderSide::Buy => "buy",
+            OrderSide::Sell => "sell",
+        };
         let order = Order {
-            id: format!("sell_{}_{}", self.next_order_id, self.current_timestamp()),
-            trader,
-            asset,
-            quantity: quantity as u64,
-            price: price as u64,
-            side: OrderSide::Sell,
+            id: format!("{}_{}_{}", prefix, self.next_order_id, self.current_timestamp()),
+            trader: request.trader.clone(),
+            asset: request.asset.clone(),
+            quantity: request.quantity,
+            price: request.price,
+            side,
+            order_type,
+            time_in_force,
+            client_order_id: request.client_order_id(),
             timestamp: self.current_timestamp(),
         };
         self.next_order_id += 1;
         let order_id = order.id.clone();
-        self.match_order(order);
-        order_id
+        Ok((order_id, self.match_order(order)))
     }
 
     // NEW: Cancel an order
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn cancel_order(&mut self, order_id: String) -> bool {
-        // Try to remove from bids
-        if let Some(pos) = self.bids.iter().position(|o| o.id == order_id) {
-            self.bids.remove(pos);
-            return true;
-        }
-
-        // Try to remove from asks
-        if let Some(pos) = self.asks.iter().position(|o| o.id == order_id) {
-            self.asks.remove(pos);
-            return true;
-        }
-
-        false
+        self.book.cancel(&order_id).is_some()
     }
 
     // NEW: Update order quantity (for partial fills)
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn update_order_quantity(&mut self, order_id: String, new_quantity: u32) -> bool {
-        let new_qty = new_quantity as u64;
-
-        // Update in bids
-        if let Some(order) = self.bids.iter_mut().find(|o| o.id == order_id) {
-            if new_qty == 0 {
-                // Remove order if quantity is 0
-                self.bids.retain(|o| o.id != order_id);
-            } else {
-                order.quantity = new_qty;
-            }
-            // Re-sort bids
-            self.bids.sort_by(|a, b| b.price.cmp(&a.price));
-            return true;
-        }
-
-        // Update in asks
-        if let Some(order) = self.asks.iter_mut().find(|o| o.id == order_id) {
-            if new_qty == 0 {
-                // Remove order if quantity is 0
-                self.asks.retain(|o| o.id != order_id);
-            } else {
-                order.quantity = new_qty;
-            }
-            // Re-sort asks
-            self.asks.sort_by(|a, b| a.price.cmp(&b.price));
-            return true;
-        }
-
-        false
+        // Zero removes the order; a larger quantity goes to the back of its price level
+        self.book.set_quantity(&order_id, new_quantity as u64)
     }
 
     // NEW: Execute a cross-network trade and update local order book
@@ -1173,35 +3133,9 @@ impl OrderBook {
         // Remove/reduce orders that were involved in this trade
         let mut updated_orders = Vec::new();
 
-        // Update buy orders if buyer is local
-        self.bids.retain_mut(|order| {
-            if order.asset == asset && order.trader == buyer && order.price >= px {
-                if order.quantity <= qty {
-                    updated_orders.push(format!("Removed buy order {}", order.id));
-                    return false; // Remove this order completely
-                } else {
-                    order.quantity -= qty;
-                    updated_orders.push(format!("Updated buy order {} to {} units", order.id, order.quantity));
-                    return true; // Keep order with reduced quantity
-                }
-            }
-            true
-        });
-
-        // Update sell orders if seller is local
-        self.asks.retain_mut(|order| {
-            if order.asset == asset && order.trader == seller && order.price <= px {
-                if order.quantity <= qty {
-                    updated_orders.push(format!("Removed sell order {}", order.id));
-                    return false; // Remove this order completely
-                } else {
-                    order.quantity -= qty;
-                    updated_orders.push(format!("Updated sell order {} to {} units", order.id, order.quantity));
-                    return true; // Keep order with reduced quantity
-                }
-            }
-            true
-        });
+        // Update buy orders if buyer is local, sell orders if seller is local
+        self.reduce_trader_orders(&asset, OrderSide::Buy, &buyer, qty, px, &mut updated_orders);
+        self.reduce_trader_orders(&asset, OrderSide::Sell, &seller, qty, px, &mut updated_orders);
 
         // Record the trade
         let trade = Trade {
@@ -1233,110 +3167,90 @@ impl OrderBook {
     // NEW: Clear all orders for a specific trader
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn clear_trader_orders(&mut self, trader: String) -> u32 {
-        let initial_count = self.bids.len() + self.asks.len();
-
-        self.bids.retain(|o| o.trader != trader);
-        self.asks.retain(|o| o.trader != trader);
-
-        let final_count = self.bids.len() + self.asks.len();
-        (initial_count - final_count) as u32
+        let ids: Vec<String> = self.book.side_orders(OrderSide::Buy).into_iter()
+            .chain(self.book.side_orders(OrderSide::Sell))
+            .filter(|o| o.trader == trader)
+            .map(|o| o.id.clone())
+            .collect();
+        for id in &ids {
+            self.book.cancel(id);
+        }
+        ids.len() as u32
     }
 
     // NEW: Get a specific order by ID
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_order(&self, order_id: String) -> String {
-        // Check bids
-        if let Some(order) = self.bids.iter().find(|o| o.id == order_id) {
-            return serde_json::to_string(order).unwrap_or_default();
+        match self.book.get(&order_id) {
+            Some(order) => serde_json::to_string(order).unwrap_or_default(),
+            None => "{}".to_string(),
         }
+    }
 
-        // Check asks
-        if let Some(order) = self.asks.iter().find(|o| o.id == order_id) {
-            return serde_json::to_string(order).unwrap_or_default();
+    // A cross-network fill reduces each of the trader's orders that the
+    // trade price satisfies
+    fn reduce_trader_orders(&mut self, asset: &str, side: OrderSide, trader: &str, qty: u64, px: u64, updates: &mut Vec<String>) {
+        let orders: Vec<(String, u64)> = self.book.orders(asset, side).into_iter()
+            .filter(|o| o.trader == trader && match side {
+                OrderSide::Buy => o.price >= px,
+                OrderSide::Sell => o.price <= px,
+            })
+            .map(|o| (o.id.clone(), o.quantity))
+            .collect();
+        let label = match side {
+            OrderSide::Buy => "buy",
+            OrderSide::Sell => "sell",
+        };
+        for (id, quantity) in orders {
+            if quantity <= qty {
+                self.book.cancel(&id);
+                updates.push(format!("Removed {} order {}", label, id));
+            } else {
+                self.book.set_quantity(&id, quantity - qty);
+                updates.push(format!("Updated {} order {} to {} units", label, id, quantity - qty));
+            }
         }
-
-        "{}".to_string()
     }
 
-    // Keep existing methods...
-    fn match_order(&mut self, mut order: Order) {
+    // Price-time priority against the shared matching core, by order type
+    fn match_order(&mut self, order: Order) -> Vec<Execution<Order>> {
         let current_time = self.current_timestamp();
-
-        // Get the opposite side orders to match against
-        let opposite_orders = match order.side {
-            OrderSide::Buy => &mut self.asks,
-            OrderSide::Sell => &mut self.bids,
-        };
-
-        let mut i = 0;
-        while i < opposite_orders.len() && order.quantity > 0 {
-            let opposite_order = &mut opposite_orders[i];
-
-            // Check if orders can match (same asset and price crosses)
-            let can_match = order.asset == opposite_order.asset && match order.side {
-                OrderSide::Buy => order.price >= opposite_order.price,  // Buy at ask or higher
-                OrderSide::Sell => order.price <= opposite_order.price, // Sell at bid or lower
-            };
-
-            if can_match {
-                // Execute trade for the minimum quantity
-                let trade_quantity = order.quantity.min(opposite_order.quantity);
-                let trade_price = opposite_order.price; // Price discovery: maker's price
-
-                // Create trade record
-                let trade = Trade {
+        let executions = self.book.submit(order, current_time);
+
+        for execution in &executions {
+            let taker = &execution.order;
+            for fill in &execution.fills {
+                let (buyer, seller) = match taker.side {
+                    OrderSide::Buy => (taker.trader.clone(), fill.maker.trader.clone()),
+                    OrderSide::Sell => (fill.maker.trader.clone(), taker.trader.clone()),
+                };
+                self.trades.push(Trade {
                     id: format!("trade_{}", current_time),
-                    asset: order.asset.clone(),
-                    quantity: trade_quantity,
-                    price: trade_price,
-                    buyer: match order.side {
-                        OrderSide::Buy => order.trader.clone(),
-                        OrderSide::Sell => opposite_order.trader.clone(),
-                    },
-                    seller: match order.side {
-                        OrderSide::Sell => order.trader.clone(),
-                        OrderSide::Buy => opposite_order.trader.clone(),
-                    },
+                    asset: taker.asset.clone(),
+                    quantity: fill.quantity,
+                    price: fill.price, // Price discovery: maker's price
+                    buyer,
+                    seller,
                     timestamp: current_time,
-                };
-
-                self.trades.push(trade);
-
-                // Update quantities
-                order.quantity -= trade_quantity;
-                opposite_order.quantity -= trade_quantity;
-
-                // Remove opposite order if fully filled
-                if opposite_order.quantity == 0 {
-                    opposite_orders.remove(i);
-                } else {
-                    i += 1;
-                }
-            } else {
-                i += 1;
+                });
             }
         }
+        executions
+    }
 
-        // IMPORTANT: Add remaining order to book if not fully filled
-        if order.quantity > 0 {
-            match order.side {
-                OrderSide::Buy => {
-                    self.bids.push(order);
-                    self.bids.sort_by(|a, b| b.price.cmp(&a.price)); // Highest price first
-                }
-                OrderSide::Sell => {
-                    self.asks.push(order);
-                    self.asks.sort_by(|a, b| a.price.cmp(&b.price)); // Lowest price first
-                }
-            }
-        }
+    // Drop GTT/GTD orders that have expired; returns how many
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn expire_orders(&mut self) -> u32 {
+        let now = self.current_timestamp();
+        self.book.expire(now).len() as u32
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_order_book_json(&self) -> String {
         let data = serde_json::json!({
-            "bids": self.bids,
-            "asks": self.asks
+            "bids": self.book.side_orders(OrderSide::Buy),
+            "asks": self.book.side_orders(OrderSide::Sell),
+            "stops": self.book.stop_orders()
         });
         serde_json::to_string(&data).unwrap_or_default()
     }
@@ -1369,6 +3283,7 @@ pub struct SmartContractExecutor {
     contracts: HashMap<String, (String, serde_json::Value)>, // (type, state)
     next_contract_id: u32,
     gas_prices: HashMap<String, u64>, // gas cost per operation
+    gas_price: u64, // coin per unit of gas, reported as the call's fee
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -1377,18 +3292,25 @@ impl SmartContractExecutor {
     pub fn new() -> Self {
         let mut gas_prices = HashMap::new();
         // Simple gas pricing
-        gas_prices.insert("deploy".to_string(), 100);
-        gas_prices.insert("read".to_string(), 5);
-        gas_prices.insert("write".to_string(), 20);
-        gas_prices.insert("compute".to_string(), 10);
+        gas_prices.insert("deploy".to_string(), fees::GAS_DEPLOY);
+        gas_prices.insert("read".to_string(), fees::GAS_READ);
+        gas_prices.insert("write".to_string(), fees::GAS_WRITE);
+        gas_prices.insert("compute".to_string(), fees::GAS_COMPUTE);
         
         Self {
             contracts: HashMap::new(),
             next_contract_id: 1,
             gas_prices,
+            gas_price: 0,
         }
     }
 
+    // Same unit as FeeSchedule::gas_price
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_gas_price(&mut self, gas_price: u64) {
+        self.gas_price = gas_price;
+    }
+
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn deploy_contract(&mut self, contract_type_str: String, init_data: String, owner: String) -> String {
         let contract_id = format!("contract_{}", self.next_contract_id);
@@ -1497,6 +3419,7 @@ impl SmartContractExecutor {
         // Add gas info to result
         if let serde_json::Value::Object(mut map) = result {
             map.insert("gas_used".to_string(), serde_json::Value::Number(serde_json::Number::from(gas_used)));
+            map.insert("fee".to_string(), serde_json::Value::Number(serde_json::Number::from(gas_used.saturating_mul(self.gas_price))));
             serde_json::Value::Object(map).to_string()
         } else {
             result.to_string()
@@ -1868,3 +3791,788 @@ impl SmartContractExecutor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use staking::StakingConfig;
+
+    fn chain_with_validators(validators: &[(&str, u32)]) -> Blockchain {
+        let mut bc = Blockchain::new();
+        for (address, stake) in validators {
+            bc.add_validator(address.to_string(), *stake);
+        }
+        bc
+    }
+
+    // Opening balance without going through a block
+    fn fund(bc: &mut Blockchain, account: &str, asset: &str, amount: u64) {
+        let mint = Transaction {
+            id: format!("fund_{}_{}", account, asset),
+            from: "genesis".to_string(),
+            to: account.to_string(),
+            amount,
+            tx_type: TransactionType::Mint { asset: asset.to_string() },
+            timestamp: 0,
+            public_key: None,
+            signature: None,
+        };
+        bc.ledger.apply(&mint).unwrap();
+        // Bound to its key the way issuing to it on chain would
+        if !signing::is_key_address(account) {
+            bc.account_keys.entry(account.to_string()).or_insert_with(|| KeyPair::from_seed(account.as_bytes()).public_key_hex());
+        }
+    }
+
+    #[test]
+    fn test_proposer_schedule_is_deterministic_across_nodes() {
+        // Insertion order differs, the schedule must not
+        let a = chain_with_validators(&[("alice", 100), ("bob", 300), ("carol", 600)]);
+        let b = chain_with_validators(&[("carol", 600), ("alice", 100), ("bob", 300)]);
+
+        for height in 1..50 {
+            for round in 0..3 {
+                assert_eq!(
+                    a.select_proposer("prev", height, round),
+                    b.select_proposer("prev", height, round)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_proposer_schedule_follows_stake() {
+        let bc = chain_with_validators(&[("small", 100), ("large", 900)]);
+        let large_wins = (0..1000u32)
+            .filter(|h| bc.select_proposer("seed", *h, 0).as_deref() == Some("large"))
+            .count();
+        assert!(large_wins > 800 && large_wins < 980, "large won {} of 1000", large_wins);
+    }
+
+    #[test]
+    fn test_block_from_wrong_proposer_is_rejected() {
+        let mut bc = chain_with_validators(&[("alice", 100), ("bob", 100), ("carol", 100)]);
+        bc.add_message("hello".to_string(), "alice".to_string());
+
+        let expected = bc.get_next_proposer();
+        let impostor = ["alice", "bob", "carol"].iter().find(|v| **v != expected).unwrap();
+
+        let forged = bc.create_block_proposal(impostor, 0).unwrap();
+        assert!(!bc.validate_block(&forged));
+
+        let rightful = bc.create_block_proposal(&expected, 0).unwrap();
+        assert!(bc.add_block(rightful));
+        assert_eq!(bc.get_pending_count(), 0);
+    }
+
+    #[test]
+    fn test_rounds_follow_the_clock() {
+        let mut bc = chain_with_validators(&[("alice", 100), ("bob", 100), ("carol", 100)]);
+        bc.add_message("one".to_string(), "alice".to_string());
+        let block = bc.create_block_proposal(&bc.get_next_proposer(), 0).unwrap();
+        assert!(bc.add_block(block));
+        let parent = bc.get_latest().clone();
+        bc.add_message("two".to_string(), "alice".to_string());
+        let valid_at = |bc: &mut Blockchain, round: u32, timestamp: u64| {
+            let proposer = bc.select_proposer(&parent.hash, 2, round).unwrap();
+            let mut block = bc.create_block_proposal(&proposer, round).unwrap();
+            block.timestamp = timestamp;
+            block.hash = block.calculate_hash();
+            block.sign(&bc.node_key);
+            bc.validate_block(&block)
+        };
+
+        // Nobody gets to shop for a round that picks them
+        assert!(!valid_at(&mut bc, 3, parent.timestamp));
+        assert!(!valid_at(&mut bc, 2, parent.timestamp + 3 * ROUND_SECONDS));
+        // A round is due once its time has come, and not by claiming it has
+        assert!(valid_at(&mut bc, 3, parent.timestamp + 3 * ROUND_SECONDS + 1));
+        assert!(!valid_at(&mut bc, 100, parent.timestamp + 100 * ROUND_SECONDS));
+        assert!(!valid_at(&mut bc, 0, parent.timestamp - 1));
+        assert!(valid_at(&mut bc, 0, parent.timestamp));
+    }
+
+    fn signed_transfer(key: &KeyPair, from: &str, amount: u64) -> Transaction {
+        let mut tx = Transaction {
+            id: format!("tx_{}_{}", from, amount),
+            from: from.to_string(),
+            to: "bob".to_string(),
+            amount,
+            tx_type: TransactionType::Transfer,
+            timestamp: 1,
+            public_key: None,
+            signature: None,
+        };
+        tx.sign(key);
+        tx
+    }
+
+    #[test]
+    fn test_forged_transactions_are_rejected() {
+        let mut bc = chain_with_validators(&[("alice", 100)]);
+        fund(&mut bc, "alice", "", 100);
+        let alice = KeyPair::from_seed(b"alice");
+        let mallory = KeyPair::from_seed(b"mallory");
+
+        let mut tampered = signed_transfer(&alice, "alice", 10);
+        tampered.amount = 10_000;
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&tampered).unwrap()));
+
+        let mut unsigned = signed_transfer(&alice, "alice", 10);
+        unsigned.signature = None;
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&unsigned).unwrap()));
+
+        // Key addresses can only be spent from by that key
+        let impersonation = signed_transfer(&mallory, &alice.public_key_hex(), 10);
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&impersonation).unwrap()));
+
+        // Once alice's key is bound on-chain, nobody else can sign as alice
+        assert!(bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&alice, "alice", 10)).unwrap()));
+        let block = bc.create_block_proposal("alice", 0).unwrap();
+        assert!(bc.add_block(block));
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&mallory, "alice", 20)).unwrap()));
+        assert!(bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&alice, "alice", 20)).unwrap()));
+    }
+
+    #[test]
+    fn test_block_must_be_signed_by_registered_validator_key() {
+        let mut bc = chain_with_validators(&[]);
+        let validator = KeyPair::from_seed(b"validator1");
+        assert!(bc.add_validator_with_key("validator1".to_string(), 100, validator.public_key_hex()));
+        bc.add_message("hello".to_string(), "alice".to_string());
+
+        // Our node key is not validator1's key
+        let forged = bc.create_block_proposal("validator1", 0).unwrap();
+        assert!(!bc.validate_block(&forged));
+
+        let mut tampered = forged.clone();
+        tampered.sign(&validator);
+        tampered.hash = "f".repeat(64);
+        assert!(tampered.verify_signatures().is_err());
+
+        let mut signed = forged;
+        signed.sign(&validator);
+        assert!(signed.verify_signatures().is_ok());
+        assert!(bc.add_p2p_block(serde_json::to_string(&signed).unwrap()));
+    }
+
+    #[test]
+    fn test_nodes_mine_as_themselves_on_their_turn() {
+        let (key1, key2) = (KeyPair::from_seed(b"peer1"), KeyPair::from_seed(b"peer2"));
+        let network = |own: &KeyPair| {
+            let mut bc = chain_with_validators(&[]);
+            bc.add_validator_with_key("peer1".to_string(), 100, key1.public_key_hex());
+            bc.add_validator_with_key("peer2".to_string(), 300, key2.public_key_hex());
+            bc.set_node_key(own.clone());
+            bc
+        };
+        let (mut a, mut b) = (network(&key1), network(&key2));
+
+        // Each block names the node that signed it, in a round it is scheduled for
+        let mine_and_share = |miner: &mut Blockchain, other: &mut Blockchain| {
+            miner.add_message(format!("hello {}", miner.height()), miner.get_public_key());
+            let block: Block = serde_json::from_str(&miner.mine_block_and_get()).unwrap();
+            assert_eq!(block.validator_key, Some(miner.get_public_key()));
+            assert_eq!(miner.own_validator(), Some(block.validator.clone()));
+            assert!(other.add_p2p_block(serde_json::to_string(&block).unwrap()));
+        };
+        // Whoever the schedule picks mines, until both have
+        let mut mined = std::collections::HashSet::new();
+        while mined.len() < 2 {
+            assert!(a.height() < 64, "one node was never scheduled");
+            let scheduled = a.select_proposer(&a.get_latest().hash, a.height() + 1, 0).unwrap();
+            let (miner, other) = if scheduled == "peer1" { (&mut a, &mut b) } else { (&mut b, &mut a) };
+            mine_and_share(miner, other);
+            mined.insert(scheduled);
+        }
+
+        // A node that is not a validator mines nothing
+        let mut outsider = network(&KeyPair::from_seed(b"outsider"));
+        outsider.add_message("hello".to_string(), "alice".to_string());
+        assert!(!outsider.mine_block());
+        assert_eq!(outsider.get_pending_count(), 1);
+    }
+
+    #[test]
+    fn test_block_hash_commits_to_transactions() {
+        let mut bc = chain_with_validators(&[("alice", 100)]);
+        for i in 0..5 {
+            bc.add_message(format!("hello {}", i), format!("user{}", i));
+            bc.pending_transactions[i].id = format!("msg_{}", i);
+            bc.pending_transactions[i].sign(&bc.node_key.clone());
+        }
+        let block = bc.create_block_proposal("alice", 0).unwrap();
+
+        let mut tampered = block.clone();
+        tampered.transactions[2].amount = 1_000_000;
+        assert!(tampered.verify_contents().is_err());
+        tampered.merkle_root = tampered.calculate_merkle_root();
+        assert!(tampered.verify_contents().is_err());
+        assert!(!bc.validate_block(&tampered));
+
+        assert!(bc.add_block(block));
+        let proof = bc.get_tx_proof("msg_3".to_string());
+        assert!(bc.verify_tx_proof(proof.clone()));
+
+        let mut forged: TxProof = serde_json::from_str(&proof).unwrap();
+        forged.tx_hash = hex::encode(merkle::leaf_hash(b"not in the block"));
+        assert!(!bc.verify_tx_proof(serde_json::to_string(&forged).unwrap()));
+        assert_eq!(bc.get_tx_proof("missing".to_string()), "{}");
+    }
+
+    #[test]
+    fn test_trading_side_is_explicit_with_legacy_fallback() {
+        // Written before Trading carried a side: same bytes back out, side from the id
+        let legacy = r#"{"id":"buy_17","from":"alice","to":"trading_contract","amount":5,"tx_type":{"Trading":{"asset":"BTC","quantity":10,"price":50}},"timestamp":1}"#;
+        let tx: Transaction = serde_json::from_str(legacy).unwrap();
+        assert_eq!(serde_json::to_string(&tx).unwrap(), legacy);
+        assert_eq!(tx.order_side(), Some(OrderSide::Buy));
+
+        let unlabelled = legacy.replace("buy_17", "order_17");
+        let tx: Transaction = serde_json::from_str(&unlabelled).unwrap();
+        assert_eq!(tx.order_side(), None);
+
+        let mut bc = Blockchain::new();
+        bc.call_contract_order("BTC".to_string(), "SELL".to_string(), 10.0, 50.0, "alice".to_string(), "c-1".to_string());
+        let tx = bc.pending_transactions.last().unwrap().clone();
+        assert_eq!(tx.order_side(), Some(OrderSide::Sell));
+        match &tx.tx_type {
+            TransactionType::Trading { client_order_id, .. } => assert_eq!(client_order_id.as_deref(), Some("c-1")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(tx.verify_signature());
+    }
+
+    #[test]
+    fn test_order_types_and_trade_executions_on_chain() {
+        let mut bc = Blockchain::new();
+        let queued = bc.call_contract_order_json(r#"{"trader": "alice", "asset": "BTC", "side": "buy",
+            "quantity": 5, "order_type": "stop", "trigger_price": 120, "time_in_force": "ioc"}"#.to_string());
+        assert!(queued.contains("success"), "{}", queued);
+        match &bc.pending_transactions.last().unwrap().tx_type {
+            TransactionType::Trading { order_type, time_in_force, .. } => {
+                assert_eq!(*order_type, OrderType::Stop { trigger_price: 120 });
+                assert_eq!(*time_in_force, TimeInForce::Ioc);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let rejected = bc.call_contract_order_json(r#"{"trader": "alice", "asset": "BTC", "side": "buy",
+            "quantity": 5, "order_type": "stop"}"#.to_string());
+        assert!(rejected.contains("trigger_price"));
+        let overflowing = bc.call_contract_order_json(format!(r#"{{"trader": "alice", "asset": "BTC", "side": "buy",
+            "quantity": {}, "price": 2}}"#, u64::MAX));
+        assert!(overflowing.contains("overflows"), "{}", overflowing);
+
+        let mut book = OrderBook::new();
+        book.place_sell_order("bob".to_string(), "BTC".to_string(), 3, 100);
+        let result: serde_json::Value = serde_json::from_str(&book.submit_order(
+            r#"{"trader": "alice", "asset": "BTC", "side": "buy", "quantity": 5, "price": 100, "time_in_force": "fok"}"#.to_string()
+        )).unwrap();
+        assert_eq!(result["executions"][0]["result"]["status"], "rejected");
+
+        // Each validator records a matched trade under the same id, once
+        let execution = Transaction {
+            id: "exec_t-1".to_string(),
+            from: String::new(),
+            to: "settlement".to_string(),
+            amount: 3,
+            tx_type: TransactionType::TradeExecution {
+                asset: "BTC".to_string(),
+                quantity: 3,
+                price: 100,
+                buyer: "alice".to_string(),
+                seller: "bob".to_string(),
+                trade_id: Some("t-1".to_string()),
+                taker: None,
+            },
+            timestamp: 7,
+            public_key: None,
+            signature: None,
+        };
+        let mut bc = Blockchain::new();
+        bc.add_validator_with_key("alice".to_string(), 100, bc.get_public_key());
+        fund(&mut bc, "alice", ledger::NATIVE_COIN, 3);
+        fund(&mut bc, "bob", "BTC", 3);
+        // Unsigned, as the page builds it; the node signs it
+        assert!(bc.record_trade_execution_json(serde_json::to_string(&execution).unwrap()));
+        assert!(bc.pending_transactions[0].verify_signature());
+        assert!(!bc.record_trade_execution(execution.clone()));
+        let block = bc.create_block_proposal("alice", 0).unwrap();
+        assert!(bc.add_block(block));
+        assert!(!bc.record_trade_execution(execution.clone()));
+        assert!(bc.verify_chain().valid);
+        // A node started from a snapshot knows the trades of its anchor
+        let mut joined = Blockchain::from_snapshot(bc.create_snapshot(serde_json::json!({}))).unwrap();
+        assert!(!joined.record_trade_execution(execution));
+    }
+
+    #[test]
+    fn test_signed_transactions_cannot_be_replayed() {
+        let mut bc = Blockchain::new();
+        bc.add_validator_with_key("validator1".to_string(), 100, bc.get_public_key());
+        fund(&mut bc, "alice", "", 100);
+        let alice = KeyPair::from_seed(b"alice");
+        let transfer = serde_json::to_string(&signed_transfer(&alice, "alice", 10)).unwrap();
+
+        assert!(bc.add_p2p_transaction(transfer.clone()));
+        assert!(!bc.add_p2p_transaction(transfer.clone()));
+        let block = bc.create_block_proposal("validator1", 0).unwrap();
+        assert!(bc.add_block(block));
+        assert_eq!(bc.get_balance("bob".to_string(), String::new()), 10);
+
+        // Once committed it is neither queued nor accepted in a block again
+        assert!(!bc.add_p2p_transaction(transfer));
+        let mut replay = bc.create_block_proposal("validator1", 0);
+        assert!(replay.is_none());
+        bc.add_message("to make a block".to_string(), "validator1".to_string());
+        replay = bc.create_block_proposal("validator1", 0);
+        let mut replay = replay.unwrap();
+        replay.transactions.push(signed_transfer(&alice, "alice", 10));
+        replay.merkle_root = replay.calculate_merkle_root();
+        replay.hash = replay.calculate_hash();
+        replay.sign(&bc.node_key);
+        assert!(!bc.add_block(replay));
+
+        // Nor twice within one block
+        let mut doubled = bc.create_block_proposal("validator1", 0).unwrap();
+        doubled.transactions.push(signed_transfer(&alice, "alice", 20));
+        doubled.transactions.push(signed_transfer(&alice, "alice", 20));
+        doubled.merkle_root = doubled.calculate_merkle_root();
+        doubled.hash = doubled.calculate_hash();
+        doubled.sign(&bc.node_key);
+        assert!(!bc.add_block(doubled));
+        assert_eq!(bc.get_balance("alice".to_string(), String::new()), 90);
+    }
+
+    #[test]
+    fn test_peer_block_clears_the_transactions_it_commits() {
+        let (key1, key2) = (KeyPair::from_seed(b"peer1"), KeyPair::from_seed(b"peer2"));
+        let network = |own: &KeyPair| {
+            let mut bc = chain_with_validators(&[]);
+            bc.add_validator_with_key("peer1".to_string(), 100, key1.public_key_hex());
+            bc.add_validator_with_key("peer2".to_string(), 300, key2.public_key_hex());
+            bc.set_node_key(own.clone());
+            fund(&mut bc, "alice", "", 100);
+            bc.set_timed_rounds(false);
+            bc
+        };
+        let (mut a, mut b) = (network(&key1), network(&key2));
+        let transfer = serde_json::to_string(&signed_transfer(&KeyPair::from_seed(b"alice"), "alice", 10)).unwrap();
+        assert!(a.add_p2p_transaction(transfer.clone()));
+        assert!(b.add_p2p_transaction(transfer));
+
+        let block: Block = serde_json::from_str(&a.mine_block_and_get()).unwrap();
+        assert!(b.add_p2p_block(serde_json::to_string(&block).unwrap()));
+        assert_eq!(b.get_pending_count(), 0);
+
+        // And b's own next block does not repeat it
+        b.add_message("hello".to_string(), b.get_public_key());
+        let round = (0..).find(|r| b.select_proposer(&block.hash, 2, *r).as_deref() == Some("peer2")).unwrap();
+        let next = b.create_block_proposal("peer2", round).unwrap();
+        assert!(b.add_block(next.clone()));
+        assert!(a.add_p2p_block(serde_json::to_string(&next).unwrap()));
+    }
+
+    #[test]
+    fn test_blocks_stored_after_the_state_are_applied_on_load() {
+        let dir = std::env::temp_dir().join(format!("distli-replay-{}", uuid::Uuid::new_v4()));
+        let mut bc = Blockchain::new_with_store(Box::new(store::BlockLogStore::new(&dir)));
+        bc.add_validator_with_key("validator1".to_string(), 100, bc.get_public_key());
+        fund(&mut bc, "alice", "", 100);
+        bc.save_to_disk().unwrap();
+        let state_before = std::fs::read(dir.join("state.json")).unwrap();
+
+        let transfer = serde_json::to_string(&signed_transfer(&KeyPair::from_seed(b"alice"), "alice", 10)).unwrap();
+        assert!(bc.add_p2p_transaction(transfer));
+        let block = bc.create_block_proposal("validator1", 0).unwrap();
+        assert!(bc.add_block(block));
+
+        // Crashed after appending the block, before replacing the state
+        std::fs::write(dir.join("state.json"), state_before).unwrap();
+        let reloaded = Blockchain::new_with_store(Box::new(store::BlockLogStore::new(&dir)));
+        assert!(reloaded.storage_error().is_none());
+        assert_eq!(reloaded.height(), 1);
+        assert_eq!(reloaded.get_balance("bob".to_string(), String::new()), 10);
+        assert_eq!(reloaded.get_balance("alice".to_string(), String::new()), 90);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_funded_accounts_cannot_be_claimed_by_strangers() {
+        let mut bc = Blockchain::new();
+        bc.add_validator_with_key("validator1".to_string(), 100, bc.get_public_key());
+        fund(&mut bc, "alice", "", 100);
+        let alice = KeyPair::from_seed(b"alice");
+        let mallory = KeyPair::from_seed(b"mallory");
+
+        // Issued to carol before she ever signed: she is whoever it was issued for
+        assert!(!bc.issue("carol".to_string(), String::new(), 50.0).is_empty());
+        for bad in [0.5, -1.0, f64::NAN, f64::INFINITY, 1e20] {
+            assert!(bc.issue("dave".to_string(), String::new(), bad).is_empty(), "{} was issued", bad);
+        }
+        // bob is paid without having signed anything either
+        assert!(bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&alice, "alice", 30)).unwrap()));
+        let block = bc.create_block_proposal("validator1", 0).unwrap();
+        assert!(bc.add_block(block));
+        assert_eq!(bc.account_keys.get("carol"), Some(&bc.get_public_key()));
+
+        for victim in ["carol", "bob"] {
+            let theft = signed_transfer(&mallory, victim, 10);
+            assert!(bc.verify_transaction(&theft).is_err(), "{} was claimed", victim);
+            assert!(!bc.add_p2p_transaction(serde_json::to_string(&theft).unwrap()));
+        }
+        // The issuer's key spends carol's coin; an empty account is still
+        // claimed by the first key it signs with
+        bc.add_message("hello".to_string(), "carol".to_string());
+        assert!(bc.verify_transaction(&bc.pending_transactions[0]).is_ok());
+        assert!(bc.verify_transaction(&signed_transfer(&mallory, "mallory", 0)).is_ok());
+    }
+
+    #[test]
+    fn test_ledger_rejects_overdrafts_and_only_validators_issue() {
+        // alice runs the validator, as a page does, so what it issues to
+        // her is hers
+        let mut bc = Blockchain::new();
+        let alice = KeyPair::from_seed(b"alice");
+        bc.set_node_key(alice.clone());
+        bc.add_validator_with_key("validator1".to_string(), 100, bc.get_public_key());
+
+        // Nothing to spend yet, then a validator issues
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&alice, "alice", 10)).unwrap()));
+        assert!(!bc.issue("alice".to_string(), String::new(), 50.0).is_empty());
+        let block = bc.create_block_proposal("validator1", 0).unwrap();
+        assert!(bc.add_block(block));
+        assert_eq!(bc.get_balance("alice".to_string(), String::new()), 50);
+
+        // Pending transfers count against the balance too
+        assert!(bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&alice, "alice", 30)).unwrap()));
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&alice, "alice", 21)).unwrap()));
+
+        // A block spending more than alice has is invalid as a whole
+        let mut overdraft = bc.create_block_proposal("validator1", 0).unwrap();
+        overdraft.transactions.push(signed_transfer(&alice, "alice", 40));
+        overdraft.merkle_root = overdraft.calculate_merkle_root();
+        overdraft.hash = overdraft.calculate_hash();
+        overdraft.sign(&bc.node_key);
+        assert!(!bc.validate_block(&overdraft));
+
+        // Accounts that are not validators cannot issue
+        let mut mint = Transaction {
+            id: "mint_mallory".to_string(),
+            from: "mallory".to_string(),
+            to: "mallory".to_string(),
+            amount: 1_000,
+            tx_type: TransactionType::Mint { asset: String::new() },
+            timestamp: 1,
+            public_key: None,
+            signature: None,
+        };
+        mint.sign(&KeyPair::from_seed(b"mallory"));
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&mint).unwrap()));
+
+        // Nor can they by sending from a validator's address, or record a
+        // trade that takes funds from others
+        mint.id = "mint_spoofed".to_string();
+        mint.from = "validator1".to_string();
+        mint.sign(&KeyPair::from_seed(b"mallory"));
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&mint).unwrap()));
+        let mallory = KeyPair::from_seed(b"mallory");
+        let mut trade = Transaction {
+            id: "exec_mallory".to_string(),
+            from: mallory.public_key_hex(),
+            to: "settlement".to_string(),
+            amount: 10,
+            tx_type: TransactionType::TradeExecution {
+                asset: ledger::NATIVE_COIN.to_string(),
+                quantity: 10,
+                price: 0,
+                buyer: "mallory".to_string(),
+                seller: "alice".to_string(),
+                trade_id: None,
+                taker: None,
+            },
+            timestamp: 1,
+            public_key: None,
+            signature: None,
+        };
+        trade.sign(&mallory);
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&trade).unwrap()));
+        let mut forged = bc.create_block_proposal("validator1", 0).unwrap();
+        forged.transactions.push(trade);
+        forged.merkle_root = forged.calculate_merkle_root();
+        forged.hash = forged.calculate_hash();
+        forged.sign(&bc.node_key);
+        assert!(!bc.validate_block(&forged));
+
+        let block = bc.create_block_proposal("validator1", 0).unwrap();
+        assert!(bc.add_block(block));
+        assert_eq!(bc.get_balances_json("bob".to_string()), r#"{"coin":30}"#);
+        assert_eq!(bc.create_snapshot(serde_json::Value::Null).balances.balance("alice", "coin"), 20);
+    }
+
+    #[test]
+    fn test_local_transactions_must_pay_their_fee() {
+        let mut bc = Blockchain::new();
+        let alice = KeyPair::from_seed(b"alice");
+        bc.set_node_key(alice.clone());
+        bc.add_validator_with_key("validator1".to_string(), 100, bc.get_public_key());
+        bc.set_fee_schedule(FeeSchedule { flat: 10, ..Default::default() });
+        fund(&mut bc, "alice", ledger::NATIVE_COIN, 15);
+
+        // Nobody funded mallory, and alice covers one fee
+        assert!(bc.add_message("hi".to_string(), "mallory".to_string()).is_empty());
+        assert!(!bc.add_message("hello".to_string(), "alice".to_string()).is_empty());
+        assert!(bc.add_message("again".to_string(), "alice".to_string()).is_empty());
+        assert!(bc.call_contract_buy("BTC".to_string(), 1.0, 100.0, "mallory".to_string()).contains("error"));
+
+        // What the balances no longer cover is dropped rather than left to
+        // fail every block
+        bc.pending_transactions.push(signed_transfer(&alice, "alice", 5));
+        assert!(bc.mine_block());
+        assert_eq!(bc.get_pending_count(), 0);
+        assert_eq!(bc.balance("alice", ledger::NATIVE_COIN), 5);
+
+        bc.pending_transactions.push(signed_transfer(&alice, "alice", 6));
+        assert!(bc.create_block_proposal("validator1", 0).is_none());
+        assert_eq!(bc.get_pending_count(), 0);
+    }
+
+    #[test]
+    fn test_fees_are_charged_and_shared_by_stake() {
+        let schedule = FeeSchedule { flat: 10, ..Default::default() };
+        let mut bc = chain_with_validators(&[("v1", 100), ("v2", 300)]);
+        bc.set_fee_schedule(schedule.clone());
+        fund(&mut bc, "alice", ledger::NATIVE_COIN, 100);
+        let alice = KeyPair::from_seed(b"alice");
+
+        // The fee counts against the balance before the block
+        assert!(bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&alice, "alice", 80)).unwrap()));
+        assert!(!bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&alice, "alice", 1)).unwrap()));
+
+        let proposer = bc.get_next_proposer();
+        let block = bc.create_block_proposal(&proposer, 0).unwrap();
+        let fees = block.fees.clone().unwrap();
+        assert_eq!((fees.total, fees.charged.get("alice")), (10, Some(&10)));
+        assert_eq!(fees.paid.get("v2"), Some(&7)); // 7.5 rounded down
+        assert_eq!(fees.paid.values().sum::<u64>(), 10);
+
+        // Peers only accept the block under the same schedule
+        let mut peer = chain_with_validators(&[("v1", 100), ("v2", 300)]);
+        fund(&mut peer, "alice", ledger::NATIVE_COIN, 100);
+        assert!(!peer.validate_block(&block));
+        peer.set_fee_schedule(schedule);
+        assert!(peer.validate_block(&block));
+
+        let mut tampered = block.clone();
+        tampered.fees.as_mut().unwrap().paid.insert("v1".to_string(), 10);
+        assert_ne!(tampered.calculate_hash(), block.hash);
+
+        assert!(bc.add_block(block));
+        assert_eq!(bc.balance("alice", ledger::NATIVE_COIN), 10);
+        assert_eq!(bc.balance("bob", ledger::NATIVE_COIN), 80);
+        assert_eq!(bc.balance("v1", ledger::NATIVE_COIN) + bc.balance("v2", ledger::NATIVE_COIN), 10);
+    }
+
+    #[test]
+    fn test_staking_changes_validators_at_epoch_boundaries() {
+        let mut bc = Blockchain::new();
+        bc.enable_staking(StakingConfig { epoch_length: 2, unbonding_epochs: 1, ..Default::default() });
+        bc.add_validator("v1".to_string(), 100);
+        bc.set_fee_schedule(FeeSchedule { flat: 10, ..Default::default() });
+        assert_eq!(bc.balance(STAKING_ACCOUNT, ledger::NATIVE_COIN), 100);
+        fund(&mut bc, "alice", ledger::NATIVE_COIN, 1_000);
+        let alice = KeyPair::from_seed(b"alice");
+        let staking_tx = |id: &str, amount: u64, tx_type: TransactionType| {
+            let mut tx = Transaction {
+                id: id.to_string(),
+                from: "alice".to_string(),
+                to: STAKING_ACCOUNT.to_string(),
+                amount,
+                tx_type,
+                timestamp: 1,
+                public_key: None,
+                signature: None,
+            };
+            tx.sign(&alice);
+            serde_json::to_string(&tx).unwrap()
+        };
+        // alice's blocks must be signed with the key she staked with
+        let commit = |bc: &mut Blockchain, tx: String| {
+            assert!(bc.add_p2p_transaction(tx));
+            let proposer = bc.get_next_proposer();
+            bc.set_node_key(KeyPair::from_seed(proposer.as_bytes()));
+            let block = bc.create_block_proposal(&proposer, 0).unwrap();
+            assert!(bc.add_block(block));
+        };
+        let set = |bc: &Blockchain| bc.validator_set().into_iter().collect::<Vec<_>>();
+
+        assert!(!bc.add_p2p_transaction(staking_tx("d0", 100, TransactionType::Delegate { validator: "bob".to_string() })));
+        commit(&mut bc, staking_tx("s1", 300, TransactionType::Stake));
+        assert_eq!(set(&bc), vec![("v1".to_string(), 100)]);
+        assert_eq!(bc.balance("alice", ledger::NATIVE_COIN), 690);
+
+        // Block 2 ends the epoch; its fee still goes to v1's pool alone
+        commit(&mut bc, staking_tx("d1", 100, TransactionType::Delegate { validator: "v1".to_string() }));
+        assert_eq!(set(&bc), vec![("alice".to_string(), 300), ("v1".to_string(), 200)]);
+        assert_eq!(bc.get_total_stake(), 500);
+        assert_eq!(bc.staking().pool("v1").unwrap().rewards("v1"), 20);
+
+        // Fees of block 3 are shared 4:6 between the pools, and the unbonded
+        // coin stops counting at the next epoch
+        commit(&mut bc, staking_tx("u1", 100, TransactionType::Unbond { validator: "alice".to_string() }));
+        assert_eq!(bc.staking().bonds_of("alice")["v1"].rewards, 2);
+        assert_eq!(bc.staking().bonds_of("alice")["alice"].rewards, 6);
+        assert_eq!(set(&bc)[0], ("alice".to_string(), 300));
+        // The claim includes the fees of its own block
+        commit(&mut bc, staking_tx("c1", 0, TransactionType::ClaimRewards { validator: "alice".to_string() }));
+        assert_eq!(set(&bc)[0], ("alice".to_string(), 200));
+        assert_eq!(bc.balance("alice", ledger::NATIVE_COIN), 690 - 100 - 10 - 10 - 10 + 6 + 6);
+        assert!(!bc.add_p2p_transaction(staking_tx("c2", 0, TransactionType::ClaimRewards { validator: "alice".to_string() })));
+
+        // Released two blocks after unbonding
+        let unbonded = bc.balance("alice", ledger::NATIVE_COIN);
+        commit(&mut bc, staking_tx("d2", 10, TransactionType::Delegate { validator: "v1".to_string() }));
+        assert!(bc.staking().unbonding().is_empty());
+        assert_eq!(bc.balance("alice", ledger::NATIVE_COIN), unbonded - 10 - 10 + 100);
+    }
+
+    #[test]
+    fn test_evidence_slashes_and_jails_validators() {
+        // Rounds are picked here, as BFT consensus would
+        let mut bc = Blockchain::new();
+        bc.set_timed_rounds(false);
+        bc.enable_staking(StakingConfig {
+            epoch_length: 1_000,
+            slashing: slashing::SlashingConfig { double_sign_bps: 1_000, downtime_bps: 100, downtime_blocks: 2, jail_epochs: 1 },
+            ..Default::default()
+        });
+        for validator in ["v1", "v2", "v3"] {
+            assert!(bc.add_validator_with_key(validator.to_string(), 100, KeyPair::from_seed(validator.as_bytes()).public_key_hex()));
+        }
+        let tester = KeyPair::from_seed(b"tester");
+        let queue_message = |bc: &mut Blockchain, n: u32| {
+            let mut tx = Transaction {
+                id: format!("msg_{}", n),
+                from: "tester".to_string(),
+                to: "broadcast".to_string(),
+                amount: 0,
+                tx_type: TransactionType::Message { content: n.to_string() },
+                timestamp: 1,
+                public_key: None,
+                signature: None,
+            };
+            tx.sign(&tester);
+            assert!(bc.add_p2p_transaction(serde_json::to_string(&tx).unwrap()));
+        };
+        let propose = |bc: &mut Blockchain, round: u32| {
+            let proposer = bc.select_proposer(&bc.get_latest().hash, bc.height() + 1, round).unwrap();
+            bc.set_node_key(KeyPair::from_seed(proposer.as_bytes()));
+            bc.create_block_proposal(&proposer, round).unwrap()
+        };
+
+        // Two blocks for height 1, round 0
+        queue_message(&mut bc, 0);
+        let first = propose(&mut bc, 0);
+        queue_message(&mut bc, 1);
+        let second = propose(&mut bc, 0);
+        let cheat = first.validator.clone();
+        assert!(bc.submit_evidence(Evidence::double_sign(&first, &first)).is_err());
+        let mut forged = second.clone();
+        forged.signature = first.signature.clone();
+        assert!(bc.submit_evidence(Evidence::double_sign(&first, &forged)).is_err());
+        bc.submit_evidence(Evidence::double_sign(&first, &second)).unwrap();
+        assert!(bc.submit_evidence(Evidence::double_sign(&second, &first)).is_err()); // accused once
+
+        let block = propose(&mut bc, 0);
+        assert!(bc.add_block(block));
+        assert!(!bc.validator_set().iter().any(|(v, _)| *v == cheat));
+        assert_eq!(bc.staking().pool(&cheat).unwrap().bonded, 90);
+        assert_eq!((bc.get_total_stake(), bc.balance(STAKING_ACCOUNT, ledger::NATIVE_COIN)), (200, 290));
+        assert!(bc.check_evidence(&Evidence::double_sign(&first, &second), 2).is_err());
+
+        // Whoever is due at round 0 from here on never proposes
+        let absent = bc.select_proposer(&bc.get_latest().hash, 2, 0).unwrap();
+        let mut n = 2;
+        while bc.find_downtime().is_empty() {
+            assert!(n < 50, "{} never missed twice", absent);
+            queue_message(&mut bc, n);
+            let round = (0..).find(|round| bc.select_proposer(&bc.get_latest().hash, bc.height() + 1, *round).unwrap() != absent).unwrap();
+            let block = propose(&mut bc, round);
+            assert!(bc.add_block(block));
+            n += 1;
+        }
+        let evidence = bc.find_downtime().remove(0);
+        assert_eq!(evidence.validator(), absent);
+        bc.submit_evidence(evidence).unwrap();
+        let block = propose(&mut bc, 0);
+        assert!(bc.add_block(block));
+        assert_eq!(bc.validator_set().len(), 1);
+        assert_eq!(bc.staking().pool(&absent).unwrap().bonded, 99);
+        assert_eq!(bc.staking().pool(&absent).unwrap().jailed_until, bc.height() + 1_000);
+        assert!(bc.find_downtime().is_empty());
+    }
+
+    #[test]
+    fn test_heavier_branch_wins_and_abandoned_transactions_return() {
+        let mut a = chain_with_validators(&[("v1", 100), ("v2", 300)]);
+        let mut b = chain_with_validators(&[("v1", 100), ("v2", 300)]);
+        // Each branch's proposer takes the first round it may propose in
+        a.set_timed_rounds(false);
+        b.set_timed_rounds(false);
+        fund(&mut a, "alice", ledger::NATIVE_COIN, 100);
+        fund(&mut b, "alice", ledger::NATIVE_COIN, 100);
+        let alice = KeyPair::from_seed(b"alice");
+        let transfer = |bc: &mut Blockchain, amount: u64| {
+            assert!(bc.add_p2p_transaction(serde_json::to_string(&signed_transfer(&alice, "alice", amount)).unwrap()));
+        };
+        // A block by `proposer`, in the first round it may propose in
+        let propose = |bc: &mut Blockchain, proposer: &str| {
+            let tip = bc.get_latest().clone();
+            let round = (0..100)
+                .find(|round| bc.select_proposer(&tip.hash, tip.height + 1, *round).as_deref() == Some(proposer))
+                .unwrap();
+            bc.set_node_key(KeyPair::from_seed(proposer.as_bytes()));
+            let block = bc.create_block_proposal(proposer, round).unwrap();
+            assert!(bc.add_block(block.clone()));
+            serde_json::to_string(&block).unwrap()
+        };
+
+        // Both have block 1, then v1 and v2 each build a block 2
+        transfer(&mut a, 10);
+        let common = propose(&mut a, "v1");
+        assert!(b.add_p2p_block(common));
+        transfer(&mut a, 20);
+        let light = propose(&mut a, "v1");
+        transfer(&mut b, 30);
+        let heavy = propose(&mut b, "v2");
+        assert_eq!(a.balance("bob", ledger::NATIVE_COIN), 30);
+
+        // v2 has more stake: b stays, a switches and rolls its balances back
+        assert!(!b.add_p2p_block(light.clone()));
+        assert!(b.take_reorg_events().is_empty());
+        assert!(a.add_p2p_block(heavy.clone()));
+        assert_eq!(a.get_latest_block_json(), heavy);
+        assert_eq!(a.balance("bob", ledger::NATIVE_COIN), 40);
+
+        let reorgs = a.take_reorg_events();
+        assert_eq!(reorgs.len(), 1);
+        assert_eq!((reorgs[0].fork_height, reorgs[0].removed.len(), reorgs[0].added.len()), (1, 1, 1));
+        assert_eq!(reorgs[0].returned_to_pending, vec!["tx_alice_20".to_string()]);
+        assert_eq!(a.get_pending_count(), 1);
+        assert!(a.take_reorg_events().is_empty());
+
+        // A block claiming more stake than its validator has wins nothing
+        let mut inflated: Block = serde_json::from_str(&light).unwrap();
+        inflated.stake_weight = u64::MAX;
+        inflated.hash = inflated.calculate_hash();
+        inflated.sign(&KeyPair::from_seed(b"v1"));
+        assert!(!b.add_p2p_block(serde_json::to_string(&inflated).unwrap()));
+        assert!(b.take_reorg_events().is_empty());
+        assert_eq!(b.get_latest_block_json(), heavy);
+
+        // Without reorgs the other branch is refused outright
+        b.set_reorg_depth(0);
+        assert!(!b.add_p2p_block(light));
+        assert_eq!(b.get_latest_block_json(), heavy);
+    }
+}
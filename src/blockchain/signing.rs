@@ -0,0 +1,149 @@
+// Schnorr signatures over Ristretto255
+// Accounts and validators are identified by a key pair; transactions are
+// signed by `from` and blocks by `validator`. Keys and signatures travel as
+// hex so they fit the existing JSON wire format.
+
+use curve25519_dalek_ng::constants::RISTRETTO_BASEPOINT_TABLE;
+use curve25519_dalek_ng::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek_ng::scalar::Scalar;
+use sha2::{Digest, Sha512};
+
+const NONCE_DOMAIN: &[u8] = b"distli-schnorr-nonce";
+const CHALLENGE_DOMAIN: &[u8] = b"distli-schnorr-challenge";
+
+#[derive(Clone)]
+pub struct KeyPair {
+    secret: Scalar,
+    public: RistrettoPoint,
+}
+
+impl KeyPair {
+    pub fn generate() -> Self {
+        Self::from_seed(&random_seed())
+    }
+
+    // Same seed, same key pair; lets nodes persist just the 32 byte secret
+    pub fn from_seed(seed: &[u8]) -> Self {
+        let secret = hash_to_scalar(&[b"distli-schnorr-key", seed]);
+        let public = &secret * &RISTRETTO_BASEPOINT_TABLE;
+        KeyPair { secret, public }
+    }
+
+    pub fn from_secret_hex(secret_hex: &str) -> Result<Self, String> {
+        let bytes = hex::decode(secret_hex.trim()).map_err(|e| format!("Invalid secret key: {}", e))?;
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| "Secret key must be 32 bytes".to_string())?;
+        let secret = Scalar::from_canonical_bytes(bytes).ok_or("Secret key is not a canonical scalar")?;
+        let public = &secret * &RISTRETTO_BASEPOINT_TABLE;
+        Ok(KeyPair { secret, public })
+    }
+
+    pub fn secret_hex(&self) -> String {
+        hex::encode(self.secret.to_bytes())
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.public.compress().to_bytes())
+    }
+
+    // Deterministic nonce (RFC 6979 style) so signing never depends on the RNG
+    pub fn sign(&self, message: &[u8]) -> String {
+        let public = self.public.compress();
+        let nonce = hash_to_scalar(&[NONCE_DOMAIN, self.secret.as_bytes(), message]);
+        let commitment = (&nonce * &RISTRETTO_BASEPOINT_TABLE).compress();
+        let challenge = hash_to_scalar(&[CHALLENGE_DOMAIN, commitment.as_bytes(), public.as_bytes(), message]);
+        let response = nonce + challenge * self.secret;
+
+        let mut signature = Vec::with_capacity(64);
+        signature.extend_from_slice(commitment.as_bytes());
+        signature.extend_from_slice(response.as_bytes());
+        hex::encode(signature)
+    }
+}
+
+pub fn verify(public_key_hex: &str, message: &[u8], signature_hex: &str) -> bool {
+    let public_bytes: [u8; 32] = match hex::decode(public_key_hex).ok().and_then(|b| b.try_into().ok()) {
+        Some(bytes) => bytes,
+        None => return false,
+    };
+    let signature: [u8; 64] = match hex::decode(signature_hex).ok().and_then(|b| b.try_into().ok()) {
+        Some(bytes) => bytes,
+        None => return false,
+    };
+
+    let public = CompressedRistretto(public_bytes);
+    let public_point = match public.decompress() {
+        Some(point) => point,
+        None => return false,
+    };
+
+    let mut commitment_bytes = [0u8; 32];
+    commitment_bytes.copy_from_slice(&signature[..32]);
+    let commitment = match CompressedRistretto(commitment_bytes).decompress() {
+        Some(point) => point,
+        None => return false,
+    };
+
+    let mut response_bytes = [0u8; 32];
+    response_bytes.copy_from_slice(&signature[32..]);
+    let response = match Scalar::from_canonical_bytes(response_bytes) {
+        Some(scalar) => scalar,
+        None => return false,
+    };
+
+    let challenge = hash_to_scalar(&[CHALLENGE_DOMAIN, &commitment_bytes, public.as_bytes(), message]);
+    &response * &RISTRETTO_BASEPOINT_TABLE == commitment + challenge * public_point
+}
+
+// Accounts may use their hex public key directly as address
+pub fn is_key_address(address: &str) -> bool {
+    address.len() == 64 && address.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn hash_to_scalar(parts: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    let mut wide = [0u8; 64];
+    wide.copy_from_slice(&hasher.finalize());
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+pub(crate) fn random_seed() -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    #[cfg(target_arch = "wasm32")]
+    {
+        for byte in seed.iter_mut() {
+            *byte = (js_sys::Math::random() * 256.0) as u8;
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use rand_core::{OsRng, RngCore};
+        OsRng.fill_bytes(&mut seed);
+    }
+    seed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = KeyPair::generate();
+        let signature = key.sign(b"transfer 10 to bob");
+        assert!(verify(&key.public_key_hex(), b"transfer 10 to bob", &signature));
+        assert!(!verify(&key.public_key_hex(), b"transfer 99 to bob", &signature));
+        assert!(!verify(&KeyPair::generate().public_key_hex(), b"transfer 10 to bob", &signature));
+    }
+
+    #[test]
+    fn test_secret_round_trip() {
+        let key = KeyPair::from_seed(b"validator1");
+        let restored = KeyPair::from_secret_hex(&key.secret_hex()).unwrap();
+        assert_eq!(key.public_key_hex(), restored.public_key_hex());
+        assert!(is_key_address(&key.public_key_hex()));
+    }
+}
@@ -0,0 +1,100 @@
+// Evidence of validator misbehaviour, and what it costs
+//
+// Evidence goes on chain in an Evidence transaction. Anyone can submit it and
+// every node checks it against its own chain and the validator keys it knows:
+//   DoubleSign  two different blocks for the same height and round, both
+//               signed with the validator's key. Proposing again in a later
+//               round is allowed, so only the same round counts.
+//   Downtime    heights at which the validator was the round 0 proposer but
+//               the block came from a later round, at least downtime_blocks of
+//               them, with no block of its own in between. All of them are
+//               from the current validator set (see Staking::set_since), so the
+//               proposer schedule can be recomputed.
+// The validator loses a share of everything bonded to it, coin still unbonding
+// from it included, and the share is burned. It is jailed as well: it leaves
+// the validator set right away and total stake is recomputed. Double signing
+// jails for good; downtime for jail_epochs epochs, after which the validator
+// rejoins at an epoch boundary if it still has stake of its own.
+
+use super::{signing, Block};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashingConfig {
+    pub double_sign_bps: u64, // of the stake, in basis points
+    pub downtime_bps: u64,
+    pub downtime_blocks: u32, // missed proposals that count as downtime
+    pub jail_epochs: u32,     // for downtime
+}
+
+impl Default for SlashingConfig {
+    fn default() -> Self {
+        Self { double_sign_bps: 500, downtime_bps: 100, downtime_blocks: 10, jail_epochs: 2 }
+    }
+}
+
+impl SlashingConfig {
+    // SLASH_DOUBLE_SIGN_BPS, SLASH_DOWNTIME_BPS, DOWNTIME_BLOCKS and JAIL_EPOCHS
+    #[cfg(feature = "native")]
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok());
+        Self {
+            double_sign_bps: var("SLASH_DOUBLE_SIGN_BPS").unwrap_or(defaults.double_sign_bps).min(10_000),
+            downtime_bps: var("SLASH_DOWNTIME_BPS").unwrap_or(defaults.downtime_bps).min(10_000),
+            downtime_blocks: var("DOWNTIME_BLOCKS").map_or(defaults.downtime_blocks, |v| v as u32).max(1),
+            jail_epochs: var("JAIL_EPOCHS").map_or(defaults.jail_epochs, |v| v as u32),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Evidence {
+    DoubleSign { first: Box<Block>, second: Box<Block> },
+    Downtime { validator: String, missed: Vec<u32> },
+}
+
+impl Evidence {
+    // Transactions are left out; the headers carry the Merkle root they hash to
+    pub fn double_sign(first: &Block, second: &Block) -> Self {
+        let header = |block: &Block| Box::new(Block { transactions: Vec::new(), ..block.clone() });
+        Evidence::DoubleSign { first: header(first), second: header(second) }
+    }
+
+    pub fn validator(&self) -> &str {
+        match self {
+            Evidence::DoubleSign { first, .. } => &first.validator,
+            Evidence::Downtime { validator, .. } => validator,
+        }
+    }
+
+    // Everything that can be checked without the chain; `key` is the
+    // validator's registered key
+    pub fn check_double_sign(first: &Block, second: &Block, key: &str) -> Result<(), String> {
+        if first.validator != second.validator {
+            return Err("the blocks are from different validators".to_string());
+        }
+        if (first.height, first.round) != (second.height, second.round) {
+            return Err("the blocks are not for the same height and round".to_string());
+        }
+        if first.hash == second.hash {
+            return Err("both blocks are the same".to_string());
+        }
+        for block in [first, second] {
+            if block.calculate_hash() != block.hash {
+                return Err(format!("block {} does not match its header", block.hash));
+            }
+            let signed = block.validator_key.as_deref() == Some(key)
+                && block.signature.as_deref().is_some_and(|signature| signing::verify(key, block.hash.as_bytes(), signature));
+            if !signed {
+                return Err(format!("block {} is not signed by {}", block.hash, block.validator));
+            }
+        }
+        Ok(())
+    }
+}
+
+// `bps` of `amount`
+pub fn share(amount: u64, bps: u64) -> u64 {
+    (amount as u128 * bps.min(10_000) as u128 / 10_000) as u64
+}
@@ -0,0 +1,539 @@
+// State snapshots and pruning (native only)
+//
+// A snapshot captures everything a node needs besides blocks - validator set,
+// account keys, balances, ids of committed transactions, contract states,
+// tenant chain heads and application state such
+// as the enterprise order books - anchored at one committed block. A new node
+// can start from a snapshot plus the blocks after it instead of replaying the
+// whole chain, and a running node can drop old blocks from memory once a
+// snapshot covers them. Pruned blocks stay in the block log; overflowing tenant
+// blocks are appended to an archive instead of being discarded.
+
+use super::ledger::Ledger;
+use super::staking::Staking;
+use super::tenants::TenantRegistry;
+use super::store::write_atomic;
+use super::{Block, Blockchain, SmartContract, TenantBlockData, Validator};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct PruningPolicy {
+    pub snapshot_interval: u32,         // blocks between snapshots, 0 disables them
+    pub keep_snapshots: usize,          // snapshot files kept on disk
+    pub keep_blocks: Option<u32>,       // recent blocks kept in memory, None keeps all
+    pub tenant_blocks_in_memory: usize, // older tenant blocks go to the archive
+    pub archive_dir: PathBuf,
+}
+
+impl Default for PruningPolicy {
+    fn default() -> Self {
+        Self {
+            snapshot_interval: 1000,
+            keep_snapshots: 3,
+            keep_blocks: None,
+            tenant_blocks_in_memory: 100,
+            archive_dir: PathBuf::from("data/archive"),
+        }
+    }
+}
+
+impl PruningPolicy {
+    // SNAPSHOT_INTERVAL, KEEP_SNAPSHOTS, KEEP_BLOCKS, TENANT_BLOCKS_IN_MEMORY, ARCHIVE_DIR
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        Self {
+            snapshot_interval: var("SNAPSHOT_INTERVAL").and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.snapshot_interval),
+            keep_snapshots: var("KEEP_SNAPSHOTS").and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.keep_snapshots)
+                .max(1),
+            keep_blocks: var("KEEP_BLOCKS").and_then(|v| v.trim().parse().ok()),
+            tenant_blocks_in_memory: var("TENANT_BLOCKS_IN_MEMORY").and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.tenant_blocks_in_memory),
+            archive_dir: var("ARCHIVE_DIR").map(PathBuf::from).unwrap_or(defaults.archive_dir),
+        }
+    }
+}
+
+// Latest known block of a tenant network, kept even after its blocks are archived
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TenantHead {
+    pub network_id: String,
+    pub block_id: u32,
+    pub block_hash: String,
+    pub timestamp: u64,
+    pub block_count: usize,
+    pub transaction_count: usize,
+    #[serde(default)]
+    pub archived_through: Option<u32>, // highest block id moved to the archive
+}
+
+impl TenantHead {
+    pub fn new(network_id: &str) -> Self {
+        Self {
+            network_id: network_id.to_string(),
+            block_id: 0,
+            block_hash: String::new(),
+            timestamp: 0,
+            block_count: 0,
+            transaction_count: 0,
+            archived_through: None,
+        }
+    }
+
+    pub fn record(&mut self, block: &TenantBlockData) {
+        if self.block_count == 0 || block.block_id >= self.block_id {
+            self.block_id = block.block_id;
+            self.block_hash = block.block_hash.clone();
+        }
+        self.timestamp = self.timestamp.max(block.timestamp);
+        self.block_count += 1;
+        self.transaction_count += block.transactions.len();
+    }
+
+    // Blocks a reorg at `fork_height` dropped
+    pub fn rewind(&mut self, dropped: &[TenantBlockData], fork_height: u32, fork_hash: &str) {
+        self.block_count = self.block_count.saturating_sub(dropped.len());
+        self.transaction_count = self.transaction_count
+            .saturating_sub(dropped.iter().map(|b| b.transactions.len()).sum());
+        if self.block_id > fork_height {
+            self.block_id = fork_height;
+            self.block_hash = fork_hash.to_string();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainSnapshot {
+    pub height: u32,
+    pub block_hash: String,
+    pub anchor: Block, // the block at `height`, so the next block can link to it
+    pub validators: BTreeMap<String, Validator>,
+    pub account_keys: BTreeMap<String, String>,
+    pub contracts: BTreeMap<String, SmartContract>,
+    pub tenant_heads: BTreeMap<String, TenantHead>,
+    // Left out when empty so snapshots taken before balances keep their hash
+    #[serde(default, skip_serializing_if = "Ledger::is_empty")]
+    pub balances: Ledger,
+    #[serde(default, skip_serializing_if = "Staking::is_empty")]
+    pub staking: Staking,
+    #[serde(default, skip_serializing_if = "TenantRegistry::is_empty")]
+    pub tenants: TenantRegistry,
+    // Ids of the transactions up to `height`, so they cannot be replayed
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tx_ids: BTreeSet<String>,
+    pub app_state: serde_json::Value,
+    pub created_at: u64,
+    pub snapshot_hash: String, // sha256 over all of the above
+}
+
+impl ChainSnapshot {
+    pub fn calculate_hash(&self) -> String {
+        let mut unsigned = self.clone();
+        unsigned.snapshot_hash = String::new();
+        let bytes = serde_json::to_vec(&unsigned).unwrap_or_default();
+        hex::encode(Sha256::digest(&bytes))
+    }
+
+    // Internal consistency only; whether `block_hash` is the right block at
+    // `height` is for the caller to establish, e.g. against its peers
+    pub fn verify(&self) -> Result<(), String> {
+        if self.calculate_hash() != self.snapshot_hash {
+            return Err("snapshot hash does not match its contents".to_string());
+        }
+        if self.anchor.height != self.height || self.anchor.hash != self.block_hash {
+            return Err(format!("anchor block is not block {} at height {}", self.block_hash, self.height));
+        }
+        if self.height == 0 {
+            return Ok(());
+        }
+        self.anchor.verify_signatures()?;
+        match self.validators.get(&self.anchor.validator) {
+            Some(validator) => match (&validator.public_key, &self.anchor.validator_key) {
+                (Some(registered), Some(used)) if registered != used => {
+                    Err(format!("anchor block not signed with {}'s registered key", self.anchor.validator))
+                }
+                _ => Ok(()),
+            },
+            None => Err(format!("anchor block validator {} is not in the snapshot", self.anchor.validator)),
+        }
+    }
+}
+
+impl Blockchain {
+    // Snapshot of the current state anchored at the chain tip
+    pub fn create_snapshot(&self, app_state: serde_json::Value) -> ChainSnapshot {
+        let anchor = self.get_latest().clone();
+        let mut snapshot = ChainSnapshot {
+            height: anchor.height,
+            block_hash: anchor.hash.clone(),
+            anchor,
+            validators: self.validators.clone().into_iter().collect(),
+            account_keys: self.account_keys.clone().into_iter().collect(),
+            contracts: self.contracts.clone().into_iter().collect(),
+            tenant_heads: self.tenant_heads.clone().into_iter().collect(),
+            balances: self.ledger.clone(),
+            staking: self.staking.clone(),
+            tenants: self.tenants.clone(),
+            tx_ids: self.tx_ids.iter().cloned().collect(),
+            app_state,
+            created_at: Self::current_timestamp(),
+            snapshot_hash: String::new(),
+        };
+        snapshot.snapshot_hash = snapshot.calculate_hash();
+        snapshot
+    }
+
+    // Chain whose first block is the snapshot anchor; later blocks are added
+    // with add_block as usual and are checked against the snapshot's validators
+    pub fn from_snapshot(snapshot: ChainSnapshot) -> Result<Self, String> {
+        snapshot.verify()?;
+        let mut blockchain = Self::new();
+        blockchain.chain = vec![snapshot.anchor];
+        blockchain.total_stake = snapshot.validators.values().filter(|v| v.active).map(|v| v.stake).sum();
+        blockchain.validators = snapshot.validators.into_iter().collect();
+        blockchain.validator_sets.insert(blockchain.height(), blockchain.validators.clone());
+        blockchain.account_keys = snapshot.account_keys.into_iter().collect();
+        blockchain.contracts = snapshot.contracts.into_iter().collect();
+        blockchain.tenant_heads = snapshot.tenant_heads.into_iter().collect();
+        blockchain.ledger = snapshot.balances;
+        blockchain.staking = snapshot.staking;
+        blockchain.tenants = snapshot.tenants;
+        blockchain.base_tx_ids = snapshot.tx_ids;
+        blockchain.index_transactions();
+        Ok(blockchain)
+    }
+
+    pub fn set_pruning_policy(&mut self, policy: PruningPolicy) {
+        self.pruning = policy;
+    }
+
+    pub fn pruning_policy(&self) -> &PruningPolicy {
+        &self.pruning
+    }
+
+    // Drop blocks below `height` from memory, always keeping the tip. Only
+    // allowed when the store keeps them on disk; returns how many were dropped.
+    pub fn prune_blocks_below(&mut self, height: u32) -> Result<usize, String> {
+        match &self.store {
+            Some(store) if store.keeps_pruned_blocks() => {}
+            Some(store) => return Err(format!("{} does not keep pruned blocks", store.describe())),
+            None => return Err("no store to keep pruned blocks".to_string()),
+        }
+        let prunable = self.chain.len() - 1;
+        let count = self.chain.iter().take(prunable).take_while(|b| b.height < height).count();
+        self.chain.drain(..count);
+        Ok(count)
+    }
+
+    // Blocks after `height` that are still in memory
+    pub fn blocks_after(&self, height: u32) -> Vec<Block> {
+        self.chain.iter().filter(|b| b.height > height).cloned().collect()
+    }
+
+    pub fn tenant_heads(&self) -> &std::collections::HashMap<String, TenantHead> {
+        &self.tenant_heads
+    }
+
+    // Seen before: still in memory, or at or below what was archived
+    pub fn is_known_tenant_block(&self, block: &TenantBlockData) -> bool {
+        let archived = self.tenant_heads.get(&block.network_id)
+            .and_then(|head| head.archived_through)
+            .is_some_and(|through| block.block_id <= through);
+        archived || self.tenant_blocks.iter().any(|existing| {
+            existing.network_id == block.network_id
+                && existing.block_id == block.block_id
+                && existing.block_hash == block.block_hash
+        })
+    }
+
+    // Move the oldest tenant blocks beyond the in-memory limit to the archive
+    pub(super) fn archive_tenant_blocks(&mut self) {
+        let limit = self.pruning.tenant_blocks_in_memory;
+        if self.tenant_blocks.len() <= limit {
+            return;
+        }
+
+        let overflow: Vec<TenantBlockData> = self.tenant_blocks.drain(..self.tenant_blocks.len() - limit).collect();
+        let mut by_network: BTreeMap<&str, Vec<&TenantBlockData>> = BTreeMap::new();
+        for block in &overflow {
+            by_network.entry(block.network_id.as_str()).or_default().push(block);
+        }
+
+        for (network_id, blocks) in by_network {
+            let path = self.pruning.archive_dir.join(format!("tenant_{}.jsonl", network_id));
+            match append_lines(&path, &blocks) {
+                Ok(()) => {
+                    let ledger = self.tenant_ledgers.entry(network_id.to_string()).or_default();
+                    let mut archived = blocks.clone();
+                    archived.sort_by_key(|b| b.block_id);
+                    for block in archived {
+                        block.apply_to(ledger);
+                    }
+                    let head = self.tenant_heads.entry(network_id.to_string())
+                        .or_insert_with(|| TenantHead::new(network_id));
+                    let highest = blocks.iter().map(|b| b.block_id).max();
+                    head.archived_through = head.archived_through.max(highest);
+                    println!("Archived {} blocks of network {} to {}", blocks.len(), network_id, path.display());
+                }
+                Err(e) => {
+                    // Keep them in memory rather than lose them
+                    println!("STORAGE ERROR: failed to archive tenant blocks to {}: {}", path.display(), e);
+                    let kept: Vec<TenantBlockData> = blocks.into_iter().cloned().collect();
+                    self.tenant_blocks.splice(0..0, kept);
+                }
+            }
+        }
+    }
+}
+
+fn append_lines<T: Serialize>(path: &Path, items: &[T]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut bytes = Vec::new();
+    for item in items {
+        serde_json::to_writer(&mut bytes, item)?;
+        bytes.push(b'\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&bytes)?;
+    file.sync_data()
+}
+
+// Snapshot files named by height: snapshot-000000001000.json
+#[derive(Debug, Clone)]
+pub struct SnapshotDir {
+    dir: PathBuf,
+}
+
+impl SnapshotDir {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path_for(&self, height: u32) -> PathBuf {
+        self.dir.join(format!("snapshot-{:012}.json", height))
+    }
+
+    pub fn heights(&self) -> Vec<u32> {
+        let mut heights: Vec<u32> = fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries.filter_map(|e| e.ok())
+                    .filter_map(|e| {
+                        let name = e.file_name().to_string_lossy().to_string();
+                        name.strip_prefix("snapshot-")?.strip_suffix(".json")?.parse().ok()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        heights.sort_unstable();
+        heights
+    }
+
+    // Write atomically, then drop all but the newest `keep`
+    pub fn save(&self, snapshot: &ChainSnapshot, keep: usize) -> Result<PathBuf, String> {
+        let path = self.path_for(snapshot.height);
+        let json = serde_json::to_vec(snapshot).map_err(|e| e.to_string())?;
+        write_atomic(&path, &json).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+
+        let heights = self.heights();
+        for height in heights.iter().take(heights.len().saturating_sub(keep.max(1))) {
+            let _ = fs::remove_file(self.path_for(*height));
+        }
+        Ok(path)
+    }
+
+    pub fn load(&self, height: u32) -> Result<ChainSnapshot, String> {
+        let path = self.path_for(height);
+        let content = fs::read(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let snapshot: ChainSnapshot = serde_json::from_slice(&content)
+            .map_err(|e| format!("{} is not a snapshot: {}", path.display(), e))?;
+        snapshot.verify().map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(snapshot)
+    }
+
+    // Newest snapshot that loads and verifies
+    pub fn latest(&self) -> Option<ChainSnapshot> {
+        self.heights().into_iter().rev().find_map(|height| match self.load(height) {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                println!("Skipping snapshot: {}", e);
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::store::BlockLogStore;
+    use crate::blockchain::{TenantReorg, Transaction, TransactionType};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("distli-snapshot-{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    fn grow(bc: &mut Blockchain, blocks: usize) {
+        for i in 0..blocks {
+            bc.add_message(format!("message {}", i), "alice".to_string());
+            let block = bc.create_block_proposal("alice", 0).unwrap();
+            assert!(bc.add_block(block));
+        }
+    }
+
+    #[test]
+    fn test_node_starts_from_snapshot_plus_following_blocks() {
+        let mut source = Blockchain::new();
+        source.add_validator("alice".to_string(), 100);
+        grow(&mut source, 3);
+        let snapshot = source.create_snapshot(serde_json::json!({"order_engine": {"buy_orders": []}}));
+        grow(&mut source, 2);
+
+        let mut joined = Blockchain::from_snapshot(snapshot.clone()).unwrap();
+        for block in source.blocks_after(snapshot.height) {
+            assert!(joined.add_block(block));
+        }
+        assert_eq!(joined.height(), source.height());
+        assert_eq!(joined.get_latest().hash, source.get_latest().hash);
+        assert!(joined.verify_chain().valid, "{}", joined.verify_chain().summary());
+
+        let mut tampered = snapshot.clone();
+        tampered.validators.get_mut("alice").unwrap().stake = 1_000_000;
+        assert!(Blockchain::from_snapshot(tampered).is_err());
+    }
+
+    #[test]
+    fn test_pruned_chain_keeps_blocks_in_the_log() {
+        let dir = temp_dir("prune");
+        let mut bc = Blockchain::new_with_store(Box::new(BlockLogStore::new(&dir)));
+        bc.add_validator("alice".to_string(), 100);
+        grow(&mut bc, 5);
+
+        assert_eq!(bc.prune_blocks_below(4).unwrap(), 4);
+        assert_eq!(bc.get_blocks()[0].height, 4);
+        grow(&mut bc, 1);
+
+        let reloaded = Blockchain::load_from_file(dir.to_str().unwrap()).unwrap();
+        assert_eq!(reloaded.get_blocks().len(), 7);
+        assert_eq!(reloaded.get_latest().hash, bc.get_latest().hash);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_snapshot_tx_ids_survive_a_restart() {
+        let dir = temp_dir("base-ids");
+        let mut source = Blockchain::new();
+        source.add_validator("alice".to_string(), 100);
+        grow(&mut source, 3);
+        let early = source.get_blocks()[1].transactions[0].id.clone();
+
+        let mut joined = Blockchain::from_snapshot(source.create_snapshot(serde_json::json!({}))).unwrap();
+        joined.set_store(Box::new(BlockLogStore::new(&dir)));
+        joined.save_to_disk().unwrap();
+        let state: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("state.json")).unwrap()).unwrap();
+        assert!(state.get("tx_ids").is_none());
+
+        let reloaded = Blockchain::new_with_store(Box::new(BlockLogStore::new(&dir)));
+        assert!(reloaded.storage_error().is_none());
+        assert!(reloaded.tx_ids.contains(&early));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_tenant_overflow_is_archived_not_dropped() {
+        let archive = temp_dir("archive");
+        let mut bc = Blockchain::new();
+        bc.set_pruning_policy(PruningPolicy {
+            tenant_blocks_in_memory: 2,
+            archive_dir: archive.clone(),
+            ..PruningPolicy::default()
+        });
+
+        let blocks: Vec<TenantBlockData> = (1..=5).map(|id| TenantBlockData {
+            network_id: "net-a".to_string(),
+            block_id: id,
+            block_hash: format!("{:064x}", id),
+            transactions: Vec::new(),
+            timestamp: id as u64,
+            previous_hash: format!("{:064x}", id - 1),
+            merkle_root: None,
+            tenant_signature: None,
+        }).collect();
+        for block in &blocks {
+            bc.tenant_blocks.push(block.clone());
+            bc.tenant_heads.entry("net-a".to_string()).or_insert_with(|| TenantHead::new("net-a")).record(block);
+        }
+        bc.archive_tenant_blocks();
+
+        assert_eq!(bc.get_recent_tenant_blocks(10).len(), 2);
+        let archived = fs::read_to_string(archive.join("tenant_net-a.jsonl")).unwrap();
+        assert_eq!(archived.lines().count(), 3);
+        let head = &bc.tenant_heads()["net-a"];
+        assert_eq!((head.block_id, head.block_count, head.archived_through), (5, 5, Some(3)));
+        assert!(bc.is_known_tenant_block(&blocks[0]));
+
+        let _ = fs::remove_dir_all(&archive);
+    }
+
+    #[test]
+    fn test_tenant_balances_follow_its_blocks() {
+        let archive = temp_dir("tenant_balances");
+        let mut bc = Blockchain::new();
+        bc.set_pruning_policy(PruningPolicy {
+            tenant_blocks_in_memory: 2,
+            archive_dir: archive.clone(),
+            ..PruningPolicy::default()
+        });
+
+        let mint = |id: u32, to: &str, amount: u64| {
+            let tx = Transaction {
+                id: format!("mint_{}", id),
+                from: "validator".to_string(),
+                to: to.to_string(),
+                amount,
+                tx_type: TransactionType::Mint { asset: "BTC".to_string() },
+                timestamp: id as u64,
+                public_key: None,
+                signature: None,
+            };
+            TenantBlockData {
+                network_id: "net-a".to_string(),
+                block_id: id,
+                block_hash: format!("{:064x}", id),
+                transactions: vec![serde_json::to_string(&tx).unwrap()],
+                timestamp: id as u64,
+                previous_hash: format!("{:064x}", id - 1),
+                merkle_root: None,
+                tenant_signature: None,
+            }
+        };
+        for block in [mint(1, "alice", 5), mint(2, "alice", 3), mint(3, "bob", 7)] {
+            bc.tenant_blocks.push(block);
+        }
+        assert_eq!(bc.tenant_ledger("net-a", 1).balance("alice", "BTC"), 5);
+        assert_eq!(bc.tenant_ledger("net-a", 3).balance("alice", "BTC"), 8);
+        assert_eq!(bc.tenant_ledger("net-b", 3).balance("alice", "BTC"), 0);
+
+        // Archived blocks still count, blocks a reorg dropped do not
+        bc.archive_tenant_blocks();
+        assert_eq!(bc.tenant_blocks.len(), 2);
+        bc.rewind_tenant_blocks("net-a", &TenantReorg {
+            fork_height: 2,
+            fork_hash: format!("{:064x}", 2),
+            removed: vec![format!("{:064x}", 3)],
+            signature: None,
+        });
+        let ledger = bc.tenant_ledger("net-a", 3);
+        assert_eq!((ledger.balance("alice", "BTC"), ledger.balance("bob", "BTC")), (8, 0));
+
+        let _ = fs::remove_dir_all(&archive);
+    }
+}
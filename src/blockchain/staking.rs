@@ -0,0 +1,430 @@
+// On-chain staking: bonds, delegations, unbonding and rewards
+//
+// What the staking transactions do, amounts in the native coin:
+//   Stake                       `from` bonds `amount` as its own validator stake
+//   Delegate { validator }      `from` bonds `amount` behind a validator that
+//                               has its own stake bonded
+//   Unbond { validator }        `from` unbonds `amount` of its bond behind the
+//                               validator; the coin comes back once the
+//                               unbonding period has passed
+//   ClaimRewards { validator }  pays out all of `from`'s rewards from that pool
+// Bonded coin, coin being unbonded and unclaimed rewards are held by the
+// STAKING_ACCOUNT ledger account. Fees paid to a validator with a pool (see
+// fees.rs) go to that pool and are shared by everyone bonded to it in
+// proportion to their bond; unbonding coin earns nothing.
+//
+// The validator set only follows the pools at epoch boundaries: when a block
+// at a multiple of the epoch length commits, every pool whose validator has
+// its own stake bonded becomes a validator with the pool total as its stake,
+// and everyone else leaves the set. The validators a chain starts with
+// (enterprise-validator --stake and peer discovery) are bonded at genesis,
+// see Blockchain::add_validator. Jailed validators stay out, see slashing.rs.
+
+use super::slashing::{self, SlashingConfig};
+use super::{Transaction, TransactionType, Validator};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const STAKING_ACCOUNT: &str = "staking";
+pub const DEFAULT_EPOCH_LENGTH: u32 = 10;
+pub const DEFAULT_UNBONDING_EPOCHS: u32 = 2;
+pub const TOMBSTONED: u32 = u32::MAX;
+
+// Fixed point of Pool::reward_per_unit
+const REWARD_SCALE: u128 = 1_000_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StakingConfig {
+    pub epoch_length: u32,     // blocks
+    pub unbonding_epochs: u32, // epochs before unbonded coin is released
+    pub slashing: SlashingConfig,
+}
+
+impl Default for StakingConfig {
+    fn default() -> Self {
+        Self {
+            epoch_length: DEFAULT_EPOCH_LENGTH,
+            unbonding_epochs: DEFAULT_UNBONDING_EPOCHS,
+            slashing: SlashingConfig::default(),
+        }
+    }
+}
+
+impl StakingConfig {
+    // STAKING_EPOCH_BLOCKS and UNBONDING_EPOCHS
+    #[cfg(feature = "native")]
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u32>().ok());
+        Self {
+            epoch_length: var("STAKING_EPOCH_BLOCKS").unwrap_or(defaults.epoch_length).max(1),
+            unbonding_epochs: var("UNBONDING_EPOCHS").unwrap_or(defaults.unbonding_epochs),
+            slashing: SlashingConfig::from_env(),
+        }
+    }
+
+    pub fn is_epoch_boundary(&self, height: u32) -> bool {
+        height > 0 && height.is_multiple_of(self.epoch_length.max(1))
+    }
+
+    // Height at which coin unbonded in the block at `height` is released
+    pub fn release_height(&self, height: u32) -> u32 {
+        height.saturating_add(self.epoch_length.max(1).saturating_mul(self.unbonding_epochs))
+    }
+
+    // Height until which a validator punished for downtime at `height` is jailed
+    pub fn jailed_until(&self, height: u32) -> u32 {
+        height.saturating_add(self.epoch_length.max(1).saturating_mul(self.slashing.jail_epochs))
+    }
+}
+
+// One delegator's bond in a pool
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bond {
+    pub amount: u64,
+    #[serde(default)]
+    pub rewards: u64, // settled, not yet claimed
+    #[serde(default)]
+    reward_debt: u128, // amount * reward_per_unit when last settled
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pool {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>, // the key the validator bonded with
+    pub bonded: u64,
+    #[serde(default)]
+    reward_per_unit: u128, // rewards per bonded coin so far, times REWARD_SCALE
+    pub bonds: BTreeMap<String, Bond>, // by delegator, the validator's own included
+    #[serde(default)]
+    pub jailed_until: u32, // TOMBSTONED for good
+    #[serde(default)]
+    pub slashed_at: u32, // height of the last punished offence
+}
+
+impl Pool {
+    pub fn is_jailed(&self, height: u32) -> bool {
+        self.jailed_until > height
+    }
+
+    pub fn self_bond(&self, validator: &str) -> u64 {
+        self.bonds.get(validator).map_or(0, |bond| bond.amount)
+    }
+
+    // Rewards of `delegator` including those not settled yet
+    pub fn rewards(&self, delegator: &str) -> u64 {
+        self.bonds.get(delegator).map_or(0, |bond| bond.rewards.saturating_add(self.unsettled(bond)))
+    }
+
+    fn unsettled(&self, bond: &Bond) -> u64 {
+        ((bond.amount as u128 * self.reward_per_unit).saturating_sub(bond.reward_debt) / REWARD_SCALE) as u64
+    }
+
+    // Settle the delegator's rewards, then change its bond
+    fn rebond(&mut self, delegator: &str, change: impl FnOnce(u64) -> u64) {
+        let reward_per_unit = self.reward_per_unit;
+        let bond = self.bonds.entry(delegator.to_string()).or_default();
+        let unsettled = ((bond.amount as u128 * reward_per_unit).saturating_sub(bond.reward_debt) / REWARD_SCALE) as u64;
+        bond.rewards = bond.rewards.saturating_add(unsettled);
+        let before = bond.amount;
+        bond.amount = change(before);
+        bond.reward_debt = bond.amount as u128 * reward_per_unit;
+        self.bonded = self.bonded - before + bond.amount;
+        if bond.amount == 0 && bond.rewards == 0 {
+            self.bonds.remove(delegator);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Unbonding {
+    pub delegator: String,
+    pub validator: String,
+    pub amount: u64,
+    pub release_height: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Staking {
+    #[serde(default)]
+    pools: BTreeMap<String, Pool>, // by validator
+    #[serde(default)]
+    unbonding: Vec<Unbonding>, // in release order
+    #[serde(default)]
+    set_since: u32, // height at which the validator set last changed
+}
+
+impl Staking {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty() && self.unbonding.is_empty()
+    }
+
+    pub fn pools(&self) -> &BTreeMap<String, Pool> {
+        &self.pools
+    }
+
+    pub fn pool(&self, validator: &str) -> Option<&Pool> {
+        self.pools.get(validator)
+    }
+
+    pub fn unbonding(&self) -> &[Unbonding] {
+        &self.unbonding
+    }
+
+    pub fn set_since(&self) -> u32 {
+        self.set_since
+    }
+
+    pub fn set_changed(&mut self, height: u32) {
+        self.set_since = height;
+    }
+
+    pub fn is_staking(tx: &Transaction) -> bool {
+        matches!(tx.tx_type,
+            TransactionType::Stake
+            | TransactionType::Delegate { .. }
+            | TransactionType::Unbond { .. }
+            | TransactionType::ClaimRewards { .. })
+    }
+
+    // Bond of a validator the chain starts with; its coin is issued to the
+    // staking account by the caller
+    pub fn bond_genesis(&mut self, validator: &str, public_key: Option<String>, amount: u64) {
+        let pool = self.pools.entry(validator.to_string()).or_default();
+        if pool.public_key.is_none() {
+            pool.public_key = public_key;
+        }
+        pool.rebond(validator, |bonded| bonded.saturating_add(amount));
+    }
+
+    // Apply a committed staking transaction of the block at `height`.
+    // Returns the coin to pay out of the staking account right away, i.e.
+    // claimed rewards; the ledger has already taken bonded coin from `from`.
+    pub fn apply(&mut self, tx: &Transaction, height: u32, config: &StakingConfig) -> Result<Option<(String, u64)>, String> {
+        let fail = |reason: String| Err(format!("transaction {}: {}", tx.id, reason));
+        match &tx.tx_type {
+            TransactionType::Stake => {
+                if tx.amount == 0 {
+                    return fail("nothing to stake".to_string());
+                }
+                let pool = self.pools.entry(tx.from.clone()).or_default();
+                if pool.public_key.is_none() {
+                    pool.public_key = tx.public_key.clone();
+                }
+                pool.rebond(&tx.from, |bonded| bonded.saturating_add(tx.amount));
+                Ok(None)
+            }
+            TransactionType::Delegate { validator } => {
+                let pool = match self.pools.get_mut(validator) {
+                    Some(pool) if pool.is_jailed(height) => return fail(format!("{} is jailed", validator)),
+                    Some(pool) if pool.self_bond(validator) > 0 => pool,
+                    _ => return fail(format!("{} has no stake of its own to delegate to", validator)),
+                };
+                if tx.amount == 0 {
+                    return fail("nothing to delegate".to_string());
+                }
+                pool.rebond(&tx.from, |bonded| bonded.saturating_add(tx.amount));
+                Ok(None)
+            }
+            TransactionType::Unbond { validator } => {
+                let bonded = self.pools.get(validator)
+                    .and_then(|pool| pool.bonds.get(&tx.from))
+                    .map_or(0, |bond| bond.amount);
+                if tx.amount == 0 || tx.amount > bonded {
+                    return fail(format!("{} has {} bonded with {}, cannot unbond {}", tx.from, bonded, validator, tx.amount));
+                }
+                if let Some(pool) = self.pools.get_mut(validator) {
+                    pool.rebond(&tx.from, |bonded| bonded - tx.amount);
+                }
+                self.unbonding.push(Unbonding {
+                    delegator: tx.from.clone(),
+                    validator: validator.clone(),
+                    amount: tx.amount,
+                    release_height: config.release_height(height),
+                });
+                Ok(None)
+            }
+            TransactionType::ClaimRewards { validator } => {
+                let pool = match self.pools.get_mut(validator) {
+                    Some(pool) if pool.rewards(&tx.from) > 0 => pool,
+                    _ => return fail(format!("{} has no rewards to claim from {}", tx.from, validator)),
+                };
+                pool.rebond(&tx.from, |bonded| bonded);
+                let rewards = pool.bonds.get_mut(&tx.from).map_or(0, |bond| std::mem::take(&mut bond.rewards));
+                if pool.bonds.get(&tx.from).is_some_and(|bond| bond.amount == 0) {
+                    pool.bonds.remove(&tx.from);
+                }
+                Ok(Some((tx.from.clone(), rewards)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Whether the staking transactions apply one after the other in the
+    // block at `height`, without changing anything
+    pub fn check<'a>(&self, txs: impl IntoIterator<Item = &'a Transaction>, height: u32, config: Option<&StakingConfig>) -> Result<(), String> {
+        let mut staged = self.clone();
+        for tx in txs.into_iter().filter(|tx| Self::is_staking(tx)) {
+            match config {
+                Some(config) => staged.apply(tx, height, config)?,
+                None => return Err(format!("transaction {}: staking is not enabled on this chain", tx.id)),
+            };
+        }
+        Ok(())
+    }
+
+    // Keep the transactions whose staking part still applies in order, e.g.
+    // pending claims after a block paid the rewards out; returns how many
+    // were dropped
+    pub fn retain_applicable(&self, txs: &mut Vec<Transaction>, height: u32, config: Option<&StakingConfig>) -> usize {
+        let before = txs.len();
+        let mut staged = self.clone();
+        txs.retain(|tx| {
+            if !Self::is_staking(tx) {
+                return true;
+            }
+            let mut attempt = staged.clone();
+            let fits = config.is_some_and(|config| attempt.apply(tx, height, config).is_ok());
+            if fits {
+                staged = attempt;
+            }
+            fits
+        });
+        before - txs.len()
+    }
+
+    // Split a block's fee payouts into what goes straight to validators and
+    // what goes to their pools, crediting the pools. Returns the direct
+    // payouts and the total for the staking account.
+    pub fn distribute(&mut self, paid: &BTreeMap<String, u64>) -> (BTreeMap<String, u64>, u64) {
+        let mut direct = BTreeMap::new();
+        let mut pooled = 0u64;
+        for (validator, fee) in paid {
+            match self.pools.get_mut(validator) {
+                Some(pool) if pool.bonded > 0 => {
+                    // What does not divide evenly stays in the staking account
+                    pool.reward_per_unit += *fee as u128 * REWARD_SCALE / pool.bonded as u128;
+                    pooled = pooled.saturating_add(*fee);
+                }
+                _ => {
+                    direct.insert(validator.clone(), *fee);
+                }
+            }
+        }
+        (direct, pooled)
+    }
+
+    // Take `bps` of every bond with the validator and of the coin still
+    // unbonding from it, and jail it until `jailed_until`. Returns the coin
+    // taken, for the caller to burn from the staking account.
+    pub fn slash(&mut self, validator: &str, bps: u64, height: u32, jailed_until: u32) -> u64 {
+        let mut slashed = 0u64;
+        if let Some(pool) = self.pools.get_mut(validator) {
+            let delegators: Vec<String> = pool.bonds.keys().cloned().collect();
+            for delegator in delegators {
+                pool.rebond(&delegator, |bonded| {
+                    let taken = slashing::share(bonded, bps);
+                    slashed += taken;
+                    bonded - taken
+                });
+            }
+            pool.jailed_until = pool.jailed_until.max(jailed_until);
+            pool.slashed_at = height;
+        }
+        for unbonding in self.unbonding.iter_mut().filter(|unbonding| unbonding.validator == validator) {
+            let taken = slashing::share(unbonding.amount, bps);
+            unbonding.amount -= taken;
+            slashed += taken;
+        }
+        slashed
+    }
+
+    // Unbondings due at `height`, removed from the queue
+    pub fn release(&mut self, height: u32) -> Vec<Unbonding> {
+        let (due, waiting) = std::mem::take(&mut self.unbonding).into_iter()
+            .partition(|unbonding| unbonding.release_height <= height);
+        self.unbonding = waiting;
+        due
+    }
+
+    // The validator set the pools make for the epoch after `height`
+    pub fn validators(&self, height: u32) -> Vec<Validator> {
+        self.pools.iter()
+            .filter(|(address, pool)| pool.self_bond(address) > 0 && !pool.is_jailed(height))
+            .map(|(address, pool)| Validator {
+                address: address.clone(),
+                stake: pool.bonded,
+                active: true,
+                public_key: pool.public_key.clone(),
+            })
+            .collect()
+    }
+
+    // Bonds of one account by validator, with rewards up to date
+    pub fn bonds_of(&self, delegator: &str) -> BTreeMap<String, Bond> {
+        self.pools.iter()
+            .filter_map(|(validator, pool)| {
+                let bond = pool.bonds.get(delegator)?;
+                Some((validator.clone(), Bond { rewards: pool.rewards(delegator), ..bond.clone() }))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(id: &str, from: &str, amount: u64, tx_type: TransactionType) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            from: from.to_string(),
+            to: STAKING_ACCOUNT.to_string(),
+            amount,
+            tx_type,
+            timestamp: 1,
+            public_key: None,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_bonds_delegations_rewards_and_unbonding() {
+        let config = StakingConfig { epoch_length: 5, unbonding_epochs: 2, ..Default::default() };
+        let delegate = |validator: &str| TransactionType::Delegate { validator: validator.to_string() };
+        let mut staking = Staking::new();
+
+        // Nobody to delegate to before v1 bonds
+        assert!(staking.check([&tx("d0", "dave", 100, delegate("v1"))], 1, Some(&config)).is_err());
+        assert!(staking.check([&tx("s0", "v1", 100, TransactionType::Stake)], 1, None).is_err());
+        staking.apply(&tx("s1", "v1", 100, TransactionType::Stake), 1, &config).unwrap();
+        staking.apply(&tx("d1", "dave", 300, delegate("v1")), 1, &config).unwrap();
+        assert_eq!(staking.validators(1).iter().map(|v| (v.address.as_str(), v.stake)).collect::<Vec<_>>(), vec![("v1", 400)]);
+
+        // 40 coin of fees shared 1:3, v2 has no pool and is paid directly
+        let (direct, pooled) = staking.distribute(&BTreeMap::from([("v1".to_string(), 40), ("v2".to_string(), 7)]));
+        assert_eq!((direct, pooled), (BTreeMap::from([("v2".to_string(), 7)]), 40));
+        assert_eq!((staking.pool("v1").unwrap().rewards("v1"), staking.pool("v1").unwrap().rewards("dave")), (10, 30));
+
+        // Unbonding stops earning and is released two epochs later
+        staking.apply(&tx("u1", "dave", 200, TransactionType::Unbond { validator: "v1".to_string() }), 3, &config).unwrap();
+        assert!(staking.apply(&tx("u2", "dave", 101, TransactionType::Unbond { validator: "v1".to_string() }), 3, &config).is_err());
+        staking.distribute(&BTreeMap::from([("v1".to_string(), 20)]));
+        assert_eq!(staking.bonds_of("dave")["v1"].rewards, 40);
+        let claim = tx("c1", "dave", 0, TransactionType::ClaimRewards { validator: "v1".to_string() });
+        assert_eq!(staking.apply(&claim, 4, &config).unwrap(), Some(("dave".to_string(), 40)));
+        assert!(staking.apply(&claim, 4, &config).is_err());
+
+        assert!(staking.release(12).is_empty());
+        assert_eq!(staking.release(13).iter().map(|u| (u.delegator.as_str(), u.amount)).collect::<Vec<_>>(), vec![("dave", 200)]);
+
+        // A validator that unbonds all of its own stake leaves the set
+        staking.apply(&tx("u3", "v1", 100, TransactionType::Unbond { validator: "v1".to_string() }), 14, &config).unwrap();
+        assert!(staking.validators(14).is_empty());
+        assert!(config.is_epoch_boundary(15) && !config.is_epoch_boundary(0));
+    }
+}
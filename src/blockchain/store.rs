@@ -0,0 +1,607 @@
+// Chain storage backends (native only)
+//
+// BlockLogStore keeps blocks in append-only segment files with a fixed-size
+// index, so persisting a new block costs one append instead of rewriting the
+// whole chain. Non-block state (mempool, validators, contracts, ...) is small
+// and is replaced atomically on every save. JsonFileStore keeps the original
+// single-file JSON layout for existing deployments and tools.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+pub trait StoredBlock: Serialize + DeserializeOwned {
+    fn block_hash(&self) -> &str;
+    fn block_height(&self) -> u64;
+}
+
+impl StoredBlock for super::Block {
+    fn block_hash(&self) -> &str {
+        &self.hash
+    }
+
+    fn block_height(&self) -> u64 {
+        self.height as u64
+    }
+}
+
+impl StoredBlock for crate::common::blockchain::Block {
+    fn block_hash(&self) -> &str {
+        &self.hash
+    }
+
+    fn block_height(&self) -> u64 {
+        self.id
+    }
+}
+
+pub trait ChainStore<B: StoredBlock>: Send + Sync {
+    // Ok(None) when nothing has been stored yet
+    fn load(&mut self) -> io::Result<Option<(Vec<B>, serde_json::Value)>>;
+
+    // Make `chain` and `state` durable. Blocks already stored with the same
+    // hash are left alone; a diverging tail is replaced. `chain` may start
+    // above the first stored height once old blocks are pruned from memory.
+    fn persist(&mut self, chain: &[B], state: &serde_json::Value) -> io::Result<()>;
+
+    // Ids of the transactions below the first stored block, which only a
+    // chain started from a snapshot has. The ids of stored blocks are read
+    // back from the blocks, and these never change, so they are written once
+    // instead of with the state.
+    fn load_base_tx_ids(&mut self) -> io::Result<BTreeSet<String>>;
+    fn persist_base_tx_ids(&mut self, ids: &BTreeSet<String>) -> io::Result<()>;
+
+    // Whether blocks dropped from memory stay on disk. Only then is it safe
+    // to prune the in-memory chain.
+    fn keeps_pruned_blocks(&self) -> bool {
+        false
+    }
+
+    fn describe(&self) -> String;
+}
+
+// "*.json" keeps the legacy single-file layout, anything else is a block log directory
+pub fn open_store<B: StoredBlock>(path: &str) -> Box<dyn ChainStore<B>> {
+    if path.ends_with(".json") {
+        Box::new(JsonFileStore::new(path))
+    } else {
+        Box::new(BlockLogStore::new(path))
+    }
+}
+
+// Write to a temp file, fsync, then rename over the target so readers and
+// crashes only ever see the old or the new contents
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    fs::create_dir_all(&dir)?;
+
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("data");
+    let tmp = dir.join(format!(".{}.tmp", file_name));
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    sync_dir(&dir)
+}
+
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    // Directory fsync makes the rename durable; not supported everywhere
+    match File::open(dir) {
+        Ok(handle) => {
+            let _ = handle.sync_all();
+            Ok(())
+        }
+        Err(_) => Ok(()),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_tx_ids(path: &Path) -> io::Result<BTreeSet<String>> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| invalid_data(format!("{} is not a list of ids: {}", path.display(), e))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_tx_ids(path: &Path, ids: &BTreeSet<String>) -> io::Result<()> {
+    let json = serde_json::to_vec(ids).map_err(|e| invalid_data(e.to_string()))?;
+    write_atomic(path, &json)
+}
+
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn base_tx_ids_path(&self) -> PathBuf {
+        self.path.with_extension("base_tx_ids.json")
+    }
+}
+
+impl<B: StoredBlock> ChainStore<B> for JsonFileStore {
+    fn load(&mut self) -> io::Result<Option<(Vec<B>, serde_json::Value)>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&self.path)?;
+        let state: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| invalid_data(format!("{} is not valid JSON: {}", self.path.display(), e)))?;
+        let chain = serde_json::from_value(state["chain"].clone())
+            .map_err(|e| invalid_data(format!("{} has no readable chain: {}", self.path.display(), e)))?;
+        Ok(Some((chain, state)))
+    }
+
+    fn persist(&mut self, chain: &[B], state: &serde_json::Value) -> io::Result<()> {
+        let mut data = state.clone();
+        data["chain"] = serde_json::to_value(chain).map_err(|e| invalid_data(e.to_string()))?;
+        let json = serde_json::to_string_pretty(&data).map_err(|e| invalid_data(e.to_string()))?;
+        write_atomic(&self.path, json.as_bytes())
+    }
+
+    fn load_base_tx_ids(&mut self) -> io::Result<BTreeSet<String>> {
+        read_tx_ids(&self.base_tx_ids_path())
+    }
+
+    fn persist_base_tx_ids(&mut self, ids: &BTreeSet<String>) -> io::Result<()> {
+        write_tx_ids(&self.base_tx_ids_path(), ids)
+    }
+
+    fn describe(&self) -> String {
+        format!("json file {}", self.path.display())
+    }
+}
+
+const INDEX_ENTRY_SIZE: usize = 48;
+const DEFAULT_SEGMENT_BLOCKS: usize = 10_000;
+
+// One fixed-size index record per block: where it lives and a digest of its hash
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IndexEntry {
+    segment: u32,
+    offset: u64,
+    len: u32, // including the trailing newline
+    hash_digest: [u8; 32],
+}
+
+impl IndexEntry {
+    fn to_bytes(self) -> [u8; INDEX_ENTRY_SIZE] {
+        let mut bytes = [0u8; INDEX_ENTRY_SIZE];
+        bytes[0..4].copy_from_slice(&self.segment.to_be_bytes());
+        bytes[4..12].copy_from_slice(&self.offset.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.len.to_be_bytes());
+        bytes[16..48].copy_from_slice(&self.hash_digest);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut segment = [0u8; 4];
+        let mut offset = [0u8; 8];
+        let mut len = [0u8; 4];
+        let mut hash_digest = [0u8; 32];
+        segment.copy_from_slice(&bytes[0..4]);
+        offset.copy_from_slice(&bytes[4..12]);
+        len.copy_from_slice(&bytes[12..16]);
+        hash_digest.copy_from_slice(&bytes[16..48]);
+        IndexEntry {
+            segment: u32::from_be_bytes(segment),
+            offset: u64::from_be_bytes(offset),
+            len: u32::from_be_bytes(len),
+            hash_digest,
+        }
+    }
+}
+
+fn hash_digest(hash: &str) -> [u8; 32] {
+    Sha256::digest(hash.as_bytes()).into()
+}
+
+// Directory layout:
+//   blocks-000000.log, blocks-000001.log, ...  one JSON block per line
+//   blocks.idx                                  IndexEntry per block, in height order
+//   base_height                                 height of the first block (0 unless
+//                                               the log was started from a snapshot)
+//   base_tx_ids.json                            ids committed below the first block
+//   state.json                                  everything that is not a block
+pub struct BlockLogStore {
+    dir: PathBuf,
+    segment_blocks: usize,
+    index: Vec<IndexEntry>,
+    base_height: u64,
+    opened: bool,
+}
+
+impl BlockLogStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::with_segment_size(dir, DEFAULT_SEGMENT_BLOCKS)
+    }
+
+    pub fn with_segment_size(dir: impl Into<PathBuf>, segment_blocks: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            segment_blocks: segment_blocks.max(1),
+            index: Vec::new(),
+            base_height: 0,
+            opened: false,
+        }
+    }
+
+    fn segment_path(&self, segment: u32) -> PathBuf {
+        self.dir.join(format!("blocks-{:06}.log", segment))
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join("blocks.idx")
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.dir.join("state.json")
+    }
+
+    fn base_height_path(&self) -> PathBuf {
+        self.dir.join("base_height")
+    }
+
+    fn base_tx_ids_path(&self) -> PathBuf {
+        self.dir.join("base_tx_ids.json")
+    }
+
+    fn segment_len(&self, segment: u32) -> u64 {
+        fs::metadata(self.segment_path(segment)).map(|m| m.len()).unwrap_or(0)
+    }
+
+    // Load the index and repair whatever a crash mid-append left behind: index
+    // records pointing past the end of a segment are dropped, complete blocks
+    // written after the last index record are re-indexed, and torn tails are cut.
+    fn open(&mut self) -> io::Result<()> {
+        if self.opened {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+
+        let mut raw = Vec::new();
+        if let Ok(mut file) = File::open(self.index_path()) {
+            file.read_to_end(&mut raw)?;
+        }
+        let mut index: Vec<IndexEntry> = raw.chunks_exact(INDEX_ENTRY_SIZE).map(IndexEntry::from_bytes).collect();
+        while let Some(last) = index.last() {
+            if last.offset + last.len as u64 <= self.segment_len(last.segment) {
+                break;
+            }
+            index.pop();
+        }
+        self.index = index;
+        self.base_height = match fs::read_to_string(self.base_height_path()) {
+            Ok(content) => content.trim().parse()
+                .map_err(|_| invalid_data(format!("{} is not a height", self.base_height_path().display())))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+
+        self.recover_unindexed()?;
+        self.rewrite_tail()?;
+        self.opened = true;
+        Ok(())
+    }
+
+    fn recover_unindexed(&mut self) -> io::Result<()> {
+        let (segment, mut offset) = match self.index.last() {
+            Some(last) => (last.segment, last.offset + last.len as u64),
+            None => (0, 0),
+        };
+        let mut data = Vec::new();
+        match File::open(self.segment_path(segment)) {
+            Ok(mut file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_to_end(&mut data)?;
+            }
+            Err(_) => return Ok(()),
+        }
+
+        let mut rest = &data[..];
+        while let Some(newline) = rest.iter().position(|b| *b == b'\n') {
+            let line = &rest[..newline];
+            let hash = match serde_json::from_slice::<serde_json::Value>(line) {
+                Ok(block) => block["hash"].as_str().unwrap_or_default().to_string(),
+                Err(_) => break,
+            };
+            let len = newline as u32 + 1;
+            self.index.push(IndexEntry { segment, offset, len, hash_digest: hash_digest(&hash) });
+            offset += len as u64;
+            rest = &rest[newline + 1..];
+        }
+        Ok(())
+    }
+
+    // Truncate the segment and index files to exactly what self.index covers
+    fn rewrite_tail(&mut self) -> io::Result<()> {
+        let (last_segment, end) = match self.index.last() {
+            Some(last) => (last.segment, last.offset + last.len as u64),
+            None => (0, 0),
+        };
+
+        if self.segment_path(last_segment).exists() {
+            let file = OpenOptions::new().write(true).open(self.segment_path(last_segment))?;
+            file.set_len(end)?;
+            file.sync_all()?;
+        }
+        let mut segment = last_segment + 1;
+        while self.segment_path(segment).exists() {
+            fs::remove_file(self.segment_path(segment))?;
+            segment += 1;
+        }
+
+        let index_file = OpenOptions::new().create(true).write(true).truncate(false).open(self.index_path())?;
+        index_file.set_len((self.index.len() * INDEX_ENTRY_SIZE) as u64)?;
+        index_file.sync_all()?;
+        sync_dir(&self.dir)
+    }
+
+    fn read_entry<B: StoredBlock>(&self, entry: &IndexEntry) -> io::Result<B> {
+        let mut file = File::open(self.segment_path(entry.segment))?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut line = vec![0u8; entry.len as usize];
+        file.read_exact(&mut line)?;
+        serde_json::from_slice(&line[..line.len() - 1])
+            .map_err(|e| invalid_data(format!("corrupt block record in segment {}: {}", entry.segment, e)))
+    }
+
+    // Random access by height without loading the chain
+    pub fn read_block<B: StoredBlock>(&mut self, height: u64) -> io::Result<Option<B>> {
+        self.open()?;
+        if height < self.base_height {
+            return Ok(None);
+        }
+        match self.index.get((height - self.base_height) as usize) {
+            Some(entry) => self.read_entry(entry).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn base_height(&mut self) -> io::Result<u64> {
+        self.open()?;
+        Ok(self.base_height)
+    }
+
+    pub fn block_count(&mut self) -> io::Result<usize> {
+        self.open()?;
+        Ok(self.index.len())
+    }
+
+    fn append<B: StoredBlock>(&mut self, blocks: &[B]) -> io::Result<()> {
+        if blocks.is_empty() {
+            return Ok(());
+        }
+
+        let (mut segment, mut offset, mut in_segment) = match self.index.last() {
+            Some(last) => (
+                last.segment,
+                last.offset + last.len as u64,
+                self.index.iter().rev().take_while(|e| e.segment == last.segment).count(),
+            ),
+            None => (0, 0, 0),
+        };
+
+        let mut index_bytes = Vec::with_capacity(blocks.len() * INDEX_ENTRY_SIZE);
+        let mut file = OpenOptions::new().create(true).append(true).open(self.segment_path(segment))?;
+        for block in blocks {
+            if in_segment >= self.segment_blocks {
+                file.sync_data()?;
+                segment += 1;
+                offset = 0;
+                in_segment = 0;
+                file = OpenOptions::new().create(true).append(true).open(self.segment_path(segment))?;
+            }
+
+            let mut line = serde_json::to_vec(block).map_err(|e| invalid_data(e.to_string()))?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+
+            let entry = IndexEntry { segment, offset, len: line.len() as u32, hash_digest: hash_digest(block.block_hash()) };
+            index_bytes.extend_from_slice(&entry.to_bytes());
+            self.index.push(entry);
+            offset += line.len() as u64;
+            in_segment += 1;
+        }
+        file.sync_data()?;
+
+        // Blocks are durable before the index points at them
+        let mut index_file = OpenOptions::new().create(true).append(true).open(self.index_path())?;
+        index_file.write_all(&index_bytes)?;
+        index_file.sync_data()
+    }
+}
+
+impl<B: StoredBlock> ChainStore<B> for BlockLogStore {
+    fn load(&mut self) -> io::Result<Option<(Vec<B>, serde_json::Value)>> {
+        self.open()?;
+        if self.index.is_empty() {
+            return Ok(None);
+        }
+
+        let mut chain = Vec::with_capacity(self.index.len());
+        for entry in &self.index {
+            chain.push(self.read_entry(entry)?);
+        }
+        let state = match fs::read_to_string(self.state_path()) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| invalid_data(format!("{} is not valid JSON: {}", self.state_path().display(), e)))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => serde_json::Value::Null,
+            Err(e) => return Err(e),
+        };
+        Ok(Some((chain, state)))
+    }
+
+    fn persist(&mut self, chain: &[B], state: &serde_json::Value) -> io::Result<()> {
+        self.open()?;
+
+        if let Some(first) = chain.first() {
+            if self.index.is_empty() && first.block_height() != self.base_height {
+                self.base_height = first.block_height();
+                write_atomic(&self.base_height_path(), self.base_height.to_string().as_bytes())?;
+            }
+            // Blocks below the log's base were never stored here; skip them
+            let skip = self.base_height.saturating_sub(first.block_height()) as usize;
+            let chain = &chain[skip.min(chain.len())..];
+            // Log position of chain[0]; above zero once memory has been pruned
+            let start = (first.block_height() + skip as u64 - self.base_height) as usize;
+            if start > self.index.len() {
+                return Err(invalid_data(format!(
+                    "chain starts at height {} but {} only reaches {}",
+                    first.block_height(),
+                    self.dir.display(),
+                    self.base_height + self.index.len() as u64
+                )));
+            }
+
+            // Longest stored prefix that still matches; normally the whole index
+            let mut common = self.index.len().min(start + chain.len());
+            while common > start && self.index[common - 1].hash_digest != hash_digest(chain[common - 1 - start].block_hash()) {
+                common -= 1;
+            }
+            if common < self.index.len() {
+                self.index.truncate(common);
+                self.rewrite_tail()?;
+            }
+            self.append(&chain[common - start..])?;
+        }
+
+        let json = serde_json::to_vec(state).map_err(|e| invalid_data(e.to_string()))?;
+        write_atomic(&self.state_path(), &json)
+    }
+
+    fn load_base_tx_ids(&mut self) -> io::Result<BTreeSet<String>> {
+        read_tx_ids(&self.base_tx_ids_path())
+    }
+
+    fn persist_base_tx_ids(&mut self, ids: &BTreeSet<String>) -> io::Result<()> {
+        write_tx_ids(&self.base_tx_ids_path(), ids)
+    }
+
+    fn keeps_pruned_blocks(&self) -> bool {
+        true
+    }
+
+    fn describe(&self) -> String {
+        format!("block log {}", self.dir.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("distli-store-{}-{}", name, uuid::Uuid::new_v4()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn chain_of(blocks: usize) -> Vec<super::super::Block> {
+        let mut bc = Blockchain::new();
+        bc.add_validator("alice".to_string(), 100);
+        for i in 0..blocks {
+            bc.add_message(format!("message {}", i), "alice".to_string());
+            let block = bc.create_block_proposal("alice", 0).unwrap();
+            assert!(bc.add_block(block));
+        }
+        bc.get_blocks().clone()
+    }
+
+    #[test]
+    fn test_block_log_round_trip_across_segments() {
+        let dir = temp_dir("roundtrip");
+        let chain = chain_of(7);
+        let state = serde_json::json!({"pending": []});
+
+        let mut store = BlockLogStore::with_segment_size(&dir, 3);
+        ChainStore::persist(&mut store, &chain[..5], &state).unwrap();
+        ChainStore::persist(&mut store, &chain, &state).unwrap();
+        assert!(dir.join("blocks-000002.log").exists());
+
+        let mut reopened = BlockLogStore::with_segment_size(&dir, 3);
+        let (loaded, loaded_state) = ChainStore::<super::super::Block>::load(&mut reopened).unwrap().unwrap();
+        let hashes: Vec<&str> = loaded.iter().map(|b| b.hash.as_str()).collect();
+        let expected: Vec<&str> = chain.iter().map(|b| b.hash.as_str()).collect();
+        assert_eq!(hashes, expected);
+        assert_eq!(loaded_state, state);
+        assert_eq!(reopened.read_block::<super::super::Block>(4).unwrap().unwrap().hash, chain[4].hash);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_torn_append_is_repaired_on_open() {
+        let dir = temp_dir("torn");
+        let chain = chain_of(3);
+        let mut store = BlockLogStore::new(&dir);
+        ChainStore::persist(&mut store, &chain, &serde_json::Value::Null).unwrap();
+
+        // Half-written block after the last indexed one, and a torn index record
+        let mut segment = OpenOptions::new().append(true).open(dir.join("blocks-000000.log")).unwrap();
+        segment.write_all(b"{\"height\": 4, \"hash\": \"trunc").unwrap();
+        let mut index = OpenOptions::new().append(true).open(dir.join("blocks.idx")).unwrap();
+        index.write_all(&[1, 2, 3]).unwrap();
+
+        let mut reopened = BlockLogStore::new(&dir);
+        let (loaded, _) = ChainStore::<super::super::Block>::load(&mut reopened).unwrap().unwrap();
+        assert_eq!(loaded.len(), chain.len());
+
+        // And appending continues cleanly after the repair
+        let longer = chain_of(4);
+        ChainStore::persist(&mut reopened, &longer, &serde_json::Value::Null).unwrap();
+        let (loaded, _) = ChainStore::<super::super::Block>::load(&mut BlockLogStore::new(&dir)).unwrap().unwrap();
+        assert_eq!(loaded.last().unwrap().hash, longer.last().unwrap().hash);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_unreadable_chain_is_not_saved_over() {
+        let dir = temp_dir("unreadable");
+        let chain = chain_of(5);
+        ChainStore::persist(&mut BlockLogStore::new(&dir), &chain, &serde_json::Value::Null).unwrap();
+        fs::write(dir.join("state.json"), b"{not json").unwrap();
+
+        let mut reopened = Blockchain::new_with_store(Box::new(BlockLogStore::new(&dir)));
+        assert!(reopened.storage_error().is_some());
+        assert!(reopened.save_to_disk().is_err());
+        assert_eq!(BlockLogStore::new(&dir).block_count().unwrap(), chain.len());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_diverging_tail_is_replaced() {
+        let dir = temp_dir("fork");
+        let ours = chain_of(4);
+        let mut store = BlockLogStore::new(&dir);
+        ChainStore::persist(&mut store, &ours, &serde_json::Value::Null).unwrap();
+
+        let mut theirs = ours[..2].to_vec();
+        let mut replacement = ours[2].clone();
+        replacement.hash = "f".repeat(64);
+        theirs.push(replacement);
+        ChainStore::persist(&mut store, &theirs, &serde_json::Value::Null).unwrap();
+
+        let (loaded, _) = ChainStore::<super::super::Block>::load(&mut BlockLogStore::new(&dir)).unwrap().unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded[2].hash, "f".repeat(64));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
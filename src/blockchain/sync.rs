@@ -0,0 +1,384 @@
+// Headers-first catch-up for a node joining a network late
+//
+// The messages go through the tracker, which relays them to `target` in the
+// same network and replaces `target` with the sender, as for WebRTC offers:
+//   get_headers  {target, from_height, count}   -> headers {target, tip_height, headers}
+//   get_blocks   {target, from_height, count}   -> blocks  {target, blocks}
+// Headers are blocks without their transactions (see Block::verify_header).
+// At most MAX_SYNC_BATCH of either go in one message.
+//
+// BlockSync asks one peer for headers from our height on and checks that
+// each is signed and links to the one before, the first to a block we have.
+// If it does not, our chain has blocks the peer's does not, and we ask from
+// further back to find where they part. Once it has the headers up to the
+// peer's tip (or MAX_QUEUED_HEADERS of them) it fetches the bodies, checks
+// each against its header and adds it like any peer block, so a branch we
+// were on is replaced through the fork choice (see forks.rs).
+
+use super::{Block, Blockchain};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+pub const MAX_SYNC_BATCH: u32 = 50;
+const MAX_QUEUED_HEADERS: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SyncMessage {
+    #[serde(rename = "get_headers")]
+    GetHeaders { target: String, from_height: u32, count: u32 },
+
+    #[serde(rename = "headers")]
+    Headers { target: String, tip_height: u32, headers: Vec<Block> },
+
+    #[serde(rename = "get_blocks")]
+    GetBlocks { target: String, from_height: u32, count: u32 },
+
+    #[serde(rename = "blocks")]
+    Blocks { target: String, blocks: Vec<Block> },
+}
+
+impl Blockchain {
+    // Blocks from `from_height` on, at most `count` and MAX_SYNC_BATCH
+    pub fn blocks_from(&self, from_height: u32, count: u32) -> Vec<Block> {
+        (from_height..from_height.saturating_add(count.min(MAX_SYNC_BATCH)))
+            .map_while(|height| self.block_at(height).cloned())
+            .collect()
+    }
+
+    // The answer to a peer's request; `target` is the peer, as the tracker
+    // relays it
+    pub fn answer_sync(&self, request: &SyncMessage) -> Option<SyncMessage> {
+        match request {
+            SyncMessage::GetHeaders { target, from_height, count } => Some(SyncMessage::Headers {
+                target: target.clone(),
+                tip_height: self.height(),
+                headers: self.blocks_from(*from_height, *count).into_iter()
+                    .map(|block| Block { transactions: Vec::new(), ..block })
+                    .collect(),
+            }),
+            SyncMessage::GetBlocks { target, from_height, count } => Some(SyncMessage::Blocks {
+                target: target.clone(),
+                blocks: self.blocks_from(*from_height, *count),
+            }),
+            _ => None,
+        }
+    }
+
+    // Validators of a network are learned from their blocks, pinned to the
+    // key that signed the first one. Their stake is whatever that first block
+    // claims: the peer supplies it and nothing here can check it, so it only
+    // weighs in the fork choice among the network's own blocks, and later
+    // blocks must claim the same. A stake that does not fit is refused.
+    pub fn learn_validator(&mut self, block: &Block) -> Result<(), String> {
+        if self.has_validator(&block.validator) {
+            return Ok(());
+        }
+        let stake = u32::try_from(block.stake_weight)
+            .map_err(|_| format!("block {} claims a stake of {} for {}", block.height, block.stake_weight, block.validator))?;
+        match &block.validator_key {
+            Some(key) => {
+                self.add_validator_with_key(block.validator.clone(), stake, key.clone());
+            }
+            None => self.add_validator(block.validator.clone(), stake),
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl Blockchain {
+    // answer_sync for JSON messages; empty if there is nothing to send back
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn answer_sync_json(&self, request_json: String) -> String {
+        serde_json::from_str::<SyncMessage>(&request_json).ok()
+            .and_then(|request| self.answer_sync(&request))
+            .and_then(|answer| serde_json::to_string(&answer).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Default)]
+pub struct BlockSync {
+    peer: String,
+    from_height: u32, // of the next headers request
+    peer_tip: u32,
+    headers: VecDeque<Block>, // checked, bodies still to fetch
+    applied: Vec<Block>,      // until taken
+    done: bool,
+    error: Option<String>,
+}
+
+impl BlockSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(&mut self, bc: &Blockchain, peer: &str) -> SyncMessage {
+        *self = Self { peer: peer.to_string(), from_height: bc.height() + 1, ..Self::default() };
+        self.get_headers()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    // Blocks added to the chain since the last call
+    pub fn take_applied(&mut self) -> Vec<Block> {
+        std::mem::take(&mut self.applied)
+    }
+
+    // The next request for the peer, None when there is none: caught up, or
+    // failed (see error). Messages from other peers are ignored.
+    pub fn handle(&mut self, bc: &mut Blockchain, message: SyncMessage) -> Option<SyncMessage> {
+        let result = match message {
+            SyncMessage::Headers { target, tip_height, headers } if target == self.peer && !self.done => {
+                self.on_headers(bc, tip_height, headers)
+            }
+            SyncMessage::Blocks { target, blocks } if target == self.peer && !self.done => self.on_blocks(bc, blocks),
+            _ => return None,
+        };
+        match result {
+            Ok(Some(next)) => Some(next),
+            Ok(None) => {
+                self.done = true;
+                bc.set_last_sync_block(bc.height());
+                println!("Caught up with {} at block {}", self.peer, bc.height());
+                None
+            }
+            Err(e) => {
+                println!("Sync with {} failed: {}", self.peer, e);
+                self.error = Some(e);
+                self.done = true;
+                None
+            }
+        }
+    }
+
+    fn on_headers(&mut self, bc: &Blockchain, tip_height: u32, headers: Vec<Block>) -> Result<Option<SyncMessage>, String> {
+        if headers.len() > MAX_SYNC_BATCH as usize {
+            return Err(format!("{} headers in one batch", headers.len()));
+        }
+        self.peer_tip = tip_height;
+        let first = match headers.first() {
+            Some(first) => first,
+            None => {
+                // Nothing from there on, whatever tip it claims
+                self.peer_tip = self.peer_tip.min(self.from_height - 1);
+                return Ok(self.next_request());
+            }
+        };
+
+        // The first header links to the last queued one or to our chain
+        let anchor = self.headers.back().or_else(|| first.height.checked_sub(1).and_then(|h| bc.block_at(h)));
+        let linked = anchor.is_some_and(|anchor| anchor.height + 1 == first.height && anchor.hash == first.previous_hash);
+        if !linked {
+            let base = bc.get_blocks().first().map_or(0, |b| b.height);
+            if !self.headers.is_empty() || first.height != self.from_height || self.from_height <= base + 1 {
+                return Err(format!("header {} does not link to our chain", first.height));
+            }
+            // The peer's chain and ours part further back
+            self.from_height = self.from_height.saturating_sub(MAX_SYNC_BATCH).max(base + 1);
+            return Ok(Some(self.get_headers()));
+        }
+
+        for (i, header) in headers.iter().enumerate() {
+            header.verify_header()?;
+            if !header.transactions.is_empty() {
+                return Err(format!("header {} carries transactions", header.height));
+            }
+            if i > 0 && (header.height != headers[i - 1].height + 1 || header.previous_hash != headers[i - 1].hash) {
+                return Err(format!("header {} does not link to the one before", header.height));
+            }
+        }
+        self.from_height = headers[headers.len() - 1].height + 1;
+        // After stepping back, the first headers can be blocks we have
+        let known = headers.iter()
+            .take_while(|header| self.headers.is_empty() && bc.block_at(header.height).is_some_and(|b| b.hash == header.hash))
+            .count();
+        self.headers.extend(headers.into_iter().skip(known));
+        Ok(self.next_request())
+    }
+
+    fn on_blocks(&mut self, bc: &mut Blockchain, blocks: Vec<Block>) -> Result<Option<SyncMessage>, String> {
+        if blocks.is_empty() || blocks.len() > MAX_SYNC_BATCH as usize {
+            return Err(format!("{} blocks in one batch", blocks.len()));
+        }
+        for block in blocks {
+            let header = self.headers.pop_front().ok_or("blocks that were not asked for")?;
+            if block.hash != header.hash {
+                return Err(format!("block {} does not match its header", block.height));
+            }
+            block.verify_contents()?;
+            bc.learn_validator(&block)?;
+            if !bc.add_peer_block(block.clone()) {
+                return Err(format!("block {} was rejected", block.height));
+            }
+            self.applied.push(block);
+        }
+        Ok(self.next_request())
+    }
+
+    // More headers until the peer's tip or a full queue, then their bodies
+    fn next_request(&self) -> Option<SyncMessage> {
+        if self.from_height <= self.peer_tip && self.headers.len() < MAX_QUEUED_HEADERS {
+            return Some(self.get_headers());
+        }
+        let first = self.headers.front()?;
+        Some(SyncMessage::GetBlocks {
+            target: self.peer.clone(),
+            from_height: first.height,
+            count: (self.headers.len() as u32).min(MAX_SYNC_BATCH),
+        })
+    }
+
+    fn get_headers(&self) -> SyncMessage {
+        SyncMessage::GetHeaders { target: self.peer.clone(), from_height: self.from_height, count: MAX_SYNC_BATCH }
+    }
+}
+
+// The same for the page, with messages as JSON
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl BlockSync {
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
+    pub fn new_sync() -> Self {
+        Self::default()
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn start_json(&mut self, bc: &Blockchain, peer: String) -> String {
+        serde_json::to_string(&self.start(bc, &peer)).unwrap_or_default()
+    }
+
+    // The next request as JSON, empty if there is none
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn handle_json(&mut self, bc: &mut Blockchain, message_json: String) -> String {
+        serde_json::from_str::<SyncMessage>(&message_json).ok()
+            .and_then(|message| self.handle(bc, message))
+            .and_then(|next| serde_json::to_string(&next).ok())
+            .unwrap_or_default()
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn is_finished(&self) -> bool {
+        self.done
+    }
+
+    // Empty unless the sync failed
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_error(&self) -> String {
+        self.error.clone().unwrap_or_default()
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn take_applied_json(&mut self) -> String {
+        serde_json::to_string(&self.take_applied()).unwrap_or_else(|_| "[]".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::signing::KeyPair;
+
+    // A network of one validator with `blocks` message blocks
+    fn chain(blocks: usize) -> Blockchain {
+        let mut bc = Blockchain::new();
+        let key = KeyPair::from_seed(b"v1");
+        bc.add_validator_with_key("v1".to_string(), 100, key.public_key_hex());
+        bc.set_node_key(key);
+        for i in 0..blocks {
+            bc.add_message(format!("message {}", i), "v1".to_string());
+            assert!(bc.mine_block());
+        }
+        bc
+    }
+
+    // Relay between `node` syncing from `peer`, as the tracker would
+    fn run(sync: &mut BlockSync, node: &mut Blockchain, peer: &Blockchain, first: SyncMessage) -> usize {
+        let mut next = Some(first);
+        let mut requests = 0;
+        while let Some(request) = next {
+            requests += 1;
+            let answer = peer.answer_sync(&request).unwrap();
+            next = sync.handle(node, answer);
+        }
+        requests
+    }
+
+    #[test]
+    fn test_late_joiner_catches_up_headers_first() {
+        let network = chain(120);
+        let mut node = Blockchain::new();
+        let mut sync = BlockSync::new();
+
+        let first = sync.start(&node, "peer");
+        // Three batches of headers, then three of bodies
+        assert_eq!(run(&mut sync, &mut node, &network, first), 6);
+        assert!(sync.is_done() && sync.error().is_none());
+        assert_eq!(node.get_latest().hash, network.get_latest().hash);
+        assert_eq!(sync.take_applied().len(), 120);
+        assert!(node.get_sync_summary().contains("\"last_sync_height\": 120, \"new_blocks\": 0"));
+
+        // Nothing to fetch once caught up
+        let first = sync.start(&node, "peer");
+        assert_eq!(run(&mut sync, &mut node, &network, first), 1);
+        assert!(sync.error().is_none());
+    }
+
+    #[test]
+    fn test_tampered_headers_and_bodies_stop_the_sync() {
+        let network = chain(3);
+        let mut node = Blockchain::new();
+        let mut sync = BlockSync::new();
+
+        let Some(SyncMessage::Headers { target, tip_height, mut headers }) = network.answer_sync(&sync.start(&node, "peer")) else {
+            panic!("no headers");
+        };
+        headers[1].validator = "v2".to_string();
+        assert!(sync.handle(&mut node, SyncMessage::Headers { target, tip_height, headers }).is_none());
+        assert!(sync.error().is_some());
+
+        // Genuine headers, but a body without its transactions
+        let headers = network.answer_sync(&sync.start(&node, "peer")).unwrap();
+        let request = sync.handle(&mut node, headers).unwrap();
+        let Some(SyncMessage::Blocks { target, mut blocks }) = network.answer_sync(&request) else {
+            panic!("no blocks");
+        };
+        blocks[1].transactions.clear();
+        assert!(sync.handle(&mut node, SyncMessage::Blocks { target, blocks }).is_none());
+        assert_eq!((node.height(), sync.error().is_some()), (1, true));
+
+        // Answers from anyone but the peer asked are ignored
+        let headers = network.answer_sync(&SyncMessage::GetHeaders { target: "other".to_string(), from_height: 2, count: 2 }).unwrap();
+        sync.start(&node, "peer");
+        assert!(sync.handle(&mut node, headers).is_none());
+        assert!(!sync.is_done());
+    }
+
+    #[test]
+    fn test_learned_stake_must_fit() {
+        let network = chain(1);
+        let mut block = network.get_latest().clone();
+        let mut node = Blockchain::new();
+        block.stake_weight = u32::MAX as u64 + 1;
+        assert!(node.learn_validator(&block).is_err());
+        assert!(!node.has_validator(&block.validator));
+
+        block.stake_weight = u32::MAX as u64;
+        assert!(node.learn_validator(&block).is_ok());
+        assert_eq!(node.get_total_stake(), u32::MAX as u64);
+    }
+}
@@ -0,0 +1,431 @@
+// Tenant networks registered on the enterprise chain, and the key each signs with
+//
+// A network_id is anyone's to use until it is registered. From then on the
+// tracker only lets a peer join it once the peer has signed a challenge with
+// the tenant's key, and validators only take its blocks and reorgs signed
+// with that key (the *_message functions say what is signed). Peers of a
+// tenant share its key, see TenantKey. The registry transactions, which only
+// validators may send:
+//   TenantRegister { network_id, public_key }   a network that is not registered yet
+//   TenantRotateKey { network_id, public_key }  its blocks are checked against the
+//                                               new key from the next block on
+//   TenantSuspend { network_id }                its peers and blocks are turned away
+//   TenantReinstate { network_id }              until this
+// REQUIRE_TENANT_REGISTRATION=true on the tracker and validators also turns
+// away networks that are not registered.
+
+use super::forks::ChainReorg;
+use super::signing::{self, KeyPair};
+use super::{Block, Transaction, TransactionType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[cfg(feature = "native")]
+use super::{TenantBlockData, TenantBlockchainUpdate};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantRecord {
+    pub public_key: String,
+    pub registered_at: u32, // height
+    pub key_changed_at: u32,
+    pub suspended: bool,
+}
+
+impl TenantRecord {
+    pub fn verify(&self, network_id: &str, message: &str, signature: Option<&str>) -> Result<(), String> {
+        if self.suspended {
+            return Err(format!("network {} is suspended", network_id));
+        }
+        match signature {
+            Some(signature) if signing::verify(&self.public_key, message.as_bytes(), signature) => Ok(()),
+            Some(_) => Err(format!("signature is not by the key of network {}", network_id)),
+            None => Err(format!("network {} is registered, its messages must be signed", network_id)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TenantRegistry {
+    tenants: BTreeMap<String, TenantRecord>,
+}
+
+pub fn join_message(network_id: &str, nonce: &str) -> String {
+    format!("tenant-join:{}:{}", network_id, nonce)
+}
+
+// What is signed of a block: where it goes in the chain, its hash, and its
+// transactions through the Merkle root, so none can be swapped or replayed
+// under another height
+pub fn block_fields(block_id: u32, previous_hash: &str, block_hash: &str, merkle_root: &str, timestamp: u64) -> String {
+    format!("{}:{}:{}:{}:{}", block_id, previous_hash, block_hash, merkle_root, timestamp)
+}
+
+pub fn block_message(network_id: &str, fields: &str) -> String {
+    format!("tenant-block:{}:{}", network_id, fields)
+}
+
+// Covers the blocks of the new branch too, by their block_fields, oldest first
+pub fn reorg_message(network_id: &str, fork_height: u32, fork_hash: &str, removed: &[String], added: &[String]) -> String {
+    format!("tenant-reorg:{}:{}:{}:{}:{}", network_id, fork_height, fork_hash, removed.join(","), added.join(","))
+}
+
+fn tenant_block_fields(block: &Block) -> String {
+    block_fields(block.height, &block.previous_hash, &block.hash, &block.merkle_root, block.timestamp)
+}
+
+// A registered tenant's blocks must carry their Merkle root
+#[cfg(feature = "native")]
+fn forwarded_block_fields(block: &TenantBlockData) -> Result<String, String> {
+    match &block.merkle_root {
+        Some(root) => Ok(block_fields(block.block_id, &block.previous_hash, &block.block_hash, root, block.timestamp)),
+        None => Err(format!("block #{} has no merkle root", block.block_id)),
+    }
+}
+
+#[cfg(feature = "native")]
+pub fn registration_required() -> bool {
+    std::env::var("REQUIRE_TENANT_REGISTRATION").is_ok_and(|v| v.trim() == "true")
+}
+
+impl TenantRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    pub fn get(&self, network_id: &str) -> Option<&TenantRecord> {
+        self.tenants.get(network_id)
+    }
+
+    pub fn tenants(&self) -> &BTreeMap<String, TenantRecord> {
+        &self.tenants
+    }
+
+    pub fn is_registry(tx: &Transaction) -> bool {
+        matches!(tx.tx_type,
+            TransactionType::TenantRegister { .. }
+            | TransactionType::TenantRotateKey { .. }
+            | TransactionType::TenantSuspend { .. }
+            | TransactionType::TenantReinstate { .. })
+    }
+
+    // Apply a committed registry transaction of the block at `height`; that
+    // a validator sent it is for the caller to check
+    pub fn apply(&mut self, tx: &Transaction, height: u32) -> Result<(), String> {
+        let fail = |reason: String| Err(format!("transaction {}: {}", tx.id, reason));
+        match &tx.tx_type {
+            TransactionType::TenantRegister { network_id, public_key } => {
+                if network_id.is_empty() || !signing::is_key_address(public_key) {
+                    return fail("a tenant needs a network id and a public key".to_string());
+                }
+                if self.tenants.contains_key(network_id) {
+                    return fail(format!("network {} is already registered", network_id));
+                }
+                self.tenants.insert(network_id.clone(), TenantRecord {
+                    public_key: public_key.clone(),
+                    registered_at: height,
+                    key_changed_at: height,
+                    suspended: false,
+                });
+                Ok(())
+            }
+            TransactionType::TenantRotateKey { network_id, public_key } => {
+                let record = match self.tenants.get_mut(network_id) {
+                    Some(record) => record,
+                    None => return fail(format!("network {} is not registered", network_id)),
+                };
+                if !signing::is_key_address(public_key) || *public_key == record.public_key {
+                    return fail(format!("{:?} is not a new key for {}", public_key, network_id));
+                }
+                record.public_key = public_key.clone();
+                record.key_changed_at = height;
+                Ok(())
+            }
+            TransactionType::TenantSuspend { network_id } | TransactionType::TenantReinstate { network_id } => {
+                let suspend = matches!(tx.tx_type, TransactionType::TenantSuspend { .. });
+                match self.tenants.get_mut(network_id) {
+                    Some(record) if record.suspended != suspend => {
+                        record.suspended = suspend;
+                        Ok(())
+                    }
+                    Some(_) => fail(format!("network {} is already {}", network_id, if suspend { "suspended" } else { "active" })),
+                    None => fail(format!("network {} is not registered", network_id)),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Whether the registry transactions among `txs` apply in order
+    pub fn check<'a>(&self, txs: impl IntoIterator<Item = &'a Transaction>, height: u32) -> Result<(), String> {
+        let mut staged = self.clone();
+        for tx in txs.into_iter().filter(|tx| Self::is_registry(tx)) {
+            staged.apply(tx, height)?;
+        }
+        Ok(())
+    }
+
+    // Keep the transactions whose registry part still applies in order;
+    // returns how many were dropped
+    pub fn retain_applicable(&self, txs: &mut Vec<Transaction>, height: u32) -> usize {
+        let before = txs.len();
+        let mut staged = self.clone();
+        txs.retain(|tx| !Self::is_registry(tx) || staged.apply(tx, height).is_ok());
+        before - txs.len()
+    }
+
+    // The network's record if `signature` over `message` is by its key;
+    // None if it is not registered
+    pub fn authenticate(&self, network_id: &str, message: &str, signature: Option<&str>) -> Result<Option<&TenantRecord>, String> {
+        match self.tenants.get(network_id) {
+            Some(record) => record.verify(network_id, message, signature).map(|_| Some(record)),
+            None => Ok(None),
+        }
+    }
+
+    // Signed with the tenant's key: every block of the update, or the reorg
+    // as a whole when there is one
+    #[cfg(feature = "native")]
+    pub fn verify_update(&self, update: &TenantBlockchainUpdate, require_registration: bool) -> Result<(), String> {
+        if !self.tenants.contains_key(&update.network_id) {
+            return match require_registration {
+                true => Err(format!("network {} is not registered", update.network_id)),
+                false => Ok(()),
+            };
+        }
+        if let Some(block) = update.new_blocks.iter().find(|block| block.network_id != update.network_id) {
+            return Err(format!("block {} is from network {}", block.block_hash, block.network_id));
+        }
+        let added = update.new_blocks.iter().map(forwarded_block_fields).collect::<Result<Vec<_>, _>>()?;
+        if let Some(reorg) = &update.reorg {
+            let message = reorg_message(&update.network_id, reorg.fork_height, &reorg.fork_hash, &reorg.removed, &added);
+            return self.authenticate(&update.network_id, &message, reorg.signature.as_deref())
+                .map(|_| ())
+                .map_err(|e| format!("reorg: {}", e));
+        }
+        for (block, fields) in update.new_blocks.iter().zip(&added) {
+            let message = block_message(&update.network_id, fields);
+            self.authenticate(&update.network_id, &message, block.tenant_signature.as_deref())
+                .map_err(|e| format!("block #{}: {}", block.block_id, e))?;
+        }
+        Ok(())
+    }
+}
+
+// The key a tenant network's peers sign with
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub struct TenantKey {
+    key: KeyPair,
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl TenantKey {
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn generate() -> TenantKey {
+        TenantKey { key: KeyPair::generate() }
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn from_secret(secret_hex: String) -> Option<TenantKey> {
+        KeyPair::from_secret_hex(secret_hex.trim()).ok().map(|key| TenantKey { key })
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn public_key(&self) -> String {
+        self.key.public_key_hex()
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn secret(&self) -> String {
+        self.key.secret_hex()
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn sign_join(&self, network_id: String, nonce: String) -> String {
+        self.key.sign(join_message(&network_id, &nonce).as_bytes())
+    }
+
+    // A Block as JSON; empty if it is not one
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn sign_block_json(&self, network_id: String, block_json: String) -> String {
+        serde_json::from_str::<Block>(&block_json)
+            .map(|block| self.sign_block(&network_id, &block))
+            .unwrap_or_default()
+    }
+
+    // A forks::ChainReorg as JSON; empty if it is not one
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn sign_reorg_json(&self, network_id: String, reorg_json: String) -> String {
+        serde_json::from_str::<ChainReorg>(&reorg_json)
+            .map(|reorg| self.sign_reorg(&network_id, &reorg))
+            .unwrap_or_default()
+    }
+}
+
+impl TenantKey {
+    pub fn sign_block(&self, network_id: &str, block: &Block) -> String {
+        self.key.sign(block_message(network_id, &tenant_block_fields(block)).as_bytes())
+    }
+
+    pub fn sign_reorg(&self, network_id: &str, reorg: &ChainReorg) -> String {
+        let removed: Vec<String> = reorg.removed.iter().map(|block| block.hash.clone()).collect();
+        let added: Vec<String> = reorg.added.iter().map(tenant_block_fields).collect();
+        let message = reorg_message(network_id, reorg.fork_height, &reorg.fork_hash, &removed, &added);
+        self.key.sign(message.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{merkle, TenantReorg};
+
+    fn registry_tx(id: &str, tx_type: TransactionType) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            from: "validator".to_string(),
+            to: String::new(),
+            amount: 0,
+            tx_type,
+            timestamp: 0,
+            public_key: None,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_registered_networks_answer_to_their_current_key() {
+        let (old, new) = (TenantKey::generate(), TenantKey::generate());
+        let net = "net-a".to_string();
+        let mut registry = TenantRegistry::new();
+        let register = registry_tx("r", TransactionType::TenantRegister { network_id: net.clone(), public_key: old.public_key() });
+        registry.apply(&register, 3).unwrap();
+        assert!(registry.check([&register], 4).is_err()); // registered once
+
+        let message = join_message(&net, "n1");
+        let signed = old.sign_join(net.clone(), "n1".to_string());
+        assert!(registry.authenticate(&net, &message, Some(&signed)).unwrap().is_some());
+        assert!(registry.authenticate(&net, &message, None).is_err());
+        assert!(registry.authenticate("net-b", &message, None).unwrap().is_none());
+
+        // After a rotation only the new key counts
+        registry.apply(&registry_tx("k", TransactionType::TenantRotateKey { network_id: net.clone(), public_key: new.public_key() }), 5).unwrap();
+        assert!(registry.authenticate(&net, &message, Some(&signed)).is_err());
+        assert!(registry.authenticate(&net, &message, Some(&new.sign_join(net.clone(), "n1".to_string()))).is_ok());
+        assert_eq!(registry.get(&net).unwrap().key_changed_at, 5);
+
+        // Suspended networks are turned away whatever they sign
+        let suspend = registry_tx("s", TransactionType::TenantSuspend { network_id: net.clone() });
+        let mut pending = vec![suspend.clone(), suspend.clone()];
+        assert_eq!(registry.retain_applicable(&mut pending, 6), 1);
+        registry.apply(&suspend, 6).unwrap();
+        assert!(registry.authenticate(&net, &join_message(&net, "n"), Some(&new.sign_join(net.clone(), "n".to_string()))).is_err());
+        registry.apply(&registry_tx("u", TransactionType::TenantReinstate { network_id: net.clone() }), 7).unwrap();
+        assert!(!registry.get(&net).unwrap().suspended);
+    }
+
+    // As the tracker forwards it
+    fn forwarded(block: &Block, network_id: &str, tenant_signature: Option<String>) -> TenantBlockData {
+        TenantBlockData {
+            block_id: block.height,
+            block_hash: block.hash.clone(),
+            transactions: block.transactions.iter().map(|tx| serde_json::to_string(tx).unwrap()).collect(),
+            timestamp: block.timestamp,
+            previous_hash: block.previous_hash.clone(),
+            network_id: network_id.to_string(),
+            merkle_root: Some(block.merkle_root.clone()),
+            tenant_signature,
+        }
+    }
+
+    #[test]
+    fn test_updates_of_registered_networks_must_be_signed_as_sent() {
+        let key = TenantKey::generate();
+        let net = "net-a".to_string();
+        let mut registry = TenantRegistry::new();
+        registry.apply(&registry_tx("r", TransactionType::TenantRegister { network_id: net.clone(), public_key: key.public_key() }), 1).unwrap();
+
+        let mut block = Block {
+            height: 4,
+            hash: String::new(),
+            previous_hash: "p3".to_string(),
+            timestamp: 40,
+            validator: "peer".to_string(),
+            transactions: vec![registry_tx("order", TransactionType::Transfer)],
+            merkle_root: String::new(),
+            stake_weight: 1,
+            round: 0,
+            nonce: None,
+            data: None,
+            validator_key: None,
+            signature: None,
+            fees: None,
+        };
+        block.merkle_root = block.calculate_merkle_root();
+        block.hash = block.calculate_hash();
+        let update = |blocks: Vec<TenantBlockData>, reorg: Option<TenantReorg>| TenantBlockchainUpdate {
+            network_id: net.clone(),
+            peer_id: "peer".to_string(),
+            new_blocks: blocks,
+            timestamp: 0,
+            reorg,
+        };
+
+        let signed = forwarded(&block, &net, Some(key.sign_block(&net, &block)));
+        assert!(registry.verify_update(&update(vec![signed.clone()], None), false).is_ok());
+        assert!(registry.verify_update(&update(vec![forwarded(&block, &net, None)], None), false).is_err());
+
+        // The signature does not carry over to another height, parent or
+        // set of transactions, and the Merkle root is not optional
+        let mut replayed = signed.clone();
+        replayed.block_id = 9;
+        assert!(registry.verify_update(&update(vec![replayed], None), false).is_err());
+        let mut reparented = signed.clone();
+        reparented.previous_hash = "other".to_string();
+        assert!(registry.verify_update(&update(vec![reparented], None), false).is_err());
+        let mut swapped = signed.clone();
+        swapped.transactions = vec![serde_json::to_string(&registry_tx("forged", TransactionType::Transfer)).unwrap()];
+        swapped.merkle_root = Some(merkle::merkle_root(&[merkle::leaf_hash(swapped.transactions[0].as_bytes())]));
+        assert!(registry.verify_update(&update(vec![swapped], None), false).is_err());
+        let mut rootless = signed.clone();
+        rootless.merkle_root = None;
+        assert!(registry.verify_update(&update(vec![rootless], None), false).is_err());
+
+        // A reorg is signed as a whole, new branch included
+        let chain_reorg = ChainReorg {
+            fork_height: 3,
+            fork_hash: "p3".to_string(),
+            old_tip: "o4".to_string(),
+            new_tip: block.hash.clone(),
+            removed: Vec::new(),
+            added: vec![block.clone()],
+            returned_to_pending: Vec::new(),
+        };
+        let reorg = TenantReorg {
+            fork_height: 3,
+            fork_hash: "p3".to_string(),
+            removed: vec!["o4".to_string()],
+            signature: None,
+        };
+        let mut signed_reorg = chain_reorg.clone();
+        signed_reorg.removed = vec![Block { hash: "o4".to_string(), ..block.clone() }];
+        let signature = Some(key.sign_reorg(&net, &signed_reorg));
+        let with_signature = TenantReorg { signature: signature.clone(), ..reorg.clone() };
+        assert!(registry.verify_update(&update(vec![forwarded(&block, &net, None)], Some(with_signature.clone())), false).is_ok());
+        let mut moved = forwarded(&block, &net, None);
+        moved.block_id = 5;
+        assert!(registry.verify_update(&update(vec![moved], Some(with_signature)), false).is_err());
+        assert!(registry.verify_update(&update(vec![forwarded(&block, &net, None)], Some(reorg)), false).is_err());
+
+        // Unregistered networks only when registration is not required
+        let other = TenantBlockchainUpdate { network_id: "net-b".to_string(), ..update(Vec::new(), None) };
+        assert!(registry.verify_update(&other, false).is_ok());
+        assert!(registry.verify_update(&other, true).is_err());
+    }
+}
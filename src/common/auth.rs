@@ -0,0 +1,297 @@
+// API keys for the tracker's and validators' /api endpoints
+//
+// Each node reads the keys it accepts from
+//   API_KEYS=<key>:<holder>:<scope>+<scope>[:<network>+<network>],...
+// e.g. API_KEYS=k3y-of-tracker:tracker:submit-blocks+broadcast-trades,d4sh:dashboard:read-only
+// A key that lists networks may only submit blocks, settlement votes and order
+// acks for those networks, and may do nothing else beyond reading.
+// Requests carry the key in an X-Api-Key header, or as ?api_key= where a
+// header cannot be set (browser WebSockets). Scopes:
+//   read-only         GET endpoints; every key has it
+//   submit-blocks     tenant blocks and chain sync
+//   broadcast-trades  trades, order book updates, settlement and order acks
+//   validator         consensus messages, issuing, staking and alert reviews
+// The key a node sends to the others is API_KEY, so the tracker and the
+// validators authenticate each other when each lists the other's key. With no
+// API_KEYS every request is let through, as before; an API_KEYS none of whose
+// entries parse keeps the node from starting. CORS_ORIGINS (comma
+// separated) limits which pages may call the API; any origin if unset.
+
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    ReadOnly,
+    SubmitBlocks,
+    BroadcastTrades,
+    Validator,
+}
+
+impl Scope {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "read-only" => Some(Scope::ReadOnly),
+            "submit-blocks" => Some(Scope::SubmitBlocks),
+            "broadcast-trades" => Some(Scope::BroadcastTrades),
+            "validator" => Some(Scope::Validator),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ReadOnly => "read-only",
+            Scope::SubmitBlocks => "submit-blocks",
+            Scope::BroadcastTrades => "broadcast-trades",
+            Scope::Validator => "validator",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub holder: String,
+    pub scopes: HashSet<Scope>,
+    pub networks: Option<HashSet<String>>, // None acts for every network
+}
+
+// Keys are held by their SHA-256 so a lookup does not compare secrets
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    keys: HashMap<String, ApiKey>,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    Missing,
+    Unknown,
+    Forbidden { holder: String, scope: Scope },
+    WrongNetwork { holder: String, network_id: Option<String> },
+}
+
+impl warp::reject::Reject for AuthError {}
+
+fn digest(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+impl ApiKeys {
+    // Err when API_KEYS is set but not one of its keys parses: running open
+    // would not be what was asked for
+    pub fn from_env() -> Result<Self, String> {
+        let spec = std::env::var("API_KEYS").unwrap_or_default();
+        let keys = Self::parse(&spec);
+        if keys.is_enabled() {
+            println!("API auth: {} keys", keys.keys.len());
+        } else if !spec.trim().is_empty() {
+            return Err("API_KEYS is set but none of its entries is a valid key".to_string());
+        } else {
+            println!("WARNING: no API_KEYS set - /api endpoints are open to anyone");
+        }
+        Ok(keys)
+    }
+
+    // "key:holder:scope+scope[:network+network],..."; malformed entries are skipped
+    pub fn parse(spec: &str) -> Self {
+        let mut keys = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let parts: Vec<&str> = entry.split(':').map(str::trim).collect();
+            let scopes: Option<HashSet<Scope>> = match parts.as_slice() {
+                [key, holder, scopes, ..] if parts.len() <= 4 && !key.is_empty() && !holder.is_empty() => {
+                    scopes.split('+').map(|name| Scope::parse(name.trim())).collect()
+                }
+                _ => None,
+            };
+            let networks: Option<HashSet<String>> = parts.get(3).map(|networks| {
+                networks.split('+').map(str::trim).filter(|n| !n.is_empty()).map(str::to_string).collect()
+            });
+            match scopes {
+                Some(_) if networks.as_ref().is_some_and(HashSet::is_empty) => {
+                    println!("WARNING: skipping API_KEYS entry for {:?} with an empty network list", parts[1]);
+                }
+                Some(mut scopes) => {
+                    scopes.insert(Scope::ReadOnly);
+                    keys.insert(digest(parts[0]), ApiKey { holder: parts[1].to_string(), scopes, networks });
+                }
+                None => println!("WARNING: skipping malformed API_KEYS entry for {:?}", parts.get(1).unwrap_or(&"")),
+            }
+        }
+        Self { keys }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    // The holder of `key` if it grants `scope`; None when auth is off. Keys
+    // bound to networks only read here, see authorize_network.
+    pub fn authorize(&self, key: Option<&str>, scope: Scope) -> Result<Option<String>, AuthError> {
+        self.check(key, scope, None)
+    }
+
+    // Like authorize, for a request acting for `network_id`
+    pub fn authorize_network(&self, key: Option<&str>, scope: Scope, network_id: Option<&str>) -> Result<Option<String>, AuthError> {
+        self.check(key, scope, Some(network_id))
+    }
+
+    fn check(&self, key: Option<&str>, scope: Scope, network_id: Option<Option<&str>>) -> Result<Option<String>, AuthError> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let api_key = self.keys.get(&digest(key.ok_or(AuthError::Missing)?)).ok_or(AuthError::Unknown)?;
+        if !api_key.scopes.contains(&scope) {
+            return Err(AuthError::Forbidden { holder: api_key.holder.clone(), scope });
+        }
+        if let (Some(networks), true) = (&api_key.networks, scope != Scope::ReadOnly) {
+            let network_id = network_id.flatten();
+            if !network_id.is_some_and(|network_id| networks.contains(network_id)) {
+                return Err(AuthError::WrongNetwork { holder: api_key.holder.clone(), network_id: network_id.map(str::to_string) });
+            }
+        }
+        Ok(Some(api_key.holder.clone()))
+    }
+}
+
+// Goes after a route's path and method, so requests for other routes are not
+// turned away by it
+pub fn require(keys: Arc<ApiKeys>, scope: Scope) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>(API_KEY_HEADER)
+        .and(warp::query::<HashMap<String, String>>().or(warp::any().map(HashMap::new)).unify())
+        .and_then(move |header: Option<String>, query: HashMap<String, String>| {
+            let keys = keys.clone();
+            async move {
+                let key = header.or_else(|| query.get("api_key").cloned());
+                keys.authorize(key.as_deref(), scope)
+                    .map(|_| ())
+                    .map_err(warp::reject::custom)
+            }
+        })
+        .untuple_one()
+}
+
+// Like require, for routes acting for one tenant network: also takes the
+// JSON body, whose network a key bound to networks must hold
+pub fn require_network<T>(keys: Arc<ApiKeys>, scope: Scope, network_of: fn(&T) -> Option<&str>) -> impl Filter<Extract = (T,), Error = Rejection> + Clone
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    warp::header::optional::<String>(API_KEY_HEADER)
+        .and(warp::query::<HashMap<String, String>>().or(warp::any().map(HashMap::new)).unify())
+        .and(warp::body::json::<T>())
+        .and_then(move |header: Option<String>, query: HashMap<String, String>, body: T| {
+            let keys = keys.clone();
+            async move {
+                let key = header.or_else(|| query.get("api_key").cloned());
+                keys.authorize_network(key.as_deref(), scope, network_of(&body))
+                    .map(|_| body)
+                    .map_err(warp::reject::custom)
+            }
+        })
+}
+
+// 401 or 403 for auth failures; other rejections go on as they were
+pub async fn recover(err: Rejection) -> Result<impl Reply, Rejection> {
+    let (status, message) = match err.find::<AuthError>() {
+        Some(AuthError::Missing) => (StatusCode::UNAUTHORIZED, "missing API key".to_string()),
+        Some(AuthError::Unknown) => (StatusCode::UNAUTHORIZED, "unknown API key".to_string()),
+        Some(AuthError::Forbidden { holder, scope }) => {
+            (StatusCode::FORBIDDEN, format!("{} may not {}", holder, scope.as_str()))
+        }
+        Some(AuthError::WrongNetwork { holder, network_id: Some(network_id) }) => {
+            (StatusCode::FORBIDDEN, format!("{} may not act for network {}", holder, network_id))
+        }
+        Some(AuthError::WrongNetwork { holder, network_id: None }) => {
+            (StatusCode::FORBIDDEN, format!("{} may only act for its own networks", holder))
+        }
+        None => return Err(err),
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&json!({ "status": "error", "message": message })), status))
+}
+
+pub fn cors() -> warp::cors::Builder {
+    let cors = warp::cors()
+        .allow_headers(vec!["content-type", API_KEY_HEADER])
+        .allow_methods(vec!["GET", "POST", "OPTIONS"]);
+    let origins = std::env::var("CORS_ORIGINS").unwrap_or_default();
+    let origins: Vec<&str> = origins.split(',').map(str::trim).filter(|origin| !origin.is_empty()).collect();
+    if origins.is_empty() {
+        cors.allow_any_origin()
+    } else {
+        cors.allow_origins(origins)
+    }
+}
+
+// For calls to the other nodes: adds this node's API_KEY, if it has one
+pub fn with_api_key(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match std::env::var("API_KEY") {
+        Ok(key) if !key.is_empty() => request.header(API_KEY_HEADER, key),
+        _ => request,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keys_grant_only_their_scopes() {
+        let keys = Arc::new(ApiKeys::parse("tr4cker:tracker:submit-blocks+broadcast-trades, d4sh:dashboard:read-only, bad:entry"));
+        let route = warp::path("blocks")
+            .and(warp::post())
+            .and(require(keys.clone(), Scope::SubmitBlocks))
+            .map(|| "ok")
+            .recover(recover);
+        let post = |key: Option<&str>| {
+            let request = warp::test::request().method("POST").path("/blocks");
+            match key {
+                Some(key) => request.header(API_KEY_HEADER, key),
+                None => request,
+            }
+        };
+
+        assert_eq!(post(Some("tr4cker")).reply(&route).await.status(), StatusCode::OK);
+        assert_eq!(post(None).reply(&route).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(post(Some("guess")).reply(&route).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(post(Some("d4sh")).reply(&route).await.status(), StatusCode::FORBIDDEN);
+        let by_query = warp::test::request().method("POST").path("/blocks?api_key=tr4cker").reply(&route).await;
+        assert_eq!(by_query.status(), StatusCode::OK);
+
+        // Every key can read; without keys everything is open
+        assert_eq!(keys.authorize(Some("tr4cker"), Scope::ReadOnly).unwrap(), Some("tracker".to_string()));
+        assert!(keys.authorize(Some("bad"), Scope::ReadOnly).is_err());
+        assert_eq!(ApiKeys::parse("").authorize(None, Scope::Validator).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_network_keys_act_only_for_their_networks() {
+        let keys = Arc::new(ApiKeys::parse("tr4cker:tracker:submit-blocks, n3t-a:net-a:submit-blocks+broadcast-trades:net-a, n0ne:x:submit-blocks:"));
+        let route = warp::path("blocks")
+            .and(warp::post())
+            .and(require_network(keys.clone(), Scope::SubmitBlocks, |body: &serde_json::Value| body["network_id"].as_str()))
+            .map(|_| "ok")
+            .recover(recover);
+        let post = |key: &str, network_id: &str| {
+            warp::test::request().method("POST").path("/blocks")
+                .header(API_KEY_HEADER, key)
+                .json(&json!({ "network_id": network_id }))
+        };
+
+        assert_eq!(post("n3t-a", "net-a").reply(&route).await.status(), StatusCode::OK);
+        assert_eq!(post("n3t-a", "net-b").reply(&route).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(post("tr4cker", "net-b").reply(&route).await.status(), StatusCode::OK);
+
+        // Bound keys read anywhere but act nowhere else; empty lists are malformed
+        assert!(keys.authorize(Some("n3t-a"), Scope::ReadOnly).is_ok());
+        assert!(matches!(keys.authorize(Some("n3t-a"), Scope::BroadcastTrades), Err(AuthError::WrongNetwork { .. })));
+        assert!(keys.authorize(Some("n0ne"), Scope::ReadOnly).is_err());
+        assert!(ApiKeys::parse("bad:entry").keys.is_empty());
+    }
+}
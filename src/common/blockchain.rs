@@ -2,9 +2,8 @@
 use serde::{Deserialize, Serialize};
 use crate::common::{crypto::hash_data, time::current_timestamp};
 use crate::common::contracts::{ContractVM, SmartContract, ContractCall, ContractResult, create_trading_contract};
+use crate::blockchain::store::{self, ChainStore};
 use std::collections::VecDeque;
-use std::fs;
-use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
@@ -100,6 +99,8 @@ pub struct Blockchain {
     pub chain: Vec<Block>,
     pub pending: VecDeque<Transaction>,
     pub storage_path: Option<String>,
+    store: Option<Box<dyn ChainStore<Block>>>,
+    pub storage_error: Option<String>, // last failed write, cleared by the next good one
     pub contract_vm: ContractVM,
     pub offline_orders: Vec<OfflineOrder>, // New: Store offline orders
 }
@@ -122,6 +123,8 @@ impl Blockchain {
             chain: Vec::new(),
             pending: VecDeque::new(),
             storage_path: None,
+            store: None,
+            storage_error: None,
             contract_vm: ContractVM::new(),
             offline_orders: Vec::new(),
         };
@@ -138,21 +141,25 @@ impl Blockchain {
     
     pub fn new_with_storage(storage_path: String) -> Self {
         let mut blockchain = Self::new();
+        blockchain.store = Some(store::open_store(&storage_path));
         blockchain.storage_path = Some(storage_path);
-        blockchain.load_from_disk();
+        if let Err(e) = blockchain.load_from_disk() {
+            println!("Failed to load stored chain: {}", e);
+            blockchain.storage_error = Some(e);
+        }
         blockchain
     }
 
     // Add different types of transactions
     pub fn add_transaction(&mut self, transaction: Transaction) {
         self.pending.push_back(transaction);
-        self.save_to_disk();
+        self.autosave();
     }
 
     pub fn add_message(&mut self, data: String, sender: String) -> Transaction {
         let transaction = Transaction::new_message(data, sender);
         self.pending.push_back(transaction.clone());
-        self.save_to_disk();
+        self.autosave();
         transaction
     }
 
@@ -163,7 +170,7 @@ impl Blockchain {
         
         let transaction = Transaction::new_contract_deploy(contract, sender);
         self.pending.push_back(transaction.clone());
-        self.save_to_disk();
+        self.autosave();
         Ok(transaction)
     }
 
@@ -181,7 +188,7 @@ impl Blockchain {
         }
         
         self.pending.push_back(transaction.clone());
-        self.save_to_disk();
+        self.autosave();
         transaction
     }
 
@@ -199,7 +206,7 @@ impl Blockchain {
         };
         
         self.offline_orders.push(order.clone());
-        self.save_to_disk();
+        self.autosave();
         order
     }
 
@@ -228,7 +235,7 @@ impl Blockchain {
         }
         
         if !executed_transactions.is_empty() {
-            self.save_to_disk();
+            self.autosave();
         }
         
         executed_transactions
@@ -243,7 +250,7 @@ impl Blockchain {
     pub fn cancel_offline_order(&mut self, order_id: &str, trader: &str) -> bool {
         if let Some(pos) = self.offline_orders.iter().position(|o| o.id == order_id && o.trader == trader) {
             self.offline_orders.remove(pos);
-            self.save_to_disk();
+            self.autosave();
             true
         } else {
             false
@@ -260,7 +267,7 @@ impl Blockchain {
         );
         
         self.chain.push(new_block.clone());
-        self.save_to_disk();
+        self.autosave();
         new_block
     }
 
@@ -290,7 +297,7 @@ impl Blockchain {
         
         block.mine();
         self.chain.push(block.clone());
-        self.save_to_disk();
+        self.autosave();
         Some(block)
     }
 
@@ -309,7 +316,7 @@ impl Blockchain {
             }
             
             self.chain.push(block);
-            self.save_to_disk();
+            self.autosave();
             true
         } else {
             false
@@ -406,43 +413,58 @@ impl Blockchain {
         self.chain.len() as u64 - 1
     }
 
-    pub fn save_to_disk(&self) {
-        if let Some(path) = &self.storage_path {
-            let data = BlockchainData {
-                chain: self.chain.clone(),
-                pending: self.pending.iter().cloned().collect(),
-                contracts: self.contract_vm.list_contracts().iter().map(|c| (*c).clone()).collect(),
-                offline_orders: Some(self.offline_orders.clone()),
-            };
+    pub fn save_to_disk(&mut self) -> Result<(), String> {
+        let state = serde_json::json!({
+            "pending": self.pending,
+            "contracts": self.contract_vm.list_contracts(),
+            "offline_orders": self.offline_orders
+        });
+
+        match self.store.as_mut() {
+            Some(store) => store.persist(&self.chain, &state)
+                .map_err(|e| format!("failed to save to {}: {}", store.describe(), e)),
+            None => Ok(()),
+        }
+    }
 
-            if let Ok(json) = serde_json::to_string_pretty(&data) {
-                if let Some(parent) = Path::new(path).parent() {
-                    let _ = fs::create_dir_all(parent);
-                }
-                let _ = fs::write(path, json);
+    // Save after a state change; failures are logged and kept in storage_error
+    fn autosave(&mut self) {
+        match self.save_to_disk() {
+            Ok(()) => self.storage_error = None,
+            Err(e) => {
+                println!("STORAGE ERROR: {}", e);
+                self.storage_error = Some(e);
             }
         }
     }
 
-    pub fn load_from_disk(&mut self) {
-        if let Some(path) = &self.storage_path {
-            if Path::new(path).exists() {
-                if let Ok(content) = fs::read_to_string(path) {
-                    if let Ok(data) = serde_json::from_str::<BlockchainData>(&content) {
-                        if !data.chain.is_empty() {
-                            self.chain = data.chain;
-                            self.pending = data.pending.into_iter().collect();
-                            self.offline_orders = data.offline_orders.unwrap_or_default();
-                            
-                            // Restore contracts
-                            for contract in data.contracts {
-                                let _ = self.contract_vm.deploy_contract(contract);
-                            }
-                        }
-                    }
-                }
+    pub fn load_from_disk(&mut self) -> Result<(), String> {
+        let store = match self.store.as_mut() {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        let (chain, data) = match store.load() {
+            Ok(Some(stored)) => stored,
+            Ok(None) => return Ok(()),
+            Err(e) => return Err(format!("failed to load {}: {}", store.describe(), e)),
+        };
+        if chain.is_empty() {
+            return Ok(());
+        }
+
+        self.chain = chain;
+        if let Ok(pending) = serde_json::from_value::<Vec<Transaction>>(data["pending"].clone()) {
+            self.pending = pending.into_iter().collect();
+        }
+        self.offline_orders = serde_json::from_value(data["offline_orders"].clone()).unwrap_or_default();
+
+        // Restore contracts
+        if let Ok(contracts) = serde_json::from_value::<Vec<SmartContract>>(data["contracts"].clone()) {
+            for contract in contracts {
+                let _ = self.contract_vm.deploy_contract(contract);
             }
         }
+        Ok(())
     }
 }
 
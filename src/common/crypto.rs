@@ -1,4 +1,5 @@
 // Shared cryptographic utilities
+use crate::blockchain::merkle;
 use sha2::{Sha256, Digest};
 
 pub fn hash_data(data: &str) -> String {
@@ -13,16 +14,12 @@ pub fn hash_bytes(data: &[u8]) -> String {
     hex::encode(hasher.finalize())
 }
 
+// Binary Merkle tree over the items, same construction as block transaction roots
 pub fn calculate_merkle_root(items: &[String]) -> String {
-    if items.is_empty() {
-        return "0".repeat(64);
-    }
-    
-    let mut hasher = Sha256::new();
-    for item in items {
-        hasher.update(item.as_bytes());
-    }
-    hex::encode(hasher.finalize())
+    let leaves: Vec<[u8; 32]> = items.iter()
+        .map(|item| merkle::leaf_hash(item.as_bytes()))
+        .collect();
+    merkle::merkle_root(&leaves)
 }
 
 pub fn verify_proof_of_work(hash: &str, difficulty: u32) -> bool {
@@ -4,6 +4,7 @@ pub mod types;
 pub mod crypto;
 pub mod time;
 pub mod api_utils;
+pub mod auth; // API keys and CORS for the /api endpoints
 pub mod contracts; // Add contracts module
 pub mod zk_range_proofs; // Zero-knowledge range proofs with Bulletproofs
 pub mod imsi_commitments; // IMSI commitment scheme with Pedersen commitments
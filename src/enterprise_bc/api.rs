@@ -1,37 +1,55 @@
 // src/enterprise_bc/api.rs - SIMPLIFIED WORKING VERSION
-use crate::blockchain::{Blockchain, TenantBlockchainUpdate, TenantBlockData};
+use crate::blockchain::{Blockchain, TenantBlockchainUpdate, TenantBlockData, TransactionType};
+use crate::blockchain::snapshot::SnapshotDir;
+use crate::blockchain::tenants;
+use crate::enterprise_bc::market_data::parse_interval;
 use crate::enterprise_bc::order_engine::EnterpriseOrderEngine;
+use crate::enterprise_bc::settlement::{Settlement, SettlementStatus};
+use crate::enterprise_bc::stream::{self, MarketStream};
+use crate::enterprise_bc::surveillance::{AlertKind, ReviewStatus};
+use crate::enterprise_bc::consensus::{ConsensusHandle, ConsensusMessage};
 use crate::common::PrivateContractManager;
+use crate::common::auth::{self, ApiKeys, Scope};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use warp::Filter;
+use warp::{Filter, Reply};
 
+#[allow(clippy::too_many_arguments)]
 pub async fn start_api_server(
     port: u16, 
     blockchain: Arc<RwLock<Blockchain>>,
     order_engine: Arc<RwLock<EnterpriseOrderEngine>>,
-    tracker_url: Option<String>
+    tracker_url: Option<String>,
+    consensus: ConsensusHandle,
+    snapshots: SnapshotDir,
+    market_stream: Arc<MarketStream>,
+    api_keys: Arc<ApiKeys>
 ) {
     println!("Starting Enterprise API server with order matching on port {}", port);
 
     let blockchain_filter = warp::any().map(move || blockchain.clone());
     let order_engine_filter = warp::any().map(move || order_engine.clone());
     let tracker_filter = warp::any().map(move || tracker_url.clone());
+    let consensus_filter = warp::any().map(move || consensus.clone());
+    let snapshots_filter = warp::any().map(move || snapshots.clone());
+    let stream_filter = warp::any().map(move || market_stream.clone());
 
     // Main endpoint for processing tenant blockchain updates
     let tenant_blockchain_update = warp::path("api")
         .and(warp::path("tenant-blockchain-update"))
         .and(warp::post())
-        .and(warp::body::json())
+        .and(auth::require_network(api_keys.clone(), Scope::SubmitBlocks, |update: &TenantBlockchainUpdate| Some(update.network_id.as_str())))
         .and(blockchain_filter.clone())
         .and(order_engine_filter.clone())
         .and(tracker_filter.clone())
+        .and(stream_filter.clone())
         .and_then(handle_tenant_blockchain_update);
 
     // Order book status endpoint
     let order_book_status = warp::path("api")
         .and(warp::path("order-book-status"))
         .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
         .and(order_engine_filter.clone())
         .and_then(handle_order_book_status);
 
@@ -39,6 +57,7 @@ pub async fn start_api_server(
     let debug_orders = warp::path("api")
         .and(warp::path("debug-orders"))
         .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
         .and(order_engine_filter.clone())
         .and_then(handle_debug_orders);
 
@@ -46,12 +65,14 @@ pub async fn start_api_server(
     let status = warp::path("api")
         .and(warp::path("status"))
         .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
         .and(blockchain_filter.clone())
         .and_then(handle_status);
 
     let blocks = warp::path("api")
         .and(warp::path("blocks"))
         .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
         .and(warp::query::<BlocksQuery>())
         .and(blockchain_filter.clone())
         .and_then(handle_blocks);
@@ -59,6 +80,7 @@ pub async fn start_api_server(
     let tenants = warp::path("api")
         .and(warp::path("tenants"))
         .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
         .and(blockchain_filter.clone())
         .and_then(handle_tenants);
 
@@ -66,6 +88,7 @@ pub async fn start_api_server(
     let operator_contracts = warp::path("api")
         .and(warp::path("operator-contracts"))
         .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
         .and(warp::query::<OperatorQuery>())
         .and(blockchain_filter.clone())
         .and_then(handle_operator_contracts);
@@ -74,15 +97,292 @@ pub async fn start_api_server(
         .and(warp::path("contract-details"))
         .and(warp::path::param::<String>())
         .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
         .and(warp::query::<OperatorQuery>())
         .and_then(handle_contract_details);
 
+    // Two-phase settlement of cross-network trades
+    let settlement_vote = warp::path("api")
+        .and(warp::path("settlements"))
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(auth::require_network(api_keys.clone(), Scope::BroadcastTrades, |vote: &SettlementVote| Some(vote.network_id.as_str())))
+        .and(blockchain_filter.clone())
+        .and(order_engine_filter.clone())
+        .and(tracker_filter.clone())
+        .and(stream_filter.clone())
+        .and_then(handle_settlement_vote);
+
+    let settlement_status = warp::path("api")
+        .and(warp::path("settlements"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(order_engine_filter.clone())
+        .and_then(handle_settlement_status);
+
+    let settlements = warp::path("api")
+        .and(warp::path("settlements"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(warp::query::<SettlementsQuery>())
+        .and(order_engine_filter.clone())
+        .and_then(handle_settlements);
+
+    let escrow = warp::path("api")
+        .and(warp::path("escrow"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::param::<String>())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(order_engine_filter.clone())
+        .and_then(handle_escrow);
+
+    // Pushed trades, book deltas, tenant blocks and settlements (see stream.rs)
+    let market_stream_ws = warp::path("api")
+        .and(warp::path("stream"))
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(stream_filter.clone())
+        .map(|ws: warp::ws::Ws, market_stream: Arc<MarketStream>| {
+            ws.on_upgrade(move |socket| stream::serve_client(socket, market_stream))
+        });
+
+    // Market data: L2 depth, tickers and OHLCV candles (see market_data.rs)
+    let market_depth = warp::path("api")
+        .and(warp::path("market"))
+        .and(warp::path("depth"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(warp::query::<DepthQuery>())
+        .and(order_engine_filter.clone())
+        .and_then(handle_market_depth);
+
+    let market_tickers = warp::path("api")
+        .and(warp::path("market"))
+        .and(warp::path("ticker"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(order_engine_filter.clone())
+        .and_then(handle_market_tickers);
+
+    let market_ticker = warp::path("api")
+        .and(warp::path("market"))
+        .and(warp::path("ticker"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(order_engine_filter.clone())
+        .and_then(handle_market_ticker);
+
+    let market_candles = warp::path("api")
+        .and(warp::path("market"))
+        .and(warp::path("candles"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(warp::query::<CandlesQuery>())
+        .and(order_engine_filter.clone())
+        .and_then(handle_market_candles);
+
+    // Surveillance alerts for compliance review
+    let surveillance_alerts = warp::path("api")
+        .and(warp::path("surveillance"))
+        .and(warp::path("alerts"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(warp::query::<AlertsQuery>())
+        .and(order_engine_filter.clone())
+        .and_then(handle_surveillance_alerts);
+
+    let surveillance_alert = warp::path("api")
+        .and(warp::path("surveillance"))
+        .and(warp::path("alerts"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(order_engine_filter.clone())
+        .and_then(handle_surveillance_alert);
+
+    let surveillance_review = warp::path("api")
+        .and(warp::path("surveillance"))
+        .and(warp::path("alerts"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("review"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(auth::require(api_keys.clone(), Scope::Validator))
+        .and(warp::body::json())
+        .and(order_engine_filter.clone())
+        .and_then(handle_surveillance_review);
+
+    // Boxed so the route chain below stays within the compiler's type depth
+    let surveillance = surveillance_alerts
+        .or(surveillance_alert)
+        .or(surveillance_review)
+        .boxed();
+
+    // Committed balances, and issuing from this validator's key
+    let balances = warp::path("api")
+        .and(warp::path("balances"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(blockchain_filter.clone())
+        .and_then(handle_balances);
+
+    let ledger_issue = warp::path("api")
+        .and(warp::path("ledger"))
+        .and(warp::path("issue"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(auth::require(api_keys.clone(), Scope::Validator))
+        .and(warp::body::json())
+        .and(blockchain_filter.clone())
+        .and_then(handle_ledger_issue);
+
+    // Bonds, delegations and rewards, and staking from accounts this node signs for
+    let staking_overview = warp::path("api")
+        .and(warp::path("staking"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(blockchain_filter.clone())
+        .and_then(handle_staking_overview);
+
+    let staking_account = warp::path("api")
+        .and(warp::path("staking"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(blockchain_filter.clone())
+        .and_then(handle_staking_account);
+
+    let staking_submit = warp::path("api")
+        .and(warp::path("staking"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(auth::require(api_keys.clone(), Scope::Validator))
+        .and(warp::body::json())
+        .and(blockchain_filter.clone())
+        .and_then(handle_staking_submit);
+
+    let accounts = balances
+        .or(ledger_issue)
+        .or(staking_overview)
+        .or(staking_account)
+        .or(staking_submit)
+        .boxed();
+
+    // Tenant networks, their keys, and registering them from this validator's key
+    let tenant_registry = warp::path("api")
+        .and(warp::path("tenant-registry"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(blockchain_filter.clone())
+        .and_then(handle_tenant_registry);
+
+    let tenant_record = warp::path("api")
+        .and(warp::path("tenant-registry"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(blockchain_filter.clone())
+        .and_then(handle_tenant_record);
+
+    let tenant_registry_submit = warp::path("api")
+        .and(warp::path("tenant-registry"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(auth::require(api_keys.clone(), Scope::Validator))
+        .and(warp::body::json())
+        .and(blockchain_filter.clone())
+        .and_then(handle_tenant_registry_submit);
+
+    let registry = tenant_registry
+        .or(tenant_record)
+        .or(tenant_registry_submit)
+        .boxed();
+
     let settlement_verification = warp::path("api")
         .and(warp::path("verify-settlement"))
         .and(warp::path::param::<String>())
         .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
         .and_then(handle_settlement_verification);
 
+    // Merkle inclusion proof for an enterprise or tenant transaction
+    let tx_proof = warp::path("api")
+        .and(warp::path("tx-proof"))
+        .and(warp::path::param::<String>())
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(blockchain_filter.clone())
+        .and_then(handle_tx_proof);
+
+    // Snapshot plus the blocks after it, for validators joining without history
+    let latest_snapshot = warp::path("api")
+        .and(warp::path("snapshot"))
+        .and(warp::path("latest"))
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(snapshots_filter.clone())
+        .and(blockchain_filter.clone())
+        .and(order_engine_filter.clone())
+        .and_then(handle_latest_snapshot);
+
+    let chain_blocks = warp::path("api")
+        .and(warp::path("chain"))
+        .and(warp::path("blocks"))
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(warp::query::<ChainBlocksQuery>())
+        .and(blockchain_filter.clone())
+        .and_then(handle_chain_blocks);
+
+    // Validator-to-validator consensus endpoints
+    let consensus_message = warp::path("api")
+        .and(warp::path("consensus"))
+        .and(warp::path("message"))
+        .and(warp::post())
+        .and(auth::require(api_keys.clone(), Scope::Validator))
+        .and(warp::body::json())
+        .and(consensus_filter.clone())
+        .and_then(handle_consensus_message);
+
+    let consensus_info = warp::path("api")
+        .and(warp::path("consensus"))
+        .and(warp::path("info"))
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(consensus_filter.clone())
+        .and(blockchain_filter.clone())
+        .and_then(handle_consensus_info);
+
+    let consensus_status = warp::path("api")
+        .and(warp::path("consensus"))
+        .and(warp::path("status"))
+        .and(warp::get())
+        .and(auth::require(api_keys.clone(), Scope::ReadOnly))
+        .and(consensus_filter.clone())
+        .and(blockchain_filter.clone())
+        .and_then(handle_consensus_status);
+
     let health = warp::path("health")
         .and(warp::get())
         .map(|| warp::reply::json(&serde_json::json!({
@@ -93,10 +393,12 @@ pub async fn start_api_server(
                 .as_secs()
         })));
 
-    let cors = warp::cors()
-        .allow_any_origin()
-        .allow_headers(vec!["content-type"])
-        .allow_methods(vec!["GET", "POST", "OPTIONS"]);
+    let market = market_stream_ws
+        .or(market_depth)
+        .or(market_tickers)
+        .or(market_ticker)
+        .or(market_candles)
+        .boxed();
 
     let routes = tenant_blockchain_update
         .or(order_book_status)
@@ -107,8 +409,23 @@ pub async fn start_api_server(
         .or(operator_contracts)
         .or(contract_details)
         .or(settlement_verification)
+        .or(settlement_vote)
+        .or(settlement_status)
+        .or(settlements)
+        .or(escrow)
+        .or(accounts)
+        .or(registry)
+        .or(market)
+        .or(surveillance)
+        .or(tx_proof)
+        .or(latest_snapshot)
+        .or(chain_blocks)
+        .or(consensus_message)
+        .or(consensus_info)
+        .or(consensus_status)
         .or(health)
-        .with(cors);
+        .recover(auth::recover)
+        .with(auth::cors());
 
     println!("Enterprise API server ready on http://0.0.0.0:{}", port);
     
@@ -122,6 +439,80 @@ struct BlocksQuery {
     limit: Option<usize>,
 }
 
+#[derive(serde::Deserialize)]
+struct SettlementsQuery {
+    state: Option<String>, // preparing, committed or aborted
+}
+
+#[derive(serde::Deserialize)]
+struct DepthQuery {
+    levels: Option<usize>, // per side, default 20
+}
+
+#[derive(serde::Deserialize)]
+struct CandlesQuery {
+    interval: Option<String>, // e.g. 1m, 15m, 1h, 1d; default 1m
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<usize>, // most recent candles, default 100, at most 1000
+}
+
+#[derive(serde::Deserialize)]
+struct AlertsQuery {
+    kind: Option<String>, // wash_trading, spoofing, layering or self_trade_prevented
+    trader: Option<String>,
+    open: Option<bool>,   // only alerts nobody reviewed yet
+    limit: Option<usize>, // newest first, default 100, at most 1000
+}
+
+#[derive(serde::Deserialize)]
+struct AlertReview {
+    reviewer: String,
+    status: String, // dismissed or escalated
+    #[serde(default)]
+    note: String,
+}
+
+#[derive(serde::Deserialize)]
+struct IssueRequest {
+    to: String,
+    #[serde(default)]
+    asset: String, // empty for the native coin
+    amount: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct StakingRequest {
+    action: String, // stake, delegate, unbond or claim
+    account: String,
+    #[serde(default)]
+    validator: String, // the account itself when staking
+    #[serde(default)]
+    amount: u64, // not used when claiming
+}
+
+#[derive(serde::Deserialize)]
+struct TenantRegistryRequest {
+    action: String, // register, rotate, suspend or reinstate
+    network_id: String,
+    #[serde(default)]
+    public_key: String, // when registering or rotating
+}
+
+#[derive(serde::Deserialize)]
+struct SettlementVote {
+    trade_id: String,
+    network_id: String,
+    prepared: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ChainBlocksQuery {
+    after: u32,
+}
+
 #[derive(serde::Deserialize)]
 struct OperatorQuery {
     operator: String,
@@ -174,6 +565,51 @@ async fn handle_settlement_verification(contract_id: String) -> Result<impl warp
     Ok(warp::reply::json(&verification))
 }
 
+async fn handle_tx_proof(
+    tx_id: String,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let bc = blockchain.read().await;
+    let proof = bc.find_tx_proof(&tx_id).map(|p| ("enterprise", p))
+        .or_else(|| bc.find_tenant_tx_proof(&tx_id));
+
+    match proof {
+        Some((chain, proof)) => Ok(warp::reply::json(&serde_json::json!({
+            "chain": chain,
+            "proof": proof,
+            "valid": proof.verify()
+        }))),
+        None => Ok(warp::reply::json(&serde_json::json!({
+            "error": "Transaction not found in any block with a merkle root",
+            "tx_id": tx_id
+        }))),
+    }
+}
+
+// Newest snapshot on disk, or one of the current state if none was taken yet
+async fn handle_latest_snapshot(
+    snapshots: SnapshotDir,
+    blockchain: Arc<RwLock<Blockchain>>,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let snapshot = match snapshots.latest() {
+        Some(snapshot) => snapshot,
+        None => {
+            let app_state = order_engine.read().await.snapshot_state();
+            blockchain.read().await.create_snapshot(app_state)
+        }
+    };
+    Ok(warp::reply::json(&snapshot))
+}
+
+async fn handle_chain_blocks(
+    query: ChainBlocksQuery,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let bc = blockchain.read().await;
+    Ok(warp::reply::json(&bc.blocks_after(query.after)))
+}
+
 async fn get_real_contracts_for_operator(
     operator: &str, 
     blockchain: Arc<RwLock<Blockchain>>
@@ -501,9 +937,22 @@ async fn handle_tenant_blockchain_update(
     update: TenantBlockchainUpdate,
     blockchain: Arc<RwLock<Blockchain>>,
     order_engine: Arc<RwLock<EnterpriseOrderEngine>>,
-    tracker_url: Option<String>
-) -> Result<impl warp::Reply, warp::Rejection> {
+    tracker_url: Option<String>,
+    market_stream: Arc<MarketStream>
+) -> Result<warp::reply::Response, warp::Rejection> {
     println!("ENTERPRISE BC: Processing tenant update from network: {}", update.network_id);
+
+    // Registered networks must sign with their tenant key
+    let verified = blockchain.read().await.tenants().verify_update(&update, tenants::registration_required());
+    if let Err(e) = verified {
+        println!("REJECTED update from network {}: {}", update.network_id, e);
+        let error = warp::reply::json(&serde_json::json!({
+            "status": "error",
+            "message": e,
+            "network_id": update.network_id
+        }));
+        return Ok(warp::reply::with_status(error, warp::http::StatusCode::FORBIDDEN).into_response());
+    }
     println!("Blocks to process: {}", update.new_blocks.len());
 
     let blocks_count = update.new_blocks.len();
@@ -515,26 +964,17 @@ async fn handle_tenant_blockchain_update(
     // DEDUPLICATION: Check for existing blocks before processing
     let new_blocks: Vec<&TenantBlockData> = {
         let bc = blockchain.read().await;
-        let existing_blocks = bc.get_recent_tenant_blocks(1000); // Get recent blocks to check against
         
         update.new_blocks.iter()
             .filter(|block| {
-                // Check if this block already exists
-                let is_duplicate = existing_blocks.iter().any(|existing| {
-                    if let (Some(existing_hash), Some(existing_network), Some(existing_id)) = (
-                        existing.get("block_hash").and_then(|h| h.as_str()),
-                        existing.get("network_id").and_then(|n| n.as_str()),
-                        existing.get("block_id").and_then(|i| i.as_u64())
-                    ) {
-                        existing_hash == block.block_hash && 
-                        existing_network == block.network_id && 
-                        existing_id == block.block_id as u64
-                    } else {
-                        false
-                    }
-                });
+                // Check if this block already exists, in memory or archived
+                let is_duplicate = bc.is_known_tenant_block(block);
                 
-                if is_duplicate {
+                if !block.verify_merkle_root() {
+                    println!("REJECTED block #{} from network {}: transactions do not match merkle root",
+                            block.block_id, block.network_id);
+                    false
+                } else if is_duplicate {
                     println!("SKIPPING duplicate block #{} (hash: {}) from network {}", 
                             block.block_id, &block.block_hash[..16], block.network_id);
                     false
@@ -551,7 +991,8 @@ async fn handle_tenant_blockchain_update(
     blocks_processed = new_blocks.len();
     blocks_skipped = blocks_count - blocks_processed;
 
-    if new_blocks.is_empty() {
+    // A reorg still has blocks to drop when its new branch is already known
+    if new_blocks.is_empty() && update.reorg.is_none() {
         println!("No new blocks to process - all were duplicates");
         return Ok(warp::reply::json(&serde_json::json!({
             "status": "success",
@@ -563,7 +1004,7 @@ async fn handle_tenant_blockchain_update(
             "transactions_processed": 0,
             "orders_processed": 0,
             "trades_executed": 0
-        })));
+        })).into_response());
     }
 
     // Store NEW blocks in blockchain
@@ -575,14 +1016,19 @@ async fn handle_tenant_blockchain_update(
             peer_id: update.peer_id.clone(),
             new_blocks: new_blocks.iter().map(|&b| b.clone()).collect(),
             timestamp: update.timestamp,
+            reorg: update.reorg.clone(),
         };
         bc.add_tenant_blocks(&filtered_update);
         println!("Stored {} new blocks in enterprise blockchain", new_blocks.len());
     }
+    for block in &new_blocks {
+        market_stream.publish_tenant_block(block);
+    }
 
     // Process each NEW block for order matching
     let mut all_trades = Vec::new();
     {
+        let bc = blockchain.read().await;
         let mut engine = order_engine.write().await;
 
         for block in &new_blocks {
@@ -611,7 +1057,7 @@ async fn handle_tenant_blockchain_update(
                 }
             }
 
-            let block_trades = engine.process_block(block);
+            let block_trades = engine.process_block(block, &bc.tenant_ledger(&block.network_id, block.block_id));
             if !block_trades.is_empty() {
                 println!("Block generated {} trades", block_trades.len());
                 all_trades.extend(block_trades);
@@ -619,20 +1065,17 @@ async fn handle_tenant_blockchain_update(
         }
     }
 
-    // Send trade notifications back to networks (only if we have new trades)
+    // Ask both networks of every new trade to prepare its settlement
     if !all_trades.is_empty() {
-        println!("Broadcasting {} cross-network trades", all_trades.len());
-
-        if let Some(ref tracker_url) = tracker_url {
-            for trade in &all_trades {
-                println!("Sending trade notification: {} {} {} @ {} between {} and {}",
-                         trade.trade_id, trade.quantity, trade.asset, trade.price,
-                         trade.buyer_network, trade.seller_network);
+        println!("Opening settlement for {} cross-network trades", all_trades.len());
+    }
+    publish_settlements(&order_engine, &blockchain, tracker_url.as_deref(), &market_stream).await;
 
-                send_trade_to_tracker(trade, tracker_url).await;
-            }
-        } else {
-            println!("No tracker URL configured - trades not broadcast");
+    // Tell the requesting networks how their cancels and amends went
+    let acks = order_engine.write().await.take_acks();
+    if let Some(ref tracker_url) = tracker_url {
+        for ack in &acks {
+            send_order_ack_to_tracker(ack, tracker_url).await;
         }
     }
 
@@ -647,39 +1090,13 @@ async fn handle_tenant_blockchain_update(
 
     // Always broadcast order book state if we processed any blocks
     if blocks_processed > 0 {
-        let engine = order_engine.read().await;
-        let all_orders = engine.get_all_orders();
+        let (all_orders, cancelled) = {
+            let mut engine = order_engine.write().await;
+            (engine.get_all_orders(), engine.take_cancellations())
+        };
 
         if let Some(ref tracker_url) = tracker_url {
-            println!("Broadcasting updated order book to tracker");
-
-            let order_book_update = serde_json::json!({
-                "type": "order_book_broadcast",
-                "orders": all_orders,
-                "timestamp": std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs()
-            });
-
-            let client = reqwest::Client::new();
-            let url = format!("{}/api/order-book-broadcast", tracker_url);
-
-            match client.post(&url).json(&order_book_update).send().await {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        let body = response.text().await.unwrap_or_else(|_| "".to_string());
-                        println!("Order book broadcast successful: {}", body);
-                    } else {
-                        let status = response.status();
-                        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                        println!("Order book broadcast failed: {} - {}", status, error_text);
-                    }
-                }
-                Err(e) => {
-                    println!("Failed to broadcast order book: {}", e);
-                }
-            }
+            broadcast_order_book(all_orders, &cancelled, tracker_url).await;
         }
     }
 
@@ -694,11 +1111,51 @@ async fn handle_tenant_blockchain_update(
         "orders_processed": orders_processed,
         "trades_executed": all_trades.len(),
         "trades": all_trades
-    })))
+    })).into_response())
 }
 
 
-async fn send_trade_to_tracker(trade: &crate::enterprise_bc::order_engine::Trade, tracker_url: &str) {
+// Current order book plus any orders cancelled without a trade (expired or
+// from a silent network), so every network can drop them
+pub(crate) async fn broadcast_order_book(
+    orders: serde_json::Value,
+    cancelled: &[crate::enterprise_bc::order_engine::CancelledOrder],
+    tracker_url: &str,
+) {
+    println!("Broadcasting updated order book to tracker");
+
+    let order_book_update = serde_json::json!({
+        "type": "order_book_broadcast",
+        "orders": orders,
+        "cancelled": cancelled,
+        "timestamp": std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    });
+
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/order-book-broadcast", tracker_url);
+
+    match auth::with_api_key(client.post(&url)).json(&order_book_update).send().await {
+        Ok(response) => {
+            if response.status().is_success() {
+                let body = response.text().await.unwrap_or_else(|_| "".to_string());
+                println!("Order book broadcast successful: {}", body);
+            } else {
+                let status = response.status();
+                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                println!("Order book broadcast failed: {} - {}", status, error_text);
+            }
+        }
+        Err(e) => {
+            println!("Failed to broadcast order book: {}", e);
+        }
+    }
+}
+
+// True once the tracker has accepted the trade
+pub(crate) async fn send_trade_to_tracker(trade: &crate::enterprise_bc::order_engine::Trade, tracker_url: &str) -> bool {
     let trade_notification = serde_json::json!({
         "type": "cross_network_trade",
         "trade_id": trade.trade_id,
@@ -717,16 +1174,129 @@ async fn send_trade_to_tracker(trade: &crate::enterprise_bc::order_engine::Trade
     
     println!("Sending trade notification to tracker: {}", url);
     
-    match client.post(&url).json(&trade_notification).send().await {
+    match auth::with_api_key(client.post(&url)).json(&trade_notification).send().await {
         Ok(response) => {
             if response.status().is_success() {
                 println!("Successfully sent trade {} to tracker", trade.trade_id);
+                true
             } else {
                 println!("Failed to send trade to tracker: HTTP {}", response.status());
+                false
             }
         }
         Err(e) => {
             println!("Failed to send trade to tracker: {}", e);
+            false
+        }
+    }
+}
+
+// Send opened and decided settlements to both networks of each trade, and
+// record and deliver the committed trades
+pub(crate) async fn publish_settlements(
+    order_engine: &Arc<RwLock<EnterpriseOrderEngine>>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    tracker_url: Option<&str>,
+    market_stream: &MarketStream,
+) {
+    let updates = {
+        let mut engine = order_engine.write().await;
+        market_stream.publish_books(&engine);
+        engine.take_settlement_updates()
+    };
+    let committed: Vec<_> = updates.iter()
+        .filter(|s| matches!(s.status, SettlementStatus::Committed { .. }))
+        .map(|s| s.trade.clone())
+        .collect();
+    for settlement in &updates {
+        market_stream.publish_settlement(settlement);
+    }
+    for trade in &committed {
+        market_stream.publish_trade(trade);
+    }
+    if !committed.is_empty() {
+        let mut bc = blockchain.write().await;
+        for trade in &committed {
+            trade.record_on_chain(&mut bc);
+        }
+    }
+
+    let tracker_url = match tracker_url {
+        Some(tracker_url) => tracker_url,
+        None => {
+            if !updates.is_empty() {
+                println!("No tracker URL configured - settlements not sent");
+            }
+            return;
+        }
+    };
+    for settlement in &updates {
+        send_settlement_to_tracker(settlement, tracker_url).await;
+    }
+
+    let mut delivered = Vec::new();
+    for trade in &committed {
+        println!("Sending trade notification: {} {} {} @ {} between {} and {}",
+                 trade.trade_id, trade.quantity, trade.asset, trade.price,
+                 trade.buyer_network, trade.seller_network);
+        if send_trade_to_tracker(trade, tracker_url).await {
+            delivered.push(trade.trade_id.clone());
+        }
+    }
+    order_engine.write().await.mark_broadcast(&delivered);
+}
+
+// True once the tracker has accepted it
+async fn send_settlement_to_tracker(settlement: &Settlement, tracker_url: &str) -> bool {
+    let notification = serde_json::json!({
+        "type": "settlement",
+        "phase": settlement.phase(),
+        "trade": settlement.trade,
+        "status": settlement.status,
+        "escrow": settlement.escrow()
+    });
+
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/settlement", tracker_url);
+
+    match auth::with_api_key(client.post(&url)).json(&notification).send().await {
+        Ok(response) => {
+            if response.status().is_success() {
+                println!("Sent settlement {} for trade {}", settlement.phase(), settlement.trade_id());
+                true
+            } else {
+                println!("Failed to send settlement to tracker: HTTP {}", response.status());
+                false
+            }
+        }
+        Err(e) => {
+            println!("Failed to send settlement to tracker: {}", e);
+            false
+        }
+    }
+}
+
+// True once the tracker has accepted the ack
+pub(crate) async fn send_order_ack_to_tracker(ack: &crate::enterprise_bc::order_engine::OrderAck, tracker_url: &str) -> bool {
+    let mut notification = serde_json::json!(ack);
+    notification["type"] = serde_json::json!("order_ack");
+
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/order-ack", tracker_url);
+
+    match auth::with_api_key(client.post(&url)).json(&notification).send().await {
+        Ok(response) => {
+            if response.status().is_success() {
+                println!("Sent {} ack for order {} to network {}", ack.action, ack.order_id, ack.network_id);
+                true
+            } else {
+                println!("Failed to send order ack to tracker: HTTP {}", response.status());
+                false
+            }
+        }
+        Err(e) => {
+            println!("Failed to send order ack to tracker: {}", e);
+            false
         }
     }
 }
@@ -753,6 +1323,370 @@ async fn handle_order_book_status(
     Ok(warp::reply::json(&response))
 }
 
+async fn handle_settlement_vote(
+    vote: SettlementVote,
+    blockchain: Arc<RwLock<Blockchain>>,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>,
+    tracker_url: Option<String>,
+    market_stream: Arc<MarketStream>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let result = order_engine.write().await
+        .vote(&vote.trade_id, &vote.network_id, vote.prepared, vote.reason.as_deref(), now);
+    publish_settlements(&order_engine, &blockchain, tracker_url.as_deref(), &market_stream).await;
+
+    match result {
+        Ok(status) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "success",
+            "trade_id": vote.trade_id,
+            "settlement": status
+        }))),
+        Err(e) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "error",
+            "trade_id": vote.trade_id,
+            "message": e
+        }))),
+    }
+}
+
+async fn handle_settlement_status(
+    trade_id: String,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match order_engine.read().await.settlements.get(&trade_id) {
+        Some(settlement) => Ok(warp::reply::json(settlement)),
+        None => Ok(warp::reply::json(&serde_json::json!({
+            "error": "No settlement for this trade",
+            "trade_id": trade_id
+        }))),
+    }
+}
+
+async fn handle_settlements(
+    query: SettlementsQuery,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let engine = order_engine.read().await;
+    let settlements: Vec<&Settlement> = engine.settlements.values()
+        .filter(|s| match query.state.as_deref() {
+            Some("preparing") => s.is_preparing(),
+            Some("committed") => matches!(s.status, SettlementStatus::Committed { .. }),
+            Some("aborted") => matches!(s.status, SettlementStatus::Aborted { .. }),
+            _ => true,
+        })
+        .collect();
+    Ok(warp::reply::json(&serde_json::json!({
+        "settlements": settlements,
+        "count": settlements.len()
+    })))
+}
+
+async fn handle_escrow(
+    network_id: String,
+    trader: String,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let locked = order_engine.read().await.escrow(&network_id, &trader);
+    Ok(warp::reply::json(&serde_json::json!({
+        "network_id": network_id,
+        "trader": trader,
+        "locked": locked
+    })))
+}
+
+async fn handle_surveillance_alerts(
+    query: AlertsQuery,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let kind = match query.kind.as_deref().map(|kind| (kind, AlertKind::parse(kind))) {
+        Some((kind, None)) => return Ok(warp::reply::json(&serde_json::json!({
+            "status": "error",
+            "message": format!("unknown alert kind {:?}", kind)
+        }))),
+        Some((_, kind)) => kind,
+        None => None,
+    };
+    let engine = order_engine.read().await;
+    let limit = query.limit.unwrap_or(100).min(1000);
+    let alerts = engine.surveillance.alerts(kind, query.trader.as_deref(), query.open.unwrap_or(false), limit);
+    Ok(warp::reply::json(&serde_json::json!({
+        "self_trade_prevention": engine.book.self_trade_prevention(),
+        "open": engine.surveillance.open_alerts(),
+        "alerts": alerts,
+        "count": alerts.len()
+    })))
+}
+
+async fn handle_surveillance_alert(
+    alert_id: String,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match order_engine.read().await.surveillance.alert(&alert_id) {
+        Some(alert) => Ok(warp::reply::json(alert)),
+        None => Ok(warp::reply::json(&serde_json::json!({
+            "error": "No such alert",
+            "alert_id": alert_id
+        }))),
+    }
+}
+
+async fn handle_surveillance_review(
+    alert_id: String,
+    review: AlertReview,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let result = match ReviewStatus::parse(&review.status) {
+        _ if review.reviewer.trim().is_empty() => Err("a review needs a reviewer".to_string()),
+        Some(status) => order_engine.write().await
+            .review_alert(&alert_id, status, review.reviewer.trim(), &review.note, unix_now()),
+        None => Err(format!("review status must be dismissed or escalated, not {:?}", review.status)),
+    };
+    match result {
+        Ok(alert) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "success",
+            "alert": alert
+        }))),
+        Err(e) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "error",
+            "alert_id": alert_id,
+            "message": e
+        }))),
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+async fn handle_market_depth(
+    asset: String,
+    query: DepthQuery,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let depth = order_engine.read().await.depth(&asset, query.levels.unwrap_or(20));
+    Ok(warp::reply::json(&depth))
+}
+
+async fn handle_market_tickers(
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let tickers = order_engine.read().await.tickers(unix_now());
+    Ok(warp::reply::json(&serde_json::json!({
+        "tickers": tickers,
+        "count": tickers.len()
+    })))
+}
+
+async fn handle_market_ticker(
+    asset: String,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ticker = order_engine.read().await.ticker(&asset, unix_now());
+    Ok(warp::reply::json(&ticker))
+}
+
+async fn handle_market_candles(
+    asset: String,
+    query: CandlesQuery,
+    order_engine: Arc<RwLock<EnterpriseOrderEngine>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let interval_name = query.interval.unwrap_or_else(|| "1m".to_string());
+    let interval = match parse_interval(&interval_name) {
+        Ok(interval) => interval,
+        Err(e) => return Ok(warp::reply::json(&serde_json::json!({
+            "status": "error",
+            "message": e
+        }))),
+    };
+    let limit = query.limit.unwrap_or(100).min(1000);
+    let candles = order_engine.read().await.market_data.candles(
+        &asset,
+        interval,
+        query.from.unwrap_or(0),
+        query.to.unwrap_or(u64::MAX),
+        limit,
+    );
+    Ok(warp::reply::json(&serde_json::json!({
+        "asset": asset,
+        "interval": interval_name,
+        "interval_secs": interval,
+        "candles": candles
+    })))
+}
+
+async fn handle_balances(
+    account: String,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let balances = blockchain.read().await.balances(&account);
+    Ok(warp::reply::json(&serde_json::json!({
+        "account": account,
+        "balances": balances
+    })))
+}
+
+// Queued for the next block; refused unless this node's key is a validator's
+async fn handle_ledger_issue(
+    request: IssueRequest,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match blockchain.write().await.mint(&request.to, &request.asset, request.amount) {
+        Ok(tx_id) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "success",
+            "tx_id": tx_id,
+            "to": request.to,
+            "asset": request.asset,
+            "amount": request.amount
+        }))),
+        Err(e) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "error",
+            "message": e
+        }))),
+    }
+}
+
+async fn handle_staking_overview(
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let bc = blockchain.read().await;
+    let config = bc.staking_config().copied();
+    let next_epoch = config.map(|config| {
+        let epoch = config.epoch_length.max(1);
+        (bc.height() / epoch + 1) * epoch
+    });
+    let pools: Vec<serde_json::Value> = bc.staking().pools().iter().map(|(validator, pool)| serde_json::json!({
+        "validator": validator,
+        "self_bond": pool.self_bond(validator),
+        "bonded": pool.bonded,
+        "delegators": pool.bonds.len(),
+        "jailed": pool.is_jailed(bc.height()),
+        "jailed_until": pool.jailed_until
+    })).collect();
+    Ok(warp::reply::json(&serde_json::json!({
+        "enabled": config.is_some(),
+        "config": config,
+        "height": bc.height(),
+        "next_epoch_at": next_epoch,
+        "validators": bc.validator_set(),
+        "total_stake": bc.get_total_stake(),
+        "pools": pools,
+        "unbonding": bc.staking().unbonding()
+    })))
+}
+
+async fn handle_staking_account(
+    account: String,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let bc = blockchain.read().await;
+    let unbonding: Vec<_> = bc.staking().unbonding().iter()
+        .filter(|unbonding| unbonding.delegator == account)
+        .collect();
+    Ok(warp::reply::json(&serde_json::json!({
+        "account": account,
+        "bonds": bc.staking().bonds_of(&account),
+        "unbonding": unbonding
+    })))
+}
+
+// Queued for the next block, signed with this node's key
+async fn handle_staking_submit(
+    request: StakingRequest,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let validator = request.validator.clone();
+    let tx_type = match request.action.trim().to_ascii_lowercase().as_str() {
+        "stake" => Some(TransactionType::Stake),
+        "delegate" => Some(TransactionType::Delegate { validator }),
+        "unbond" => Some(TransactionType::Unbond { validator }),
+        "claim" => Some(TransactionType::ClaimRewards { validator }),
+        _ => None,
+    };
+    let amount = if matches!(tx_type, Some(TransactionType::ClaimRewards { .. })) { 0 } else { request.amount };
+    let result = match tx_type {
+        Some(tx_type) => blockchain.write().await.queue_staking(&request.account, tx_type, amount),
+        None => Err(format!("action must be stake, delegate, unbond or claim, not {:?}", request.action)),
+    };
+    match result {
+        Ok(tx_id) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "success",
+            "tx_id": tx_id,
+            "action": request.action,
+            "account": request.account,
+            "amount": amount
+        }))),
+        Err(e) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "error",
+            "message": e
+        }))),
+    }
+}
+
+async fn handle_tenant_registry(
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let bc = blockchain.read().await;
+    Ok(warp::reply::json(&serde_json::json!({
+        "registration_required": tenants::registration_required(),
+        "tenants": bc.tenants().tenants()
+    })))
+}
+
+async fn handle_tenant_record(
+    network_id: String,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let bc = blockchain.read().await;
+    match bc.tenants().get(&network_id) {
+        Some(record) => Ok(warp::reply::json(record).into_response()),
+        None => Ok(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({
+                "status": "error",
+                "message": format!("network {} is not registered", network_id)
+            })),
+            warp::http::StatusCode::NOT_FOUND,
+        ).into_response()),
+    }
+}
+
+// Queued for the next block, signed with this node's key
+async fn handle_tenant_registry_submit(
+    request: TenantRegistryRequest,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let network_id = request.network_id.clone();
+    let public_key = request.public_key.clone();
+    let tx_type = match request.action.trim().to_ascii_lowercase().as_str() {
+        "register" => Some(TransactionType::TenantRegister { network_id, public_key }),
+        "rotate" => Some(TransactionType::TenantRotateKey { network_id, public_key }),
+        "suspend" => Some(TransactionType::TenantSuspend { network_id }),
+        "reinstate" => Some(TransactionType::TenantReinstate { network_id }),
+        _ => None,
+    };
+    let result = match tx_type {
+        Some(tx_type) => blockchain.write().await.queue_registry(tx_type),
+        None => Err(format!("action must be register, rotate, suspend or reinstate, not {:?}", request.action)),
+    };
+    match result {
+        Ok(tx_id) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "success",
+            "tx_id": tx_id,
+            "action": request.action,
+            "network_id": request.network_id
+        }))),
+        Err(e) => Ok(warp::reply::json(&serde_json::json!({
+            "status": "error",
+            "message": e
+        }))),
+    }
+}
+
 async fn handle_debug_orders(
     order_engine: Arc<RwLock<EnterpriseOrderEngine>>
 ) -> Result<impl warp::Reply, warp::Rejection> {
@@ -781,13 +1715,48 @@ async fn handle_status(
         "total_transactions": pending_count,
         "active_validators": validator_count,
         "active_tenants": tenant_summaries.len(),
-        "chain_health": "healthy",
-        "consensus": "proof_of_stake"
+        "chain_health": if bc.storage_error().is_some() { "storage_error" } else { "healthy" },
+        "storage_error": bc.storage_error(),
+        "consensus": "bft_proof_of_stake",
+        "chain_height": bc.height(),
+        "fee_schedule": bc.fee_schedule()
     });
     
     Ok(warp::reply::json(&status))
 }
 
+async fn handle_consensus_message(
+    message: ConsensusMessage,
+    consensus: ConsensusHandle
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let accepted = consensus.inbox.send(message).is_ok();
+    Ok(warp::reply::json(&serde_json::json!({
+        "status": if accepted { "accepted" } else { "error" }
+    })))
+}
+
+// Height tells a validator starting out whether the genesis set is still open
+async fn handle_consensus_info(
+    consensus: ConsensusHandle,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&serde_json::json!({
+        "validator_id": consensus.validator_id,
+        "stake": consensus.stake,
+        "public_key": consensus.public_key,
+        "height": blockchain.read().await.height()
+    })))
+}
+
+async fn handle_consensus_status(
+    consensus: ConsensusHandle,
+    blockchain: Arc<RwLock<Blockchain>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let bc = blockchain.read().await;
+    let status = consensus.engine.read().await.status(&bc);
+    Ok(warp::reply::json(&status))
+}
+
 async fn handle_blocks(
     query: BlocksQuery,
     blockchain: Arc<RwLock<Blockchain>>
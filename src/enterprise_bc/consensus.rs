@@ -0,0 +1,893 @@
+// src/enterprise_bc/consensus.rs - Tendermint-style BFT consensus between enterprise validators
+//
+// Each height runs one or more rounds. In every round the scheduled proposer
+// broadcasts a block, validators prevote for it (or nil), and once a 2/3+ stake
+// quorum of prevotes agrees they precommit. A 2/3+ quorum of precommits for the
+// same block finalizes it through Blockchain::add_block.
+//
+// The engine does no I/O: callers feed it messages and clock ticks and ship the
+// returned messages to the other validators. The validator wires it to HTTP,
+// the tests wire it to an in-process message queue.
+//
+// A proposer that sends two different blocks for one round is reported with
+// double signing evidence, and after every commit validators that keep missing
+// their proposals are reported for downtime (see slashing.rs).
+//
+// A validator that missed blocks, or the proposal of a block the others
+// committed, learns so from a 2/3+ quorum of precommits for a block it does
+// not have. catch_up_target says which; the caller fetches the blocks up to
+// it from a peer and hands them to apply_fetched, which only takes a run of
+// blocks that hash-links our tip to the certified one. The quorum is counted
+// with our current validator set.
+use crate::blockchain::slashing::Evidence;
+use crate::blockchain::{Block, Blockchain};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Step {
+    Propose,
+    Prevote,
+    Precommit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VoteType {
+    Prevote,
+    Precommit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vote {
+    pub vote_type: VoteType,
+    pub height: u32,
+    pub round: u32,
+    pub block_hash: Option<String>, // None is a nil vote
+    pub validator: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl Vote {
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&(&self.vote_type, self.height, self.round, &self.block_hash, &self.validator))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ConsensusMessage {
+    #[serde(rename = "proposal")]
+    Proposal {
+        height: u32,
+        round: u32,
+        block: Box<Block>,
+        valid_round: Option<u32>,
+    },
+
+    #[serde(rename = "vote")]
+    Vote { vote: Vote },
+}
+
+impl ConsensusMessage {
+    pub fn height(&self) -> u32 {
+        match self {
+            ConsensusMessage::Proposal { height, .. } => *height,
+            ConsensusMessage::Vote { vote } => vote.height,
+        }
+    }
+
+    pub fn round(&self) -> u32 {
+        match self {
+            ConsensusMessage::Proposal { round, .. } => *round,
+            ConsensusMessage::Vote { vote } => vote.round,
+        }
+    }
+
+    pub fn sender(&self) -> &str {
+        match self {
+            ConsensusMessage::Proposal { block, .. } => &block.validator,
+            ConsensusMessage::Vote { vote } => &vote.validator,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsensusConfig {
+    pub propose_timeout_ms: u64,
+    pub prevote_timeout_ms: u64,
+    pub precommit_timeout_ms: u64,
+    // Added to every timeout per round so slow networks eventually converge
+    pub round_increment_ms: u64,
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        Self {
+            propose_timeout_ms: 3000,
+            prevote_timeout_ms: 1000,
+            precommit_timeout_ms: 1000,
+            round_increment_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsensusStatus {
+    pub validator_id: String,
+    pub height: u32,
+    pub round: u32,
+    pub step: Step,
+    pub locked_round: Option<u32>,
+    pub validators: Vec<(String, u64)>,
+}
+
+// Shared with the API server so peers can deliver messages and query state
+#[derive(Clone)]
+pub struct ConsensusHandle {
+    pub validator_id: String,
+    pub stake: u64,
+    pub public_key: String,
+    pub engine: Arc<RwLock<ConsensusEngine>>,
+    pub inbox: mpsc::UnboundedSender<ConsensusMessage>,
+}
+
+type VoteSet = HashMap<u32, HashMap<String, Option<String>>>; // round -> validator -> block hash
+
+pub struct ConsensusEngine {
+    validator_id: String,
+    config: ConsensusConfig,
+    height: u32,
+    round: u32,
+    step: Step,
+    step_started_at: u64,
+    proposals: HashMap<u32, Block>,      // round -> proposed block
+    proposal_valid_rounds: HashMap<u32, Option<u32>>,
+    blocks: HashMap<String, Block>,      // every proposed block at this height by hash
+    prevotes: VoteSet,
+    precommits: VoteSet,
+    locked: Option<(u32, Block)>,
+    valid: Option<(u32, Block)>,
+    proposed_in_round: Option<u32>,
+    future_messages: Vec<ConsensusMessage>,
+    future_precommits: HashMap<u32, VoteSet>, // height -> precommits beyond the current height
+    certified: Option<(u32, String)>,         // highest committed block we lack: height and hash
+    evidence: Vec<Evidence>, // found while recording, not submitted yet
+}
+
+// Heights ahead of ours whose precommits are kept to spot a commit quorum
+const MAX_FUTURE_HEIGHTS: usize = 64;
+// Messages for the next height kept per validator until we get there
+const MAX_FUTURE_MESSAGES_PER_SENDER: usize = 64;
+
+impl ConsensusEngine {
+    pub fn new(validator_id: String, config: ConsensusConfig) -> Self {
+        Self {
+            validator_id,
+            config,
+            height: 0,
+            round: 0,
+            step: Step::Propose,
+            step_started_at: 0,
+            proposals: HashMap::new(),
+            proposal_valid_rounds: HashMap::new(),
+            blocks: HashMap::new(),
+            prevotes: HashMap::new(),
+            precommits: HashMap::new(),
+            locked: None,
+            valid: None,
+            proposed_in_round: None,
+            future_messages: Vec::new(),
+            future_precommits: HashMap::new(),
+            certified: None,
+            evidence: Vec::new(),
+        }
+    }
+
+    pub fn validator_id(&self) -> &str {
+        &self.validator_id
+    }
+
+    pub fn status(&self, bc: &Blockchain) -> ConsensusStatus {
+        ConsensusStatus {
+            validator_id: self.validator_id.clone(),
+            height: self.height,
+            round: self.round,
+            step: self.step,
+            locked_round: self.locked.as_ref().map(|(r, _)| *r),
+            validators: bc.validator_set(),
+        }
+    }
+
+    // Proposer schedule for the height on top of our current tip
+    pub fn proposer_for(bc: &Blockchain, height: u32, round: u32) -> Option<String> {
+        if height != bc.height() + 1 {
+            return None;
+        }
+        bc.select_proposer(&bc.get_latest().hash, height, round)
+    }
+
+    // Drive timeouts and our own proposals. Call this periodically.
+    pub fn tick(&mut self, bc: &mut Blockchain, now: u64) -> Vec<ConsensusMessage> {
+        let mut out = Vec::new();
+        self.sync_height(bc, now);
+
+        if self.step == Step::Propose {
+            self.try_propose(bc, now, &mut out);
+        }
+
+        let elapsed = now.saturating_sub(self.step_started_at);
+        let extra = self.config.round_increment_ms * self.round as u64;
+        match self.step {
+            Step::Propose => {
+                if elapsed >= self.config.propose_timeout_ms + extra {
+                    if self.is_idle(bc) {
+                        // Nothing to agree on yet; keep waiting instead of burning rounds
+                        self.step_started_at = now;
+                    } else {
+                        println!("Consensus {}: propose timeout at {}/{}, prevoting nil",
+                                 self.validator_id, self.height, self.round);
+                        self.cast_vote(bc, VoteType::Prevote, None, now, &mut out);
+                    }
+                }
+            }
+            Step::Prevote => {
+                if elapsed >= self.config.prevote_timeout_ms + extra {
+                    self.cast_vote(bc, VoteType::Precommit, None, now, &mut out);
+                }
+            }
+            Step::Precommit => {
+                if elapsed >= self.config.precommit_timeout_ms + extra {
+                    let next_round = self.round + 1;
+                    self.start_round(next_round, now);
+                    self.try_propose(bc, now, &mut out);
+                }
+            }
+        }
+
+        self.advance(bc, now, &mut out);
+        out
+    }
+
+    // Handle a message from another validator (or our own loopback)
+    pub fn handle_message(&mut self, bc: &mut Blockchain, message: ConsensusMessage, now: u64) -> Vec<ConsensusMessage> {
+        let mut out = Vec::new();
+        self.sync_height(bc, now);
+
+        if message.height() > self.height {
+            // A peer is ahead of us; keep it until we commit our current height.
+            // Only signed messages of our validators, so nobody else can crowd
+            // out theirs, and a bounded number from each.
+            if !Self::is_authentic(bc, &message) {
+                return out;
+            }
+            self.record_future_precommit(bc, &message);
+            let buffered = self.future_messages.iter().filter(|m| m.sender() == message.sender()).count();
+            if message.height() == self.height + 1 && buffered < MAX_FUTURE_MESSAGES_PER_SENDER {
+                self.future_messages.push(message);
+            }
+            return out;
+        }
+        if message.height() < self.height {
+            return out;
+        }
+
+        let recorded = self.record(bc, message);
+        self.submit_evidence(bc);
+        if recorded {
+            self.skip_round_if_behind(bc, now, &mut out);
+            self.advance(bc, now, &mut out);
+        }
+        out
+    }
+
+    // Height and hash of the furthest block a quorum committed that we do not
+    // have, until we have it
+    pub fn catch_up_target(&self) -> Option<(u32, String)> {
+        self.certified.clone()
+    }
+
+    // Blocks fetched from a peer for catch_up_target, oldest first. Only those
+    // leading from our tip to the certified block are added; returns how many.
+    pub fn apply_fetched(&mut self, bc: &mut Blockchain, blocks: Vec<Block>, now: u64) -> Result<u32, String> {
+        let (target_height, target_hash) = self.certified.clone().ok_or("nothing to catch up on")?;
+        let from = bc.height();
+        let run: Vec<Block> = blocks.into_iter()
+            .filter(|block| block.height > from && block.height <= target_height)
+            .collect();
+        let linked = run.len() as u32 == target_height - from
+            && run.last().is_some_and(|block| block.hash == target_hash)
+            && run.iter().zip(run.iter().skip(1)).all(|(parent, child)| child.previous_hash == parent.hash && child.height == parent.height + 1)
+            && run.first().is_some_and(|block| block.previous_hash == bc.get_latest().hash);
+        if !linked {
+            return Err(format!("blocks do not lead from height {} to the committed block {} at {}", from, target_hash, target_height));
+        }
+        for block in run {
+            let height = block.height;
+            if !bc.add_block(block) {
+                self.sync_height(bc, now);
+                return Err(format!("fetched block {} does not validate", height));
+            }
+        }
+        println!("Consensus {}: caught up from height {} to {}", self.validator_id, from, target_height);
+        self.sync_height(bc, now);
+        Ok(target_height - from)
+    }
+
+    // Sent by one of our validators and signed with its key
+    fn is_authentic(bc: &Blockchain, message: &ConsensusMessage) -> bool {
+        if !bc.validator_set().iter().any(|(id, _)| id == message.sender()) {
+            return false;
+        }
+        match message {
+            ConsensusMessage::Proposal { block, .. } => bc.verify_block_signatures(block).is_ok(),
+            ConsensusMessage::Vote { vote } => vote.signature.as_deref()
+                .is_some_and(|sig| bc.verify_validator_signature(&vote.validator, &vote.signing_bytes(), sig)),
+        }
+    }
+
+    // A signed precommit for a later height; a quorum of them means we fell behind
+    fn record_future_precommit(&mut self, bc: &Blockchain, message: &ConsensusMessage) {
+        let vote = match message {
+            ConsensusMessage::Vote { vote } if vote.vote_type == VoteType::Precommit && vote.block_hash.is_some() => vote,
+            _ => return,
+        };
+        let known_height = self.future_precommits.contains_key(&vote.height);
+        if !known_height && self.future_precommits.len() >= MAX_FUTURE_HEIGHTS {
+            return;
+        }
+        let votes = self.future_precommits.entry(vote.height).or_default();
+        votes.entry(vote.round).or_default().entry(vote.validator.clone()).or_insert_with(|| vote.block_hash.clone());
+        if let Some(Some(hash)) = self.quorum(bc, &self.future_precommits[&vote.height], vote.round) {
+            self.certify(vote.height, hash);
+        }
+    }
+
+    fn certify(&mut self, height: u32, hash: String) {
+        if self.certified.as_ref().is_some_and(|(known, _)| *known >= height) {
+            return;
+        }
+        println!("Consensus {}: validators committed block {} at height {}, which we lack",
+                 self.validator_id, &hash[..16.min(hash.len())], height);
+        self.certified = Some((height, hash));
+    }
+
+    fn submit_evidence(&mut self, bc: &mut Blockchain) {
+        for evidence in self.evidence.drain(..) {
+            let validator = evidence.validator().to_string();
+            match bc.submit_evidence(evidence) {
+                Ok(tx_id) => println!("Consensus {}: submitted evidence against {} ({})", self.validator_id, validator, tx_id),
+                Err(e) => println!("Consensus {}: evidence against {} not submitted: {}", self.validator_id, validator, e),
+            }
+        }
+    }
+
+    fn sync_height(&mut self, bc: &Blockchain, now: u64) {
+        let next_height = bc.height() + 1;
+        if self.height != next_height {
+            self.start_height(next_height, now);
+            let buffered = std::mem::take(&mut self.future_messages);
+            for message in buffered {
+                if message.height() == self.height {
+                    self.record(bc, message);
+                }
+            }
+        }
+    }
+
+    fn start_height(&mut self, height: u32, now: u64) {
+        self.height = height;
+        self.future_precommits.retain(|future, _| *future >= height);
+        if self.certified.as_ref().is_some_and(|(certified, _)| *certified < height) {
+            self.certified = None;
+        }
+        self.proposals.clear();
+        self.proposal_valid_rounds.clear();
+        self.blocks.clear();
+        self.prevotes.clear();
+        self.precommits.clear();
+        self.locked = None;
+        self.valid = None;
+        self.start_round(0, now);
+    }
+
+    fn start_round(&mut self, round: u32, now: u64) {
+        if round > 0 {
+            println!("Consensus {}: height {} moving to round {}", self.validator_id, self.height, round);
+        }
+        self.round = round;
+        self.step = Step::Propose;
+        self.step_started_at = now;
+        self.proposed_in_round = None;
+    }
+
+    fn is_idle(&self, bc: &Blockchain) -> bool {
+        bc.get_pending_count() == 0
+            && self.round == 0
+            && self.proposals.is_empty()
+            && self.prevotes.is_empty()
+            && self.precommits.is_empty()
+    }
+
+    fn try_propose(&mut self, bc: &mut Blockchain, now: u64, out: &mut Vec<ConsensusMessage>) {
+        if self.proposed_in_round == Some(self.round) {
+            return;
+        }
+        if Self::proposer_for(bc, self.height, self.round).as_deref() != Some(self.validator_id.as_str()) {
+            return;
+        }
+
+        // Re-propose a block that already gathered a polka, otherwise build a fresh one
+        let (block, valid_round) = match &self.valid {
+            Some((round, block)) => (block.clone(), Some(*round)),
+            None => match bc.create_block_proposal(&self.validator_id, self.round) {
+                Some(block) => (block, None),
+                None => return,
+            },
+        };
+
+        self.proposed_in_round = Some(self.round);
+        println!("Consensus {}: proposing block {} at height {} round {}",
+                 self.validator_id, &block.hash[..16.min(block.hash.len())], self.height, self.round);
+
+        let proposal = ConsensusMessage::Proposal {
+            height: self.height,
+            round: self.round,
+            block: Box::new(block),
+            valid_round,
+        };
+        out.push(proposal.clone());
+        self.record(bc, proposal);
+        self.advance(bc, now, out);
+    }
+
+    // Store a message if it is well-formed and from a current validator
+    fn record(&mut self, bc: &Blockchain, message: ConsensusMessage) -> bool {
+        if !bc.validator_set().iter().any(|(id, _)| id == message.sender()) {
+            return false;
+        }
+
+        match message {
+            ConsensusMessage::Proposal { height, round, block, valid_round } => {
+                if Self::proposer_for(bc, height, round).as_deref() != Some(block.validator.as_str()) {
+                    println!("Consensus {}: ignoring proposal from non-proposer {}", self.validator_id, block.validator);
+                    return false;
+                }
+                if block.height != height || block.round != round {
+                    return false;
+                }
+                if let Err(e) = bc.verify_block_signatures(&block) {
+                    println!("Consensus {}: ignoring proposal from {}: {}", self.validator_id, block.validator, e);
+                    return false;
+                }
+                if let Some(first) = self.proposals.get(&round) {
+                    if first.hash != block.hash {
+                        println!("Consensus {}: {} proposed two blocks at {}/{}", self.validator_id, block.validator, height, round);
+                        self.evidence.push(Evidence::double_sign(first, &block));
+                    }
+                    return false;
+                }
+                self.blocks.insert(block.hash.clone(), (*block).clone());
+                self.proposals.insert(round, *block);
+                self.proposal_valid_rounds.insert(round, valid_round);
+                true
+            }
+            ConsensusMessage::Vote { vote } => {
+                let signed = vote.signature.as_deref()
+                    .is_some_and(|sig| bc.verify_validator_signature(&vote.validator, &vote.signing_bytes(), sig));
+                if !signed {
+                    println!("Consensus {}: ignoring unsigned vote from {}", self.validator_id, vote.validator);
+                    return false;
+                }
+                let votes = match vote.vote_type {
+                    VoteType::Prevote => &mut self.prevotes,
+                    VoteType::Precommit => &mut self.precommits,
+                };
+                let round_votes = votes.entry(vote.round).or_default();
+                if round_votes.contains_key(&vote.validator) {
+                    return false;
+                }
+                round_votes.insert(vote.validator, vote.block_hash);
+                true
+            }
+        }
+    }
+
+    fn cast_vote(&mut self, bc: &mut Blockchain, vote_type: VoteType, block_hash: Option<String>, now: u64, out: &mut Vec<ConsensusMessage>) {
+        let mut vote = Vote {
+            vote_type,
+            height: self.height,
+            round: self.round,
+            block_hash,
+            validator: self.validator_id.clone(),
+            signature: None,
+        };
+        vote.signature = Some(bc.sign_with_node_key(&vote.signing_bytes()));
+        let message = ConsensusMessage::Vote { vote };
+        self.step = match vote_type {
+            VoteType::Prevote => Step::Prevote,
+            VoteType::Precommit => Step::Precommit,
+        };
+        self.step_started_at = now;
+        out.push(message.clone());
+        self.record(bc, message);
+    }
+
+    // Apply every state transition the recorded messages allow
+    fn advance(&mut self, bc: &mut Blockchain, now: u64, out: &mut Vec<ConsensusMessage>) {
+        loop {
+            if self.try_commit(bc, now, out) {
+                return;
+            }
+
+            let before = (self.round, self.step);
+            match self.step {
+                Step::Propose => self.on_proposal(bc, now, out),
+                Step::Prevote => self.on_prevotes(bc, now, out),
+                Step::Precommit => {
+                    if let Some(None) = self.quorum(bc, &self.precommits, self.round) {
+                        let next_round = self.round + 1;
+                        self.start_round(next_round, now);
+                        self.try_propose(bc, now, out);
+                    }
+                }
+            }
+            if (self.round, self.step) == before {
+                return;
+            }
+        }
+    }
+
+    fn on_proposal(&mut self, bc: &mut Blockchain, now: u64, out: &mut Vec<ConsensusMessage>) {
+        let block = match self.proposals.get(&self.round) {
+            Some(block) => block.clone(),
+            None => return,
+        };
+        let valid_round = self.proposal_valid_rounds.get(&self.round).copied().flatten();
+
+        let unlocked = match &self.locked {
+            None => true,
+            Some((_, locked_block)) if locked_block.hash == block.hash => true,
+            Some((locked_round, _)) => match valid_round {
+                Some(vr) => vr >= *locked_round
+                    && self.quorum(bc, &self.prevotes, vr) == Some(Some(block.hash.clone())),
+                None => false,
+            },
+        };
+
+        let vote = if unlocked && bc.validate_block(&block) {
+            Some(block.hash.clone())
+        } else {
+            None
+        };
+        self.cast_vote(bc, VoteType::Prevote, vote, now, out);
+    }
+
+    fn on_prevotes(&mut self, bc: &mut Blockchain, now: u64, out: &mut Vec<ConsensusMessage>) {
+        match self.quorum(bc, &self.prevotes, self.round) {
+            Some(Some(hash)) => {
+                if let Some(block) = self.blocks.get(&hash).cloned() {
+                    self.locked = Some((self.round, block.clone()));
+                    self.valid = Some((self.round, block));
+                    self.cast_vote(bc, VoteType::Precommit, Some(hash), now, out);
+                }
+            }
+            Some(None) => self.cast_vote(bc, VoteType::Precommit, None, now, out),
+            None => {}
+        }
+    }
+
+    fn try_commit(&mut self, bc: &mut Blockchain, now: u64, out: &mut Vec<ConsensusMessage>) -> bool {
+        let rounds: Vec<u32> = self.precommits.keys().copied().collect();
+        for round in rounds {
+            if let Some(Some(hash)) = self.quorum(bc, &self.precommits, round) {
+                if !self.blocks.contains_key(&hash) {
+                    // Committed without us seeing the proposal
+                    self.certify(self.height, hash.clone());
+                }
+                if let Some(block) = self.blocks.get(&hash).cloned() {
+                    let height = block.height;
+                    if bc.add_block(block) {
+                        println!("Consensus {}: committed block {} at height {} (round {})",
+                                 self.validator_id, &hash[..16.min(hash.len())], height, round);
+                        for evidence in bc.find_downtime() {
+                            // Already pending evidence is refused quietly
+                            if let Ok(tx_id) = bc.submit_evidence(evidence) {
+                                println!("Consensus {}: submitted downtime evidence ({})", self.validator_id, tx_id);
+                            }
+                        }
+                        self.sync_height(bc, now);
+                        self.advance(bc, now, out);
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    // Jump ahead when more than 1/3 of the stake is already in a later round
+    fn skip_round_if_behind(&mut self, bc: &mut Blockchain, now: u64, out: &mut Vec<ConsensusMessage>) {
+        let total: u64 = bc.validator_set().iter().map(|(_, stake)| stake).sum();
+        let mut rounds: Vec<u32> = self.prevotes.keys().chain(self.precommits.keys())
+            .copied()
+            .filter(|r| *r > self.round)
+            .collect();
+        rounds.sort_unstable();
+        rounds.dedup();
+
+        for round in rounds.into_iter().rev() {
+            let mut senders: Vec<&String> = Vec::new();
+            for votes in [&self.prevotes, &self.precommits] {
+                if let Some(round_votes) = votes.get(&round) {
+                    senders.extend(round_votes.keys());
+                }
+            }
+            senders.sort();
+            senders.dedup();
+            let stake = Self::stake_of(bc, senders.into_iter());
+            if stake * 3 > total {
+                self.start_round(round, now);
+                self.try_propose(bc, now, out);
+                return;
+            }
+        }
+    }
+
+    // The value (block hash or nil) backed by more than 2/3 of the stake, if any
+    fn quorum(&self, bc: &Blockchain, votes: &VoteSet, round: u32) -> Option<Option<String>> {
+        let round_votes = votes.get(&round)?;
+        let total: u64 = bc.validator_set().iter().map(|(_, stake)| stake).sum();
+
+        let mut tally: HashMap<&Option<String>, Vec<&String>> = HashMap::new();
+        for (validator, value) in round_votes {
+            tally.entry(value).or_default().push(validator);
+        }
+        tally.into_iter()
+            .find(|(_, voters)| Self::stake_of(bc, voters.iter().copied()) * 3 > total * 2)
+            .map(|(value, _)| value.clone())
+    }
+
+    fn stake_of<'a>(bc: &Blockchain, validators: impl Iterator<Item = &'a String>) -> u64 {
+        let set: HashMap<String, u64> = bc.validator_set().into_iter().collect();
+        validators.map(|v| set.get(v).copied().unwrap_or(0)).sum()
+    }
+}
+
+// In-process multi-validator network for exercising consensus without HTTP
+#[cfg(test)]
+pub(crate) mod harness {
+    use super::*;
+    use crate::blockchain::signing::KeyPair;
+    use crate::blockchain::{Transaction, TransactionType};
+    use std::collections::{HashSet, VecDeque};
+
+    pub struct LocalNetwork {
+        pub nodes: Vec<(ConsensusEngine, Blockchain)>,
+        pub offline: HashSet<usize>,
+        pub now: u64,
+        queue: VecDeque<(usize, ConsensusMessage)>,
+    }
+
+    impl LocalNetwork {
+        pub fn new(validators: &[(&str, u32)]) -> Self {
+            let nodes = validators.iter().map(|(id, _)| {
+                let mut bc = Blockchain::new();
+                bc.set_node_key(KeyPair::from_seed(id.as_bytes()));
+                bc.set_timed_rounds(false);
+                for (other, stake) in validators {
+                    let key = KeyPair::from_seed(other.as_bytes()).public_key_hex();
+                    assert!(bc.add_validator_with_key(other.to_string(), *stake, key));
+                }
+                (ConsensusEngine::new(id.to_string(), ConsensusConfig::default()), bc)
+            }).collect();
+
+            Self { nodes, offline: HashSet::new(), now: 1, queue: VecDeque::new() }
+        }
+
+        // Submit the same signed transaction to every node's mempool
+        pub fn submit_message(&mut self, content: &str) {
+            let mut tx = Transaction {
+                id: format!("msg_{}", content),
+                from: "tester".to_string(),
+                to: "broadcast".to_string(),
+                amount: 0,
+                tx_type: TransactionType::Message { content: content.to_string() },
+                timestamp: self.now,
+                public_key: None,
+                signature: None,
+            };
+            tx.sign(&KeyPair::from_seed(b"tester"));
+            let tx = serde_json::to_string(&tx).unwrap();
+            for (_, bc) in self.nodes.iter_mut() {
+                assert!(bc.add_p2p_transaction(tx.clone()));
+            }
+        }
+
+        fn deliver(&mut self) {
+            while let Some((from, message)) = self.queue.pop_front() {
+                for to in 0..self.nodes.len() {
+                    if to == from || self.offline.contains(&to) {
+                        continue;
+                    }
+                    let (engine, bc) = &mut self.nodes[to];
+                    let replies = engine.handle_message(bc, message.clone(), self.now);
+                    self.queue.extend(replies.into_iter().map(|m| (to, m)));
+                }
+            }
+        }
+
+        // Advance the clock in 100ms steps until every online node reaches the height
+        pub fn run_until_height(&mut self, height: u32, max_ms: u64) -> bool {
+            let deadline = self.now + max_ms;
+            while self.now < deadline {
+                for i in 0..self.nodes.len() {
+                    if self.offline.contains(&i) {
+                        continue;
+                    }
+                    let (engine, bc) = &mut self.nodes[i];
+                    let out = engine.tick(bc, self.now);
+                    self.queue.extend(out.into_iter().map(|m| (i, m)));
+                }
+                self.deliver();
+                self.catch_up();
+
+                let done = (0..self.nodes.len())
+                    .filter(|i| !self.offline.contains(i))
+                    .all(|i| self.nodes[i].1.height() >= height);
+                if done {
+                    return true;
+                }
+                self.now += 100;
+            }
+            false
+        }
+
+        // Nodes that fell behind fetch the missing blocks from the first
+        // online node that has them, as the validator does over HTTP
+        fn catch_up(&mut self) {
+            for i in 0..self.nodes.len() {
+                if self.offline.contains(&i) {
+                    continue;
+                }
+                let (height, _) = match self.nodes[i].0.catch_up_target() {
+                    Some(target) => target,
+                    None => continue,
+                };
+                let from = self.nodes[i].1.height();
+                let source = (0..self.nodes.len())
+                    .find(|j| *j != i && !self.offline.contains(j) && self.nodes[*j].1.height() >= height);
+                if let Some(j) = source {
+                    let blocks = self.nodes[j].1.blocks_after(from);
+                    let (engine, bc) = &mut self.nodes[i];
+                    engine.apply_fetched(bc, blocks, self.now).unwrap();
+                }
+            }
+        }
+
+        pub fn chain_hashes(&self, node: usize) -> Vec<String> {
+            self.nodes[node].1.get_blocks().iter().map(|b| b.hash.clone()).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::harness::LocalNetwork;
+    use super::{ConsensusEngine, ConsensusMessage, Vote, VoteType, MAX_FUTURE_MESSAGES_PER_SENDER};
+    use crate::blockchain::signing::KeyPair;
+
+    #[test]
+    fn test_three_validators_agree_on_one_chain() {
+        let mut net = LocalNetwork::new(&[("validator1", 1000), ("validator2", 1000), ("validator3", 1000)]);
+
+        net.submit_message("first");
+        assert!(net.run_until_height(1, 10_000));
+        net.submit_message("second");
+        assert!(net.run_until_height(2, 10_000));
+
+        let reference = net.chain_hashes(0);
+        assert_eq!(reference.len(), 3);
+        for node in 1..3 {
+            assert_eq!(net.chain_hashes(node), reference);
+        }
+        for (_, bc) in &net.nodes {
+            assert_eq!(bc.get_pending_count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_offline_proposer_is_skipped_by_round_change() {
+        let mut net = LocalNetwork::new(&[("v1", 100), ("v2", 100), ("v3", 100), ("v4", 100)]);
+
+        let proposer = ConsensusEngine::proposer_for(&net.nodes[0].1, 1, 0).unwrap();
+        let offline = net.nodes.iter().position(|(e, _)| e.validator_id() == proposer).unwrap();
+        net.offline.insert(offline);
+        net.submit_message("needs a live proposer");
+        assert!(net.run_until_height(1, 30_000));
+
+        let online: Vec<usize> = (0..4).filter(|i| *i != offline).collect();
+        let committed = &net.nodes[online[0]].1.get_blocks()[1];
+        assert_ne!(committed.validator, proposer);
+        assert!(committed.round > 0);
+        for node in &online[1..] {
+            assert_eq!(net.chain_hashes(online[0]), net.chain_hashes(*node));
+        }
+    }
+
+    #[test]
+    fn test_no_commit_without_two_thirds_stake() {
+        let mut net = LocalNetwork::new(&[("v1", 100), ("v2", 100), ("v3", 100)]);
+
+        // Two of three equal validators hold exactly 2/3, which is not a quorum
+        net.offline.insert(2);
+        net.submit_message("stuck");
+        assert!(!net.run_until_height(1, 10_000));
+        assert_eq!(net.nodes[0].1.height(), 0);
+        assert_eq!(net.nodes[1].1.height(), 0);
+    }
+
+    #[test]
+    fn test_validator_that_fell_behind_catches_up() {
+        let mut net = LocalNetwork::new(&[("v1", 100), ("v2", 100), ("v3", 100), ("v4", 100)]);
+
+        net.offline.insert(3);
+        for height in 1..=3 {
+            net.submit_message(&format!("while v4 is away {}", height));
+            assert!(net.run_until_height(height, 30_000));
+        }
+        assert_eq!(net.nodes[3].1.height(), 0);
+
+        // The next commit tells v4 how far behind it is
+        net.offline.clear();
+        net.submit_message("v4 is back");
+        assert!(net.run_until_height(4, 30_000));
+        assert_eq!(net.chain_hashes(3), net.chain_hashes(0));
+        assert!(net.nodes[3].0.catch_up_target().is_none());
+
+        // Blocks that do not lead to the certified one are refused
+        let (engine, bc) = &mut net.nodes[0];
+        assert!(engine.apply_fetched(bc, Vec::new(), 0).is_err());
+    }
+
+    #[test]
+    fn test_forged_future_messages_are_not_buffered() {
+        let mut net = LocalNetwork::new(&[("v1", 100), ("v2", 100), ("v3", 100)]);
+        let (engine, bc) = &mut net.nodes[0];
+        engine.tick(bc, 1);
+        let vote = |validator: &str, round: u32| Vote {
+            vote_type: VoteType::Prevote,
+            height: 2,
+            round,
+            block_hash: None,
+            validator: validator.to_string(),
+            signature: None,
+        };
+
+        for round in 0..10 {
+            engine.handle_message(bc, ConsensusMessage::Vote { vote: vote("v2", round) }, 1);
+            engine.handle_message(bc, ConsensusMessage::Vote { vote: vote("mallory", round) }, 1);
+        }
+        assert!(engine.future_messages.is_empty());
+
+        let v2 = KeyPair::from_seed(b"v2");
+        for round in 0..100 {
+            let mut signed = vote("v2", round);
+            signed.signature = Some(v2.sign(&signed.signing_bytes()));
+            engine.handle_message(bc, ConsensusMessage::Vote { vote: signed }, 1);
+        }
+        assert_eq!(engine.future_messages.len(), MAX_FUTURE_MESSAGES_PER_SENDER);
+    }
+
+    #[test]
+    fn test_idle_validators_do_not_advance_rounds() {
+        let mut net = LocalNetwork::new(&[("v1", 100), ("v2", 100), ("v3", 100)]);
+        assert!(!net.run_until_height(1, 10_000));
+        for (engine, bc) in &net.nodes {
+            assert_eq!(engine.status(bc).round, 0);
+        }
+    }
+}
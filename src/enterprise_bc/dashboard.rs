@@ -288,6 +288,13 @@ const DASHBOARD_HTML: &str = r#"
     <script>
         let API_BASE = '';
 
+        // For validators with API_KEYS set, open the dashboard with ?api_key=<read-only key>
+        const API_KEY = new URLSearchParams(window.location.search).get('api_key');
+
+        function apiFetch(url) {
+            return fetch(url, API_KEY ? { headers: { 'X-Api-Key': API_KEY } } : {});
+        }
+
         function initializeApiUrl() {
             API_BASE = `http://${window.location.hostname}:8080`;
             document.getElementById('api-url').value = API_BASE;
@@ -325,11 +332,12 @@ const DASHBOARD_HTML: &str = r#"
 
         async function loadDashboard() {
             try {
+                // While the stream is open the order book refreshes on events instead
                 await Promise.all([
                     loadValidatorStatus(),
                     loadBlocksWithDetails(),
                     loadTenants(),
-                    loadOrderBookStatus()
+                    marketStreamOpen ? Promise.resolve() : loadOrderBookStatus()
                 ]);
             } catch (error) {
                 console.error('Error loading dashboard:', error);
@@ -338,7 +346,7 @@ const DASHBOARD_HTML: &str = r#"
 
         async function loadValidatorStatus() {
             try {
-                const response = await fetch(`${API_BASE}/api/status`);
+                const response = await apiFetch(`${API_BASE}/api/status`);
                 if (response.ok) {
                     const status = await response.json();
 
@@ -369,7 +377,8 @@ const DASHBOARD_HTML: &str = r#"
                 const quantity = (trading.quantity / 100).toFixed(2);
                 const price = (trading.price / 100).toFixed(2);
                 
-                const orderType = tx.id.includes("buy_") ? "BUY" : "SELL";
+                // Legacy blocks have no side; they used a buy_/sell_ id prefix
+                const orderType = trading.side ? trading.side.toUpperCase() : (tx.id.includes("buy_") ? "BUY" : "SELL");
                 
                 typeInfo = {
                     type: `${orderType} Order`,
@@ -432,7 +441,8 @@ const DASHBOARD_HTML: &str = r#"
                 if (assetMatch && quantityMatch && priceMatch) {
                     const quantity = (parseInt(quantityMatch[1]) / 100).toFixed(2);
                     const price = (parseInt(priceMatch[1]) / 100).toFixed(2);
-                    const orderType = txString.includes('buy_') ? 'BUY' : 'SELL';
+                    const sideMatch = txString.match(/"side":"(Buy|Sell)"/);
+                    const orderType = sideMatch ? sideMatch[1].toUpperCase() : (txString.includes('buy_') ? 'BUY' : 'SELL');
                     
                     typeInfo = {
                         type: `${orderType} Order`,
@@ -465,7 +475,7 @@ const DASHBOARD_HTML: &str = r#"
         
     async function loadBlocksWithDetails() {
         try {
-            const response = await fetch(`${API_BASE}/api/blocks?limit=10`);
+            const response = await apiFetch(`${API_BASE}/api/blocks?limit=10`);
             const blocks = await response.json();
 
             const container = document.getElementById('recent-blocks');
@@ -637,7 +647,7 @@ const DASHBOARD_HTML: &str = r#"
 
         async function loadOrderBookStatus() {
             try {
-                const response = await fetch(`${API_BASE}/api/order-book-status`);
+                const response = await apiFetch(`${API_BASE}/api/order-book-status`);
                 if (response.ok) {
                     const data = await response.json();
                     
@@ -687,7 +697,7 @@ const DASHBOARD_HTML: &str = r#"
 
         async function loadTenants() {
             try {
-                const response = await fetch(`${API_BASE}/api/tenants`);
+                const response = await apiFetch(`${API_BASE}/api/tenants`);
 
                 if (!response.ok) {
                     throw new Error(`HTTP ${response.status}: ${response.statusText}`);
@@ -725,6 +735,28 @@ const DASHBOARD_HTML: &str = r#"
             }
         }
 
+        // Book deltas, trades and settlements pushed by the validator
+        let marketStream = null;
+        let marketStreamOpen = false;
+        let orderBookRefresh = null;
+
+        function connectMarketStream() {
+            marketStream = new WebSocket(`${API_BASE.replace(/^http/, 'ws')}/api/stream${API_KEY ? '?api_key=' + encodeURIComponent(API_KEY) : ''}`);
+            marketStream.onopen = () => {
+                marketStreamOpen = true;
+                marketStream.send(JSON.stringify({ op: 'subscribe', topics: ['book.*', 'trades.*', 'settlements'] }));
+            };
+            marketStream.onmessage = () => {
+                // Coalesce bursts of events into one refresh
+                clearTimeout(orderBookRefresh);
+                orderBookRefresh = setTimeout(loadOrderBookStatus, 250);
+            };
+            marketStream.onclose = () => {
+                marketStreamOpen = false;
+                setTimeout(connectMarketStream, 5000);
+            };
+        }
+
         // Auto-refresh every 10 seconds for trading updates
         setInterval(loadDashboard, 10000);
 
@@ -732,6 +764,7 @@ const DASHBOARD_HTML: &str = r#"
         initializeApiUrl();
         testConnection();
         loadDashboard();
+        connectMarketStream();
     </script>
 </body>
 </html>
@@ -1108,6 +1141,13 @@ const ZK_DASHBOARD_HTML: &str = r#"
     </div>
 
     <script>
+        // For validators with API_KEYS set, open the dashboard with ?api_key=<read-only key>
+        const API_KEY = new URLSearchParams(window.location.search).get('api_key');
+
+        function apiFetch(url) {
+            return fetch(url, API_KEY ? { headers: { 'X-Api-Key': API_KEY } } : {});
+        }
+
         let currentOperator = 'tmobile';
         let allBlocks = [];
 
@@ -1116,7 +1156,7 @@ const ZK_DASHBOARD_HTML: &str = r#"
             const apiUrl = document.getElementById('apiUrl').value;
             
             try {
-                const response = await fetch(`${apiUrl}/api/blocks?limit=100`);
+                const response = await apiFetch(`${apiUrl}/api/blocks?limit=100`);
                 const data = await response.json();
                 
                 // API returns blocks directly, not wrapped in a 'blocks' key
@@ -0,0 +1,249 @@
+// src/enterprise_bc/market_data.rs
+// Market data for dashboards: OHLCV candles, L2 depth and tickers
+//
+// Every committed trade is folded into a one-minute candle of its asset.
+// Longer intervals (any multiple of a minute) are aggregated from those on
+// request, and the 24h ticker figures come from the last 1440 of them, so
+// the history does not depend on how many trades recent_trades keeps.
+// Minute candles more than RETENTION before an asset's latest one are
+// dropped. Minutes without trades have no candle.
+//
+// Prices are as in orders (hundredths); volume is in asset units.
+
+use crate::blockchain::matching::DepthLevel;
+use crate::enterprise_bc::order_engine::Trade;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const BASE_INTERVAL: u64 = 60;
+pub const RETENTION: u64 = 7 * 24 * 3600;
+const DAY: u64 = 24 * 3600;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Candle {
+    pub start: u64, // unix seconds, a multiple of the interval
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    pub volume: u64,
+    pub trades: u64,
+}
+
+impl Candle {
+    fn new(start: u64, price: u64, quantity: u64) -> Self {
+        Self { start, open: price, high: price, low: price, close: price, volume: quantity, trades: 1 }
+    }
+
+    // Trades of one minute are taken in commit order
+    fn add(&mut self, price: u64, quantity: u64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume = self.volume.saturating_add(quantity);
+        self.trades += 1;
+    }
+
+    // Fold in the candle that follows this one
+    fn extend(&mut self, next: &Candle) {
+        self.high = self.high.max(next.high);
+        self.low = self.low.min(next.low);
+        self.close = next.close;
+        self.volume = self.volume.saturating_add(next.volume);
+        self.trades += next.trades;
+    }
+}
+
+// "1m", "15m", "4h", "1d" or plain seconds; a multiple of one minute
+pub fn parse_interval(interval: &str) -> Result<u64, String> {
+    let interval = interval.trim();
+    let (number, unit) = match interval.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => (&interval[..i], unit),
+        _ => (interval, 's'),
+    };
+    let scale = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => DAY,
+        _ => return Err(format!("unknown interval unit in {:?}", interval)),
+    };
+    let seconds = number.parse::<u64>().ok()
+        .and_then(|n| n.checked_mul(scale))
+        .ok_or_else(|| format!("invalid interval {:?}", interval))?;
+    if seconds == 0 || seconds % BASE_INTERVAL != 0 {
+        return Err(format!("interval {:?} is not a whole number of minutes", interval));
+    }
+    Ok(seconds)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarketData {
+    candles: BTreeMap<String, BTreeMap<u64, Candle>>, // asset -> minute -> candle
+}
+
+impl MarketData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, trade: &Trade) {
+        let minute = trade.timestamp - trade.timestamp % BASE_INTERVAL;
+        let candles = self.candles.entry(trade.asset.clone()).or_default();
+        match candles.get_mut(&minute) {
+            Some(candle) => candle.add(trade.price, trade.quantity),
+            None => {
+                candles.insert(minute, Candle::new(minute, trade.price, trade.quantity));
+            }
+        }
+
+        let latest = *candles.keys().next_back().unwrap_or(&minute);
+        let keep_from = latest.saturating_sub(RETENTION);
+        *candles = candles.split_off(&keep_from);
+    }
+
+    pub fn assets(&self) -> impl Iterator<Item = &str> {
+        self.candles.keys().map(|asset| asset.as_str())
+    }
+
+    // Candles of `interval` seconds starting in [from, to), the last `limit` of them
+    pub fn candles(&self, asset: &str, interval: u64, from: u64, to: u64, limit: usize) -> Vec<Candle> {
+        let minutes = match self.candles.get(asset) {
+            Some(minutes) if from < to => minutes,
+            _ => return Vec::new(),
+        };
+        let first_minute = from - from % interval;
+        let mut candles: Vec<Candle> = Vec::new();
+        for candle in minutes.range(first_minute..to.saturating_add(interval)).map(|(_, candle)| candle) {
+            let start = candle.start - candle.start % interval;
+            if start < from || start >= to {
+                continue;
+            }
+            match candles.last_mut() {
+                Some(last) if last.start == start => last.extend(candle),
+                _ => candles.push(Candle { start, ..candle.clone() }),
+            }
+        }
+        let skip = candles.len().saturating_sub(limit);
+        candles.split_off(skip)
+    }
+
+    // Everything traded in the 24 hours up to `now`, as one candle
+    pub fn last_24h(&self, asset: &str, now: u64) -> Option<Candle> {
+        let since = now.saturating_sub(DAY);
+        let mut minutes = self.candles.get(asset)?
+            .range(since - since % BASE_INTERVAL..=now)
+            .map(|(_, candle)| candle);
+        let mut day = Candle { start: since, ..minutes.next()?.clone() };
+        minutes.for_each(|candle| day.extend(candle));
+        Some(day)
+    }
+}
+
+// Aggregated book of one asset
+#[derive(Debug, Clone, Serialize)]
+pub struct Depth {
+    pub asset: String,
+    pub bids: Vec<DepthLevel>, // best (highest) first
+    pub asks: Vec<DepthLevel>, // best (lowest) first
+    pub best_bid: Option<u64>,
+    pub best_ask: Option<u64>,
+    pub spread: Option<u64>,
+}
+
+impl Depth {
+    pub fn new(asset: &str, bids: Vec<DepthLevel>, asks: Vec<DepthLevel>) -> Self {
+        let best_bid = bids.first().map(|level| level.price);
+        let best_ask = asks.first().map(|level| level.price);
+        Self { asset: asset.to_string(), bids, asks, best_bid, best_ask, spread: spread(best_bid, best_ask) }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ticker {
+    pub asset: String,
+    pub last_price: Option<u64>,
+    pub best_bid: Option<u64>,
+    pub best_ask: Option<u64>,
+    pub spread: Option<u64>,
+    pub open_24h: Option<u64>,
+    pub high_24h: Option<u64>,
+    pub low_24h: Option<u64>,
+    pub volume_24h: u64,
+    pub trades_24h: u64,
+    pub change_24h: i64, // close minus open
+}
+
+impl Ticker {
+    pub fn new(asset: &str, last_price: Option<u64>, best_bid: Option<u64>, best_ask: Option<u64>, day: Option<Candle>) -> Self {
+        Self {
+            asset: asset.to_string(),
+            last_price,
+            best_bid,
+            best_ask,
+            spread: spread(best_bid, best_ask),
+            open_24h: day.as_ref().map(|d| d.open),
+            high_24h: day.as_ref().map(|d| d.high),
+            low_24h: day.as_ref().map(|d| d.low),
+            volume_24h: day.as_ref().map_or(0, |d| d.volume),
+            trades_24h: day.as_ref().map_or(0, |d| d.trades),
+            change_24h: day.as_ref().map_or(0, |d| d.close as i64 - d.open as i64),
+        }
+    }
+}
+
+// None unless both sides are quoted; a crossed book never rests, so ask >= bid
+fn spread(best_bid: Option<u64>, best_ask: Option<u64>) -> Option<u64> {
+    Some(best_ask?.saturating_sub(best_bid?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(timestamp: u64, price: u64, quantity: u64) -> Trade {
+        Trade {
+            trade_id: format!("t{}", timestamp),
+            asset: "BTC".to_string(),
+            quantity,
+            price,
+            buyer: "bob".to_string(),
+            seller: "alice".to_string(),
+            buyer_network: "net-b".to_string(),
+            seller_network: "net-a".to_string(),
+            timestamp,
+            taker_side: None,
+        }
+    }
+
+    #[test]
+    fn test_candles_aggregate_minutes_and_day_window() {
+        assert_eq!(parse_interval("5m"), Ok(300));
+        assert_eq!(parse_interval("3600"), Ok(3600));
+        assert!(parse_interval("90s").is_err());
+        assert!(parse_interval("1w").is_err());
+
+        let mut data = MarketData::new();
+        let base = 1_000 * DAY;
+        for (offset, price, quantity) in [(0, 100, 1), (30, 105, 2), (70, 95, 3), (310, 110, 4)] {
+            data.record(&trade(base + offset, price, quantity));
+        }
+
+        let minutes = data.candles("BTC", 60, 0, u64::MAX, 10);
+        assert_eq!(minutes.len(), 3);
+        assert_eq!(minutes[0], Candle { start: base, open: 100, high: 105, low: 100, close: 105, volume: 3, trades: 2 });
+
+        let five = data.candles("BTC", 300, 0, u64::MAX, 10);
+        assert_eq!(five.iter().map(|c| (c.start, c.open, c.high, c.low, c.close, c.volume)).collect::<Vec<_>>(),
+                   vec![(base, 100, 105, 95, 95, 6), (base + 300, 110, 110, 110, 110, 4)]);
+        assert_eq!(data.candles("BTC", 300, 0, u64::MAX, 1)[0].start, base + 300);
+
+        let day = data.last_24h("BTC", base + DAY + 60).unwrap();
+        assert_eq!((day.open, day.close, day.volume), (95, 110, 7));
+        assert!(data.last_24h("BTC", base + 2 * DAY).is_none());
+
+        // Minutes beyond the retention of the latest candle are dropped
+        data.record(&trade(base + RETENTION + 120, 120, 1));
+        assert_eq!(data.candles("BTC", 60, 0, u64::MAX, 10).len(), 2);
+    }
+}
@@ -3,8 +3,15 @@ pub mod validator;
 pub mod api;
 pub mod dashboard;
 pub mod order_engine;
+pub mod market_data;
+pub mod order_journal;
+pub mod settlement;
+pub mod stream;
+pub mod consensus;
+pub mod surveillance;
 
 // Re-export main types
 pub use validator::Validator;
 pub use dashboard::start_dashboard;
 pub use order_engine::EnterpriseOrderEngine;
+pub use consensus::{ConsensusEngine, ConsensusMessage};
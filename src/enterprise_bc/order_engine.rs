@@ -1,7 +1,24 @@
 // src/enterprise_bc/order_engine.rs - FIXED BORROWING ISSUE
-use serde::{Deserialize, Serialize};
-use crate::blockchain::{TenantBlockData, Transaction, TransactionType};
-use std::collections::HashSet;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use crate::blockchain::ledger::Ledger;
+use crate::blockchain::matching::{BookOrder, MatchingBook, OrderStatus, SelfTradePrevention};
+use crate::blockchain::{Blockchain, TenantBlockData, TimeInForce, OrderType, Transaction, TransactionType};
+pub use crate::blockchain::OrderSide;
+use crate::enterprise_bc::market_data::{Depth, MarketData, Ticker};
+use crate::enterprise_bc::order_journal::{JournalEntry, OrderJournal};
+use crate::enterprise_bc::settlement::{EscrowLock, Settlement, SettlementStatus};
+use crate::enterprise_bc::surveillance::{Alert, ReviewStatus, Surveillance};
+use std::collections::{BTreeMap, HashSet};
+
+// Undelivered trades kept for redelivery, e.g. while the tracker is down
+const MAX_PENDING_BROADCAST: usize = 10_000;
+
+// Decided settlements kept for status queries
+const MAX_SETTLEMENTS: usize = 10_000;
+
+// Ledger balances by trader, then by asset, of the traders in one block
+pub type Balances = BTreeMap<String, BTreeMap<String, u64>>;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,15 +30,15 @@ pub struct OrderBookEntry {
     pub quantity: u64,
     pub price: u64,
     pub side: OrderSide,
+    #[serde(default)]
+    pub order_type: OrderType,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
     pub timestamp: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum OrderSide {
-    Buy,
-    Sell,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trade {
     pub trade_id: String,
@@ -33,32 +50,526 @@ pub struct Trade {
     pub buyer_network: String,
     pub seller_network: String,
     pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taker_side: Option<OrderSide>, // None for trades from before it was kept
+}
+
+// A resting order the engine dropped without a trade: an expired GTT/GTD
+// order, or one from a network that went silent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelledOrder {
+    pub order_id: String,
+    pub trader: String,
+    pub network_id: String,
+    pub asset: String,
+    pub remaining: u64,
+    pub reason: String,
+}
+
+impl CancelledOrder {
+    pub(crate) fn new(order: &OrderBookEntry, reason: &str) -> Self {
+        Self {
+            order_id: order.order_id.clone(),
+            trader: order.trader.clone(),
+            network_id: order.network_id.clone(),
+            asset: order.asset.clone(),
+            remaining: order.quantity,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+// Outcome of an OrderCancel or OrderAmend request, sent back to the
+// requesting network through the tracker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderAck {
+    pub request_id: String, // id of the cancel/amend transaction
+    pub order_id: String,
+    pub trader: String,
+    pub network_id: String,
+    pub action: String, // "cancel" or "amend"
+    pub accepted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<OrderBookEntry>, // the order as it is after an amend
+}
+
+impl BookOrder for OrderBookEntry {
+    fn order_id(&self) -> &str {
+        &self.order_id
+    }
+
+    fn asset(&self) -> &str {
+        &self.asset
+    }
+
+    fn side(&self) -> OrderSide {
+        self.side
+    }
+
+    fn price(&self) -> u64 {
+        self.price
+    }
+
+    fn quantity(&self) -> u64 {
+        self.quantity
+    }
+
+    fn set_quantity(&mut self, quantity: u64) {
+        self.quantity = quantity;
+    }
+
+    fn order_type(&self) -> OrderType {
+        self.order_type
+    }
+
+    fn time_in_force(&self) -> TimeInForce {
+        self.time_in_force
+    }
+
+    // The same trader on two networks is still one owner
+    fn owner(&self) -> &str {
+        &self.trader
+    }
+}
+
+impl Trade {
+    // Queue this trade as a TradeExecution transaction on the enterprise chain
+    pub fn record_on_chain(&self, bc: &mut Blockchain) -> bool {
+        bc.record_trade_execution(Transaction {
+            id: format!("exec_{}", self.trade_id),
+            from: String::new(), // the recording validator's key address
+            to: "settlement".to_string(),
+            amount: self.quantity.saturating_mul(self.price) / 100, // prices are in cents
+            tx_type: TransactionType::TradeExecution {
+                asset: self.asset.clone(),
+                quantity: self.quantity,
+                price: self.price,
+                buyer: self.buyer.clone(),
+                seller: self.seller.clone(),
+                trade_id: Some(self.trade_id.clone()),
+                taker: self.taker_side,
+            },
+            timestamp: self.timestamp,
+            public_key: None,
+            signature: None,
+        })
+    }
 }
 
+#[derive(Deserialize)]
+#[serde(from = "EngineState")]
 pub struct EnterpriseOrderEngine {
-    pub buy_orders: Vec<OrderBookEntry>,
-    pub sell_orders: Vec<OrderBookEntry>, 
+    pub book: MatchingBook<OrderBookEntry>, // resting orders, price-time priority per asset
     pub recent_trades: Vec<Trade>,
     pub processed_transactions: HashSet<String>, // Track processed transaction IDs
     pub processed_trades: HashSet<String>, // Track processed trade IDs to prevent duplicates
+    pub pending_broadcast: Vec<Trade>, // committed but not yet confirmed by the tracker
+    pub settlements: BTreeMap<String, Settlement>, // by trade id
+    pub network_last_seen: BTreeMap<String, u64>, // last block processed per network
+    pub market_data: MarketData, // candles of committed trades
+    pub surveillance: Surveillance, // manipulation alerts and the history behind them
+    cancellations: Vec<CancelledOrder>, // dropped since the last order book broadcast
+    acks: Vec<OrderAck>, // cancel/amend outcomes not yet sent to the tracker
+    settlement_updates: Vec<Settlement>, // opened or decided, not yet sent to the tracker
+    journal: Option<OrderJournal>,
+}
+
+// On-disk layout (checkpoints and snapshots): each side as a list in
+// priority order, so loading re-queues every price level in the same order
+#[derive(Deserialize)]
+struct EngineState {
+    buy_orders: Vec<OrderBookEntry>,
+    sell_orders: Vec<OrderBookEntry>,
+    recent_trades: Vec<Trade>,
+    processed_transactions: HashSet<String>,
+    processed_trades: HashSet<String>,
+    #[serde(default)]
+    pending_broadcast: Vec<Trade>,
+    #[serde(default)]
+    stop_orders: Vec<OrderBookEntry>,
+    #[serde(default)]
+    last_prices: BTreeMap<String, u64>,
+    #[serde(default)]
+    network_last_seen: BTreeMap<String, u64>,
+    #[serde(default)]
+    settlements: BTreeMap<String, Settlement>,
+    #[serde(default)]
+    market_data: MarketData,
+    #[serde(default)]
+    surveillance: Surveillance,
+    #[serde(default)]
+    self_trade_prevention: SelfTradePrevention, // None for state from before it existed
+}
+
+impl From<EngineState> for EnterpriseOrderEngine {
+    fn from(state: EngineState) -> Self {
+        let mut book = MatchingBook::new();
+        for order in state.buy_orders.into_iter().chain(state.sell_orders) {
+            book.insert(order);
+        }
+        for order in state.stop_orders {
+            book.park_stop(order);
+        }
+        for (asset, price) in &state.last_prices {
+            book.set_last_price(asset, *price);
+        }
+        book.set_self_trade_prevention(state.self_trade_prevention);
+        Self {
+            book,
+            recent_trades: state.recent_trades,
+            processed_transactions: state.processed_transactions,
+            processed_trades: state.processed_trades,
+            pending_broadcast: state.pending_broadcast,
+            settlements: state.settlements,
+            network_last_seen: state.network_last_seen,
+            market_data: state.market_data,
+            surveillance: state.surveillance,
+            cancellations: Vec::new(),
+            acks: Vec::new(),
+            settlement_updates: Vec::new(),
+            journal: None,
+        }
+    }
+}
+
+impl Serialize for EnterpriseOrderEngine {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("EnterpriseOrderEngine", 13)?;
+        state.serialize_field("buy_orders", &self.book.side_orders(OrderSide::Buy))?;
+        state.serialize_field("sell_orders", &self.book.side_orders(OrderSide::Sell))?;
+        state.serialize_field("recent_trades", &self.recent_trades)?;
+        state.serialize_field("processed_transactions", &self.processed_transactions)?;
+        state.serialize_field("processed_trades", &self.processed_trades)?;
+        state.serialize_field("pending_broadcast", &self.pending_broadcast)?;
+        state.serialize_field("stop_orders", &self.book.stop_orders())?;
+        state.serialize_field("last_prices", self.book.last_prices())?;
+        state.serialize_field("network_last_seen", &self.network_last_seen)?;
+        state.serialize_field("settlements", &self.settlements)?;
+        state.serialize_field("market_data", &self.market_data)?;
+        state.serialize_field("surveillance", &self.surveillance)?;
+        state.serialize_field("self_trade_prevention", &self.book.self_trade_prevention())?;
+        state.end()
+    }
 }
 
 impl EnterpriseOrderEngine {
     pub fn new() -> Self {
+        let mut book = MatchingBook::new();
+        book.set_self_trade_prevention(SelfTradePrevention::CancelNewest);
         Self {
-            buy_orders: Vec::new(),
-            sell_orders: Vec::new(),
+            book,
             recent_trades: Vec::new(),
             processed_transactions: HashSet::new(),
             processed_trades: HashSet::new(),
+            pending_broadcast: Vec::new(),
+            settlements: BTreeMap::new(),
+            network_last_seen: BTreeMap::new(),
+            market_data: MarketData::new(),
+            surveillance: Surveillance::new(),
+            cancellations: Vec::new(),
+            acks: Vec::new(),
+            settlement_updates: Vec::new(),
+            journal: None,
+        }
+    }
+
+    // Journal every change from now on, starting with a checkpoint of the current state
+    pub fn attach_journal(&mut self, mut journal: OrderJournal) {
+        if let Err(e) = journal.checkpoint(self) {
+            println!("STORAGE ERROR: order engine checkpoint failed: {}", e);
+        }
+        self.journal = Some(journal);
+    }
+
+    fn journal(&mut self, entry: JournalEntry) {
+        let journal = match self.journal.as_mut() {
+            Some(journal) => journal,
+            None => return,
+        };
+        if let Err(e) = journal.append(&entry) {
+            println!("STORAGE ERROR: order journal append failed: {}", e);
+        }
+    }
+
+    fn checkpoint_if_due(&mut self) {
+        if let Some(mut journal) = self.journal.take() {
+            if journal.needs_checkpoint() {
+                if let Err(e) = journal.checkpoint(self) {
+                    println!("STORAGE ERROR: order engine checkpoint failed: {}", e);
+                }
+            }
+            self.journal = Some(journal);
+        }
+    }
+
+    // Re-apply a journal entry during recovery; nothing is journaled again
+    pub(crate) fn replay(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::Block { block, processed_at, balances } => {
+                self.match_block(&block, processed_at, balances.as_ref());
+            }
+            JournalEntry::Broadcast { trade_ids } => {
+                self.pending_broadcast.retain(|t| !trade_ids.contains(&t.trade_id));
+            }
+            JournalEntry::Sweep { at, max_silence, settlement_timeout } => {
+                self.apply_sweep(at, max_silence, settlement_timeout);
+            }
+            JournalEntry::Vote { trade_id, network_id, prepared, reason, at } => {
+                let _ = self.apply_vote(&trade_id, &network_id, prepared, reason.as_deref(), at);
+            }
+            JournalEntry::Review { alert_id, status, reviewer, note, at } => {
+                let _ = self.surveillance.review(&alert_id, status, &reviewer, &note, at);
+            }
+        }
+    }
+
+    // Orders are checked against the traders' balances on `ledger`, the
+    // block's own network as of the block (see Blockchain::tenant_ledger),
+    // less what their other orders and preparing trades there already lock
+    pub fn process_block(&mut self, block: &TenantBlockData, ledger: &Ledger) -> Vec<Trade> {
+        let processed_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let balances: Balances = block.transactions.iter()
+            .filter_map(|tx_string| serde_json::from_str::<Transaction>(tx_string).ok())
+            .map(|tx| {
+                let balances = ledger.balances(&tx.from);
+                (tx.from, balances)
+            })
+            .collect();
+        self.journal(JournalEntry::Block { block: block.clone(), processed_at, balances: Some(balances.clone()) });
+        let trades = self.match_block(block, processed_at, Some(&balances));
+        self.checkpoint_if_due();
+        trades
+    }
+
+    fn queue_broadcast(&mut self, trades: impl IntoIterator<Item = Trade>) {
+        self.pending_broadcast.extend(trades);
+        if self.pending_broadcast.len() > MAX_PENDING_BROADCAST {
+            let excess = self.pending_broadcast.len() - MAX_PENDING_BROADCAST;
+            println!("Giving up on delivering the {} oldest trades to the tracker", excess);
+            self.pending_broadcast.drain(..excess);
+        }
+    }
+
+    // Committed trades the tracker has not acknowledged yet, e.g. from before a restart
+    pub fn pending_broadcasts(&self) -> &[Trade] {
+        &self.pending_broadcast
+    }
+
+    pub fn mark_broadcast(&mut self, trade_ids: &[String]) {
+        if trade_ids.is_empty() {
+            return;
+        }
+        self.pending_broadcast.retain(|t| !trade_ids.contains(&t.trade_id));
+        self.journal(JournalEntry::Broadcast { trade_ids: trade_ids.to_vec() });
+        self.checkpoint_if_due();
+    }
+
+    // Drop expired GTT/GTD orders, and every order of a network that has sent
+    // no block for more than `max_silence` seconds (0 keeps them); abort
+    // settlements still preparing after `settlement_timeout` seconds (0
+    // waits forever). Returns what this sweep cancelled; it is also queued
+    // for `take_cancellations`, the aborts for `take_settlement_updates`.
+    pub fn sweep(&mut self, now: u64, max_silence: u64, settlement_timeout: u64) -> Vec<CancelledOrder> {
+        self.journal(JournalEntry::Sweep { at: now, max_silence, settlement_timeout });
+        let cancelled = self.apply_sweep(now, max_silence, settlement_timeout);
+        self.checkpoint_if_due();
+        cancelled
+    }
+
+    fn apply_sweep(&mut self, now: u64, max_silence: u64, settlement_timeout: u64) -> Vec<CancelledOrder> {
+        if settlement_timeout > 0 {
+            let timed_out: Vec<String> = self.settlements.values()
+                .filter(|s| s.is_preparing() && now.saturating_sub(s.opened_at) >= settlement_timeout)
+                .map(|s| s.trade_id().to_string())
+                .collect();
+            for trade_id in timed_out {
+                if let Some(settlement) = self.settlements.get_mut(&trade_id) {
+                    settlement.abort("timed out waiting for both networks", now);
+                    println!("Settlement of trade {} ABORTED: timed out", trade_id);
+                    self.settlement_updates.push(settlement.clone());
+                }
+            }
+        }
+
+        let mut cancelled: Vec<CancelledOrder> = self.book.expire(now).iter()
+            .map(|order| CancelledOrder::new(order, "expired"))
+            .collect();
+
+        if max_silence > 0 {
+            // Orders restored from before this map existed start the clock now
+            let networks: Vec<String> = self.book.side_orders(OrderSide::Buy).into_iter()
+                .chain(self.book.side_orders(OrderSide::Sell))
+                .chain(self.book.stop_orders())
+                .map(|order| order.network_id.clone())
+                .collect();
+            for network in networks {
+                self.network_last_seen.entry(network).or_insert(now);
+            }
+
+            let stale: HashSet<String> = self.network_last_seen.iter()
+                .filter(|(_, seen)| now.saturating_sub(**seen) > max_silence)
+                .map(|(network, _)| network.clone())
+                .collect();
+            if !stale.is_empty() {
+                for order in self.book.cancel_where(|order| stale.contains(&order.network_id)) {
+                    cancelled.push(CancelledOrder::new(&order, "network inactive"));
+                }
+                for network in &stale {
+                    self.network_last_seen.remove(network);
+                }
+            }
+        }
+
+        for order in &cancelled {
+            println!("Order {} from network {} cancelled ({}), {} {} unfilled",
+                     order.order_id, order.network_id, order.reason, order.remaining, order.asset);
+            self.surveillance.on_removed(&order.order_id);
+        }
+        self.cancellations.extend(cancelled.iter().cloned());
+        cancelled
+    }
+
+    // Cancellations not yet sent out with an order book broadcast
+    pub fn take_cancellations(&mut self) -> Vec<CancelledOrder> {
+        std::mem::take(&mut self.cancellations)
+    }
+
+    // Cancel/amend outcomes not yet sent to the tracker
+    pub fn take_acks(&mut self) -> Vec<OrderAck> {
+        std::mem::take(&mut self.acks)
+    }
+
+    // A network's prepare vote on one of its trades. A commit queues the
+    // trade for delivery (see `pending_broadcasts`).
+    pub fn vote(&mut self, trade_id: &str, network_id: &str, prepared: bool, reason: Option<&str>, now: u64) -> Result<SettlementStatus, String> {
+        self.journal(JournalEntry::Vote {
+            trade_id: trade_id.to_string(),
+            network_id: network_id.to_string(),
+            prepared,
+            reason: reason.map(str::to_string),
+            at: now,
+        });
+        let status = self.apply_vote(trade_id, network_id, prepared, reason, now);
+        self.checkpoint_if_due();
+        status
+    }
+
+    fn apply_vote(&mut self, trade_id: &str, network_id: &str, prepared: bool, reason: Option<&str>, now: u64) -> Result<SettlementStatus, String> {
+        let settlement = self.settlements.get_mut(trade_id)
+            .ok_or_else(|| format!("no settlement for trade {}", trade_id))?;
+        if !settlement.vote(network_id, prepared, reason, now)? {
+            return Ok(settlement.status.clone());
+        }
+
+        let settlement = settlement.clone();
+        match &settlement.status {
+            SettlementStatus::Committed { .. } => {
+                println!("Settlement of trade {} COMMITTED", trade_id);
+                self.market_data.record(&settlement.trade);
+                self.queue_broadcast([settlement.trade.clone()]);
+            }
+            SettlementStatus::Aborted { reason, .. } => println!("Settlement of trade {} ABORTED: {}", trade_id, reason),
+            SettlementStatus::Preparing => {}
         }
+        self.settlement_updates.push(settlement.clone());
+        self.prune_settlements();
+        Ok(settlement.status)
+    }
+
+    // A compliance reviewer dismisses or escalates a surveillance alert
+    pub fn review_alert(&mut self, alert_id: &str, status: ReviewStatus, reviewer: &str, note: &str, now: u64) -> Result<Alert, String> {
+        self.journal(JournalEntry::Review {
+            alert_id: alert_id.to_string(),
+            status,
+            reviewer: reviewer.to_string(),
+            note: note.to_string(),
+            at: now,
+        });
+        let alert = self.surveillance.review(alert_id, status, reviewer, note, now);
+        self.checkpoint_if_due();
+        alert
+    }
+
+    // Settlements opened or decided since the last call, for the tracker
+    pub fn take_settlement_updates(&mut self) -> Vec<Settlement> {
+        std::mem::take(&mut self.settlement_updates)
     }
 
-    pub fn process_block(&mut self, block: &TenantBlockData) -> Vec<Trade> {
+    // Everything a trader has locked: resting and parked orders plus trades
+    // still preparing, by asset (cash for buy orders)
+    pub fn escrow(&self, network_id: &str, trader: &str) -> BTreeMap<String, u64> {
+        let mut locked = BTreeMap::new();
+        for lock in self.locks() {
+            if lock.network_id == network_id && lock.trader == trader && lock.amount > 0 {
+                *locked.entry(lock.asset).or_insert(0) += lock.amount;
+            }
+        }
+        locked
+    }
+
+    fn locks(&self) -> impl Iterator<Item = EscrowLock> + '_ {
+        let orders = self.book.side_orders(OrderSide::Buy).into_iter()
+            .chain(self.book.side_orders(OrderSide::Sell))
+            .chain(self.book.stop_orders())
+            .map(EscrowLock::for_order);
+        let preparing = self.settlements.values()
+            .filter(|s| s.is_preparing())
+            .flat_map(|s| s.escrow());
+        orders.chain(preparing)
+    }
+
+    // Why `order` does not fit the trader's balance less what the trader
+    // already locks on the order's network, not counting `released`, the lock
+    // of the order it replaces. Nothing is checked without balances (old
+    // journal entries).
+    fn short_of_funds(&self, order: &OrderBookEntry, balances: Option<&Balances>, released: u64) -> Option<String> {
+        let balances = balances?;
+        let needed = EscrowLock::for_order(order);
+        if needed.amount == 0 {
+            return None;
+        }
+        let balance = balances.get(&order.trader)
+            .and_then(|assets| assets.get(&needed.asset))
+            .copied()
+            .unwrap_or(0);
+        let locked = self.locks()
+            .filter(|lock| lock.network_id == order.network_id && lock.trader == order.trader && lock.asset == needed.asset)
+            .map(|lock| lock.amount)
+            .sum::<u64>()
+            .saturating_sub(released);
+        let available = balance.saturating_sub(locked);
+        (needed.amount > available).then(|| {
+            format!("insufficient funds: {} {} available, {} needed", available, needed.asset, needed.amount)
+        })
+    }
+
+    // Forget the oldest decided settlements beyond MAX_SETTLEMENTS
+    fn prune_settlements(&mut self) {
+        if self.settlements.len() <= MAX_SETTLEMENTS {
+            return;
+        }
+        let mut decided: Vec<(u64, String)> = self.settlements.values()
+            .filter_map(|s| s.decided_at().map(|at| (at, s.trade_id().to_string())))
+            .collect();
+        decided.sort();
+        let excess = (self.settlements.len() - MAX_SETTLEMENTS + MAX_SETTLEMENTS / 10).min(decided.len());
+        for (_, trade_id) in decided.drain(..excess) {
+            self.settlements.remove(&trade_id);
+        }
+    }
+
+    // Deterministic for a given block and time, which makes journal replay exact
+    fn match_block(&mut self, block: &TenantBlockData, processed_at: u64, balances: Option<&Balances>) -> Vec<Trade> {
         println!("Processing block from network {} with {} transactions", 
                  block.network_id, block.transactions.len());
         
         let mut new_trades = Vec::new();
+        self.network_last_seen.insert(block.network_id.clone(), processed_at);
         
         for tx_string in &block.transactions {
             if let Ok(tx) = serde_json::from_str::<Transaction>(tx_string) {
@@ -68,14 +579,17 @@ impl EnterpriseOrderEngine {
                     continue;
                 }
                 
-                if let TransactionType::Trading { asset, quantity, price } = &tx.tx_type {
+                if let TransactionType::Trading { asset, quantity, price, order_type, time_in_force, client_order_id, .. } = &tx.tx_type {
                     println!("Processing new trading transaction: {} {} {} @ {}", 
                              tx.id, asset, quantity, price);
                     
-                    let order_side = if tx.id.contains("buy_") { 
-                        OrderSide::Buy 
-                    } else { 
-                        OrderSide::Sell 
+                    let order_side = match tx.order_side() {
+                        Some(side) => side,
+                        None => {
+                            println!("Ignoring trading transaction {} without a buy/sell side", tx.id);
+                            self.processed_transactions.insert(tx.id.clone());
+                            continue;
+                        }
                     };
                     
                     let order = OrderBookEntry {
@@ -86,14 +600,25 @@ impl EnterpriseOrderEngine {
                         quantity: *quantity,
                         price: *price,
                         side: order_side,
+                        order_type: *order_type,
+                        time_in_force: *time_in_force,
+                        client_order_id: client_order_id.clone(),
                         timestamp: tx.timestamp,
                     };
                     
-                    let trades = self.process_order(order);
-                    new_trades.extend(trades);
+                    if let Some(reason) = self.short_of_funds(&order, balances, 0) {
+                        println!("Order {} from {} REJECTED: {}", order.order_id, order.network_id, reason);
+                        self.cancellations.push(CancelledOrder::new(&order, &reason));
+                    } else {
+                        let trades = self.process_order(order, processed_at);
+                        new_trades.extend(trades);
+                    }
                     
                     // Mark as processed
                     self.processed_transactions.insert(tx.id.clone());
+                } else if matches!(tx.tx_type, TransactionType::OrderCancel { .. } | TransactionType::OrderAmend { .. }) {
+                    new_trades.extend(self.change_order(&tx, &block.network_id, processed_at, balances));
+                    self.processed_transactions.insert(tx.id.clone());
                 }
             }
         }
@@ -103,122 +628,153 @@ impl EnterpriseOrderEngine {
     }
 
 
-    fn process_order(&mut self, order: OrderBookEntry) -> Vec<Trade> {
-        println!("Processing order: {:?} {} {} @ {} from {}", 
-                 order.side, order.quantity, order.asset, order.price, order.network_id);
-        
-        let mut trades = Vec::new();
-        let mut remaining_order = order;
-        
-        // Get opposite side orders count for logging
-        let opposite_count = match remaining_order.side {
-            OrderSide::Buy => self.sell_orders.len(),
-            OrderSide::Sell => self.buy_orders.len(),
+    // Apply an OrderCancel/OrderAmend transaction from `network_id` and queue
+    // its ack. Only the trader and network that placed the order may change
+    // it. A quantity-only amend keeps the order's place when it shrinks; any
+    // other amend re-enters the order as new, so it can trade right away.
+    fn change_order(&mut self, tx: &Transaction, network_id: &str, now: u64, balances: Option<&Balances>) -> Vec<Trade> {
+        let (action, order_id, price, quantity) = match &tx.tx_type {
+            TransactionType::OrderCancel { order_id } => ("cancel", order_id, None, None),
+            TransactionType::OrderAmend { order_id, price, quantity } => ("amend", order_id, *price, *quantity),
+            _ => return Vec::new(),
         };
-        
-        println!("Looking for matches against {} opposite orders", opposite_count);
-        
-        // Process matches by taking ownership of the opposite orders temporarily
-        let mut opposite_orders = match remaining_order.side {
-            OrderSide::Buy => std::mem::take(&mut self.sell_orders),
-            OrderSide::Sell => std::mem::take(&mut self.buy_orders),
+        let mut ack = OrderAck {
+            request_id: tx.id.clone(),
+            order_id: order_id.clone(),
+            trader: tx.from.clone(),
+            network_id: network_id.to_string(),
+            action: action.to_string(),
+            accepted: false,
+            reason: None,
+            order: None,
+        };
+
+        let current = self.book.get(order_id).or_else(|| self.book.get_stop(order_id)).cloned();
+        let reject = match &current {
+            None => Some("order is not open".to_string()), // filled, cancelled or never seen
+            Some(order) if order.trader != tx.from || order.network_id != network_id => {
+                Some("only the order's trader and network can change it".to_string())
+            }
+            Some(_) if action == "amend" && (quantity == Some(0) || price == Some(0)) => {
+                Some("amend needs a positive price and quantity".to_string())
+            }
+            Some(order) if action == "amend" => {
+                let amended = OrderBookEntry {
+                    price: price.unwrap_or(order.price),
+                    quantity: quantity.unwrap_or(order.quantity),
+                    ..order.clone()
+                };
+                self.short_of_funds(&amended, balances, EscrowLock::for_order(order).amount)
+            }
+            Some(_) => None,
+        };
+        let mut order = match (reject, current) {
+            (None, Some(order)) => order,
+            (reason, _) => {
+                println!("{} of order {} from {} REJECTED: {}", action, order_id, network_id, reason.as_deref().unwrap_or(""));
+                ack.reason = reason;
+                self.acks.push(ack);
+                return Vec::new();
+            }
         };
+
+        let mut trades = Vec::new();
+        if action == "cancel" {
+            self.book.cancel(order_id);
+            println!("Order {} cancelled by its trader, {} {} unfilled", order_id, order.quantity, order.asset);
+            let cancelled = CancelledOrder::new(&order, "cancelled by trader");
+            self.surveillance.on_cancel(&cancelled, now);
+            self.cancellations.push(cancelled);
+        } else {
+            let price = price.unwrap_or(order.price);
+            let quantity = quantity.unwrap_or(order.quantity);
+            if price == order.price && self.book.get(order_id).is_some() {
+                self.book.set_quantity(order_id, quantity);
+            } else {
+                self.book.cancel(order_id);
+                self.surveillance.on_removed(order_id);
+                order.price = price;
+                order.quantity = quantity;
+                trades = self.process_order(order, now);
+            }
+            println!("Order {} amended to {} @ {}", order_id, quantity, price);
+            ack.order = self.book.get(order_id).or_else(|| self.book.get_stop(order_id)).cloned();
+        }
+        ack.accepted = true;
+        self.acks.push(ack);
+        trades
+    }
+
+    // The order and any stops it triggers, each by its type and time in force
+    fn process_order(&mut self, order: OrderBookEntry, now: u64) -> Vec<Trade> {
+        println!("Processing order: {:?} {:?} {} {} @ {} from {}", 
+                 order.side, order.order_type, order.quantity, order.asset, order.price, order.network_id);
         
-        let mut i = 0;
-        while i < opposite_orders.len() && remaining_order.quantity > 0 {
-            let opposite_order = &mut opposite_orders[i];
-            
-            // Check if orders can match (same asset and compatible price)
-            let can_match = remaining_order.asset == opposite_order.asset && 
-                           Self::prices_match(&remaining_order, opposite_order);
-            
-            println!("Checking match with order {} {} @ {}: can_match = {}", 
-                     opposite_order.quantity, opposite_order.asset, opposite_order.price, can_match);
-            
-            if can_match {
-                let trade_quantity = remaining_order.quantity.min(opposite_order.quantity);
-                let trade_price = opposite_order.price; // Maker's price wins
-                
+        let mut trades = Vec::new();
+        for execution in self.book.submit(order, now) {
+            let taker = &execution.order;
+            for prevented in &execution.prevented {
+                let maker = &prevented.maker;
+                println!("Self-trade prevented ({:?}): order {} from {} against {} from {}, {} {} cancelled",
+                         prevented.mode, taker.order_id, taker.network_id, maker.order_id, maker.network_id,
+                         prevented.cancelled, maker.asset);
+                self.surveillance.on_self_trade_prevented(taker, maker, prevented.mode, now);
+                if prevented.cancelled > 0 && prevented.cancelled == maker.quantity {
+                    self.surveillance.on_removed(&maker.order_id);
+                    self.cancellations.push(CancelledOrder::new(maker, "self-trade prevention"));
+                }
+            }
+            for fill in &execution.fills {
+                let maker = &fill.maker;
+                self.surveillance.on_fill(&maker.order_id, fill.quantity, maker.quantity - fill.quantity);
+                let (buy, sell) = match taker.side {
+                    OrderSide::Buy => (taker, maker),
+                    OrderSide::Sell => (maker, taker),
+                };
                 let trade = Trade {
                     trade_id: format!("trade_{}_{}_{}_{}", 
-                        remaining_order.order_id,
-                        opposite_order.order_id,
-                        trade_quantity,
-                        trade_price as u64
+                        taker.order_id,
+                        maker.order_id,
+                        fill.quantity,
+                        fill.price
                     ),
-                    asset: remaining_order.asset.clone(),
-                    quantity: trade_quantity,
-                    price: trade_price,
-                    buyer: match remaining_order.side {
-                        OrderSide::Buy => remaining_order.trader.clone(),
-                        OrderSide::Sell => opposite_order.trader.clone(),
-                    },
-                    seller: match remaining_order.side {
-                        OrderSide::Sell => remaining_order.trader.clone(),
-                        OrderSide::Buy => opposite_order.trader.clone(),
-                    },
-                    buyer_network: match remaining_order.side {
-                        OrderSide::Buy => remaining_order.network_id.clone(),
-                        OrderSide::Sell => opposite_order.network_id.clone(),
-                    },
-                    seller_network: match remaining_order.side {
-                        OrderSide::Sell => remaining_order.network_id.clone(),
-                        OrderSide::Buy => opposite_order.network_id.clone(),
-                    },
-                    timestamp: std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
+                    asset: taker.asset.clone(),
+                    quantity: fill.quantity,
+                    price: fill.price, // Maker's price wins
+                    buyer: buy.trader.clone(),
+                    seller: sell.trader.clone(),
+                    buyer_network: buy.network_id.clone(),
+                    seller_network: sell.network_id.clone(),
+                    timestamp: now,
+                    taker_side: Some(taker.side),
                 };
                 
-                // Check if this trade has already been processed
-                if self.processed_trades.contains(&trade.trade_id) {
-                    println!("Skipping duplicate trade: {}", trade.trade_id);
-                    i += 1;
-                    continue;
-                }
-                
                 println!("TRADE EXECUTED: {} {} {} @ {} between networks {} and {}", 
                          trade.trade_id, trade.quantity, trade.asset, trade.price,
                          trade.buyer_network, trade.seller_network);
                 
-                // Mark trade as processed
                 self.processed_trades.insert(trade.trade_id.clone());
+                self.surveillance.on_trade(&trade);
+                let settlement = Settlement::new(trade.clone(), now);
+                self.settlement_updates.push(settlement.clone());
+                self.settlements.insert(trade.trade_id.clone(), settlement);
                 trades.push(trade);
-                
-                // Update quantities
-                remaining_order.quantity -= trade_quantity;
-                opposite_order.quantity -= trade_quantity;
-                
-                // Remove fully filled opposite order
-                if opposite_order.quantity == 0 {
-                    println!("Removing fully filled opposite order");
-                    opposite_orders.remove(i);
-                } else {
-                    i += 1;
-                }
-            } else {
-                i += 1;
             }
-        }
-        
-        // Put the opposite orders back
-        match remaining_order.side {
-            OrderSide::Buy => self.sell_orders = opposite_orders,
-            OrderSide::Sell => self.buy_orders = opposite_orders,
-        }
-        
-        // Add remaining quantity to order book
-        if remaining_order.quantity > 0 {
-            println!("Adding remaining order to book: {} {}", remaining_order.quantity, remaining_order.asset);
-            match remaining_order.side {
-                OrderSide::Buy => {
-                    self.buy_orders.push(remaining_order);
-                    self.buy_orders.sort_by(|a, b| b.price.cmp(&a.price)); // Highest price first
+            
+            match &execution.status {
+                OrderStatus::Filled => println!("Order {} filled", taker.order_id),
+                OrderStatus::Resting { remaining } => {
+                    println!("Order {} resting with {} {}", taker.order_id, remaining, taker.asset);
+                    let rested = OrderBookEntry { quantity: *remaining, ..taker.clone() };
+                    self.surveillance.on_resting_order(&rested, taker.quantity, now);
                 }
-                OrderSide::Sell => {
-                    self.sell_orders.push(remaining_order);
-                    self.sell_orders.sort_by(|a, b| a.price.cmp(&b.price)); // Lowest price first
+                OrderStatus::Cancelled { remaining, reason } => println!("Order {} cancelled {} unfilled: {}", taker.order_id, remaining, reason),
+                OrderStatus::Rejected { reason } => println!("Order {} REJECTED: {}", taker.order_id, reason),
+                OrderStatus::StopPending => println!("Stop order {} waiting for its trigger price", taker.order_id),
+                OrderStatus::Expired { remaining } => {
+                    println!("Order {} expired with {} {} unfilled", taker.order_id, remaining, taker.asset);
+                    self.surveillance.on_removed(&taker.order_id);
+                    self.cancellations.push(CancelledOrder::new(taker, "expired"));
                 }
             }
         }
@@ -231,25 +787,17 @@ impl EnterpriseOrderEngine {
         }
         
         println!("Order processing complete. Current state:");
-        println!("Buy orders: {}, Sell orders: {}, Total trades: {}", 
-                 self.buy_orders.len(), self.sell_orders.len(), self.recent_trades.len());
+        println!("Buy orders: {}, Sell orders: {}, Stop orders: {}, Total trades: {}", 
+                 self.book.side_len(OrderSide::Buy), self.book.side_len(OrderSide::Sell),
+                 self.book.stop_orders().len(), self.recent_trades.len());
         
         trades
     }
-    
-    // FIXED: Made this a static function to avoid borrow checker issues
-    fn prices_match(order1: &OrderBookEntry, order2: &OrderBookEntry) -> bool {
-        match (&order1.side, &order2.side) {
-            (OrderSide::Buy, OrderSide::Sell) => order1.price >= order2.price,
-            (OrderSide::Sell, OrderSide::Buy) => order1.price <= order2.price,
-            _ => false,
-        }
-    }
 
     pub fn get_order_book_summary(&self) -> serde_json::Value {
         let mut asset_summary = std::collections::HashMap::new();
         
-        for order in &self.buy_orders {
+        for order in self.book.side_orders(OrderSide::Buy) {
             let entry = asset_summary.entry(order.asset.clone()).or_insert_with(|| serde_json::json!({
                 "bids": 0,
                 "asks": 0,
@@ -263,7 +811,7 @@ impl EnterpriseOrderEngine {
             ));
         }
         
-        for order in &self.sell_orders {
+        for order in self.book.side_orders(OrderSide::Sell) {
             let entry = asset_summary.entry(order.asset.clone()).or_insert_with(|| serde_json::json!({
                 "bids": 0,
                 "asks": 0,
@@ -280,6 +828,27 @@ impl EnterpriseOrderEngine {
         serde_json::Value::Object(asset_summary.into_iter().collect())
     }
 
+    // Up to `levels` aggregated price levels per side
+    pub fn depth(&self, asset: &str, levels: usize) -> Depth {
+        Depth::new(asset, self.book.depth(asset, OrderSide::Buy, levels), self.book.depth(asset, OrderSide::Sell, levels))
+    }
+
+    pub fn ticker(&self, asset: &str, now: u64) -> Ticker {
+        Ticker::new(
+            asset,
+            self.book.last_price(asset),
+            self.book.best_price(asset, OrderSide::Buy),
+            self.book.best_price(asset, OrderSide::Sell),
+            self.market_data.last_24h(asset, now),
+        )
+    }
+
+    // Every asset with resting orders or committed trades
+    pub fn tickers(&self, now: u64) -> Vec<Ticker> {
+        let assets: std::collections::BTreeSet<&str> = self.book.assets().chain(self.market_data.assets()).collect();
+        assets.into_iter().map(|asset| self.ticker(asset, now)).collect()
+    }
+
     pub fn get_recent_trades(&self, limit: usize) -> &[Trade] {
         let start = if self.recent_trades.len() > limit {
             self.recent_trades.len() - limit
@@ -291,7 +860,7 @@ impl EnterpriseOrderEngine {
 
     pub fn get_all_orders(&self) -> serde_json::Value {
         // Map orders to include all fields explicitly
-        let buy_orders_with_network: Vec<serde_json::Value> = self.buy_orders.iter().map(|o| {
+        let buy_orders_with_network: Vec<serde_json::Value> = self.book.side_orders(OrderSide::Buy).into_iter().map(|o| {
             serde_json::json!({
                 "order_id": o.order_id,
                 "trader": o.trader,
@@ -300,11 +869,14 @@ impl EnterpriseOrderEngine {
                 "quantity": o.quantity,
                 "price": o.price,
                 "side": "buy",
+                "order_type": o.order_type,
+                "time_in_force": o.time_in_force,
+                "client_order_id": o.client_order_id,
                 "timestamp": o.timestamp
             })
         }).collect();
 
-        let sell_orders_with_network: Vec<serde_json::Value> = self.sell_orders.iter().map(|o| {
+        let sell_orders_with_network: Vec<serde_json::Value> = self.book.side_orders(OrderSide::Sell).into_iter().map(|o| {
             serde_json::json!({
                 "order_id": o.order_id,
                 "trader": o.trader,
@@ -313,14 +885,317 @@ impl EnterpriseOrderEngine {
                 "quantity": o.quantity,
                 "price": o.price,
                 "side": "sell",
+                "order_type": o.order_type,
+                "time_in_force": o.time_in_force,
+                "client_order_id": o.client_order_id,
+                "timestamp": o.timestamp
+            })
+        }).collect();
+
+        let stop_orders: Vec<serde_json::Value> = self.book.stop_orders().into_iter().map(|o| {
+            serde_json::json!({
+                "order_id": o.order_id,
+                "trader": o.trader,
+                "network_id": o.network_id,
+                "asset": o.asset,
+                "quantity": o.quantity,
+                "price": o.price,
+                "side": match o.side {
+                    OrderSide::Buy => "buy",
+                    OrderSide::Sell => "sell",
+                },
+                "order_type": o.order_type,
+                "time_in_force": o.time_in_force,
+                "client_order_id": o.client_order_id,
                 "timestamp": o.timestamp
             })
         }).collect();
 
         serde_json::json!({
             "buy_orders": buy_orders_with_network,
-            "sell_orders": sell_orders_with_network
+            "sell_orders": sell_orders_with_network,
+            "stop_orders": stop_orders
         })
     }
-    
+
+    // Application state carried in chain snapshots
+    pub fn snapshot_state(&self) -> serde_json::Value {
+        serde_json::json!({ "order_engine": self })
+    }
+
+    pub fn from_snapshot_state(state: &serde_json::Value) -> Option<Self> {
+        serde_json::from_value(state["order_engine"].clone()).ok()
+    }
+}
+
+// Tenant blocks and balances shared by the engine, journal and stream tests
+#[cfg(test)]
+pub(crate) mod test_blocks {
+    use crate::blockchain::ledger::{Ledger, NATIVE_COIN};
+    use crate::blockchain::{OrderSide, TenantBlockData, Transaction, TransactionType};
+
+    // Enough of everything for the traders these tests use
+    pub fn funds() -> Ledger {
+        let mut ledger = Ledger::new();
+        for trader in ["net-a-trader", "net-b-trader", "net-c-trader", "net-d-trader", "alice", "bob", "carol"] {
+            ledger.issue(trader, "BTC", 1_000_000).unwrap();
+            ledger.issue(trader, NATIVE_COIN, 1_000_000).unwrap();
+        }
+        ledger
+    }
+
+    pub fn tx_block(network: &str, block_id: u32, tx: &Transaction) -> TenantBlockData {
+        TenantBlockData {
+            block_id,
+            block_hash: format!("{}-{}-{}", network, block_id, tx.id),
+            transactions: vec![serde_json::to_string(tx).unwrap()],
+            timestamp: 1,
+            previous_hash: String::new(),
+            network_id: network.to_string(),
+            merkle_root: None,
+            tenant_signature: None,
+        }
+    }
+
+    pub fn order_block(network: &str, block_id: u32, trader: &str, order_id: &str, side: OrderSide, price: u64, quantity: u64) -> TenantBlockData {
+        let tx = Transaction {
+            id: order_id.to_string(),
+            from: trader.to_string(),
+            to: "market".to_string(),
+            amount: 0,
+            tx_type: TransactionType::Trading {
+                asset: "BTC".to_string(),
+                quantity,
+                price,
+                side: Some(side),
+                order_type: Default::default(),
+                time_in_force: Default::default(),
+                client_order_id: None,
+            },
+            timestamp: 1,
+            public_key: None,
+            signature: None,
+        };
+        tx_block(network, block_id, &tx)
+    }
+
+    // A cancel, amend or anything else `trader` sends
+    pub fn change_block(network: &str, trader: &str, request_id: &str, tx_type: TransactionType) -> TenantBlockData {
+        let tx = Transaction {
+            id: request_id.to_string(),
+            from: trader.to_string(),
+            to: "trading_contract".to_string(),
+            amount: 0,
+            tx_type,
+            timestamp: 2,
+            public_key: None,
+            signature: None,
+        };
+        tx_block(network, 1, &tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_blocks::{change_block, funds, order_block};
+    use crate::blockchain::ledger::NATIVE_COIN;
+
+    fn sell_block(network: &str, order_id: &str, price: u64, quantity: u64) -> TenantBlockData {
+        order_block(network, 1, &format!("{}-trader", network), order_id, OrderSide::Sell, price, quantity)
+    }
+
+    #[test]
+    fn test_cancel_and_amend_only_by_owner_and_acknowledged() {
+        let mut engine = EnterpriseOrderEngine::new();
+        engine.process_block(&sell_block("net-a", "sell_a", 100, 5), &funds());
+        engine.process_block(&sell_block("net-b", "sell_b", 105, 5), &funds());
+        let cancel = |order_id: &str| TransactionType::OrderCancel { order_id: order_id.to_string() };
+
+        // Another network, or another trader on the same network, is refused
+        engine.process_block(&change_block("net-b", "net-b-trader", "cancel_1", cancel("sell_a")), &funds());
+        engine.process_block(&change_block("net-a", "mallory", "cancel_2", cancel("sell_a")), &funds());
+        engine.process_block(&change_block("net-a", "net-a-trader", "cancel_3", cancel("sell_a")), &funds());
+        engine.process_block(&change_block("net-a", "net-a-trader", "cancel_4", cancel("sell_a")), &funds());
+        let acks: Vec<(String, bool)> = engine.take_acks().into_iter().map(|a| (a.request_id, a.accepted)).collect();
+        assert_eq!(acks, vec![
+            ("cancel_1".to_string(), false),
+            ("cancel_2".to_string(), false),
+            ("cancel_3".to_string(), true),
+            ("cancel_4".to_string(), false), // already gone
+        ]);
+        assert!(!engine.book.contains("sell_a"));
+        assert_eq!(engine.take_cancellations()[0].reason, "cancelled by trader");
+
+        // Shrinking keeps the order in place; a new price re-enters and can trade
+        let amend = |price, quantity| TransactionType::OrderAmend { order_id: "sell_b".to_string(), price, quantity };
+        assert!(engine.process_block(&change_block("net-b", "net-b-trader", "amend_1", amend(None, Some(3))), &funds()).is_empty());
+        assert_eq!(engine.book.get("sell_b").unwrap().quantity, 3);
+        let mut buy = sell_block("net-c", "buy_c", 100, 10);
+        let mut tx: Transaction = serde_json::from_str(&buy.transactions[0]).unwrap();
+        if let TransactionType::Trading { side, .. } = &mut tx.tx_type {
+            *side = Some(OrderSide::Buy);
+        }
+        buy.transactions = vec![serde_json::to_string(&tx).unwrap()];
+        assert!(engine.process_block(&buy, &funds()).is_empty());
+
+        let trades = engine.process_block(&change_block("net-b", "net-b-trader", "amend_2", amend(Some(100), None)), &funds());
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].quantity, trades[0].price), (3, 100));
+        let acks = engine.take_acks();
+        assert!(acks.iter().all(|a| a.accepted && a.network_id == "net-b"));
+        assert!(acks[1].order.is_none()); // filled in full
+        assert_eq!(engine.book.get("buy_c").unwrap().quantity, 7);
+    }
+
+    #[test]
+    fn test_cross_network_ties_fill_oldest_first_and_survive_checkpoint() {
+        let mut engine = EnterpriseOrderEngine::new();
+        engine.process_block(&sell_block("net-a", "sell_a", 100, 5), &funds());
+        engine.process_block(&sell_block("net-b", "sell_b", 100, 5), &funds());
+        engine.process_block(&sell_block("net-c", "sell_c", 99, 5), &funds());
+
+        // Reloading keeps the queue order within each price level
+        let json = serde_json::to_string(&engine).unwrap();
+        let mut engine: EnterpriseOrderEngine = serde_json::from_str(&json).unwrap();
+        assert_eq!(engine.book.side_len(OrderSide::Sell), 3);
+
+        let mut buy = sell_block("net-d", "buy_d", 100, 8);
+        let mut tx: Transaction = serde_json::from_str(&buy.transactions[0]).unwrap();
+        if let TransactionType::Trading { side, .. } = &mut tx.tx_type {
+            *side = Some(OrderSide::Buy);
+        }
+        buy.transactions = vec![serde_json::to_string(&tx).unwrap()];
+
+        let trades = engine.process_block(&buy, &funds());
+        let sellers: Vec<(&str, u64, u64)> = trades.iter()
+            .map(|t| (t.seller_network.as_str(), t.quantity, t.price))
+            .collect();
+        assert_eq!(sellers, vec![("net-c", 5, 99), ("net-a", 3, 100)]);
+        assert_eq!(engine.book.get("sell_a").unwrap().quantity, 2);
+        assert_eq!(engine.book.get("sell_b").unwrap().quantity, 5);
+    }
+
+    #[test]
+    fn test_same_trader_across_networks_never_self_trades() {
+        let order = |network: &str, order_id: &str, side: OrderSide| {
+            let mut block = sell_block(network, order_id, 100, 5);
+            let mut tx: Transaction = serde_json::from_str(&block.transactions[0]).unwrap();
+            tx.from = "alice".to_string();
+            if let TransactionType::Trading { side: tx_side, .. } = &mut tx.tx_type {
+                *tx_side = Some(side);
+            }
+            block.transactions = vec![serde_json::to_string(&tx).unwrap()];
+            block
+        };
+
+        // Default: the incoming order is dropped, the resting one stays
+        let mut engine = EnterpriseOrderEngine::new();
+        engine.process_block(&order("net-a", "sell_a", OrderSide::Sell), &funds());
+        assert!(engine.process_block(&order("net-b", "buy_b", OrderSide::Buy), &funds()).is_empty());
+        assert!(engine.book.contains("sell_a") && !engine.book.contains("buy_b"));
+        let alerts = engine.surveillance.alerts(None, Some("alice"), true, 10);
+        assert_eq!(alerts[0].kind, crate::enterprise_bc::surveillance::AlertKind::SelfTradePrevented);
+        assert_eq!(alerts[0].order_ids, vec!["buy_b", "sell_a"]);
+
+        // CancelOldest survives a reload and tells the resting order's network
+        engine.book.set_self_trade_prevention(SelfTradePrevention::CancelOldest);
+        let json = serde_json::to_string(&engine).unwrap();
+        let mut engine: EnterpriseOrderEngine = serde_json::from_str(&json).unwrap();
+        assert!(engine.process_block(&order("net-b", "buy_b2", OrderSide::Buy), &funds()).is_empty());
+        assert!(!engine.book.contains("sell_a") && engine.book.contains("buy_b2"));
+        let cancelled = engine.take_cancellations();
+        assert_eq!((cancelled[0].order_id.as_str(), cancelled[0].reason.as_str()), ("sell_a", "self-trade prevention"));
+
+        let id = engine.surveillance.alerts(None, None, true, 1)[0].alert_id.clone();
+        assert!(engine.review_alert(&id, ReviewStatus::Dismissed, "compliance", "same owner, no trade", 10).is_ok());
+        assert_eq!(engine.surveillance.open_alerts(), 1);
+    }
+
+    #[test]
+    fn test_orders_must_fit_the_balance_less_escrow() {
+        let order = |network: &str, order_id: &str, trader: &str, side: OrderSide, quantity: u64| {
+            let mut block = sell_block(network, order_id, 100, quantity);
+            let mut tx: Transaction = serde_json::from_str(&block.transactions[0]).unwrap();
+            tx.from = trader.to_string();
+            if let TransactionType::Trading { side: tx_side, .. } = &mut tx.tx_type {
+                *tx_side = Some(side);
+            }
+            block.transactions = vec![serde_json::to_string(&tx).unwrap()];
+            block
+        };
+        let mut ledger = Ledger::new();
+        ledger.issue("alice", "BTC", 10).unwrap();
+        ledger.issue("bob", NATIVE_COIN, 1_000).unwrap();
+
+        // Alice's resting sell locks 6 of her 10 BTC on net-a
+        let mut engine = EnterpriseOrderEngine::new();
+        engine.process_block(&order("net-a", "sell_1", "alice", OrderSide::Sell, 6), &ledger);
+        engine.process_block(&order("net-a", "sell_2", "alice", OrderSide::Sell, 5), &ledger);
+        assert!(!engine.book.contains("sell_2"));
+        let refused = engine.take_cancellations();
+        assert_eq!(refused[0].order_id, "sell_2");
+        assert!(refused[0].reason.starts_with("insufficient funds: 4 BTC available"));
+
+        // An amend is checked without the lock of the order it replaces
+        let amend = |quantity| TransactionType::OrderAmend { order_id: "sell_1".to_string(), price: Some(101), quantity: Some(quantity) };
+        engine.process_block(&change_block("net-a", "alice", "amend_1", amend(11)), &ledger);
+        engine.process_block(&change_block("net-a", "alice", "amend_2", amend(10)), &ledger);
+        let acks: Vec<bool> = engine.take_acks().iter().map(|a| a.accepted).collect();
+        assert_eq!(acks, vec![false, true]);
+
+        // A match keeps the filled part locked while the trade prepares; an
+        // abort frees it and the same order can be entered again
+        let mut buy = order("net-b", "buy_1", "bob", OrderSide::Buy, 10);
+        let mut tx: Transaction = serde_json::from_str(&buy.transactions[0]).unwrap();
+        if let TransactionType::Trading { price, .. } = &mut tx.tx_type {
+            *price = 101;
+        }
+        buy.transactions = vec![serde_json::to_string(&tx).unwrap()];
+        let trades = engine.process_block(&buy, &ledger);
+        assert_eq!(trades.len(), 1);
+        engine.process_block(&order("net-a", "sell_3", "alice", OrderSide::Sell, 1), &ledger);
+        assert!(!engine.book.contains("sell_3"));
+
+        let status = engine.vote(&trades[0].trade_id, "net-a", false, Some("seller offline"), 20).unwrap();
+        assert!(matches!(status, SettlementStatus::Aborted { .. }));
+        assert!(engine.escrow("net-a", "alice").is_empty());
+        engine.process_block(&order("net-a", "sell_4", "alice", OrderSide::Sell, 10), &ledger);
+        assert_eq!(engine.book.get("sell_4").unwrap().quantity, 10);
+    }
+
+    #[test]
+    fn test_orders_are_funded_by_their_own_network() {
+        // Alice's BTC is minted on net-a's chain and reaches us in its block
+        let mut mint = change_block("net-a", "validator", "mint_1", TransactionType::Mint { asset: "BTC".to_string() });
+        let mut tx: Transaction = serde_json::from_str(&mint.transactions[0]).unwrap();
+        tx.to = "alice".to_string();
+        tx.amount = 5;
+        mint.transactions = vec![serde_json::to_string(&tx).unwrap()];
+        let mut net_a = Ledger::new();
+        mint.apply_to(&mut net_a);
+
+        let sell = |network: &str, order_id: &str| {
+            let mut block = change_block(network, "alice", order_id, TransactionType::Trading {
+                asset: "BTC".to_string(),
+                quantity: 5,
+                price: 100,
+                side: Some(OrderSide::Sell),
+                order_type: Default::default(),
+                time_in_force: Default::default(),
+                client_order_id: None,
+            });
+            block.block_id = 2;
+            block
+        };
+        let mut engine = EnterpriseOrderEngine::new();
+        engine.process_block(&sell("net-a", "sell_a"), &net_a);
+        assert!(engine.book.contains("sell_a"));
+        assert!(engine.take_cancellations().is_empty());
+
+        // Nothing backs the same coins on net-b
+        engine.process_block(&sell("net-b", "sell_b"), &Ledger::new());
+        assert!(!engine.book.contains("sell_b"));
+        assert!(engine.take_cancellations()[0].reason.starts_with("insufficient funds: 0 BTC available"));
+    }
 }
@@ -0,0 +1,314 @@
+// src/enterprise_bc/order_journal.rs
+// Write-ahead journal for the enterprise order engine
+//
+// Every tenant block, expiry sweep and settlement vote is journaled before
+// the engine applies it, and every trade confirmed to the tracker is
+// journaled after the broadcast. A restart loads the last checkpoint and
+// replays the journal on top of it. Replay is idempotent: transactions
+// already in the checkpoint are skipped through `processed_transactions`,
+// and blocks are matched with their original timestamp and the ledger
+// balances their traders had then, so the same trades come out again.
+//
+//   engine.json   checkpoint of the whole engine, replaced atomically
+//   journal.log   one JSON entry per line since that checkpoint
+
+use crate::blockchain::store::write_atomic;
+use crate::blockchain::TenantBlockData;
+use crate::enterprise_bc::order_engine::{Balances, EnterpriseOrderEngine};
+use crate::enterprise_bc::surveillance::ReviewStatus;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+const DEFAULT_CHECKPOINT_EVERY: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalEntry {
+    Block {
+        block: TenantBlockData,
+        processed_at: u64,
+        // None in entries written before orders were checked against funds
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        balances: Option<Balances>,
+    },
+    Broadcast { trade_ids: Vec<String> },
+    Sweep {
+        at: u64,
+        max_silence: u64,
+        #[serde(default)]
+        settlement_timeout: u64,
+    },
+    Vote {
+        trade_id: String,
+        network_id: String,
+        prepared: bool,
+        #[serde(default)]
+        reason: Option<String>,
+        at: u64,
+    },
+    Review {
+        alert_id: String,
+        status: ReviewStatus,
+        reviewer: String,
+        note: String,
+        at: u64,
+    },
+}
+
+pub struct OrderJournal {
+    dir: PathBuf,
+    checkpoint_every: usize,
+    entries: usize, // since the last checkpoint
+}
+
+impl OrderJournal {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), checkpoint_every: DEFAULT_CHECKPOINT_EVERY, entries: 0 }
+    }
+
+    pub fn with_checkpoint_every(mut self, entries: usize) -> Self {
+        self.checkpoint_every = entries.max(1);
+        self
+    }
+
+    fn checkpoint_path(&self) -> PathBuf {
+        self.dir.join("engine.json")
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.dir.join("journal.log")
+    }
+
+    pub fn exists(&self) -> bool {
+        self.checkpoint_path().exists() || self.journal_path().exists()
+    }
+
+    // Checkpoint plus journal replay. A torn last line (crash mid-append) is
+    // cut off; anything else unreadable is an error.
+    pub fn recover(&mut self) -> Result<EnterpriseOrderEngine, String> {
+        let mut engine = match fs::read(self.checkpoint_path()) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| format!("{} is not an engine checkpoint: {}", self.checkpoint_path().display(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => EnterpriseOrderEngine::new(),
+            Err(e) => return Err(format!("cannot read {}: {}", self.checkpoint_path().display(), e)),
+        };
+
+        let mut data = Vec::new();
+        if let Ok(mut file) = File::open(self.journal_path()) {
+            file.read_to_end(&mut data).map_err(|e| format!("cannot read {}: {}", self.journal_path().display(), e))?;
+        }
+
+        let mut good_len = 0;
+        self.entries = 0;
+        for line in data.split_inclusive(|b| *b == b'\n') {
+            if !line.ends_with(b"\n") {
+                break;
+            }
+            let entry: JournalEntry = serde_json::from_slice(&line[..line.len() - 1])
+                .map_err(|e| format!("corrupt entry {} in {}: {}", self.entries + 1, self.journal_path().display(), e))?;
+            engine.replay(entry);
+            good_len += line.len();
+            self.entries += 1;
+        }
+
+        if good_len < data.len() {
+            println!("Dropping torn entry at the end of {}", self.journal_path().display());
+            let file = OpenOptions::new().write(true).open(self.journal_path())
+                .map_err(|e| format!("cannot repair {}: {}", self.journal_path().display(), e))?;
+            file.set_len(good_len as u64).and_then(|_| file.sync_all())
+                .map_err(|e| format!("cannot repair {}: {}", self.journal_path().display(), e))?;
+        }
+        Ok(engine)
+    }
+
+    pub fn append(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut line = serde_json::to_vec(entry).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(self.journal_path())?;
+        file.write_all(&line)?;
+        file.sync_data()?;
+        self.entries += 1;
+        Ok(())
+    }
+
+    pub fn needs_checkpoint(&self) -> bool {
+        self.entries >= self.checkpoint_every
+    }
+
+    // Checkpoint first, then empty the journal. A crash in between replays
+    // entries the checkpoint already covers, which is harmless.
+    pub fn checkpoint(&mut self, engine: &EnterpriseOrderEngine) -> io::Result<()> {
+        let json = serde_json::to_vec(engine).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_atomic(&self.checkpoint_path(), &json)?;
+        if self.journal_path().exists() {
+            let file = OpenOptions::new().write(true).open(self.journal_path())?;
+            file.set_len(0)?;
+            file.sync_all()?;
+        }
+        self.entries = 0;
+        Ok(())
+    }
+
+    // Keep an unreadable journal for inspection instead of overwriting it
+    pub fn set_aside(&self) -> io::Result<PathBuf> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut aside = self.dir.clone().into_os_string();
+        aside.push(format!(".corrupt-{}", now));
+        let aside = PathBuf::from(aside);
+        fs::rename(&self.dir, &aside)?;
+        Ok(aside)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::ledger::NATIVE_COIN;
+    use crate::blockchain::{OrderSide, TimeInForce, Transaction, TransactionType};
+    use crate::enterprise_bc::order_engine::test_blocks::{self, funds};
+    use crate::enterprise_bc::settlement::SettlementStatus;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("distli-journal-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn order_block(network: &str, block_id: u32, order_id: &str, trader: &str, price: u64) -> TenantBlockData {
+        let side = if order_id.starts_with("buy") { OrderSide::Buy } else { OrderSide::Sell };
+        test_blocks::order_block(network, block_id, trader, order_id, side, price, 10)
+    }
+
+    #[test]
+    fn test_restart_restores_orders_and_is_idempotent() {
+        let dir = temp_dir();
+        let sell = order_block("net-a", 1, "sell_1", "alice", 100);
+        let buy = order_block("net-b", 1, "buy_1", "bob", 100);
+
+        let mut engine = EnterpriseOrderEngine::new();
+        engine.attach_journal(OrderJournal::new(&dir));
+        engine.process_block(&sell, &funds());
+        let trades = engine.process_block(&buy, &funds());
+        assert_eq!(trades.len(), 1);
+        assert!(engine.pending_broadcasts().is_empty()); // delivered only once committed
+        engine.vote(&trades[0].trade_id, "net-a", true, None, 10).unwrap();
+        engine.vote(&trades[0].trade_id, "net-b", true, None, 11).unwrap();
+        assert_eq!(engine.pending_broadcasts().len(), 1);
+
+        // Restart: same trade is still owed to the tracker, nothing duplicated
+        let mut recovered = OrderJournal::new(&dir).recover().unwrap();
+        assert_eq!(recovered.recent_trades.len(), 1);
+        assert_eq!(recovered.recent_trades[0].timestamp, trades[0].timestamp);
+        assert_eq!(recovered.pending_broadcasts().len(), 1);
+        assert!(recovered.process_block(&buy, &funds()).is_empty());
+
+        engine.mark_broadcast(&[trades[0].trade_id.clone()]);
+        let rest = order_block("net-a", 2, "sell_2", "carol", 120);
+        engine.process_block(&rest, &funds());
+        let recovered = OrderJournal::new(&dir).recover().unwrap();
+        assert!(recovered.pending_broadcasts().is_empty());
+        assert_eq!(recovered.book.side_len(OrderSide::Sell), 1);
+        assert_eq!(recovered.processed_transactions.len(), 3);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sweep_expires_orders_and_drops_silent_networks() {
+        let dir = temp_dir();
+        let mut engine = EnterpriseOrderEngine::new();
+        engine.attach_journal(OrderJournal::new(&dir));
+        engine.process_block(&order_block("net-a", 1, "sell_1", "alice", 100), &funds());
+        let seen = engine.network_last_seen["net-a"];
+
+        let mut gtt = order_block("net-b", 1, "sell_2", "bob", 110);
+        let mut tx: Transaction = serde_json::from_str(&gtt.transactions[0]).unwrap();
+        if let TransactionType::Trading { time_in_force, .. } = &mut tx.tx_type {
+            *time_in_force = TimeInForce::Gtt { expires_at: seen + 1000 };
+        }
+        gtt.transactions = vec![serde_json::to_string(&tx).unwrap()];
+        engine.process_block(&gtt, &funds());
+        assert_eq!(engine.book.side_len(OrderSide::Sell), 2);
+
+        assert!(engine.sweep(seen + 500, 3600, 0).is_empty());
+        let expired = engine.sweep(seen + 2000, 3600, 0);
+        assert_eq!(expired.len(), 1);
+        assert_eq!((expired[0].order_id.as_str(), expired[0].reason.as_str()), ("sell_2", "expired"));
+
+        let stale = engine.sweep(seen + 10_000, 3600, 0);
+        assert_eq!(stale.len(), 1);
+        assert_eq!((stale[0].order_id.as_str(), stale[0].reason.as_str()), ("sell_1", "network inactive"));
+        assert_eq!(engine.take_cancellations().len(), 2);
+        assert!(engine.book.is_empty());
+
+        // Replaying the sweeps ends in the same state
+        let recovered = OrderJournal::new(&dir).recover().unwrap();
+        assert!(recovered.book.is_empty());
+        assert!(recovered.network_last_seen.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_settlement_votes_and_timeouts_survive_restart() {
+        let dir = temp_dir();
+        let mut engine = EnterpriseOrderEngine::new();
+        engine.attach_journal(OrderJournal::new(&dir));
+        engine.process_block(&order_block("net-a", 1, "sell_1", "alice", 100), &funds());
+        engine.process_block(&order_block("net-a", 2, "sell_2", "alice", 100), &funds());
+        let buy = order_block("net-b", 1, "buy_1", "bob", 100);
+        let mut more = order_block("net-b", 2, "buy_2", "bob", 100);
+        more.transactions.extend(buy.transactions.clone());
+        let trades = engine.process_block(&more, &funds());
+        assert_eq!(trades.len(), 2);
+        assert_eq!(engine.take_settlement_updates().len(), 2);
+        assert_eq!(engine.escrow("net-a", "alice").get("BTC"), Some(&20));
+        assert_eq!(engine.escrow("net-b", "bob").get(NATIVE_COIN), Some(&20));
+
+        let opened = engine.settlements[&trades[0].trade_id].opened_at;
+        engine.vote(&trades[0].trade_id, "net-a", true, None, opened + 1).unwrap();
+        engine.vote(&trades[0].trade_id, "net-b", true, None, opened + 2).unwrap();
+        engine.vote(&trades[1].trade_id, "net-a", true, None, opened + 2).unwrap();
+        engine.sweep(opened + 60, 0, 30); // net-b never answers on the second trade
+        let updates = engine.take_settlement_updates();
+        assert!(matches!(updates[0].status, SettlementStatus::Committed { .. }));
+        assert!(matches!(updates[1].status, SettlementStatus::Aborted { .. }));
+        assert!(engine.escrow("net-a", "alice").is_empty());
+        assert!(engine.vote(&trades[1].trade_id, "net-b", true, None, opened + 61).is_err());
+
+        let recovered = OrderJournal::new(&dir).recover().unwrap();
+        let states: Vec<&SettlementStatus> = trades.iter().map(|t| &recovered.settlements[&t.trade_id].status).collect();
+        assert_eq!(states, vec![&engine.settlements[&trades[0].trade_id].status, &engine.settlements[&trades[1].trade_id].status]);
+        assert_eq!(recovered.pending_broadcasts().len(), 1);
+        // Only the committed trade counts in the market data
+        let ticker = recovered.ticker("BTC", opened + 60);
+        assert_eq!((ticker.volume_24h, ticker.trades_24h), (10, 1));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_torn_entry_and_checkpoint_replay() {
+        let dir = temp_dir();
+        let mut engine = EnterpriseOrderEngine::new();
+        engine.attach_journal(OrderJournal::new(&dir).with_checkpoint_every(1));
+        engine.process_block(&order_block("net-a", 1, "sell_1", "alice", 100), &funds());
+
+        // Crash after the checkpoint was written but before the journal was
+        // emptied, followed by a torn append
+        let mut journal = OrderJournal::new(&dir);
+        journal.append(&JournalEntry::Block {
+            block: order_block("net-a", 1, "sell_1", "alice", 100),
+            processed_at: 5,
+            balances: None,
+        }).unwrap();
+        let mut file = OpenOptions::new().append(true).open(dir.join("journal.log")).unwrap();
+        file.write_all(b"{\"kind\":\"block\",\"blo").unwrap();
+
+        let recovered = OrderJournal::new(&dir).recover().unwrap();
+        assert_eq!(recovered.book.side_len(OrderSide::Sell), 1);
+        assert_eq!(fs::read_to_string(dir.join("journal.log")).unwrap().lines().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
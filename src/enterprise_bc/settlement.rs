@@ -0,0 +1,186 @@
+// src/enterprise_bc/settlement.rs
+// Escrow and two-phase commit for cross-network trades
+//
+// A resting order is its trader's escrow lock: a sell order holds back the
+// quantity it offers, a buy order quantity * price / 100 of the native coin.
+// An order is only accepted when the trader's ledger balance, less the locks
+// the trader already holds on any network, covers its lock.
+// A match moves the filled part out of both orders into a Settlement and asks
+// the buyer's and the seller's network to prepare it:
+//
+//   Preparing   waiting for a yes vote from each of the two networks
+//   Committed   both voted yes; the trade is recorded and delivered
+//   Aborted     a network voted no, or no decision within the timeout;
+//               the escrow goes back to both traders
+//
+// Neither network applies the trade before the commit, so a trade is never
+// half-applied when one tenant is offline.
+
+use crate::blockchain::ledger::NATIVE_COIN;
+use crate::enterprise_bc::order_engine::{OrderBookEntry, OrderSide, Trade};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// Seconds a settlement may stay in Preparing (SETTLEMENT_TIMEOUT_SECS)
+pub const DEFAULT_SETTLEMENT_TIMEOUT: u64 = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum SettlementStatus {
+    Preparing,
+    Committed { at: u64 },
+    Aborted { at: u64, reason: String },
+}
+
+// What one trader has committed and cannot use elsewhere
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EscrowLock {
+    pub network_id: String,
+    pub trader: String,
+    pub asset: String,
+    pub amount: u64,
+}
+
+impl EscrowLock {
+    // Held back by a resting or parked order
+    pub fn for_order(order: &OrderBookEntry) -> Self {
+        let (asset, amount) = match order.side {
+            OrderSide::Buy => (NATIVE_COIN.to_string(), order.quantity.saturating_mul(order.price) / 100),
+            OrderSide::Sell => (order.asset.clone(), order.quantity),
+        };
+        Self { network_id: order.network_id.clone(), trader: order.trader.clone(), asset, amount }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settlement {
+    pub trade: Trade,
+    pub opened_at: u64,
+    pub prepared: BTreeSet<String>, // networks that voted yes
+    pub status: SettlementStatus,
+}
+
+impl Settlement {
+    pub fn new(trade: Trade, now: u64) -> Self {
+        Self { trade, opened_at: now, prepared: BTreeSet::new(), status: SettlementStatus::Preparing }
+    }
+
+    pub fn trade_id(&self) -> &str {
+        &self.trade.trade_id
+    }
+
+    pub fn is_preparing(&self) -> bool {
+        self.status == SettlementStatus::Preparing
+    }
+
+    // Networks whose vote is needed; one when both traders are on the same network
+    pub fn parties(&self) -> BTreeSet<&str> {
+        [self.trade.buyer_network.as_str(), self.trade.seller_network.as_str()].into_iter().collect()
+    }
+
+    // The buyer's cash and the seller's asset, held until the decision
+    pub fn escrow(&self) -> [EscrowLock; 2] {
+        let trade = &self.trade;
+        [
+            EscrowLock {
+                network_id: trade.buyer_network.clone(),
+                trader: trade.buyer.clone(),
+                asset: NATIVE_COIN.to_string(),
+                amount: trade.quantity.saturating_mul(trade.price) / 100,
+            },
+            EscrowLock {
+                network_id: trade.seller_network.clone(),
+                trader: trade.seller.clone(),
+                asset: trade.asset.clone(),
+                amount: trade.quantity,
+            },
+        ]
+    }
+
+    // Record one network's vote. True when it decided the settlement; a
+    // repeated yes is harmless, a vote on a decided settlement is an error.
+    pub fn vote(&mut self, network_id: &str, prepared: bool, reason: Option<&str>, now: u64) -> Result<bool, String> {
+        if !self.parties().contains(network_id) {
+            return Err(format!("network {} is not a party to trade {}", network_id, self.trade_id()));
+        }
+        if !self.is_preparing() {
+            return Err(format!("trade {} is already decided", self.trade_id()));
+        }
+        if !prepared {
+            let reason = format!("{} declined: {}", network_id, reason.unwrap_or("no reason given"));
+            return Ok(self.abort(&reason, now));
+        }
+        self.prepared.insert(network_id.to_string());
+        if self.parties().iter().all(|party| self.prepared.contains(*party)) {
+            self.status = SettlementStatus::Committed { at: now };
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // False if it was already decided
+    pub fn abort(&mut self, reason: &str, now: u64) -> bool {
+        if !self.is_preparing() {
+            return false;
+        }
+        self.status = SettlementStatus::Aborted { at: now, reason: reason.to_string() };
+        true
+    }
+
+    // Where the two-phase commit stands: prepare, commit or abort
+    pub fn phase(&self) -> &'static str {
+        match self.status {
+            SettlementStatus::Preparing => "prepare",
+            SettlementStatus::Committed { .. } => "commit",
+            SettlementStatus::Aborted { .. } => "abort",
+        }
+    }
+
+    pub fn decided_at(&self) -> Option<u64> {
+        match self.status {
+            SettlementStatus::Preparing => None,
+            SettlementStatus::Committed { at } | SettlementStatus::Aborted { at, .. } => Some(at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(buyer_network: &str, seller_network: &str) -> Trade {
+        Trade {
+            trade_id: "t1".to_string(),
+            asset: "BTC".to_string(),
+            quantity: 4,
+            price: 250,
+            buyer: "bob".to_string(),
+            seller: "alice".to_string(),
+            buyer_network: buyer_network.to_string(),
+            seller_network: seller_network.to_string(),
+            timestamp: 1,
+            taker_side: None,
+        }
+    }
+
+    #[test]
+    fn test_commit_needs_both_networks_and_abort_is_final() {
+        let mut settlement = Settlement::new(trade("net-b", "net-a"), 10);
+        assert_eq!(settlement.escrow()[0].amount, 10);
+        assert!(settlement.vote("net-c", true, None, 11).is_err());
+        assert_eq!(settlement.vote("net-a", true, None, 11), Ok(false));
+        assert_eq!(settlement.vote("net-a", true, None, 11), Ok(false));
+        assert_eq!(settlement.vote("net-b", true, None, 12), Ok(true));
+        assert_eq!(settlement.status, SettlementStatus::Committed { at: 12 });
+        assert!(!settlement.abort("timeout", 13));
+
+        let mut settlement = Settlement::new(trade("net-b", "net-a"), 10);
+        assert_eq!(settlement.vote("net-b", false, Some("insufficient funds"), 11), Ok(true));
+        assert!(settlement.vote("net-a", true, None, 12).is_err());
+        assert!(matches!(settlement.status, SettlementStatus::Aborted { at: 11, .. }));
+
+        // Both traders on one network: a single vote commits
+        let mut settlement = Settlement::new(trade("net-a", "net-a"), 10);
+        assert_eq!(settlement.vote("net-a", true, None, 11), Ok(true));
+    }
+}
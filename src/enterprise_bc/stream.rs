@@ -0,0 +1,324 @@
+// src/enterprise_bc/stream.rs
+// Real-time market stream served on ws://<validator>/api/stream
+//
+// Clients subscribe to topics and get pushed events instead of polling:
+//   trades.<asset>      committed trades
+//   book.<asset>        order book deltas by price level
+//   blocks.<network>    tenant blocks as they arrive
+//   settlements         settlement opened, committed or aborted
+// A trailing ".*" subscribes to a whole family, e.g. "book.*".
+//
+// Requests are JSON text frames:
+//   {"op": "subscribe", "topics": ["book.BTC", "trades.*"]}
+//   {"op": "unsubscribe", "topics": ["trades.*"]}
+//   {"op": "snapshot", "topic": "book.BTC"}
+//
+// Book topics start with a book_snapshot carrying the asset's sequence
+// number. Every book_delta after it has the next number and lists changed
+// levels, quantity 0 for a level that is gone. Deltas at or below the
+// snapshot's number are already in it and can be skipped; a gap means
+// something was lost, and the client asks for a new snapshot. A client too
+// slow to keep up gets {"type": "resync"} followed by fresh snapshots.
+
+use crate::blockchain::matching::DepthLevel;
+use crate::blockchain::TenantBlockData;
+use crate::enterprise_bc::order_engine::{EnterpriseOrderEngine, OrderSide, Trade};
+use crate::enterprise_bc::settlement::Settlement;
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use warp::ws::{Message, WebSocket};
+
+// Events a client may fall behind by before it has to resync
+const STREAM_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LevelChange {
+    pub side: OrderSide,
+    pub price: u64,
+    pub quantity: u64, // 0 when the level is gone
+    pub orders: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    Trade { trade: Trade },
+    BookSnapshot { asset: String, seq: u64, bids: Vec<DepthLevel>, asks: Vec<DepthLevel> },
+    BookDelta { asset: String, seq: u64, changes: Vec<LevelChange> },
+    TenantBlock { network_id: String, block_id: u32, block_hash: String, transactions: usize, timestamp: u64 },
+    Settlement { phase: String, settlement: Settlement },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamMessage {
+    pub topic: String,
+    #[serde(flatten)]
+    pub event: StreamEvent,
+}
+
+// Levels by price, as last published
+#[derive(Debug, Default)]
+struct PublishedBook {
+    seq: u64,
+    bids: BTreeMap<u64, DepthLevel>,
+    asks: BTreeMap<u64, DepthLevel>,
+}
+
+impl PublishedBook {
+    fn side(&self, side: OrderSide) -> &BTreeMap<u64, DepthLevel> {
+        match side {
+            OrderSide::Buy => &self.bids,
+            OrderSide::Sell => &self.asks,
+        }
+    }
+
+    // Changes that turn `self` into `levels` on one side
+    fn changes(&self, side: OrderSide, levels: &BTreeMap<u64, DepthLevel>) -> Vec<LevelChange> {
+        let old = self.side(side);
+        let mut changes: Vec<LevelChange> = levels.values()
+            .filter(|level| old.get(&level.price) != Some(*level))
+            .map(|level| LevelChange { side, price: level.price, quantity: level.quantity, orders: level.orders })
+            .collect();
+        changes.extend(old.keys()
+            .filter(|price| !levels.contains_key(price))
+            .map(|price| LevelChange { side, price: *price, quantity: 0, orders: 0 }));
+        changes
+    }
+
+    fn snapshot(&self, asset: &str) -> StreamMessage {
+        StreamMessage {
+            topic: format!("book.{}", asset),
+            event: StreamEvent::BookSnapshot {
+                asset: asset.to_string(),
+                seq: self.seq,
+                bids: self.bids.values().rev().cloned().collect(),
+                asks: self.asks.values().cloned().collect(),
+            },
+        }
+    }
+}
+
+pub struct MarketStream {
+    sender: broadcast::Sender<StreamMessage>,
+    books: Mutex<BTreeMap<String, PublishedBook>>,
+}
+
+impl Default for MarketStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarketStream {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(STREAM_BUFFER);
+        Self { sender, books: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StreamMessage> {
+        self.sender.subscribe()
+    }
+
+    // Nobody listening is fine
+    fn send(&self, topic: String, event: StreamEvent) {
+        let _ = self.sender.send(StreamMessage { topic, event });
+    }
+
+    pub fn publish_trade(&self, trade: &Trade) {
+        self.send(format!("trades.{}", trade.asset), StreamEvent::Trade { trade: trade.clone() });
+    }
+
+    pub fn publish_settlement(&self, settlement: &Settlement) {
+        self.send("settlements".to_string(), StreamEvent::Settlement {
+            phase: settlement.phase().to_string(),
+            settlement: settlement.clone(),
+        });
+    }
+
+    pub fn publish_tenant_block(&self, block: &TenantBlockData) {
+        self.send(format!("blocks.{}", block.network_id), StreamEvent::TenantBlock {
+            network_id: block.network_id.clone(),
+            block_id: block.block_id,
+            block_hash: block.block_hash.clone(),
+            transactions: block.transactions.len(),
+            timestamp: block.timestamp,
+        });
+    }
+
+    // One delta per asset whose levels changed since the last call. The lock
+    // is held while sending so snapshots and deltas never interleave.
+    pub fn publish_books(&self, engine: &EnterpriseOrderEngine) {
+        let mut books = self.books.lock().unwrap();
+        let assets: BTreeSet<String> = engine.book.assets().map(str::to_string)
+            .chain(books.keys().cloned())
+            .collect();
+        for asset in assets {
+            let by_price = |side| engine.book.depth(&asset, side, usize::MAX).into_iter()
+                .map(|level| (level.price, level))
+                .collect::<BTreeMap<_, _>>();
+            let (bids, asks) = (by_price(OrderSide::Buy), by_price(OrderSide::Sell));
+            let book = books.entry(asset.clone()).or_default();
+            let mut changes = book.changes(OrderSide::Buy, &bids);
+            changes.extend(book.changes(OrderSide::Sell, &asks));
+            if changes.is_empty() {
+                continue;
+            }
+            book.seq += 1;
+            book.bids = bids;
+            book.asks = asks;
+            let seq = book.seq;
+            self.send(format!("book.{}", asset), StreamEvent::BookDelta { asset, seq, changes });
+        }
+    }
+
+    // Snapshots of every published book matching a subscription pattern
+    pub fn book_snapshots(&self, pattern: &str) -> Vec<StreamMessage> {
+        let books = self.books.lock().unwrap();
+        match pattern {
+            "book.*" | "*" => books.iter().map(|(asset, book)| book.snapshot(asset)).collect(),
+            _ => match pattern.strip_prefix("book.") {
+                Some(asset) => vec![books.get(asset).map_or_else(
+                    || PublishedBook::default().snapshot(asset),
+                    |book| book.snapshot(asset),
+                )],
+                None => Vec::new(),
+            },
+        }
+    }
+}
+
+// "trades.*" matches every "trades.<asset>", "*" matches everything
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => topic.starts_with(prefix),
+        None => pattern == topic,
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct StreamRequest {
+    op: String,
+    #[serde(default)]
+    topics: Vec<String>,
+    #[serde(default)]
+    topic: Option<String>,
+}
+
+// Replies to one client request, updating its subscriptions
+fn handle_request(text: &str, topics: &mut BTreeSet<String>, stream: &MarketStream) -> Vec<String> {
+    let to_json = |message: &StreamMessage| serde_json::to_string(message).unwrap_or_default();
+    let request: StreamRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return vec![serde_json::json!({ "type": "error", "message": format!("bad request: {}", e) }).to_string()],
+    };
+    match request.op.as_str() {
+        "subscribe" => {
+            let mut replies = vec![serde_json::json!({ "type": "subscribed", "topics": request.topics }).to_string()];
+            for topic in request.topics {
+                replies.extend(stream.book_snapshots(&topic).iter().map(to_json));
+                topics.insert(topic);
+            }
+            replies
+        }
+        "unsubscribe" => {
+            for topic in &request.topics {
+                topics.remove(topic);
+            }
+            vec![serde_json::json!({ "type": "unsubscribed", "topics": request.topics }).to_string()]
+        }
+        "snapshot" => {
+            let topic = request.topic.unwrap_or_default();
+            let snapshots = stream.book_snapshots(&topic);
+            if snapshots.is_empty() {
+                return vec![serde_json::json!({ "type": "error", "message": format!("no snapshot for {:?}", topic) }).to_string()];
+            }
+            snapshots.iter().map(to_json).collect()
+        }
+        other => vec![serde_json::json!({ "type": "error", "message": format!("unknown op {:?}", other) }).to_string()],
+    }
+}
+
+// One WebSocket client until it disconnects
+pub async fn serve_client(socket: WebSocket, stream: std::sync::Arc<MarketStream>) {
+    let (mut outgoing, mut incoming) = socket.split();
+    let mut events = stream.subscribe();
+    let mut topics: BTreeSet<String> = BTreeSet::new();
+
+    loop {
+        let replies = tokio::select! {
+            request = incoming.next() => match request {
+                Some(Ok(message)) if message.is_close() => break,
+                Some(Ok(message)) => match message.to_str() {
+                    Ok(text) => handle_request(text, &mut topics, &stream),
+                    Err(_) => continue, // pings and binary frames
+                },
+                _ => break,
+            },
+            event = events.recv() => match event {
+                Ok(message) if topics.iter().any(|pattern| topic_matches(pattern, &message.topic)) => {
+                    vec![serde_json::to_string(&message).unwrap_or_default()]
+                }
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    let mut replies = vec![serde_json::json!({ "type": "resync", "missed": missed }).to_string()];
+                    for topic in &topics {
+                        replies.extend(stream.book_snapshots(topic).iter().map(|m| serde_json::to_string(m).unwrap_or_default()));
+                    }
+                    replies
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
+        for reply in replies {
+            if outgoing.send(Message::text(reply)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enterprise_bc::order_engine::test_blocks::{self, funds};
+
+    fn order_block(order_id: &str, side: OrderSide, price: u64, quantity: u64) -> TenantBlockData {
+        let trader = match side { OrderSide::Sell => "alice", OrderSide::Buy => "bob" };
+        test_blocks::order_block("net-a", 1, trader, order_id, side, price, quantity)
+    }
+
+    #[test]
+    fn test_book_deltas_are_sequenced_and_match_snapshots() {
+        let stream = MarketStream::new();
+        let mut events = stream.subscribe();
+        let mut engine = EnterpriseOrderEngine::new();
+
+        engine.process_block(&order_block("s1", OrderSide::Sell, 100, 5), &funds());
+        engine.process_block(&order_block("s2", OrderSide::Sell, 100, 3), &funds());
+        stream.publish_books(&engine);
+        stream.publish_books(&engine); // nothing changed, nothing sent
+        engine.process_block(&order_block("b1", OrderSide::Buy, 100, 8), &funds());
+        stream.publish_books(&engine);
+
+        let deltas: Vec<(u64, Vec<LevelChange>)> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|message| match message.event {
+                StreamEvent::BookDelta { seq, changes, .. } => (seq, changes),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(deltas, vec![
+            (1, vec![LevelChange { side: OrderSide::Sell, price: 100, quantity: 8, orders: 2 }]),
+            (2, vec![LevelChange { side: OrderSide::Sell, price: 100, quantity: 0, orders: 0 }]),
+        ]);
+
+        match &stream.book_snapshots("book.BTC")[0].event {
+            StreamEvent::BookSnapshot { seq, bids, asks, .. } => assert_eq!((*seq, bids.len(), asks.len()), (2, 0, 0)),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(topic_matches("book.*", "book.BTC"));
+        assert!(!topic_matches("trades.BTC", "trades.ETH"));
+    }
+}
@@ -0,0 +1,405 @@
+// src/enterprise_bc/surveillance.rs
+// Trade surveillance: alerts on manipulative patterns for compliance review
+//
+// The order engine reports what it does (orders resting, fills, trader
+// cancels, trades, matches stopped by self-trade prevention) and this module
+// keeps a short window of that history to look for:
+//   wash trading  a trader on both sides of a trade, or two traders trading
+//                 the same asset back and forth at about the same price
+//   spoofing      a large order cancelled soon after placing it, mostly
+//                 unfilled, while its trader traded on the other side
+//   layering      several short-lived orders at different prices on one
+//                 side, all cancelled, while the trader traded on the other
+// Every check runs on the engine's clock, so journal replay raises the same
+// alerts with the same ids. Alerts stay open until a reviewer dismisses or
+// escalates them.
+
+use crate::blockchain::matching::SelfTradePrevention;
+use crate::blockchain::OrderSide;
+use crate::enterprise_bc::order_engine::{CancelledOrder, OrderBookEntry, Trade};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+// History older than this is not looked at (seconds)
+pub const WINDOW: u64 = 300;
+// Orders cancelled within this long of resting count as short-lived
+pub const SHORT_LIVED: u64 = 60;
+// A cancelled order that filled more than 1/10 of its size was real interest
+const MAX_FILLED_PART: u64 = 10;
+// Spoofing: the cancelled order was at least this many times the trade
+const SPOOF_SIZE_RATIO: u64 = 5;
+// Layering: distinct price levels cancelled on one side
+const LAYERS: usize = 3;
+// Round trips within this many basis points of each other's price
+const WASH_PRICE_BAND_BPS: u64 = 100;
+
+// Alerts kept, oldest dropped first
+const MAX_ALERTS: usize = 10_000;
+// Resting orders followed; beyond this, those too old to be short-lived go
+const MAX_WATCHED: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    WashTrading,
+    Spoofing,
+    Layering,
+    SelfTradePrevented,
+}
+
+impl AlertKind {
+    pub fn parse(kind: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(kind.trim().to_ascii_lowercase())).ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewStatus {
+    Dismissed, // no manipulation
+    Escalated, // handed on for action
+}
+
+impl ReviewStatus {
+    pub fn parse(status: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(status.trim().to_ascii_lowercase())).ok()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Review {
+    pub status: ReviewStatus,
+    pub reviewer: String,
+    pub note: String,
+    pub at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub alert_id: String,
+    pub kind: AlertKind,
+    pub trader: String,
+    pub network_id: String,
+    pub asset: String,
+    pub detected_at: u64,
+    pub description: String,
+    pub order_ids: Vec<String>,
+    pub trade_ids: Vec<String>,
+    #[serde(default)]
+    pub review: Option<Review>,
+}
+
+impl Alert {
+    pub fn is_open(&self) -> bool {
+        self.review.is_none()
+    }
+}
+
+// A resting order as it was placed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WatchedOrder {
+    trader: String,
+    network_id: String,
+    asset: String,
+    side: OrderSide,
+    price: u64,
+    quantity: u64,
+    filled: u64,
+    rested_at: u64,
+}
+
+// A short-lived, mostly unfilled order its trader cancelled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QuickCancel {
+    order_id: String,
+    trader: String,
+    asset: String,
+    side: OrderSide,
+    price: u64,
+    at: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Surveillance {
+    alerts: VecDeque<Alert>,
+    next_alert: u64,
+    orders: BTreeMap<String, WatchedOrder>,
+    cancels: VecDeque<QuickCancel>,
+    trades: VecDeque<Trade>,
+}
+
+impl Surveillance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // An order (or its rest after fills) that went onto the book
+    pub fn on_resting_order(&mut self, order: &OrderBookEntry, placed: u64, now: u64) {
+        self.orders.insert(order.order_id.clone(), WatchedOrder {
+            trader: order.trader.clone(),
+            network_id: order.network_id.clone(),
+            asset: order.asset.clone(),
+            side: order.side,
+            price: order.price,
+            quantity: placed,
+            filled: placed - order.quantity,
+            rested_at: now,
+        });
+        if self.orders.len() > MAX_WATCHED {
+            self.orders.retain(|_, o| now.saturating_sub(o.rested_at) <= SHORT_LIVED);
+        }
+    }
+
+    // A resting order traded `quantity`; `left` is what it still shows
+    pub fn on_fill(&mut self, order_id: &str, quantity: u64, left: u64) {
+        if left == 0 {
+            self.orders.remove(order_id);
+        } else if let Some(order) = self.orders.get_mut(order_id) {
+            order.filled = order.filled.saturating_add(quantity);
+        }
+    }
+
+    // Any order leaving the book without trading further
+    pub fn on_removed(&mut self, order_id: &str) {
+        self.orders.remove(order_id);
+    }
+
+    // A trader cancelled a resting order
+    pub fn on_cancel(&mut self, cancelled: &CancelledOrder, now: u64) {
+        let order = match self.orders.remove(&cancelled.order_id) {
+            Some(order) => order,
+            None => return,
+        };
+        self.prune(now);
+        let quick = now.saturating_sub(order.rested_at) <= SHORT_LIVED;
+        let mostly_unfilled = order.filled.saturating_mul(MAX_FILLED_PART) <= order.quantity;
+        if !quick || !mostly_unfilled {
+            return;
+        }
+
+        // Trades of this trader on the other side since the order rested
+        let opposite: Vec<&Trade> = self.trades.iter()
+            .filter(|t| t.asset == order.asset && t.timestamp >= order.rested_at)
+            .filter(|t| match order.side {
+                OrderSide::Buy => t.seller == order.trader,
+                OrderSide::Sell => t.buyer == order.trader,
+            })
+            .collect();
+        let opposite_ids: Vec<String> = opposite.iter().map(|t| t.trade_id.clone()).collect();
+        let largest = opposite.iter().map(|t| t.quantity).max().unwrap_or(0);
+
+        if largest > 0 && order.quantity >= largest.saturating_mul(SPOOF_SIZE_RATIO) {
+            let description = format!(
+                "{:?} order of {} {} @ {} cancelled after {}s with {} filled, while trading {} on the other side",
+                order.side, order.quantity, order.asset, order.price,
+                now.saturating_sub(order.rested_at), order.filled, largest);
+            self.raise(AlertKind::Spoofing, &order.trader, &order.network_id, &order.asset, now, description,
+                       vec![cancelled.order_id.clone()], opposite_ids.clone());
+        }
+
+        self.cancels.push_back(QuickCancel {
+            order_id: cancelled.order_id.clone(),
+            trader: order.trader.clone(),
+            asset: order.asset.clone(),
+            side: order.side,
+            price: order.price,
+            at: now,
+        });
+        let layered: Vec<&QuickCancel> = self.cancels.iter()
+            .filter(|c| c.trader == order.trader && c.asset == order.asset && c.side == order.side)
+            .collect();
+        let mut prices: Vec<u64> = layered.iter().map(|c| c.price).collect();
+        prices.sort_unstable();
+        prices.dedup();
+        if prices.len() >= LAYERS && !opposite_ids.is_empty() {
+            let order_ids: Vec<String> = layered.iter().map(|c| c.order_id.clone()).collect();
+            let description = format!(
+                "{} {:?} orders at {} price levels cancelled within {}s, while trading on the other side",
+                order_ids.len(), order.side, prices.len(), WINDOW);
+            self.raise(AlertKind::Layering, &order.trader, &order.network_id, &order.asset, now, description,
+                       order_ids, opposite_ids);
+            // One alert per layered set
+            self.cancels.retain(|c| !(c.trader == order.trader && c.asset == order.asset && c.side == order.side));
+        }
+    }
+
+    pub fn on_trade(&mut self, trade: &Trade) {
+        let now = trade.timestamp;
+        self.prune(now);
+        if trade.buyer == trade.seller {
+            let description = format!("{} bought {} {} @ {} from themself ({} -> {})",
+                trade.buyer, trade.quantity, trade.asset, trade.price, trade.seller_network, trade.buyer_network);
+            self.raise(AlertKind::WashTrading, &trade.buyer, &trade.buyer_network, &trade.asset, now, description,
+                       Vec::new(), vec![trade.trade_id.clone()]);
+        } else if let Some(earlier) = self.trades.iter().rev().find(|t| {
+            t.asset == trade.asset && t.buyer == trade.seller && t.seller == trade.buyer && near(t.price, trade.price)
+        }) {
+            let description = format!("{} and {} traded {} {} back and forth at {} and {}",
+                trade.buyer, trade.seller, trade.quantity, trade.asset, earlier.price, trade.price);
+            let trade_ids = vec![earlier.trade_id.clone(), trade.trade_id.clone()];
+            self.raise(AlertKind::WashTrading, &trade.buyer, &trade.buyer_network, &trade.asset, now, description,
+                       Vec::new(), trade_ids);
+        }
+        self.trades.push_back(trade.clone());
+    }
+
+    // Self-trade prevention stopped `taker` from trading with `maker`
+    pub fn on_self_trade_prevented(&mut self, taker: &OrderBookEntry, maker: &OrderBookEntry, mode: SelfTradePrevention, now: u64) {
+        let description = format!("{:?} order from {} would have traded with its trader's order from {} ({:?})",
+            taker.side, taker.network_id, maker.network_id, mode);
+        self.raise(AlertKind::SelfTradePrevented, &taker.trader, &taker.network_id, &taker.asset, now, description,
+                   vec![taker.order_id.clone(), maker.order_id.clone()], Vec::new());
+    }
+
+    // Newest first
+    pub fn alerts(&self, kind: Option<AlertKind>, trader: Option<&str>, open_only: bool, limit: usize) -> Vec<&Alert> {
+        self.alerts.iter().rev()
+            .filter(|a| kind.is_none_or(|kind| a.kind == kind))
+            .filter(|a| trader.is_none_or(|trader| a.trader == trader))
+            .filter(|a| !open_only || a.is_open())
+            .take(limit)
+            .collect()
+    }
+
+    pub fn alert(&self, alert_id: &str) -> Option<&Alert> {
+        self.alerts.iter().find(|a| a.alert_id == alert_id)
+    }
+
+    pub fn open_alerts(&self) -> usize {
+        self.alerts.iter().filter(|a| a.is_open()).count()
+    }
+
+    // A reviewer's verdict; an alert is reviewed once
+    pub fn review(&mut self, alert_id: &str, status: ReviewStatus, reviewer: &str, note: &str, at: u64) -> Result<Alert, String> {
+        let alert = self.alerts.iter_mut().find(|a| a.alert_id == alert_id)
+            .ok_or_else(|| format!("no alert {}", alert_id))?;
+        if let Some(review) = &alert.review {
+            return Err(format!("alert {} was already {:?} by {}", alert_id, review.status, review.reviewer));
+        }
+        alert.review = Some(Review { status, reviewer: reviewer.to_string(), note: note.to_string(), at });
+        Ok(alert.clone())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn raise(&mut self, kind: AlertKind, trader: &str, network_id: &str, asset: &str, now: u64,
+             description: String, order_ids: Vec<String>, trade_ids: Vec<String>) {
+        self.next_alert += 1;
+        let alert = Alert {
+            alert_id: format!("alert_{}", self.next_alert),
+            kind,
+            trader: trader.to_string(),
+            network_id: network_id.to_string(),
+            asset: asset.to_string(),
+            detected_at: now,
+            description,
+            order_ids,
+            trade_ids,
+            review: None,
+        };
+        println!("SURVEILLANCE ALERT {} {:?}: {} - {}", alert.alert_id, kind, trader, alert.description);
+        self.alerts.push_back(alert);
+        if self.alerts.len() > MAX_ALERTS {
+            self.alerts.pop_front();
+        }
+    }
+
+    fn prune(&mut self, now: u64) {
+        let since = now.saturating_sub(WINDOW);
+        while self.trades.front().is_some_and(|t| t.timestamp < since) {
+            self.trades.pop_front();
+        }
+        while self.cancels.front().is_some_and(|c| c.at < since) {
+            self.cancels.pop_front();
+        }
+    }
+}
+
+fn near(a: u64, b: u64) -> bool {
+    a.abs_diff(b).saturating_mul(10_000) <= a.max(b).saturating_mul(WASH_PRICE_BAND_BPS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resting(id: &str, trader: &str, side: OrderSide, price: u64, quantity: u64) -> OrderBookEntry {
+        OrderBookEntry {
+            order_id: id.to_string(),
+            trader: trader.to_string(),
+            network_id: "net-a".to_string(),
+            asset: "BTC".to_string(),
+            quantity,
+            price,
+            side,
+            order_type: Default::default(),
+            time_in_force: Default::default(),
+            client_order_id: None,
+            timestamp: 0,
+        }
+    }
+
+    fn trade(id: &str, buyer: &str, seller: &str, price: u64, quantity: u64, at: u64) -> Trade {
+        Trade {
+            trade_id: id.to_string(),
+            asset: "BTC".to_string(),
+            quantity,
+            price,
+            buyer: buyer.to_string(),
+            seller: seller.to_string(),
+            buyer_network: "net-a".to_string(),
+            seller_network: "net-b".to_string(),
+            timestamp: at,
+            taker_side: None,
+        }
+    }
+
+    fn cancel(watch: &mut Surveillance, order: &OrderBookEntry, at: u64) {
+        watch.on_cancel(&CancelledOrder::new(order, "cancelled by trader"), at);
+    }
+
+    #[test]
+    fn test_wash_spoof_and_layering_alerts() {
+        let mut watch = Surveillance::new();
+        let kinds = |watch: &Surveillance| watch.alerts(None, None, false, 100).iter().map(|a| a.kind).collect::<Vec<_>>();
+
+        // Round trip between alice and bob; a later trade at a far price is fine
+        watch.on_trade(&trade("t1", "alice", "bob", 10_000, 5, 1_000));
+        watch.on_trade(&trade("t2", "bob", "alice", 10_050, 5, 1_010));
+        watch.on_trade(&trade("t3", "alice", "bob", 12_000, 5, 1_020));
+        assert_eq!(kinds(&watch), vec![AlertKind::WashTrading]);
+        assert_eq!(watch.alerts(None, None, true, 1)[0].trade_ids, vec!["t1", "t2"]);
+
+        // Spoofing: a big bid from carol, she sells 2, then pulls the bid
+        let spoof = resting("bid", "carol", OrderSide::Buy, 9_900, 50);
+        watch.on_resting_order(&spoof, 50, 2_000);
+        watch.on_trade(&trade("t4", "dave", "carol", 10_000, 2, 2_010));
+        cancel(&mut watch, &spoof, 2_020);
+        assert_eq!(watch.alerts(Some(AlertKind::Spoofing), Some("carol"), true, 10).len(), 1);
+
+        // The same bid kept for ten minutes is not short-lived
+        watch.on_resting_order(&spoof, 50, 3_000);
+        watch.on_trade(&trade("t5", "dave", "carol", 10_000, 2, 3_010));
+        cancel(&mut watch, &spoof, 3_600);
+        assert_eq!(watch.alerts(Some(AlertKind::Spoofing), None, false, 10).len(), 1);
+
+        // Layering: erin stacks three asks and cancels them after buying
+        let layers: Vec<OrderBookEntry> = (0..3).map(|i| resting(&format!("ask{}", i), "erin", OrderSide::Sell, 10_100 + i, 1)).collect();
+        for order in &layers {
+            watch.on_resting_order(order, 1, 4_000);
+        }
+        watch.on_trade(&trade("t6", "erin", "frank", 10_000, 1, 4_005));
+        for order in &layers {
+            cancel(&mut watch, order, 4_010);
+        }
+        let layering = watch.alerts(Some(AlertKind::Layering), None, true, 10);
+        assert_eq!(layering.len(), 1);
+        assert_eq!(layering[0].order_ids.len(), 3);
+
+        // Reviews close an alert once
+        let id = layering[0].alert_id.clone();
+        assert!(watch.review(&id, ReviewStatus::Escalated, "compliance", "refer", 5_000).is_ok());
+        assert!(watch.review(&id, ReviewStatus::Dismissed, "compliance", "", 5_001).is_err());
+        assert_eq!(watch.open_alerts(), 2);
+        assert_eq!(ReviewStatus::parse("Dismissed"), Some(ReviewStatus::Dismissed));
+    }
+}
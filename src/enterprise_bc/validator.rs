@@ -1,33 +1,97 @@
 // src/enterprise_bc/validator.rs
-use crate::blockchain::{Blockchain, TenantBlockchainUpdate};
+use crate::blockchain::audit::ChainReport;
+use crate::blockchain::fees::FeeSchedule;
+use crate::blockchain::matching::SelfTradePrevention;
+use crate::blockchain::signing::KeyPair;
+use crate::blockchain::snapshot::{ChainSnapshot, PruningPolicy, SnapshotDir};
+use crate::blockchain::staking::StakingConfig;
+use crate::blockchain::store;
+use crate::blockchain::{Block, Blockchain, TenantBlockchainUpdate};
+use crate::common::auth::{self, ApiKeys};
 use crate::enterprise_bc::api;
-use crate::enterprise_bc::order_engine::{EnterpriseOrderEngine, Trade};
+use crate::enterprise_bc::consensus::{ConsensusConfig, ConsensusEngine, ConsensusHandle, ConsensusMessage};
+use crate::enterprise_bc::order_engine::{EnterpriseOrderEngine, OrderSide, Trade};
+use crate::enterprise_bc::order_journal::OrderJournal;
+use crate::enterprise_bc::settlement::DEFAULT_SETTLEMENT_TIMEOUT;
+use crate::enterprise_bc::stream::MarketStream;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tokio::time::{interval, Duration};
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::{interval, sleep, Duration, Instant};
+
+// How long a restart waits for every peer before consensus runs with those
+// that answered; at genesis it waits for all of them
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(60);
+// Between attempts to fetch blocks we fell behind on
+const CATCH_UP_RETRY: Duration = Duration::from_secs(2);
 
 pub struct Validator {
     pub id: String,
     pub port: u16,
     pub blockchain: Arc<RwLock<Blockchain>>,
     pub order_engine: Arc<RwLock<EnterpriseOrderEngine>>,
+    pub consensus: Arc<RwLock<ConsensusEngine>>,
     pub stake: u64,
     pub tracker_url: Option<String>,
+    pub peers: Vec<String>, // host:port of the other validators
+    pub chain_report: ChainReport, // integrity audit of the chain loaded at startup
+    pub snapshots: SnapshotDir,
+    pub stream: Arc<MarketStream>, // pushed to WebSocket clients of /api/stream
+    pub api_keys: Arc<ApiKeys>,
 }
 
 impl Validator {
-    pub async fn new(id: String, port: u16, initial_stake: u64) -> Self {
+    // A stored chain that cannot be loaded, or with strict_verify one that
+    // fails verification, is an error returned before anything is written
+    pub async fn new(id: String, port: u16, initial_stake: u64, strict_verify: bool) -> Result<Self, String> {
+        let stake = u32::try_from(initial_stake)
+            .map_err(|_| format!("a stake of {} does not fit, the most is {}", initial_stake, u32::MAX))?;
+        let api_keys = Arc::new(ApiKeys::from_env()?);
+        let (mut blockchain, migrate_to) = Self::open_blockchain(&id);
+        // A fresh chain in place of one we could not read would pass
+        // verification and then be saved over it
+        if let Some(e) = blockchain.storage_error() {
+            return Err(format!("cannot load the stored chain: {}", e));
+        }
+        // BFT commits are final, see forks.rs
+        blockchain.set_reorg_depth(0);
+        blockchain.set_timed_rounds(false);
+        blockchain.set_pruning_policy(PruningPolicy::from_env());
+        let fees = FeeSchedule::from_env();
+        if !fees.is_free() {
+            println!("Fee schedule: {}", serde_json::to_string(&fees).unwrap_or_default());
+        }
+        blockchain.set_fee_schedule(fees);
+        let staking = StakingConfig::from_env();
+        println!("Staking: epochs of {} blocks, unbonding takes {} epochs", staking.epoch_length, staking.unbonding_epochs);
+        blockchain.enable_staking(staking);
+        let chain_report = blockchain.verify_chain();
+        if chain_report.valid {
+            println!("Stored chain verified: {}", chain_report.summary());
+        } else {
+            println!("WARNING: stored chain failed verification: {}", chain_report.summary());
+        }
+        if strict_verify && !chain_report.valid {
+            return Err(chain_report.summary());
+        }
+
         // Ensure data directory exists
         let _ = std::fs::create_dir_all("data");
+        if let Some(log_dir) = migrate_to {
+            Self::migrate_to_block_log(&mut blockchain, &id, &log_dir);
+        }
+        let key = Self::load_or_create_key(&format!("data/validator_{}.key", id))?;
+        println!("Validator public key: {}", key.public_key_hex());
+        blockchain.set_node_key(key.clone());
+        if !blockchain.add_validator_with_key(id.clone(), stake, key.public_key_hex()) {
+            println!("WARNING: {} is registered with a different key - our blocks will be rejected", id);
+        } else if !blockchain.has_validator(&id) {
+            println!("{} has no stake bonded on chain - it validates from the epoch after it stakes", id);
+        }
         
-        let storage_path = format!("data/enterprise_blockchain_{}.json", id);
-        println!("Enterprise blockchain will be saved to: {}", storage_path);
-        
-        let mut blockchain = Blockchain::new_with_storage(storage_path);
-        blockchain.add_validator(id.clone(), initial_stake as u32);
-        
-        // Force initial save to create the file
-        blockchain.save_to_disk();
+        // Force initial save to create the store
+        if let Err(e) = blockchain.save_to_disk() {
+            println!("STORAGE ERROR: {}", e);
+        }
         
         let tracker_url = std::env::var("TRACKER_URL").ok();
         if let Some(ref url) = tracker_url {
@@ -35,16 +99,209 @@ impl Validator {
         } else {
             println!("No TRACKER_URL provided - cross-network trades won't be broadcast");
         }
+
+        let snapshots = SnapshotDir::new(Self::snapshot_dir(&id));
+        let order_engine = Self::open_order_engine(&id, &blockchain, &snapshots);
+
+        let peers = Self::parse_peers(&id, port, &std::env::var("VALIDATOR_PEERS").unwrap_or_default());
+        if peers.is_empty() {
+            println!("No VALIDATOR_PEERS configured - running as a single-validator network");
+        } else {
+            println!("Consensus peers: {}", peers.join(", "));
+        }
         
-        Validator {
+        Ok(Validator {
+            consensus: Arc::new(RwLock::new(ConsensusEngine::new(id.clone(), ConsensusConfig::default()))),
             id,
             port,
             blockchain: Arc::new(RwLock::new(blockchain)),
-            order_engine: Arc::new(RwLock::new(EnterpriseOrderEngine::new())),
+            order_engine: Arc::new(RwLock::new(order_engine)),
             stake: initial_stake,
             tracker_url,
+            peers,
+            chain_report,
+            snapshots,
+            stream: Arc::new(MarketStream::new()),
+            api_keys,
+        })
+    }
+
+    fn chain_path(id: &str) -> String {
+        if std::env::var("CHAIN_STORE").as_deref() == Ok("json") {
+            format!("data/enterprise_blockchain_{}.json", id)
+        } else {
+            format!("data/enterprise_chain_{}", id)
         }
     }
+
+    fn snapshot_dir(id: &str) -> String {
+        format!("data/snapshots_{}", id)
+    }
+
+    // Journaled engine state wins; a fresh or bootstrapped node starts from
+    // the latest snapshot instead
+    fn open_order_engine(id: &str, blockchain: &Blockchain, snapshots: &SnapshotDir) -> EnterpriseOrderEngine {
+        let mut journal = OrderJournal::new(format!("data/order_engine_{}", id));
+        let mut engine = None;
+        if journal.exists() {
+            match journal.recover() {
+                Ok(recovered) => {
+                    println!("Recovered order engine: {} buy / {} sell orders, {} trades awaiting broadcast",
+                             recovered.book.side_len(OrderSide::Buy), recovered.book.side_len(OrderSide::Sell),
+                             recovered.pending_broadcasts().len());
+                    engine = Some(recovered);
+                }
+                Err(e) => {
+                    println!("STORAGE ERROR: cannot recover order engine: {}", e);
+                    match journal.set_aside() {
+                        Ok(path) => println!("Moved unreadable order journal to {}", path.display()),
+                        Err(e) => println!("Failed to move unreadable order journal: {}", e),
+                    }
+                }
+            }
+        }
+
+        let mut engine = engine.unwrap_or_else(|| Self::restore_order_engine(blockchain, snapshots));
+        // Set before the journal checkpoint, so replay uses the mode in force
+        let stp = std::env::var("STP_MODE").unwrap_or_else(|_| "cancel_newest".to_string());
+        match SelfTradePrevention::parse(&stp) {
+            Some(mode) => engine.book.set_self_trade_prevention(mode),
+            None => println!("WARNING: unknown STP_MODE {:?}, keeping {:?}", stp, engine.book.self_trade_prevention()),
+        }
+        println!("Self-trade prevention: {:?}", engine.book.self_trade_prevention());
+        engine.attach_journal(journal);
+        engine
+    }
+
+    // Order books from the newest snapshot that is part of our chain
+    fn restore_order_engine(blockchain: &Blockchain, snapshots: &SnapshotDir) -> EnterpriseOrderEngine {
+        let snapshot = match snapshots.latest() {
+            Some(snapshot) => snapshot,
+            None => return EnterpriseOrderEngine::new(),
+        };
+        let on_chain = blockchain.block_at(snapshot.height).is_some_and(|b| b.hash == snapshot.block_hash);
+        match EnterpriseOrderEngine::from_snapshot_state(&snapshot.app_state) {
+            Some(engine) if on_chain => {
+                println!("Restored order books from snapshot at height {}", snapshot.height);
+                engine
+            }
+            _ => {
+                println!("WARNING: snapshot at height {} does not match the stored chain - order books start empty",
+                         snapshot.height);
+                EnterpriseOrderEngine::new()
+            }
+        }
+    }
+
+    // Start a new validator from a peer's latest snapshot plus the blocks after
+    // it, instead of replaying the peer's whole history
+    pub async fn bootstrap_from_peer(id: &str, peer: &str) -> Result<u32, String> {
+        // Restarts with the same flags keep the chain we already have
+        let path = Self::chain_path(id);
+        if let Ok(existing) = Blockchain::load_from_file(&path) {
+            println!("{} already holds a chain at height {} - not bootstrapping", path, existing.height());
+            return Ok(existing.height());
+        }
+
+        let client = reqwest::Client::new();
+        let snapshot: ChainSnapshot = auth::with_api_key(client.get(format!("http://{}/api/snapshot/latest", peer)))
+            .send().await.map_err(|e| format!("cannot reach {}: {}", peer, e))?
+            .json().await.map_err(|e| format!("{} sent no usable snapshot: {}", peer, e))?;
+        let blocks: Vec<Block> = auth::with_api_key(client.get(format!("http://{}/api/chain/blocks?after={}", peer, snapshot.height)))
+            .send().await.map_err(|e| format!("cannot reach {}: {}", peer, e))?
+            .json().await.map_err(|e| format!("{} sent no usable blocks: {}", peer, e))?;
+
+        let snapshot_height = snapshot.height;
+        let mut blockchain = Blockchain::from_snapshot(snapshot.clone())?;
+        for block in blocks {
+            let height = block.height;
+            if !blockchain.add_block(block) {
+                return Err(format!("block {} from {} does not follow the snapshot", height, peer));
+            }
+        }
+
+        blockchain.set_store(store::open_store(&path));
+        blockchain.save_to_disk()?;
+        SnapshotDir::new(Self::snapshot_dir(id)).save(&snapshot, PruningPolicy::from_env().keep_snapshots)?;
+        println!("Bootstrapped {} from {}: snapshot at height {}, now at height {}",
+                 id, peer, snapshot_height, blockchain.height());
+        Ok(blockchain.height())
+    }
+
+    // CHAIN_STORE=json keeps the legacy single JSON file; the default is an
+    // append-only block log. Loads only: a chain still in the old JSON file
+    // comes with the block log to migrate it into, see migrate_to_block_log
+    fn open_blockchain(id: &str) -> (Blockchain, Option<String>) {
+        let json_path = format!("data/enterprise_blockchain_{}.json", id);
+        if std::env::var("CHAIN_STORE").as_deref() == Ok("json") {
+            println!("Enterprise blockchain will be saved to: {}", json_path);
+            return (Blockchain::new_with_storage(json_path), None);
+        }
+
+        let log_dir = format!("data/enterprise_chain_{}", id);
+        println!("Enterprise blockchain will be saved to block log: {}", log_dir);
+        if std::path::Path::new(&log_dir).exists() || !std::path::Path::new(&json_path).exists() {
+            return (Blockchain::new_with_storage(log_dir), None);
+        }
+        (Blockchain::new_with_storage(json_path), Some(log_dir))
+    }
+
+    fn migrate_to_block_log(blockchain: &mut Blockchain, id: &str, log_dir: &str) {
+        let json_path = format!("data/enterprise_blockchain_{}.json", id);
+        println!("Migrating {} into block log {}", json_path, log_dir);
+        blockchain.set_store(store::open_store(log_dir));
+        if let Err(e) = blockchain.save_to_disk() {
+            // Stay on the JSON file rather than leave a partial log behind
+            println!("STORAGE ERROR: migration failed, staying on {}: {}", json_path, e);
+            let _ = std::fs::remove_dir_all(log_dir);
+            blockchain.set_store(store::open_store(&json_path));
+        }
+    }
+
+    // The signing key lives next to the chain file so restarts keep the same
+    // identity. A key file we cannot read is an error rather than a reason to
+    // sign as someone else; a new one is only readable by us.
+    fn load_or_create_key(key_path: &str) -> Result<KeyPair, String> {
+        use std::io::Write;
+
+        match std::fs::read_to_string(key_path) {
+            Ok(secret) => {
+                return KeyPair::from_secret_hex(&secret)
+                    .map_err(|e| format!("unreadable key file {}: {}", key_path, e));
+            }
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("cannot read key file {}: {}", key_path, e));
+            }
+            Err(_) => {}
+        }
+
+        let key = KeyPair::generate();
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(key_path)
+            .and_then(|mut file| file.write_all(key.secret_hex().as_bytes()).and_then(|_| file.sync_all()))
+            .map_err(|e| format!("cannot save validator key to {}: {}", key_path, e))?;
+        Ok(key)
+    }
+
+    // VALIDATOR_PEERS is a comma separated host:port list that may include ourselves
+    fn parse_peers(id: &str, port: u16, peers: &str) -> Vec<String> {
+        peers.split(',')
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .filter(|p| {
+                let host = p.split(':').next().unwrap_or("");
+                let is_local = matches!(host, "localhost" | "127.0.0.1") && p.ends_with(&format!(":{}", port));
+                host != id && !is_local
+            })
+            .map(|p| p.to_string())
+            .collect()
+    }
     
     pub async fn start(self) {
         println!("Starting validator {} with order matching engine", self.id);
@@ -53,21 +310,64 @@ impl Validator {
         let order_engine = self.order_engine.clone();
         let validator_id = self.id.clone();
         
-        // Start PoS validation loop
+        let (inbox_tx, inbox_rx) = mpsc::unbounded_channel();
+        let consensus_handle = ConsensusHandle {
+            validator_id: validator_id.clone(),
+            stake: self.stake,
+            public_key: self.blockchain.read().await.get_public_key(),
+            engine: self.consensus.clone(),
+            inbox: inbox_tx,
+        };
+
+        // Start BFT consensus with the other validators
         let validation_blockchain = blockchain.clone();
-        let validation_validator_id = validator_id.clone();
+        let validation_engine = self.consensus.clone();
+        let validation_peers = self.peers.clone();
         let validation_handle = tokio::spawn(async move {
-            Self::pos_validation_loop(validation_blockchain, validation_validator_id).await;
+            Self::consensus_loop(validation_blockchain, validation_engine, inbox_rx, validation_peers).await;
+        });
+        
+        // Periodic snapshots and pruning
+        let snapshot_blockchain = blockchain.clone();
+        let snapshot_engine = order_engine.clone();
+        let snapshot_dir = self.snapshots.clone();
+        tokio::spawn(async move {
+            Self::snapshot_loop(snapshot_blockchain, snapshot_engine, snapshot_dir).await;
+        });
+        
+        // Expire GTT/GTD orders, drop orders of networks gone silent and
+        // abort settlements that timed out
+        let sweep_engine = order_engine.clone();
+        let sweep_blockchain = blockchain.clone();
+        let sweep_tracker_url = self.tracker_url.clone();
+        let sweep_stream = self.stream.clone();
+        tokio::spawn(async move {
+            Self::sweep_loop(sweep_engine, sweep_blockchain, sweep_tracker_url, sweep_stream).await;
         });
         
         // Start API server
         let api_blockchain = blockchain.clone();
         let api_order_engine = order_engine.clone();
         let api_tracker_url = self.tracker_url.clone();
+        let api_snapshots = self.snapshots.clone();
+        let api_stream = self.stream.clone();
+        let api_keys = self.api_keys.clone();
         let api_handle = tokio::spawn(async move {
-            api::start_api_server(self.port, api_blockchain, api_order_engine, api_tracker_url).await;
+            api::start_api_server(self.port, api_blockchain, api_order_engine, api_tracker_url, consensus_handle, api_snapshots, api_stream, api_keys).await;
         });
         
+        // Trades matched before a restart that the tracker never confirmed
+        if let Some(tracker_url) = self.tracker_url.clone() {
+            let redeliver_engine = order_engine.clone();
+            tokio::spawn(async move {
+                let pending = redeliver_engine.read().await.pending_broadcasts().to_vec();
+                if !pending.is_empty() {
+                    println!("Redelivering {} trades to the tracker", pending.len());
+                    Self::deliver_trades(&redeliver_engine, &tracker_url, &pending).await;
+                }
+            });
+        }
+        
         println!("Enterprise validator ready for cross-network order matching");
         
         tokio::select! {
@@ -76,22 +376,266 @@ impl Validator {
         }
     }
     
-    async fn pos_validation_loop(blockchain: Arc<RwLock<Blockchain>>, validator_id: String) {
-        let mut timer = interval(Duration::from_secs(10));
-        
+    async fn consensus_loop(
+        blockchain: Arc<RwLock<Blockchain>>,
+        engine: Arc<RwLock<ConsensusEngine>>,
+        mut inbox: mpsc::UnboundedReceiver<ConsensusMessage>,
+        peers: Vec<String>,
+    ) {
+        // The genesis set is bonded locally by every node, so nothing may be
+        // committed before every configured peer is in it
+        let at_genesis = blockchain.read().await.height() == 0;
+        let deadline = (!at_genesis).then(|| Instant::now() + DISCOVERY_TIMEOUT);
+        let missing = Self::discover_peers(&blockchain, &peers, deadline).await;
+        if !missing.is_empty() {
+            println!("Starting consensus without {} - still looking for them", missing.join(", "));
+            let blockchain = blockchain.clone();
+            tokio::spawn(async move {
+                Self::discover_peers(&blockchain, &missing, None).await;
+            });
+        }
+
+        let client = reqwest::Client::new();
+        let mut timer = interval(Duration::from_millis(250));
+        let mut last_catch_up: Option<Instant> = None;
+
         loop {
-            timer.tick().await;
-            
-            {
-                let mut bc = blockchain.write().await;
-                let pending_count = bc.get_pending_count();
-                if pending_count > 0 {
-                    if bc.mine_block() {
-                        println!("Validator {} created block via PoS", validator_id);
+            let outbound = tokio::select! {
+                _ = timer.tick() => {
+                    let mut bc = blockchain.write().await;
+                    engine.write().await.tick(&mut bc, Self::now_millis())
+                }
+                Some(message) = inbox.recv() => {
+                    let mut bc = blockchain.write().await;
+                    engine.write().await.handle_message(&mut bc, message, Self::now_millis())
+                }
+            };
+
+            let behind = engine.read().await.catch_up_target().is_some();
+            if behind && last_catch_up.is_none_or(|at| at.elapsed() >= CATCH_UP_RETRY) {
+                last_catch_up = Some(Instant::now());
+                Self::catch_up(&blockchain, &engine, &client, &peers).await;
+            }
+
+            for message in outbound {
+                for peer in &peers {
+                    let client = client.clone();
+                    let url = format!("http://{}/api/consensus/message", peer);
+                    let message = message.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = auth::with_api_key(client.post(&url)).json(&message).send().await {
+                            println!("Failed to send consensus message to {}: {}", url, e);
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    // The blocks a quorum committed while we were away or missed the
+    // proposal, from the first peer that has them
+    async fn catch_up(
+        blockchain: &Arc<RwLock<Blockchain>>,
+        engine: &Arc<RwLock<ConsensusEngine>>,
+        client: &reqwest::Client,
+        peers: &[String],
+    ) {
+        for peer in peers {
+            let from = blockchain.read().await.height();
+            let url = format!("http://{}/api/chain/blocks?after={}", peer, from);
+            let fetched = auth::with_api_key(client.get(&url).timeout(Duration::from_secs(10))).send().await;
+            let blocks = match fetched {
+                Ok(response) => response.json::<Vec<Block>>().await.map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            let blocks = match blocks {
+                Ok(blocks) => blocks,
+                Err(e) => {
+                    println!("Could not fetch blocks from {}: {}", peer, e);
+                    continue;
+                }
+            };
+            let mut bc = blockchain.write().await;
+            match engine.write().await.apply_fetched(&mut bc, blocks, Self::now_millis()) {
+                Ok(_) => return,
+                Err(e) => println!("Blocks from {} not taken: {}", peer, e),
+            }
+        }
+    }
+
+    // A validator without a chain whose peers are already past genesis would
+    // bond a genesis set of its own; it starts from a peer's snapshot instead,
+    // which carries the set the chain agreed on. The height it bootstrapped
+    // to, if it did.
+    pub async fn bootstrap_if_late(id: &str, port: u16) -> Result<Option<u32>, String> {
+        if Blockchain::load_from_file(&Self::chain_path(id)).is_ok() {
+            return Ok(None);
+        }
+        let client = reqwest::Client::new();
+        for peer in Self::parse_peers(id, port, &std::env::var("VALIDATOR_PEERS").unwrap_or_default()) {
+            let url = format!("http://{}/api/consensus/info", peer);
+            let height = match auth::with_api_key(client.get(&url).timeout(Duration::from_secs(5))).send().await {
+                Ok(response) => response.json::<serde_json::Value>().await.ok().and_then(|info| info["height"].as_u64()),
+                Err(_) => None,
+            };
+            if let Some(height) = height.filter(|height| *height > 0) {
+                println!("{} is already at height {} - joining its chain", peer, height);
+                return Self::bootstrap_from_peer(id, &peer).await.map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    // Learn every peer's validator id and stake before taking part in consensus,
+    // so all nodes start from the same validator set. Gives up at `deadline`
+    // and returns the peers that never answered. While we are at genesis a
+    // peer past it is not bonded: it needs --bootstrap-from, see
+    // bootstrap_if_late.
+    async fn discover_peers(blockchain: &Arc<RwLock<Blockchain>>, peers: &[String], deadline: Option<Instant>) -> Vec<String> {
+        let client = reqwest::Client::new();
+        let mut remaining: Vec<String> = peers.to_vec();
+
+        while !remaining.is_empty() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return remaining;
+            }
+            let mut still_missing = Vec::new();
+            for peer in remaining {
+                let url = format!("http://{}/api/consensus/info", peer);
+                let info = match auth::with_api_key(client.get(&url).timeout(Duration::from_secs(5))).send().await {
+                    Ok(response) => response.json::<serde_json::Value>().await.ok(),
+                    Err(_) => None,
+                };
+
+                let discovered = info.as_ref().and_then(|i| {
+                    Some((i["validator_id"].as_str()?, i["stake"].as_u64()?, i["public_key"].as_str()?))
+                });
+                let peer_height = info.as_ref().and_then(|i| i["height"].as_u64()).unwrap_or(0);
+                if discovered.is_some() && peer_height > 0 && blockchain.read().await.height() == 0 {
+                    println!("ERROR: {} is at height {} while we are at genesis - restart with --bootstrap-from {}",
+                             peer, peer_height, peer);
+                    still_missing.push(peer);
+                    continue;
+                }
+                match discovered {
+                    Some((validator_id, stake, public_key)) => {
+                        let stake_u32 = match u32::try_from(stake) {
+                            Ok(stake) => stake,
+                            Err(_) => {
+                                println!("WARNING: validator {} at {} reports a stake of {} that does not fit, ignoring it",
+                                         validator_id, peer, stake);
+                                continue;
+                            }
+                        };
+                        let mut bc = blockchain.write().await;
+                        if !bc.add_validator_with_key(validator_id.to_string(), stake_u32, public_key.to_string()) {
+                            println!("WARNING: validator {} at {} presented a different key than the one on record",
+                                     validator_id, peer);
+                        } else if bc.has_validator(validator_id) {
+                            println!("Discovered validator {} at {} (stake {})", validator_id, peer, stake);
+                        } else {
+                            println!("Discovered {} at {}, not a validator until it stakes", validator_id, peer);
+                        }
+                        if let Err(e) = bc.save_to_disk() {
+                            println!("STORAGE ERROR: {}", e);
+                        }
                     }
+                    None => still_missing.push(peer),
                 }
             }
+
+            remaining = still_missing;
+            if !remaining.is_empty() {
+                println!("Waiting for validators: {}", remaining.join(", "));
+                sleep(Duration::from_secs(2)).await;
+            }
         }
+        remaining
+    }
+
+    // Snapshot every SNAPSHOT_INTERVAL blocks, then drop blocks from memory
+    // that both the snapshot and KEEP_BLOCKS allow
+    async fn snapshot_loop(
+        blockchain: Arc<RwLock<Blockchain>>,
+        order_engine: Arc<RwLock<EnterpriseOrderEngine>>,
+        snapshots: SnapshotDir,
+    ) {
+        let mut last_snapshot = snapshots.heights().last().copied()
+            .unwrap_or(blockchain.read().await.get_blocks()[0].height);
+        let mut timer = interval(Duration::from_secs(5));
+
+        loop {
+            timer.tick().await;
+            let policy = blockchain.read().await.pruning_policy().clone();
+            let height = blockchain.read().await.height();
+            if policy.snapshot_interval == 0 || height / policy.snapshot_interval <= last_snapshot / policy.snapshot_interval {
+                continue;
+            }
+
+            let app_state = order_engine.read().await.snapshot_state();
+            let snapshot = blockchain.read().await.create_snapshot(app_state);
+            match snapshots.save(&snapshot, policy.keep_snapshots) {
+                Ok(path) => println!("Snapshot at height {} written to {}", snapshot.height, path.display()),
+                Err(e) => {
+                    println!("STORAGE ERROR: {}", e);
+                    continue;
+                }
+            }
+            last_snapshot = snapshot.height;
+
+            if let Some(keep) = policy.keep_blocks {
+                let prune_below = snapshot.height.min((snapshot.height + 1).saturating_sub(keep));
+                match blockchain.write().await.prune_blocks_below(prune_below) {
+                    Ok(0) => {}
+                    Ok(count) => println!("Pruned {} blocks below height {} from memory", count, prune_below),
+                    Err(e) => println!("Not pruning: {}", e),
+                }
+            }
+        }
+    }
+
+    // Every ORDER_SWEEP_SECS (default 5), cancel expired orders and those of
+    // networks without a block for STALE_NETWORK_SECS (default 3600, 0 = never),
+    // and abort settlements not prepared within SETTLEMENT_TIMEOUT_SECS (default 30)
+    async fn sweep_loop(
+        order_engine: Arc<RwLock<EnterpriseOrderEngine>>,
+        blockchain: Arc<RwLock<Blockchain>>,
+        tracker_url: Option<String>,
+        market_stream: Arc<MarketStream>,
+    ) {
+        let var = |name: &str, default: u64| std::env::var(name).ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(default);
+        let every = var("ORDER_SWEEP_SECS", 5).max(1);
+        let max_silence = var("STALE_NETWORK_SECS", 3600);
+        let settlement_timeout = var("SETTLEMENT_TIMEOUT_SECS", DEFAULT_SETTLEMENT_TIMEOUT);
+        let mut timer = interval(Duration::from_secs(every));
+
+        loop {
+            timer.tick().await;
+            let now = Self::now_millis() / 1000;
+            let swept = order_engine.write().await.sweep(now, max_silence, settlement_timeout);
+            api::publish_settlements(&order_engine, &blockchain, tracker_url.as_deref(), &market_stream).await;
+            if swept.is_empty() {
+                continue;
+            }
+            let (orders, cancelled) = {
+                let mut engine = order_engine.write().await;
+                (engine.get_all_orders(), engine.take_cancellations())
+            };
+
+            println!("Sweep cancelled {} orders", cancelled.len());
+            if let Some(ref tracker_url) = tracker_url {
+                api::broadcast_order_book(orders, &cancelled, tracker_url).await;
+            }
+        }
+    }
+
+    fn now_millis() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
     }
     
     pub async fn process_tenant_update(&self, update: TenantBlockchainUpdate) -> Vec<Trade> {
@@ -104,59 +648,46 @@ impl Validator {
             let mut bc = self.blockchain.write().await;
             bc.add_tenant_blocks(&update);
         }
+        for block in &update.new_blocks {
+            self.stream.publish_tenant_block(block);
+        }
         
         // Process orders and match them
-        {
+        let acks = {
+            let bc = self.blockchain.read().await;
             let mut engine = self.order_engine.write().await;
             for block in &update.new_blocks {
-                let trades = engine.process_block(block);
+                let trades = engine.process_block(block, &bc.tenant_ledger(&block.network_id, block.block_id));
                 all_trades.extend(trades);
             }
+            engine.take_acks()
+        };
+        if let Some(ref tracker_url) = self.tracker_url {
+            for ack in &acks {
+                api::send_order_ack_to_tracker(ack, tracker_url).await;
+            }
         }
         
-        // Broadcast matched trades back to networks
+        // Matched trades settle once both networks have prepared them
         if !all_trades.is_empty() {
             println!("Generated {} cross-network trades", all_trades.len());
-            self.broadcast_trades_to_networks(&all_trades).await;
         }
+        api::publish_settlements(&self.order_engine, &self.blockchain, self.tracker_url.as_deref(), &self.stream).await;
         
         all_trades
     }
     
-    async fn broadcast_trades_to_networks(&self, trades: &[Trade]) {
-        if let Some(ref tracker_url) = self.tracker_url {
-            for trade in trades {
-                let trade_notification = serde_json::json!({
-                    "type": "cross_network_trade",
-                    "trade_id": trade.trade_id,
-                    "buyer_network": trade.buyer_network,
-                    "seller_network": trade.seller_network,
-                    "asset": trade.asset,
-                    "quantity": trade.quantity,
-                    "price": trade.price,
-                    "buyer": trade.buyer,
-                    "seller": trade.seller,
-                    "timestamp": trade.timestamp
-                });
-                
-                let client = reqwest::Client::new();
-                let url = format!("{}/api/cross-network-trade", tracker_url);
-                
-                match client.post(&url).json(&trade_notification).send().await {
-                    Ok(response) => {
-                        if response.status().is_success() {
-                            println!("Broadcast trade {} to networks {} and {}", 
-                                  trade.trade_id, trade.buyer_network, trade.seller_network);
-                        } else {
-                            println!("Failed to broadcast trade: HTTP {}", response.status());
-                        }
-                    }
-                    Err(e) => {
-                        println!("Failed to broadcast trade: {}", e);
-                    }
-                }
+    // Send trades to the tracker and journal the ones it accepted
+    async fn deliver_trades(order_engine: &Arc<RwLock<EnterpriseOrderEngine>>, tracker_url: &str, trades: &[Trade]) {
+        let mut delivered = Vec::new();
+        for trade in trades {
+            if api::send_trade_to_tracker(trade, tracker_url).await {
+                println!("Broadcast trade {} to networks {} and {}", 
+                      trade.trade_id, trade.buyer_network, trade.seller_network);
+                delivered.push(trade.trade_id.clone());
             }
         }
+        order_engine.write().await.mark_broadcast(&delivered);
     }
     
     pub async fn get_order_book_status(&self) -> serde_json::Value {
@@ -171,3 +702,56 @@ impl Validator {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_file_is_private_and_never_replaced() {
+        let dir = std::env::temp_dir().join(format!("distli-key-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("validator.key").to_string_lossy().to_string();
+
+        let key = Validator::load_or_create_key(&path).unwrap();
+        assert_eq!(Validator::load_or_create_key(&path).unwrap().public_key_hex(), key.public_key_hex());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        std::fs::write(&path, "not a key").unwrap();
+        assert!(Validator::load_or_create_key(&path).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a key");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_peer_past_genesis_is_not_bonded_into_ours() {
+        use warp::Filter;
+        let serve = |height: u32, validator_id: &'static str| {
+            let info = warp::path!("api" / "consensus" / "info").map(move || warp::reply::json(&serde_json::json!({
+                "validator_id": validator_id,
+                "stake": 100,
+                "public_key": KeyPair::from_seed(validator_id.as_bytes()).public_key_hex(),
+                "height": height
+            })));
+            let (addr, server) = warp::serve(info).bind_ephemeral(([127, 0, 0, 1], 0));
+            tokio::spawn(server);
+            addr.to_string()
+        };
+        let ahead = serve(5, "ahead");
+        let fresh = serve(0, "fresh");
+
+        let blockchain = Arc::new(RwLock::new(Blockchain::new()));
+        blockchain.write().await.enable_staking(StakingConfig::default());
+        let deadline = Some(Instant::now() + Duration::from_millis(100));
+        let missing = Validator::discover_peers(&blockchain, &[ahead.clone(), fresh], deadline).await;
+
+        assert_eq!(missing, vec![ahead]);
+        let bc = blockchain.read().await;
+        assert!(bc.has_validator("fresh"));
+        assert!(!bc.has_validator("ahead"));
+    }
+}
@@ -1,24 +1,53 @@
+use distli_mesh_bc::blockchain::Blockchain;
 use distli_mesh_bc::enterprise_bc::Validator;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(name = "enterprise-validator")]
 #[command(about = "Enterprise blockchain validator (Proof of Stake) cross-network order matching")]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short, long, default_value = "validator1")]
     id: String,
     
     #[arg(short, long, default_value = "8080")]
     port: u16,
     
+    /// Stake bonded at genesis; once the chain has blocks, stake comes from
+    /// staking transactions and this is ignored
     #[arg(short, long, default_value = "1000")]
     stake: u64,
+
+    /// Refuse to start when the stored chain fails verification
+    #[arg(long)]
+    strict_verify: bool,
+
+    /// host:port of a running validator; start from its latest snapshot
+    /// instead of genesis when we have no chain yet
+    #[arg(long)]
+    bootstrap_from: Option<String>,
 }
+
+#[derive(Subcommand)]
+enum Command {
+    /// Audit a stored chain file and print a tamper report
+    Verify {
+        #[arg(long)]
+        data: String,
+    },
+}
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
     
     let args = Args::parse();
+
+    if let Some(Command::Verify { data }) = args.command {
+        std::process::exit(verify(&data));
+    }
     
     println!("Starting enterprise validator {} with cross-network order matching", args.id);
     println!("Port: {}, Stake: {}", args.port, args.stake);
@@ -29,11 +58,44 @@ async fn main() {
         println!("No TRACKER_URL set - cross-network trades won't be broadcast");
     }
     
-    let validator = Validator::new(
+    if let Some(peer) = &args.bootstrap_from {
+        if let Err(e) = Validator::bootstrap_from_peer(&args.id, peer).await {
+            eprintln!("Bootstrap from {} failed: {}", peer, e);
+            std::process::exit(1);
+        }
+    } else if let Err(e) = Validator::bootstrap_if_late(&args.id, args.port).await {
+        eprintln!("Joining the running chain failed: {}", e);
+        std::process::exit(1);
+    }
+    
+    let validator = match Validator::new(
         args.id,
         args.port,
-        args.stake
-    ).await;
+        args.stake,
+        args.strict_verify
+    ).await {
+        Ok(validator) => validator,
+        Err(e) => {
+            eprintln!("Refusing to start: {}", e);
+            std::process::exit(1);
+        }
+    };
     
     validator.start().await;
 }
+
+// Exit code 0 when the chain is intact, 1 when broken, 2 when unreadable
+fn verify(path: &str) -> i32 {
+    let blockchain = match Blockchain::load_from_file(path) {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+
+    let report = blockchain.verify_chain();
+    println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+    println!("{}", report.summary());
+    if report.valid { 0 } else { 1 }
+}
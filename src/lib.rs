@@ -18,7 +18,7 @@ pub use blockchain::{Blockchain, OrderBook, Block, Transaction, TransactionType}
 
 // Enterprise types only for native
 #[cfg(feature = "native")]
-pub use blockchain::{TenantBlockchainUpdate, TenantBlockData};
+pub use blockchain::{TenantBlockchainUpdate, TenantBlockData, TenantReorg};
 
 #[cfg(feature = "native")]
 pub use tracker::Tracker;
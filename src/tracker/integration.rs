@@ -1,5 +1,6 @@
 // Fixed integration.rs - uses enterprise types throughout
 use crate::blockchain::{TenantBlockchainUpdate, TenantBlockData};
+use crate::common::auth;
 use std::collections::HashMap;
 use tokio::time::{interval, Duration};
 use tracing::{info, warn};
@@ -69,7 +70,7 @@ impl EnterpriseIntegration {
         info!("Updating blockchain state for {}: {} new blocks", 
               update.network_id, update.new_blocks.len());
         
-        if update.new_blocks.is_empty() {
+        if update.new_blocks.is_empty() && update.reorg.is_none() {
             warn!("Received empty blocks list for network {}", update.network_id);
             return;
         }
@@ -81,6 +82,16 @@ impl EnterpriseIntegration {
                 last_update: current_timestamp(),
             });
 
+        // The tenant abandoned these blocks; its new branch is in new_blocks
+        if let Some(reorg) = &update.reorg {
+            state.blocks.retain(|block| !reorg.removed.contains(&block.block_hash));
+            state.last_block_id = state.last_block_id.min(reorg.fork_height);
+            if let Some(reported) = self.last_reported_state.get_mut(&update.network_id) {
+                reported.last_reported_block_id = reported.last_reported_block_id.min(reorg.fork_height);
+            }
+            info!("Network {} reorganized at block {}: dropped {} blocks", update.network_id, reorg.fork_height, reorg.removed.len());
+        }
+
         let mut new_blocks = Vec::new();
         
         for block in &update.new_blocks {
@@ -103,7 +114,7 @@ impl EnterpriseIntegration {
         self.save_to_disk();
         
         // Send to enterprise BC
-        if !new_blocks.is_empty() {
+        if !new_blocks.is_empty() || update.reorg.is_some() {
             if let Err(e) = self.send_update_to_enterprise(update).await {
                 warn!("Failed to send update to enterprise BC: {}", e);
             }
@@ -116,7 +127,7 @@ impl EnterpriseIntegration {
     async fn send_update_to_enterprise(&mut self, update: &TenantBlockchainUpdate) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         info!("Sending update from {} to enterprise BC", update.network_id);
         
-        if update.new_blocks.is_empty() {
+        if update.new_blocks.is_empty() && update.reorg.is_none() {
             info!("No blocks to send for network {}", update.network_id);
             return Ok(());
         }
@@ -124,8 +135,7 @@ impl EnterpriseIntegration {
         let client = reqwest::Client::new();
         let url = format!("{}/api/tenant-blockchain-update", self.enterprise_url);
         
-        let response = client
-            .post(&url)
+        let response = auth::with_api_key(client.post(&url))
             .json(update)
             .send()
             .await?;
@@ -180,6 +190,7 @@ impl EnterpriseIntegration {
                     peer_id: "tracker".to_string(),
                     new_blocks: unreported_blocks,
                     timestamp: current_timestamp(),
+                    reorg: None,
                 };
                 
                 if let Err(e) = self.send_update_to_enterprise(&update).await {
@@ -1,3 +1,4 @@
+use distli_mesh_bc::common::auth::ApiKeys;
 use distli_mesh_bc::tracker::{Tracker, EnterpriseIntegration};
 use std::env;
 
@@ -5,6 +6,13 @@ use std::env;
 async fn main() {
     tracing_subscriber::fmt::init();
     println!("Starting distli-mesh-bc tracker with enterprise integration...");
+    let api_keys = match ApiKeys::from_env() {
+        Ok(keys) => keys,
+        Err(e) => {
+            eprintln!("Refusing to start: {}", e);
+            std::process::exit(1);
+        }
+    };
     
     let mut tracker = Tracker::new();
     
@@ -34,5 +42,5 @@ async fn main() {
     }
     
     // Start the tracker server
-    tracker.run().await;
+    tracker.run(api_keys).await;
 }
@@ -1,5 +1,9 @@
 // src/tracker/server.rs
-use crate::blockchain::{Blockchain, Block, Transaction, TenantBlockchainUpdate, TenantBlockData};
+use crate::blockchain::{Blockchain, Block, Transaction, TransactionType, TenantBlockchainUpdate, TenantBlockData, TenantReorg};
+use crate::blockchain::forks::ChainReorg;
+use crate::blockchain::sync::MAX_SYNC_BATCH;
+use crate::blockchain::tenants::{self, TenantRecord};
+use crate::common::auth::{self, ApiKeys, Scope};
 use crate::tracker::integration::EnterpriseIntegration;
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
@@ -28,6 +32,17 @@ pub struct NetworkPeer {
 pub enum Message {
     #[serde(rename = "join_network")]
     JoinNetwork { network_id: String },
+
+    // Joining a registered network: the peer signs the nonce with the
+    // tenant's key (see blockchain/tenants.rs) and is then let in, or refused
+    #[serde(rename = "challenge")]
+    Challenge { network_id: String, nonce: String },
+
+    #[serde(rename = "challenge_response")]
+    ChallengeResponse { network_id: String, signature: String },
+
+    #[serde(rename = "join_refused")]
+    JoinRefused { network_id: String, reason: String },
     
     #[serde(rename = "peers")]
     Peers { peers: Vec<String> },
@@ -42,7 +57,12 @@ pub enum Message {
     Candidate { target: String, candidate: serde_json::Value },
     
     #[serde(rename = "block")]
-    Block { block: Block },
+    Block {
+        block: Block,
+        // By the tenant's key, for the enterprise validator
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tenant_signature: Option<String>,
+    },
     
     #[serde(rename = "transaction")]
     Transaction { transaction: Transaction },
@@ -56,11 +76,42 @@ pub enum Message {
     #[serde(rename = "network_list_update")]
     NetworkListUpdate { networks: Vec<serde_json::Value> },
     
+    // Block sync between peers of a network, see blockchain/sync.rs
+    #[serde(rename = "get_headers")]
+    GetHeaders { target: String, from_height: u32, count: u32 },
+
+    #[serde(rename = "headers")]
+    Headers { target: String, tip_height: u32, headers: Vec<Block> },
+
+    #[serde(rename = "get_blocks")]
+    GetBlocks { target: String, from_height: u32, count: u32 },
+
+    #[serde(rename = "blocks")]
+    Blocks { target: String, blocks: Vec<Block> },
+
+    // A peer switched to another branch of its network's chain
+    #[serde(rename = "chain_reorg")]
+    ChainReorg {
+        reorg: ChainReorg,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tenant_signature: Option<String>,
+    },
+
     #[serde(rename = "blockchain_sync")]
     BlockchainSync { network_id: String, blocks: Vec<Block> },
 
     #[serde(rename = "enterprise_sync")]
     EnterpriseSync { network_id: String, sync_data: serde_json::Value },
+
+    // A network's answer to a settlement prepare; the tracker fills in the
+    // network from the sender's connection
+    #[serde(rename = "settlement_vote")]
+    SettlementVote {
+        trade_id: String,
+        prepared: bool,
+        #[serde(default)]
+        reason: Option<String>,
+    },
 }
 
 pub struct Tracker {
@@ -96,12 +147,14 @@ impl Tracker {
         self.enterprise_integration = Some(Arc::new(RwLock::new(integration)));
     }
     
-    pub async fn run(&self) {
+    pub async fn run(&self, api_keys: ApiKeys) {
         let networks = self.networks.clone();
         let global_peers = self.global_peers.clone();
         let enterprise_blockchain = self.enterprise_blockchain.clone();
         let enterprise_integration = self.enterprise_integration.clone();
         let processed_blocks = self.processed_blocks.clone(); // Pass to handler
+        // The page's WebSocket and static files stay open, see common/auth.rs
+        let api_keys = Arc::new(api_keys);
 
         let ws_route = warp::path("ws")
             .and(warp::ws())
@@ -120,7 +173,7 @@ impl Tracker {
         let blockchain_sync_route = warp::path("api")
             .and(warp::path("blockchain-sync"))
             .and(warp::post())
-            .and(warp::body::json())
+            .and(auth::require_network(api_keys.clone(), Scope::SubmitBlocks, |sync: &serde_json::Value| sync["network_id"].as_str()))
             .and(warp::any().map({
                 let enterprise_blockchain = enterprise_blockchain.clone();
                 move || enterprise_blockchain.clone()
@@ -130,6 +183,7 @@ impl Tracker {
         let enterprise_update_route = warp::path("api")
             .and(warp::path("enterprise-update"))
             .and(warp::post())
+            .and(auth::require(api_keys.clone(), Scope::BroadcastTrades))
             .and(warp::body::json())
             .and(warp::any().map({
                 let networks = networks.clone();
@@ -142,6 +196,7 @@ impl Tracker {
         let cross_network_trade_route = warp::path("api")
             .and(warp::path("cross-network-trade"))
             .and(warp::post())
+            .and(auth::require(api_keys.clone(), Scope::BroadcastTrades))
             .and(warp::body::json())
             .and(warp::any().map({
                 let networks = networks.clone();
@@ -149,10 +204,34 @@ impl Tracker {
             }))
             .and_then(handle_cross_network_trade);
 
+        // Settlement prepare/commit/abort from the enterprise order engine
+        let settlement_route = warp::path("api")
+            .and(warp::path("settlement"))
+            .and(warp::post())
+            .and(auth::require(api_keys.clone(), Scope::BroadcastTrades))
+            .and(warp::body::json())
+            .and(warp::any().map({
+                let networks = networks.clone();
+                move || networks.clone()
+            }))
+            .and_then(handle_settlement);
+
+        // Cancel/amend outcomes from the enterprise order engine
+        let order_ack_route = warp::path("api")
+            .and(warp::path("order-ack"))
+            .and(warp::post())
+            .and(auth::require_network(api_keys.clone(), Scope::BroadcastTrades, |ack: &serde_json::Value| ack["network_id"].as_str()))
+            .and(warp::any().map({
+                let networks = networks.clone();
+                move || networks.clone()
+            }))
+            .and_then(handle_order_ack);
+
         let networks_for_api = self.networks.clone();
         let api_route = warp::path("api")
             .and(warp::path("networks"))
             .and(warp::get())
+            .and(auth::require(api_keys.clone(), Scope::ReadOnly))
             .and(warp::any().map(move || networks_for_api.clone()))
             .and_then(get_networks_info);
 
@@ -160,6 +239,7 @@ impl Tracker {
         let api_list_route = warp::path("api")
             .and(warp::path("network-list"))
             .and(warp::get())
+            .and(auth::require(api_keys.clone(), Scope::ReadOnly))
             .and(warp::any().map(move || networks_for_list.clone()))
             .and_then(get_network_list);
 
@@ -178,6 +258,7 @@ impl Tracker {
         let order_book_broadcast_route = warp::path("api")
             .and(warp::path("order-book-broadcast"))
             .and(warp::post())
+            .and(auth::require(api_keys.clone(), Scope::BroadcastTrades))
             .and(warp::body::json())
             .and(warp::any().map({
                 let networks = networks.clone();
@@ -189,12 +270,15 @@ impl Tracker {
             .or(blockchain_sync_route)
             .or(enterprise_update_route)
             .or(cross_network_trade_route)
+            .or(order_ack_route)
+            .or(settlement_route)
             .or(order_book_broadcast_route)
             .or(api_route)
             .or(api_list_route)
             .or(health)
             .or(static_files)
-            .with(warp::cors().allow_any_origin());
+            .recover(auth::recover)
+            .with(auth::cors());
 
         println!("Tracker running on http://0.0.0.0:3030");
         warp::serve(routes).run(([0, 0, 0, 0], 3030)).await;
@@ -209,7 +293,8 @@ async fn handle_order_book_broadcast(
         network_id: "global".to_string(),
         sync_data: serde_json::json!({
             "type": "order_book_update",
-            "orders": order_update["orders"]
+            "orders": order_update["orders"],
+            "cancelled": order_update["cancelled"]
         })
     };
     
@@ -275,6 +360,71 @@ async fn handle_cross_network_trade(
     }
 }
 
+// Both networks of the trade take part in its settlement
+async fn handle_settlement(
+    settlement: serde_json::Value,
+    networks: Networks
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let trade = &settlement["trade"];
+    let parties: HashSet<&str> = [trade["buyer_network"].as_str(), trade["seller_network"].as_str()]
+        .into_iter()
+        .flatten()
+        .collect();
+    println!("Settlement {} for trade {} to networks {:?}", settlement["phase"], trade["trade_id"], parties);
+
+    let message = Message::EnterpriseSync {
+        network_id: "cross_network".to_string(),
+        sync_data: serde_json::json!({
+            "type": "settlement",
+            "phase": settlement["phase"],
+            "trade": trade,
+            "status": settlement["status"],
+            "escrow": settlement["escrow"]
+        })
+    };
+    for network_id in &parties {
+        broadcast_to_network(&networks, network_id, "enterprise", message.clone()).await;
+    }
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "status": "success",
+        "message": "Settlement sent to both networks"
+    })))
+}
+
+// Only the network that sent the cancel/amend hears back
+async fn handle_order_ack(
+    ack: serde_json::Value,
+    networks: Networks
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let network_id = match ack["network_id"].as_str() {
+        Some(network_id) => network_id.to_string(),
+        None => {
+            return Ok(warp::reply::json(&serde_json::json!({
+                "status": "error",
+                "message": "Invalid order ack format"
+            })));
+        }
+    };
+    println!("Order {} {} ack for network {}: accepted={}",
+             ack["order_id"], ack["action"], network_id, ack["accepted"]);
+
+    let message = Message::EnterpriseSync {
+        network_id: "cross_network".to_string(),
+        sync_data: serde_json::json!({
+            "type": "order_ack",
+            "ack": ack
+        })
+    };
+    broadcast_to_network(&networks, &network_id, "enterprise", message).await;
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "status": "success",
+        "message": "Order ack sent to network",
+        "network_id": network_id
+    })))
+}
+
 async fn handle_enterprise_update(
     enterprise_update: serde_json::Value,
     (networks, _global_peers): (Networks, GlobalPeers)
@@ -320,8 +470,7 @@ async fn handle_blockchain_sync(
     if let Some(url) = std::env::var("ENTERPRISE_BC_URL").ok() {
         tokio::spawn(async move {
             let client = reqwest::Client::new();
-            let _ = client
-                .post(&format!("{}/api/blockchain-sync", url))
+            let _ = auth::with_api_key(client.post(format!("{}/api/blockchain-sync", url)))
                 .json(&sync_message)
                 .send()
                 .await;
@@ -334,45 +483,12 @@ async fn handle_blockchain_sync(
     })))
 }
 
-async fn send_block_to_enterprise(block: &Block, network_id: &str, peer_id: &str) {
+async fn send_block_to_enterprise(block: &Block, tenant_signature: Option<String>, network_id: &str, peer_id: &str) {
     if let Some(enterprise_url) = std::env::var("ENTERPRISE_BC_URL").ok() {
         println!("Converting P2P block #{} to enterprise format for network: {}", 
                  block.height, network_id);
         
-        // Better transaction serialization with error handling
-        let mut transactions = Vec::new();
-        for tx in &block.transactions {
-            match serde_json::to_string(tx) {
-                Ok(tx_json) => {
-                    transactions.push(tx_json);
-                    println!("Serialized transaction: {} (type: {:?})", tx.id, tx.tx_type);
-                }
-                Err(e) => {
-                    println!("Failed to serialize transaction {}: {}", tx.id, e);
-                    // Fallback: create a minimal transaction representation
-                    let fallback = serde_json::json!({
-                        "id": tx.id,
-                        "from": tx.from,
-                        "to": tx.to,
-                        "amount": tx.amount,
-                        "timestamp": tx.timestamp,
-                        "tx_type": format!("{:?}", tx.tx_type)
-                    });
-                    transactions.push(fallback.to_string());
-                }
-            }
-        }
-        
-        // Create TenantBlockData with improved serialization
-        let tenant_block = TenantBlockData {
-            block_id: block.height,
-            block_hash: block.hash.clone(),
-            transactions,
-            timestamp: block.timestamp,
-            previous_hash: block.previous_hash.clone(),
-            network_id: network_id.to_string(),
-        };
-        
+        let tenant_block = tenant_block_data(block, network_id, tenant_signature);
         println!("TenantBlockData network_id: {}", tenant_block.network_id);
         
         // Create TenantBlockchainUpdate
@@ -380,34 +496,108 @@ async fn send_block_to_enterprise(block: &Block, network_id: &str, peer_id: &str
             network_id: network_id.to_string(),
             peer_id: peer_id.to_string(),
             new_blocks: vec![tenant_block],
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            timestamp: current_timestamp(),
+            reorg: None,
         };
         
-        // Send to enterprise validator
-        let client = reqwest::Client::new();
-        let url = format!("{}/api/tenant-blockchain-update", enterprise_url);
-        
-        match client.post(&url).json(&update).send().await {
-            Ok(response) => {
-                if response.status().is_success() {
-                    println!("Successfully sent block #{} from network {} to enterprise validator", 
-                            block.height, network_id);
-                } else {
-                    println!("Failed to send to enterprise validator: HTTP {}", response.status());
-                }
+        if post_tenant_update(&enterprise_url, &update).await {
+            println!("Successfully sent block #{} from network {} to enterprise validator", 
+                    block.height, network_id);
+        }
+    } else {
+        println!("No ENTERPRISE_BC_URL configured - skipping enterprise sync");
+    }
+}
+
+// A tenant switched branches: the enterprise validator drops the blocks it
+// abandoned and takes the new branch in their place
+async fn send_reorg_to_enterprise(reorg: &ChainReorg, tenant_signature: Option<String>, network_id: &str, peer_id: &str) {
+    if let Ok(enterprise_url) = std::env::var("ENTERPRISE_BC_URL") {
+        let update = TenantBlockchainUpdate {
+            network_id: network_id.to_string(),
+            peer_id: peer_id.to_string(),
+            new_blocks: reorg.added.iter().map(|block| tenant_block_data(block, network_id, None)).collect(),
+            timestamp: current_timestamp(),
+            reorg: Some(TenantReorg {
+                fork_height: reorg.fork_height,
+                fork_hash: reorg.fork_hash.clone(),
+                removed: reorg.removed.iter().map(|block| block.hash.clone()).collect(),
+                signature: tenant_signature,
+            }),
+        };
+        if post_tenant_update(&enterprise_url, &update).await {
+            println!("Sent reorg of network {} at block {} to enterprise validator", network_id, reorg.fork_height);
+        }
+    } else {
+        println!("No ENTERPRISE_BC_URL configured - skipping enterprise sync");
+    }
+}
+
+fn tenant_block_data(block: &Block, network_id: &str, tenant_signature: Option<String>) -> TenantBlockData {
+    // Better transaction serialization with error handling
+    let mut transactions = Vec::new();
+    for tx in &block.transactions {
+        match serde_json::to_string(tx) {
+            Ok(tx_json) => {
+                transactions.push(tx_json);
+                println!("Serialized transaction: {} (type: {:?})", tx.id, tx.tx_type);
             }
             Err(e) => {
-                println!("Error sending to enterprise validator: {}", e);
+                println!("Failed to serialize transaction {}: {}", tx.id, e);
+                // Fallback: create a minimal transaction representation
+                let fallback = serde_json::json!({
+                    "id": tx.id,
+                    "from": tx.from,
+                    "to": tx.to,
+                    "amount": tx.amount,
+                    "timestamp": tx.timestamp,
+                    "tx_type": format!("{:?}", tx.tx_type)
+                });
+                transactions.push(fallback.to_string());
             }
         }
-    } else {
-        println!("No ENTERPRISE_BC_URL configured - skipping enterprise sync");
+    }
+    
+    TenantBlockData {
+        block_id: block.height,
+        block_hash: block.hash.clone(),
+        transactions,
+        timestamp: block.timestamp,
+        previous_hash: block.previous_hash.clone(),
+        network_id: network_id.to_string(),
+        merkle_root: Some(block.merkle_root.clone()),
+        tenant_signature,
     }
 }
 
+// Send to enterprise validator
+async fn post_tenant_update(enterprise_url: &str, update: &TenantBlockchainUpdate) -> bool {
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/tenant-blockchain-update", enterprise_url);
+    
+    match auth::with_api_key(client.post(&url)).json(update).send().await {
+        Ok(response) => {
+            if response.status().is_success() {
+                true
+            } else {
+                println!("Failed to send to enterprise validator: HTTP {}", response.status());
+                false
+            }
+        }
+        Err(e) => {
+            println!("Error sending to enterprise validator: {}", e);
+            false
+        }
+    }
+}
+
+fn current_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
 
 
 async fn handle_peer(
@@ -424,6 +614,7 @@ async fn handle_peer(
     let mut rx = UnboundedReceiverStream::new(rx);
     
     let mut current_network: Option<String> = None;
+    let mut challenge: Option<(String, String)> = None; // network and nonce of a join in progress
     
     global_peers.write().await.insert(peer_id.clone(), tx.clone());
     let _ = send_network_list_update(&global_peers, &peer_id).await;
@@ -448,43 +639,40 @@ async fn handle_peer(
                     println!("Parsed message type: {:?}", std::mem::discriminant(&message));
                     match message.clone() {
                         Message::JoinNetwork { network_id } => {
-                            if let Some(old_network) = &current_network {
-                                let mut networks_lock = networks.write().await;
-                                if let Some(network_peers) = networks_lock.get_mut(old_network) {
-                                    network_peers.remove(&peer_id);
-                                    if network_peers.is_empty() {
-                                        networks_lock.remove(old_network);
-                                    }
+                            match tenant_admission(&network_id).await {
+                                Ok(None) => join_network(&networks, &global_peers, &peer_id, &tx, &mut current_network, network_id).await,
+                                Ok(Some(_)) => {
+                                    let nonce = Uuid::new_v4().to_string();
+                                    challenge = Some((network_id.clone(), nonce.clone()));
+                                    let _ = send_to_peer_direct(&tx, Message::Challenge { network_id, nonce }).await;
+                                }
+                                Err(reason) => {
+                                    println!("Peer {} refused from network {}: {}", &peer_id[..8], network_id, reason);
+                                    let _ = send_to_peer_direct(&tx, Message::JoinRefused { network_id, reason }).await;
                                 }
                             }
-                            
-                            let network_peer = NetworkPeer {
-                                peer_id: peer_id.clone(),
-                                network_id: network_id.clone(),
-                                sender: tx.clone(),
-                                joined_at: std::time::Instant::now(),
+                        }
+                        Message::ChallengeResponse { network_id, signature } => {
+                            let nonce = match challenge.take() {
+                                Some((challenged, nonce)) if challenged == network_id => nonce,
+                                _ => continue,
                             };
-                            
-                            {
-                                let mut networks_lock = networks.write().await;
-                                networks_lock
-                                    .entry(network_id.clone())
-                                    .or_insert_with(HashMap::new)
-                                    .insert(peer_id.clone(), network_peer);
+                            // Asked again, the key may have changed since
+                            let admission = tenant_admission(&network_id).await;
+                            match verify_challenge_response(&network_id, &nonce, &signature, admission) {
+                                Ok(()) => join_network(&networks, &global_peers, &peer_id, &tx, &mut current_network, network_id).await,
+                                Err(reason) => {
+                                    println!("Peer {} refused from network {}: {}", &peer_id[..8], network_id, reason);
+                                    let _ = send_to_peer_direct(&tx, Message::JoinRefused { network_id, reason }).await;
+                                }
                             }
-                            
-                            current_network = Some(network_id.clone());
-                            
-                            let peer_list = get_network_peers(&networks, &network_id, &peer_id).await;
-                            let _ = send_network_info(&networks, &peer_id, &network_id).await;
-                            let _ = send_to_peer_direct(&tx, Message::Peers { peers: peer_list }).await;
-                            
-                            broadcast_network_list_update(&networks, &global_peers).await;
-                            
-                            println!("Peer {} joined network: {}", &peer_id[..8], network_id);
                         }
-                        Message::Block { block } => {
+                        Message::Block { block, tenant_signature } => {
                             println!("Received Block message for block #{}", block.height);
+                            if let Err(e) = block.verify_signatures() {
+                                println!("REJECTED block #{} from peer {}: {}", block.height, &peer_id[..8], e);
+                                continue;
+                            }
                             if let Some(network_id) = &current_network {
                                 // DEDUPLICATION CHECK - this is the key addition
                                 let block_key = format!("{}:{}", network_id, block.hash);
@@ -508,7 +696,7 @@ async fn handle_peer(
                                 broadcast_to_network(&networks, network_id, &peer_id, message.clone()).await;
                                 
                                 // Send to enterprise validator (only once now)
-                                send_block_to_enterprise(&block, network_id, &peer_id).await;
+                                send_block_to_enterprise(&block, tenant_signature, network_id, &peer_id).await;
                                 
                                 // Remove the duplicate enterprise integration processing
                                 // Only keep one path to enterprise BC to avoid duplicates
@@ -516,6 +704,22 @@ async fn handle_peer(
                                 println!("Block #{} processed and forwarded to enterprise", block.height);
                             }
                         }
+                        Message::ChainReorg { reorg, tenant_signature } => {
+                            if let Some(e) = reorg.added.iter().find_map(|block| block.verify_signatures().err()) {
+                                println!("REJECTED reorg from peer {}: {}", &peer_id[..8], e);
+                                continue;
+                            }
+                            if let Some(network_id) = &current_network {
+                                // Every peer that switches reports it; forward the first
+                                let reorg_key = format!("{}:reorg:{}", network_id, reorg.new_tip);
+                                if !processed_blocks.write().await.insert(reorg_key) {
+                                    continue;
+                                }
+                                println!("Peer {} in network {} reorganized at block {}: {} blocks replaced by {}",
+                                        &peer_id[..8], network_id, reorg.fork_height, reorg.removed.len(), reorg.added.len());
+                                send_reorg_to_enterprise(&reorg, tenant_signature, network_id, &peer_id).await;
+                            }
+                        }
                         Message::EnterpriseSync { network_id, sync_data } => {
                             println!("Received enterprise sync request from network: {}", network_id);
 
@@ -535,8 +739,7 @@ async fn handle_peer(
                                 let payload_clone = sync_payload.clone();
                                 tokio::spawn(async move {
                                     let client = reqwest::Client::new();
-                                    match client
-                                        .post(&format!("{}/api/delta-sync", url_clone))
+                                    match auth::with_api_key(client.post(format!("{}/api/delta-sync", url_clone)))
                                         .json(&payload_clone)
                                         .send()
                                         .await
@@ -557,6 +760,32 @@ async fn handle_peer(
                                 println!("No enterprise blockchain URL configured");
                             }
                         }
+                        Message::SettlementVote { trade_id, prepared, reason } => {
+                            let network_id = match &current_network {
+                                Some(network_id) => network_id.clone(),
+                                None => continue,
+                            };
+                            println!("Settlement vote from network {} on trade {}: {}", network_id, trade_id, prepared);
+
+                            if let Ok(url) = std::env::var("ENTERPRISE_BC_URL") {
+                                let vote = serde_json::json!({
+                                    "trade_id": trade_id,
+                                    "network_id": network_id,
+                                    "prepared": prepared,
+                                    "reason": reason
+                                });
+                                tokio::spawn(async move {
+                                    let client = reqwest::Client::new();
+                                    match auth::with_api_key(client.post(format!("{}/api/settlements/vote", url))).json(&vote).send().await {
+                                        Ok(response) if response.status().is_success() => {}
+                                        Ok(response) => println!("Settlement vote rejected: {}", response.status()),
+                                        Err(e) => println!("Failed to send settlement vote: {}", e),
+                                    }
+                                });
+                            } else {
+                                println!("No enterprise blockchain URL configured");
+                            }
+                        }
                         Message::ChatMessage { content, sender, timestamp } => {
                             if let Some(network_id) = &current_network {
                                 broadcast_to_network(&networks, network_id, &peer_id, message.clone()).await;
@@ -564,6 +793,14 @@ async fn handle_peer(
                             }
                         }
                         Message::Transaction { transaction } => {
+                            if !transaction.verify_signature() {
+                                println!("REJECTED unsigned or forged transaction {} from peer {}", transaction.id, &peer_id[..8]);
+                                continue;
+                            }
+                            if matches!(transaction.tx_type, TransactionType::Trading { .. }) && transaction.order_side().is_none() {
+                                println!("REJECTED order {} from peer {}: no buy/sell side", transaction.id, &peer_id[..8]);
+                                continue;
+                            }
                             if let Some(network_id) = &current_network {
                                 broadcast_to_network(&networks, network_id, &peer_id, message.clone()).await;
                                 println!("Transaction {} broadcast to network {}", transaction.id, network_id);
@@ -623,6 +860,36 @@ async fn handle_network_message(networks: &Networks, network_id: &str, sender_id
             let msg = Message::Candidate { target: sender_id.to_string(), candidate };
             let _ = send_to_network_peer(networks, network_id, &target, msg).await;
         }
+        // Sync answers are checked here too, so a bad peer cannot flood others
+        Message::GetHeaders { target, from_height, count } => {
+            let msg = Message::GetHeaders { target: sender_id.to_string(), from_height, count: count.min(MAX_SYNC_BATCH) };
+            let _ = send_to_network_peer(networks, network_id, &target, msg).await;
+        }
+        Message::GetBlocks { target, from_height, count } => {
+            let msg = Message::GetBlocks { target: sender_id.to_string(), from_height, count: count.min(MAX_SYNC_BATCH) };
+            let _ = send_to_network_peer(networks, network_id, &target, msg).await;
+        }
+        Message::Headers { target, tip_height, headers } => {
+            let invalid = headers.iter().find_map(|header| match header.verify_header() {
+                Ok(()) if !header.transactions.is_empty() => Some(format!("header {} carries transactions", header.height)),
+                result => result.err(),
+            });
+            if let Some(e) = check_sync_batch(headers.len()).err().or(invalid) {
+                println!("REJECTED headers from peer {}: {}", &sender_id[..8], e);
+                return;
+            }
+            let msg = Message::Headers { target: sender_id.to_string(), tip_height, headers };
+            let _ = send_to_network_peer(networks, network_id, &target, msg).await;
+        }
+        Message::Blocks { target, blocks } => {
+            let invalid = blocks.iter().find_map(|block| block.verify_signatures().err());
+            if let Some(e) = check_sync_batch(blocks.len()).err().or(invalid) {
+                println!("REJECTED blocks from peer {}: {}", &sender_id[..8], e);
+                return;
+            }
+            let msg = Message::Blocks { target: sender_id.to_string(), blocks };
+            let _ = send_to_network_peer(networks, network_id, &target, msg).await;
+        }
         _ => {}
     }
 }
@@ -640,12 +907,106 @@ async fn broadcast_to_network(networks: &Networks, network_id: &str, sender_id:
     }
 }
 
+// Add the peer to `network_id`, leaving the network it was in
+async fn join_network(
+    networks: &Networks,
+    global_peers: &GlobalPeers,
+    peer_id: &str,
+    tx: &mpsc::UnboundedSender<Result<WsMessage, warp::Error>>,
+    current_network: &mut Option<String>,
+    network_id: String,
+) {
+    if let Some(old_network) = current_network.as_ref() {
+        let mut networks_lock = networks.write().await;
+        if let Some(network_peers) = networks_lock.get_mut(old_network) {
+            network_peers.remove(peer_id);
+            if network_peers.is_empty() {
+                networks_lock.remove(old_network);
+            }
+        }
+    }
+    
+    let network_peer = NetworkPeer {
+        peer_id: peer_id.to_string(),
+        network_id: network_id.clone(),
+        sender: tx.clone(),
+        joined_at: std::time::Instant::now(),
+    };
+    
+    {
+        let mut networks_lock = networks.write().await;
+        networks_lock
+            .entry(network_id.clone())
+            .or_insert_with(HashMap::new)
+            .insert(peer_id.to_string(), network_peer);
+    }
+    
+    *current_network = Some(network_id.clone());
+    
+    let peer_list = get_network_peers(networks, &network_id, peer_id).await;
+    let _ = send_network_info(networks, peer_id, &network_id).await;
+    let _ = send_to_peer_direct(tx, Message::Peers { peers: peer_list }).await;
+    
+    broadcast_network_list_update(networks, global_peers).await;
+    
+    println!("Peer {} joined network: {}", &peer_id[..8], network_id);
+}
+
+// Whether peers may join `network_id`: None lets them in, a tenant record
+// means they must answer a challenge signed with its key. Without an
+// enterprise validator to ask there is no registry and everyone is let in.
+async fn tenant_admission(network_id: &str) -> Result<Option<TenantRecord>, String> {
+    let url = match std::env::var("ENTERPRISE_BC_URL") {
+        Ok(url) => url,
+        Err(_) => return Ok(None),
+    };
+    let lookup = async {
+        let response = auth::with_api_key(reqwest::Client::new().get(format!("{}/api/tenant-registry/{}", url, network_id)))
+            .send().await.map_err(|e| e.to_string())?;
+        match response.status() {
+            warp::http::StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => response.json::<TenantRecord>().await.map(Some).map_err(|e| e.to_string()),
+            status => Err(format!("HTTP {}", status)),
+        }
+    };
+    admit(network_id, lookup.await, tenants::registration_required())
+}
+
+// What the registry's answer (or failure to answer) means for a join
+fn admit(network_id: &str, lookup: Result<Option<TenantRecord>, String>, registration_required: bool) -> Result<Option<TenantRecord>, String> {
+    match lookup {
+        Ok(Some(record)) if record.suspended => Err(format!("network {} is suspended", network_id)),
+        Ok(Some(record)) => Ok(Some(record)),
+        Ok(None) if registration_required => Err(format!("network {} is not registered", network_id)),
+        Ok(None) => Ok(None),
+        // A registered network's peers would get in unchallenged and could
+        // vote on and cancel its orders, so nobody joins until it answers
+        Err(e) => Err(format!("cannot check the tenant registry: {}", e)),
+    }
+}
+
+// The peer's signature over the nonce we sent, against the network's key
+// as the registry has it now
+fn verify_challenge_response(network_id: &str, nonce: &str, signature: &str, admission: Result<Option<TenantRecord>, String>) -> Result<(), String> {
+    match admission? {
+        Some(record) => record.verify(network_id, &tenants::join_message(network_id, nonce), Some(signature)),
+        None => Ok(()),
+    }
+}
+
 async fn send_to_peer_direct(sender: &mpsc::UnboundedSender<Result<WsMessage, warp::Error>>, message: Message) -> Result<(), Box<dyn std::error::Error>> {
     let json = serde_json::to_string(&message)?;
     sender.send(Ok(WsMessage::text(json)))?;
     Ok(())
 }
 
+fn check_sync_batch(len: usize) -> Result<(), String> {
+    if len > MAX_SYNC_BATCH as usize {
+        return Err(format!("{} in one batch, at most {}", len, MAX_SYNC_BATCH));
+    }
+    Ok(())
+}
+
 async fn send_to_network_peer(networks: &Networks, network_id: &str, peer_id: &str, message: Message) -> Result<(), Box<dyn std::error::Error>> {
     let networks_lock = networks.read().await;
     if let Some(network_peers) = networks_lock.get(network_id) {
@@ -751,3 +1112,33 @@ async fn broadcast_network_list_update(networks: &Networks, global_peers: &Globa
         let _ = sender.send(Ok(WsMessage::text(json.clone())));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::tenants::TenantKey;
+
+    #[test]
+    fn test_join_challenge_takes_the_current_tenant_key() {
+        let key = TenantKey::generate();
+        let record = TenantRecord { public_key: key.public_key(), registered_at: 1, key_changed_at: 1, suspended: false };
+        let net = "net-a";
+
+        // Registered networks are challenged, suspended ones refused; the
+        // rest only when registration is required. No answer refuses all.
+        assert_eq!(admit(net, Ok(Some(record.clone())), false), Ok(Some(record.clone())));
+        assert!(admit(net, Ok(Some(TenantRecord { suspended: true, ..record.clone() })), false).is_err());
+        assert_eq!(admit(net, Ok(None), false), Ok(None));
+        assert!(admit(net, Ok(None), true).is_err());
+        assert!(admit(net, Err("timeout".to_string()), false).is_err());
+        assert!(admit(net, Err("timeout".to_string()), true).is_err());
+
+        let signature = key.sign_join(net.to_string(), "nonce-1".to_string());
+        assert!(verify_challenge_response(net, "nonce-1", &signature, Ok(Some(record.clone()))).is_ok());
+        assert!(verify_challenge_response(net, "nonce-2", &signature, Ok(Some(record.clone()))).is_err());
+        assert!(verify_challenge_response("net-b", "nonce-1", &signature, Ok(Some(record.clone()))).is_err());
+        let rotated = TenantRecord { public_key: TenantKey::generate().public_key(), ..record };
+        assert!(verify_challenge_response(net, "nonce-1", &signature, Ok(Some(rotated))).is_err());
+        assert!(verify_challenge_response(net, "nonce-1", &signature, Err("network net-a is suspended".to_string())).is_err());
+    }
+}
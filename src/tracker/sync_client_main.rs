@@ -0,0 +1,213 @@
+use distli_mesh_bc::blockchain::sync::{BlockSync, SyncMessage};
+use distli_mesh_bc::blockchain::tenants::TenantKey;
+use distli_mesh_bc::blockchain::Blockchain;
+use distli_mesh_bc::tracker::server::Message;
+use clap::Parser;
+use futures_util::{SinkExt, StreamExt};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+// A tenant node without the browser: joins a network through the tracker,
+// catches up with one of its peers headers first (see blockchain/sync.rs),
+// then follows the blocks the network gossips and answers other late joiners
+
+#[derive(Parser)]
+#[command(name = "sync-client")]
+#[command(about = "Tenant node that catches up on a network's chain through the tracker")]
+struct Args {
+    #[arg(long, default_value = "ws://127.0.0.1:3030/ws")]
+    tracker: String,
+
+    #[arg(short, long)]
+    network: String,
+
+    /// Chain file or block log directory; in memory when not given
+    #[arg(long)]
+    storage: Option<String>,
+
+    /// Exit once caught up (status 1 if no peer could be synced from)
+    /// instead of following the network
+    #[arg(long)]
+    exit_when_synced: bool,
+
+    /// Secret of the network's tenant key (also TENANT_KEY), for networks in
+    /// the tenant registry: answers the tracker's join challenge and signs reorgs
+    #[arg(long)]
+    tenant_key: Option<String>,
+}
+
+#[derive(PartialEq)]
+enum State {
+    WaitingForPeers,
+    Syncing,
+    Following, // caught up, or nobody left to catch up from
+}
+
+// A peer that does not answer within this is given up for the next one
+const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    let tenant_key = args.tenant_key.clone().or_else(|| std::env::var("TENANT_KEY").ok()).map(|secret| {
+        TenantKey::from_secret(secret).unwrap_or_else(|| {
+            eprintln!("Invalid tenant key");
+            std::process::exit(1);
+        })
+    });
+
+    let mut blockchain = match &args.storage {
+        Some(path) => Blockchain::new_with_storage(path.clone()),
+        None => Blockchain::new(),
+    };
+    if let Some(e) = blockchain.storage_error() {
+        eprintln!("Cannot load the stored chain: {}", e);
+        std::process::exit(1);
+    }
+    println!("Local chain at block {}", blockchain.height());
+
+    let (ws, _) = match connect_async(args.tracker.as_str()).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("Could not connect to tracker {}: {}", args.tracker, e);
+            std::process::exit(1);
+        }
+    };
+    println!("Connected to tracker {}", args.tracker);
+    let (mut ws_tx, mut ws_rx) = ws.split();
+
+    let join = Message::JoinNetwork { network_id: args.network.clone() };
+    if ws_tx.send(WsMessage::text(serde_json::to_string(&join).unwrap())).await.is_err() {
+        eprintln!("Tracker closed the connection");
+        std::process::exit(1);
+    }
+
+    let mut sync = BlockSync::new();
+    let mut candidates: VecDeque<String> = VecDeque::new();
+    let mut state = State::WaitingForPeers;
+    let mut deadline = Instant::now() + SYNC_TIMEOUT;
+
+    loop {
+        let mut outgoing = Vec::new();
+        let mut next_peer = false;
+
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline), if state == State::Syncing => {
+                println!("No answer from {}", sync.peer());
+                next_peer = true;
+            }
+            incoming = ws_rx.next() => {
+                let text = match incoming {
+                    Some(Ok(WsMessage::Text(text))) => text,
+                    Some(Ok(WsMessage::Close(_))) | None => {
+                        println!("Tracker closed the connection");
+                        std::process::exit(1);
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => {
+                        eprintln!("Connection to tracker failed: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+
+                if let Ok(message) = serde_json::from_str::<SyncMessage>(&text) {
+                    match message {
+                        SyncMessage::GetHeaders { .. } | SyncMessage::GetBlocks { .. } => {
+                            outgoing.extend(blockchain.answer_sync(&message).and_then(|answer| serde_json::to_string(&answer).ok()));
+                        }
+                        _ if state == State::Syncing => {
+                            if let Some(next) = sync.handle(&mut blockchain, message) {
+                                outgoing.push(serde_json::to_string(&next).unwrap());
+                                deadline = Instant::now() + SYNC_TIMEOUT;
+                            } else if sync.is_done() {
+                                println!("Added {} blocks, now at block {}", sync.take_applied().len(), blockchain.height());
+                                if sync.error().is_some() {
+                                    next_peer = true;
+                                } else if args.exit_when_synced {
+                                    std::process::exit(0);
+                                } else {
+                                    state = State::Following;
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                } else if let Ok(message) = serde_json::from_str::<Message>(&text) {
+                    match message {
+                        Message::Peers { peers } if state == State::WaitingForPeers => {
+                            println!("{} peers in network {}", peers.len(), args.network);
+                            if peers.is_empty() {
+                                // First in the network, nothing to catch up on
+                                if args.exit_when_synced {
+                                    std::process::exit(0);
+                                }
+                                state = State::Following;
+                            } else {
+                                candidates = peers.into();
+                                next_peer = true;
+                            }
+                        }
+                        Message::Challenge { network_id, nonce } => match &tenant_key {
+                            Some(key) => {
+                                let signature = key.sign_join(network_id.clone(), nonce);
+                                outgoing.push(serde_json::to_string(&Message::ChallengeResponse { network_id, signature }).unwrap());
+                            }
+                            None => {
+                                eprintln!("Network {} is registered, a --tenant-key is needed to join it", network_id);
+                                std::process::exit(1);
+                            }
+                        },
+                        Message::JoinRefused { network_id, reason } => {
+                            eprintln!("Could not join network {}: {}", network_id, reason);
+                            std::process::exit(1);
+                        }
+                        Message::Block { block, .. } => {
+                            let height = block.height;
+                            if let Err(e) = blockchain.learn_validator(&block) {
+                                println!("Ignoring block #{} from the network: {}", height, e);
+                            } else if blockchain.add_peer_block(block) {
+                                println!("Block #{} from the network, now at block {}", height, blockchain.height());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        if next_peer {
+            match candidates.pop_front() {
+                Some(peer) => {
+                    println!("Syncing from {}", peer);
+                    outgoing.push(serde_json::to_string(&sync.start(&blockchain, &peer)).unwrap());
+                    state = State::Syncing;
+                    deadline = Instant::now() + SYNC_TIMEOUT;
+                }
+                None => {
+                    println!("No peer left to sync from, at block {}", blockchain.height());
+                    if args.exit_when_synced {
+                        std::process::exit(1);
+                    }
+                    state = State::Following;
+                }
+            }
+        }
+
+        // Switched branches: the tracker passes it on to the enterprise BC
+        for reorg in blockchain.take_reorg_events() {
+            println!("Reorganized at block {}: {} blocks replaced by {}", reorg.fork_height, reorg.removed.len(), reorg.added.len());
+            let tenant_signature = tenant_key.as_ref().map(|key| key.sign_reorg(&args.network, &reorg));
+            outgoing.push(serde_json::to_string(&Message::ChainReorg { reorg, tenant_signature }).unwrap());
+        }
+
+        for text in outgoing {
+            if ws_tx.send(WsMessage::text(text)).await.is_err() {
+                eprintln!("Tracker closed the connection");
+                std::process::exit(1);
+            }
+        }
+    }
+}